@@ -11,6 +11,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .out_dir("src/generated")
         .build_server(true)
         .build_client(true)
+        // Served by the reflection service
+        .file_descriptor_set_path("src/generated/cl0_descriptor.bin")
         .compile_protos(
             &[
                 "proto/common/types.proto",
@@ -1,20 +1,44 @@
-use std::{error::Error, future::Future, sync::Arc};
+use futures::FutureExt;
+use std::{any::Any, error::Error, future::Future, panic::AssertUnwindSafe, sync::Arc, time::Duration};
 use tokio::{
-    sync::{mpsc, oneshot},
+    sync::{Semaphore, mpsc, oneshot},
     task,
 };
 
 /// Wrapper around results returned from the API handlers.
 pub type ApiResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
 
+/// A request together with the channel its reply is sent on, if any.
+type Envelope<Req, Res> = (Req, Option<oneshot::Sender<ApiResult<Res>>>);
+
+/// Sending side of a route's channel.
+#[derive(Debug)]
+enum RouteSender<Req, Res> {
+    Unbounded(mpsc::UnboundedSender<Envelope<Req, Res>>),
+    Bounded(mpsc::Sender<Envelope<Req, Res>>),
+}
+
+impl<Req, Res> Clone for RouteSender<Req, Res> {
+    fn clone(&self) -> Self {
+        match self {
+            RouteSender::Unbounded(tx) => RouteSender::Unbounded(tx.clone()),
+            RouteSender::Bounded(tx) => RouteSender::Bounded(tx.clone()),
+        }
+    }
+}
+
 /// A generic route representing a message/command sink with an optional reply channel.
 ///
 /// Provides two invocation styles:
 ///  - `notify`: fire-and-forget. The caller does not await the handler's completion.
 ///  - `call`: RPC-style. The caller waits for the handler result.
+///  - `call_timeout`: like `call`, but gives up after a deadline.
+///
+/// Routes built with `bounded` apply backpressure: `try_notify` rejects requests while the
+/// route is full and `call` waits for room.
 #[derive(Debug)]
 pub struct ApiRoute<Req, Res> {
-    tx: mpsc::UnboundedSender<(Req, Option<oneshot::Sender<ApiResult<Res>>>)>,
+    tx: RouteSender<Req, Res>,
 }
 
 impl<Req, Res> Clone for ApiRoute<Req, Res> {
@@ -37,42 +61,128 @@ where
         H: Fn(Req) -> F + Send + Sync + 'static,
         F: Future<Output = ApiResult<Res>> + Send + 'static,
     {
-        let (tx, mut rx) =
-            mpsc::unbounded_channel::<(Req, Option<oneshot::Sender<ApiResult<Res>>>)>();
+        let (tx, mut rx) = mpsc::unbounded_channel::<Envelope<Req, Res>>();
         let handler = Arc::new(handler);
 
         // Main dispatcher loop: consume incoming requests and spawn their handlers
         task::spawn(async move {
             while let Some((req, maybe_ack)) = rx.recv().await {
+                task::spawn(dispatch(handler.clone(), req, maybe_ack));
+            }
+        });
+
+        ApiRoute {
+            tx: RouteSender::Unbounded(tx),
+        }
+    }
+
+    /// Like `new`, but at most `capacity` requests are handled at once and at most `capacity`
+    /// more are queued. Once both are used up, `try_notify` rejects and `call` waits.
+    ///
+    /// A handler must not call back into its own route, as that can wait on itself once full.
+    pub fn bounded<H, F>(capacity: usize, handler: H) -> Self
+    where
+        H: Fn(Req) -> F + Send + Sync + 'static,
+        F: Future<Output = ApiResult<Res>> + Send + 'static,
+    {
+        let (tx, mut rx) = mpsc::channel::<Envelope<Req, Res>>(capacity);
+        let handler = Arc::new(handler);
+        let in_flight = Arc::new(Semaphore::new(capacity));
+
+        // Dispatcher loop: stop taking requests off the queue while `capacity` handlers run
+        task::spawn(async move {
+            while let Some((req, maybe_ack)) = rx.recv().await {
+                let Ok(permit) = in_flight.clone().acquire_owned().await else {
+                    break;
+                };
                 let handler = handler.clone();
                 task::spawn(async move {
-                    let result: ApiResult<Res> = handler(req).await;
-
-                    // If the caller asked for a reply, send it back
-                    if let Some(ack_tx) = maybe_ack {
-                        let _ = ack_tx.send(result);
-                    }
+                    dispatch(handler, req, maybe_ack).await;
+                    drop(permit);
                 });
             }
         });
 
-        ApiRoute { tx }
+        ApiRoute {
+            tx: RouteSender::Bounded(tx),
+        }
     }
 
-    /// Fire-and-forget invocation: no result is awaited.
+    /// Fire-and-forget invocation: no result is awaited. On a bounded route the request is
+    /// dropped when the route is full, use `try_notify` to find out.
     pub fn notify(&self, req: Req) {
-        let _ = self.tx.send((req, None));
+        let _ = self.try_notify(req);
+    }
+
+    /// Fire-and-forget invocation that reports whether the request was accepted. Only a
+    /// full bounded route or a route whose dispatcher has stopped rejects requests.
+    pub fn try_notify(&self, req: Req) -> bool {
+        match &self.tx {
+            RouteSender::Unbounded(tx) => tx.send((req, None)).is_ok(),
+            RouteSender::Bounded(tx) => tx.try_send((req, None)).is_ok(),
+        }
     }
 
     /// RPC-style invocation: waits for the handler to complete and returns its result.
+    /// On a bounded route this first waits until the route has room for the request.
     pub async fn call(&self, req: Req) -> ApiResult<Res> {
         let (ack_tx, ack_rx) = oneshot::channel::<ApiResult<Res>>();
-        self.tx
-            .send((req, Some(ack_tx)))
-            .map_err(|e| Box::<dyn Error + Send + Sync>::from(e))?;
+        match &self.tx {
+            RouteSender::Unbounded(tx) => tx
+                .send((req, Some(ack_tx)))
+                .map_err(|e| Box::<dyn Error + Send + Sync>::from(e.to_string()))?,
+            RouteSender::Bounded(tx) => tx
+                .send((req, Some(ack_tx)))
+                .await
+                .map_err(|e| Box::<dyn Error + Send + Sync>::from(e.to_string()))?,
+        }
 
-        ack_rx
+        // The responder is only dropped unanswered when the handler's task was torn down
+        ack_rx.await.map_err(|_| {
+            Box::<dyn Error + Send + Sync>::from("Handler task terminated unexpectedly")
+        })?
+    }
+
+    /// Like `call`, but fails with a timeout error when no result arrives within `dur`. The
+    /// handler keeps running in the background and the route stays usable.
+    pub async fn call_timeout(&self, req: Req, dur: Duration) -> ApiResult<Res> {
+        tokio::time::timeout(dur, self.call(req))
             .await
-            .map_err(|e| Box::<dyn Error + Send + Sync>::from(e))?
+            .map_err(|_| Box::<dyn Error + Send + Sync>::from(format!("Call timed out after {:?}", dur)))?
+    }
+}
+
+/// Runs `handler` on `req` and sends the result back if the caller asked for one. A panicking
+/// handler is reported to the caller as an error instead of silently dropping the reply.
+async fn dispatch<Req, Res, H, F>(
+    handler: Arc<H>,
+    req: Req,
+    maybe_ack: Option<oneshot::Sender<ApiResult<Res>>>,
+) where
+    H: Fn(Req) -> F,
+    F: Future<Output = ApiResult<Res>>,
+{
+    let result: ApiResult<Res> = match AssertUnwindSafe(handler(req)).catch_unwind().await {
+        Ok(result) => result,
+        Err(panic) => Err(Box::<dyn Error + Send + Sync>::from(format!(
+            "Handler task terminated unexpectedly: panicked with {}",
+            panic_message(panic.as_ref())
+        ))),
+    };
+
+    // If the caller asked for a reply, send it back
+    if let Some(ack_tx) = maybe_ack {
+        let _ = ack_tx.send(result);
+    }
+}
+
+/// The message a panic was raised with, if it was a string.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(s) = panic.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = panic.downcast_ref::<String>() {
+        s
+    } else {
+        "a non-string payload"
     }
 }
@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use subtle::ConstantTimeEq;
+use tonic::{Request, Status, service::Interceptor};
+
+/// Checks the `authorization: Bearer <token>` metadata of incoming requests.
+/// Without a configured token every request is let through.
+#[derive(Debug, Clone, Default)]
+pub struct AuthInterceptor {
+    token: Option<Arc<str>>,
+}
+
+impl AuthInterceptor {
+    pub fn new(token: Option<String>) -> Self {
+        AuthInterceptor {
+            token: token.map(Arc::from),
+        }
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let Some(expected) = &self.token else {
+            return Ok(request);
+        };
+
+        let provided = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        match provided {
+            // Constant time, so the response time doesn't reveal how much of the token matched
+            Some(token) if bool::from(token.as_bytes().ct_eq(expected.as_bytes())) => Ok(request),
+            Some(_) => Err(Status::unauthenticated("Invalid bearer token")),
+            None => Err(Status::unauthenticated("Missing bearer token")),
+        }
+    }
+}
@@ -1,23 +1,97 @@
+use cl0_parser::ast::Rule;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
-use tonic::{Request, Response, Status};
+use tonic::{
+    Request, Response, Status, server::NamedService, service::interceptor::InterceptedService,
+};
+use tonic_health::pb::health_server::{Health, HealthServer};
+use tonic_reflection::server::v1::{ServerReflection, ServerReflectionServer};
+use tower::Layer;
+use tracing::{Level, debug, info, warn};
 
+use crate::auth::AuthInterceptor;
 use crate::generated;
+use crate::node::Node;
+use crate::request_log::{RequestLog, RequestLogLayer};
+use crate::types::RuleWithArgs;
 use crate::generated::common::RuleResult;
 use crate::generated::control_plane::{
-    Ack, ControlMessage, HeartbeatAck, NodeAck, NodeHeartbeat, NodeRegistration,
+    Ack, ControlMessage, HeartbeatAck, NodeAck, NodeHeartbeat, NodeRegistration, NodeStatus,
     RuleExecutionRequest,
     control_plane_server::{ControlPlane, ControlPlaneServer},
 };
 
 type NodeId = String;
 
-#[derive(Debug, Default)]
+/// How often the reaper scans for nodes that stopped sending heartbeats.
+pub const DEFAULT_REAP_INTERVAL: Duration = Duration::from_secs(5);
+/// How long a node may go without a heartbeat before it is removed.
+pub const DEFAULT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug)]
 pub struct ControlPlaneService {
     pub state: Arc<RwLock<ControlPlaneState>>,
+    reap_interval: Duration,
+    heartbeat_timeout: Duration,
+    auth_token: Option<String>,
+    log_level: Level,
+}
+
+impl Default for ControlPlaneService {
+    fn default() -> Self {
+        ControlPlaneService::with_config(DEFAULT_REAP_INTERVAL, DEFAULT_HEARTBEAT_TIMEOUT)
+    }
+}
+
+impl ControlPlaneService {
+    /// Creates a service whose reaper runs every `reap_interval` and expires nodes
+    /// whose last heartbeat is older than `heartbeat_timeout`.
+    pub fn with_config(reap_interval: Duration, heartbeat_timeout: Duration) -> Self {
+        ControlPlaneService {
+            state: Arc::new(RwLock::new(ControlPlaneState::default())),
+            reap_interval,
+            heartbeat_timeout,
+            auth_token: None,
+            log_level: Level::INFO,
+        }
+    }
+
+    /// Requires every request to carry `authorization: Bearer <token>`.
+    pub fn with_auth_token(mut self, token: impl Into<String>) -> Self {
+        self.auth_token = Some(token.into());
+        self
+    }
+
+    /// Logs every handled call at `level` instead of [`Level::INFO`].
+    pub fn with_log_level(mut self, level: Level) -> Self {
+        self.log_level = level;
+        self
+    }
+
+    /// Starts the background task that removes stale nodes. The task stops on its own
+    /// once the shared state has been dropped.
+    pub fn spawn_reaper(&self) -> JoinHandle<()> {
+        let state = Arc::downgrade(&self.state);
+        let reap_interval = self.reap_interval;
+        let timeout = self.heartbeat_timeout.as_secs() as i64;
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(reap_interval);
+            loop {
+                ticker.tick().await;
+                let Some(state) = state.upgrade() else {
+                    break;
+                };
+                let now = chrono::Utc::now().timestamp();
+                state.write().await.remove_stale_nodes(now, timeout);
+            }
+        })
+    }
 }
 
 #[derive(Debug, Default)]
@@ -26,11 +100,42 @@ pub struct ControlPlaneState {
     pub pools: HashMap<String, Vec<NodeId>>,
 }
 
+impl ControlPlaneState {
+    /// Removes every node whose last heartbeat is more than `timeout` seconds before `now`,
+    /// along with its pool membership. Pools left empty are dropped. Returns the removed ids.
+    pub fn remove_stale_nodes(&mut self, now: i64, timeout: i64) -> Vec<NodeId> {
+        let stale: Vec<NodeId> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| now - node.last_heartbeat > timeout)
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        for id in &stale {
+            self.nodes.remove(id);
+            info!("Node expired after missed heartbeats: {id}");
+        }
+
+        if !stale.is_empty() {
+            for members in self.pools.values_mut() {
+                members.retain(|id| !stale.contains(id));
+            }
+            self.pools.retain(|_, members| !members.is_empty());
+        }
+
+        stale
+    }
+}
+
 #[derive(Debug)]
 pub struct RegisteredNode {
     pub hostname: String,
     pub last_heartbeat: i64,
     pub version: String,
+    /// The engine instance that executes rules on behalf of this node.
+    pub node: Arc<Node>,
+    /// The latest status the node reported with a heartbeat, if it reported any.
+    pub status: Option<NodeStatus>,
 }
 
 #[tonic::async_trait]
@@ -41,13 +146,17 @@ impl ControlPlane for ControlPlaneService {
     ) -> Result<Response<NodeAck>, Status> {
         let req = request.into_inner();
         let id = Uuid::new_v4().to_string();
+
+        // Build the engine before taking the lock, construction is async
+        let node = Node::with_id(id.clone(), None).await;
         let mut state = self.state.write().await;
 
-        
         let registered_node = RegisteredNode {
             hostname: req.hostname,
             version: req.version,
             last_heartbeat: chrono::Utc::now().timestamp(),
+            node,
+            status: None,
         };
 
         // Store the registered node
@@ -70,7 +179,7 @@ impl ControlPlane for ControlPlaneService {
         }
 
 
-        println!("New node registered: {id}");
+        info!("New node registered: {id}");
 
         Ok(Response::new(NodeAck {
             id: Some(generated::common::NodeId { id }),
@@ -84,15 +193,27 @@ impl ControlPlane for ControlPlaneService {
         let req = request.into_inner();
         let mut state = self.state.write().await;
 
-        if let Some(id) = req.id {
-            if let Some(node) = state.nodes.get_mut(&id.id) {
-                node.last_heartbeat = chrono::Utc::now().timestamp();
-                println!("Heartbeat received from: {}", id.id);
-                return Ok(Response::new(HeartbeatAck {}));
+        let id = req
+            .id
+            .ok_or_else(|| Status::invalid_argument("NodeHeartbeat.id required"))?;
+
+        if let Some(node) = state.nodes.get_mut(&id.id) {
+            node.last_heartbeat = chrono::Utc::now().timestamp();
+            if req.status.is_some() {
+                node.status = req.status;
             }
+            debug!("Heartbeat received from: {}", id.id);
+            return Ok(Response::new(HeartbeatAck {
+                should_reregister: false,
+            }));
         }
 
-        Err(Status::not_found("Node not found"))
+        // Unknown (e.g. expired) node: tell it to register again. Not an error, a status
+        // couldn't carry `should_reregister`, so the id only ends up in the log.
+        warn!("Heartbeat received from unknown node {}, requesting re-registration", id.id);
+        Ok(Response::new(HeartbeatAck {
+            should_reregister: true,
+        }))
     }
 
     async fn forward_message(
@@ -100,7 +221,7 @@ impl ControlPlane for ControlPlaneService {
         request: Request<ControlMessage>,
     ) -> Result<Response<Ack>, Status> {
         let msg = request.into_inner();
-        println!(
+        info!(
             "Received message from node {}: {}",
             msg.sender
                 .unwrap_or(generated::common::NodeId {
@@ -120,22 +241,84 @@ impl ControlPlane for ControlPlaneService {
         request: Request<RuleExecutionRequest>,
     ) -> Result<Response<RuleResult>, Status> {
         let req = request.into_inner();
-        println!("Received rule execution request for node {:?}", req.target);
+        debug!("Received rule execution request for node {:?}", req.target);
 
-        // TODO: forward rule to appropriate node and wait for response
-        Ok(Response::new(RuleResult {
-            success: true,
-            output: "Rule execution not yet implemented".into(),
-        }))
+        let target = req
+            .target
+            .ok_or_else(|| Status::invalid_argument("RuleExecutionRequest.target required"))?;
+        let rule = req
+            .rule
+            .ok_or_else(|| Status::invalid_argument("RuleExecutionRequest.rule required"))?;
+        let rule = Rule::try_from(rule).map_err(Status::invalid_argument)?;
+
+        // Clone the node handle so the state lock is not held while the rule executes
+        let node = {
+            let state = self.state.read().await;
+            match state.nodes.get(&target.id) {
+                Some(registered) => registered.node.clone(),
+                None => {
+                    return Err(Status::not_found(format!("Node not found: {}", target.id)));
+                }
+            }
+        };
+
+        let result = match node.api.new_rules.call(vec![RuleWithArgs::from(rule)]).await {
+            Ok(results) => {
+                let success = results.iter().all(|r| *r);
+                RuleResult {
+                    success,
+                    output: if success {
+                        "Rule applied".into()
+                    } else {
+                        "Rule was not applied".into()
+                    },
+                }
+            }
+            Err(e) => RuleResult {
+                success: false,
+                output: e.to_string(),
+            },
+        };
+
+        Ok(Response::new(result))
     }
 }
 
-pub fn new_service_instance() -> (
-    ControlPlaneServer<ControlPlaneService>,
-    Arc<RwLock<ControlPlaneState>>,
-) {
-    let service: ControlPlaneService = ControlPlaneService::default();
+/// The control plane as served: behind the auth interceptor and the request log.
+pub type ControlPlaneGrpcService =
+    RequestLog<InterceptedService<ControlPlaneServer<ControlPlaneService>, AuthInterceptor>>;
+
+pub fn new_service_instance() -> (ControlPlaneGrpcService, Arc<RwLock<ControlPlaneState>>) {
+    new_service_instance_with(ControlPlaneService::default())
+}
+
+/// Like [`new_service_instance`], but for a service built with [`ControlPlaneService::with_config`],
+/// [`ControlPlaneService::with_auth_token`] and/or [`ControlPlaneService::with_log_level`].
+pub fn new_service_instance_with(
+    service: ControlPlaneService,
+) -> (ControlPlaneGrpcService, Arc<RwLock<ControlPlaneState>>) {
     let state: Arc<RwLock<ControlPlaneState>> = service.state.clone();
-    
-    (ControlPlaneServer::new(service), state)
+    service.spawn_reaper();
+
+    let auth = AuthInterceptor::new(service.auth_token.clone());
+    let log = RequestLogLayer::new(service.log_level);
+    (log.layer(ControlPlaneServer::with_interceptor(service, auth)), state)
+}
+
+/// gRPC health service reporting the control plane as serving, to be added next to it.
+pub async fn new_health_service() -> HealthServer<impl Health> {
+    let (reporter, health) = tonic_health::server::health_reporter();
+    reporter
+        .set_serving::<ControlPlaneServer<ControlPlaneService>>()
+        .await;
+    health
+}
+
+/// gRPC reflection service describing the control plane, to be added next to it.
+pub fn new_reflection_service() -> ServerReflectionServer<impl ServerReflection> {
+    tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(generated::FILE_DESCRIPTOR_SET)
+        .with_service_name(<ControlPlaneServer<ControlPlaneService> as NamedService>::NAME)
+        .build_v1()
+        .expect("the embedded file descriptor set is valid")
 }
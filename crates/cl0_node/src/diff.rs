@@ -0,0 +1,46 @@
+//! Comparison of two versions of a policy, to preview an update before it is applied.
+
+use std::collections::HashSet;
+
+use cl0_parser::ast::Rule;
+
+/// What changed between two versions of a policy. Rules are compared with
+/// `Rule::structurally_eq`, so renaming the alias of a nested compound is not a change.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuleDiff {
+    /// Rules of the new version that the old one lacks.
+    pub added: Vec<Rule>,
+    /// Rules of the old version that the new one lacks.
+    pub removed: Vec<Rule>,
+    /// Rules of the new version that the old one has as well.
+    pub unchanged: Vec<Rule>,
+}
+
+impl RuleDiff {
+    /// Whether the two versions hold the same rules.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Diffs the rules of `old` against those of `new`, regardless of their order. Each side keeps
+/// the order it has in its own version.
+pub fn diff_rules(old: &[Rule], new: &[Rule]) -> RuleDiff {
+    let old_keys: HashSet<Rule> = old.iter().map(Rule::without_aliases).collect();
+    let new_keys: HashSet<Rule> = new.iter().map(Rule::without_aliases).collect();
+
+    let mut diff = RuleDiff::default();
+    for rule in new {
+        if old_keys.contains(&rule.without_aliases()) {
+            diff.unchanged.push(rule.clone());
+        } else {
+            diff.added.push(rule.clone());
+        }
+    }
+    diff.removed = old
+        .iter()
+        .filter(|rule| !new_keys.contains(&rule.without_aliases()))
+        .cloned()
+        .collect();
+    diff
+}
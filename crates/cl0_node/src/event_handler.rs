@@ -1,11 +1,18 @@
-use cl0_parser::ast::{CaseRule, ReactiveRule};
+use cl0_parser::ast::ReactiveRule;
 use dashmap::DashMap;
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::HashSet,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+};
 use tracing::{debug, error, info, instrument, warn};
 
 use crate::{
     api::ApiRoute,
-    node::Node, types::{ActivationStatus, ReactiveRuleWithArgs, RuleWithArgs},
+    node::Node,
+    types::{ActionContext, ActivationStatus, NodeError, ReactiveRuleWithArgs, TriggerCycle},
 };
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -14,14 +21,73 @@ struct ReactiveRuleKey {
     alias: Option<Vec<String>>,
 }
 
+/// What a handler tracks per rule: its status, priority and when it was first added.
+#[derive(Debug, Clone)]
+struct RuleEntry {
+    status: ActivationStatus,
+    priority: i32,
+    order: u64,
+}
+
+type RuleMap = DashMap<ReactiveRuleKey, RuleEntry>;
+
+/// Inserts a rule, or updates the status and priority of an existing one while keeping its
+/// original insertion order. A new rule is added to `count`.
+fn upsert_rule(
+    rules: &RuleMap,
+    next_order: &AtomicU64,
+    count: &AtomicUsize,
+    rule_with_args: ReactiveRuleWithArgs,
+) {
+    let key = ReactiveRuleKey {
+        rule: rule_with_args.rule,
+        alias: rule_with_args.alias,
+    };
+    rules
+        .entry(key)
+        .and_modify(|entry| {
+            entry.status = rule_with_args.value.clone();
+            entry.priority = rule_with_args.priority;
+        })
+        .or_insert_with(|| {
+            count.fetch_add(1, Ordering::Relaxed);
+            RuleEntry {
+                status: rule_with_args.value.clone(),
+                priority: rule_with_args.priority,
+                order: next_order.fetch_add(1, Ordering::Relaxed),
+            }
+        });
+}
+
+/// Lists the rules in evaluation order: highest priority first, then by insertion order.
+fn ordered_rules(rules: &RuleMap) -> Vec<ReactiveRuleWithArgs> {
+    let mut entries: Vec<(ReactiveRuleKey, RuleEntry)> = rules
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect();
+    entries.sort_by(|(_, a), (_, b)| b.priority.cmp(&a.priority).then(a.order.cmp(&b.order)));
+    entries
+        .into_iter()
+        .map(|(key, entry)| ReactiveRuleWithArgs {
+            rule: key.rule,
+            value: entry.status,
+            alias: key.alias,
+            priority: entry.priority,
+        })
+        .collect()
+}
+
 /// Public-facing API for an event handler. Allows adding new rules, triggering processing,
 /// and querying the active rule set.
 #[derive(Debug)]
 pub struct EventHandlerApi {
     /// Add or update a reactive rule in this handler.
     pub new_rule: ApiRoute<ReactiveRuleWithArgs, bool>,
+    /// Remove a reactive rule (matched by rule and alias), returning whether it was present.
+    pub remove_rule: ApiRoute<ReactiveRuleWithArgs, bool>,
     /// Trigger evaluation of all currently held rules and perform their side effects.
-    pub process_action: ApiRoute<(), bool>,
+    /// The context is that of the chain of actions that triggered this handler.
+    pub process_action: ApiRoute<ActionContext, bool>,
     /// Enumerate the current reactive rules this handler is tracking.
     pub get_rules: ApiRoute<bool, Vec<ReactiveRuleWithArgs>>,
 }
@@ -30,62 +96,72 @@ pub struct EventHandlerApi {
 #[derive(Debug)]
 pub struct EventHandler {
     pub id: String,
-    rules: Arc<DashMap<ReactiveRuleKey, ActivationStatus>>,
+    rules: Arc<RuleMap>,
+    /// The node's count of rules over all handlers, see `Node::rule_count`.
+    rule_count: Arc<AtomicUsize>,
     pub api: EventHandlerApi,
 }
 
 impl EventHandler {
     /// Constructs a new handler seeded with one initial reactive rule.
     pub fn new(node: Arc<Node>, rule_with_args: ReactiveRuleWithArgs) -> Self {
-        // Each rule carries a status (unknown/true/false) that can be aggregated.
-        let rules: Arc<DashMap<ReactiveRuleKey, ActivationStatus>> = Arc::new(DashMap::new());
+        // Each rule carries a status (true/false/conflict) that can be aggregated.
+        let rules: Arc<RuleMap> = Arc::new(DashMap::new());
+        let next_order = Arc::new(AtomicU64::new(0));
         let id = rule_with_args.rule.get_identifier().clone();
+        let rule_count = node.rule_count.clone();
 
-        // Insert the initial rule with an unknown status
-        rules.insert(
-            ReactiveRuleKey {
-                rule: rule_with_args.rule.clone(),
-                alias: rule_with_args.alias.clone(),
-            },
-            rule_with_args.value.clone(),
-        );
+        // Insert the initial rule with its given status
+        upsert_rule(&rules, &next_order, &rule_count, rule_with_args);
 
         // Route for inserting/updating a rule.
         let nr_rules = rules.clone();
+        let nr_order = next_order.clone();
+        let nr_count = rule_count.clone();
         let new_rule_route = ApiRoute::new(move |rule_with_args: ReactiveRuleWithArgs| {
             let rules = nr_rules.clone();
+            let next_order = nr_order.clone();
+            let count = nr_count.clone();
             let rule_desc = rule_with_args.rule.clone().to_string();
             debug!("Adding/updating rule: {} with namespace {:?} with value {:?}", rule_desc, rule_with_args.alias, rule_with_args.value);
             async move {
-                {
-                    rules.insert(
-                        ReactiveRuleKey {
-                            rule: rule_with_args.rule.clone(),
-                            alias: rule_with_args.alias.clone(),
-                        },
-                        rule_with_args.value.clone(),
-                    );
-                }
+                upsert_rule(&rules, &next_order, &count, rule_with_args);
                 Ok(true)
             }
         });
 
+        // Route for deleting a rule entirely.
+        let rr_rules = rules.clone();
+        let rr_count = rule_count.clone();
+        let remove_rule_route = ApiRoute::new(move |rule_with_args: ReactiveRuleWithArgs| {
+            let rules = rr_rules.clone();
+            let count = rr_count.clone();
+            let rule_desc = rule_with_args.rule.clone().to_string();
+            debug!("Removing rule: {} with namespace {:?}", rule_desc, rule_with_args.alias);
+            async move {
+                let removed = rules
+                    .remove(&ReactiveRuleKey {
+                        rule: rule_with_args.rule,
+                        alias: rule_with_args.alias,
+                    })
+                    .is_some();
+                if removed {
+                    count.fetch_sub(1, Ordering::Relaxed);
+                }
+                Ok(removed)
+            }
+        });
+
         // Route to evaluate all rules and apply their effects
         let pa_rules = rules.clone();
         let pa_node = node.clone();
-        let process_action_route = ApiRoute::new(move |()| {
+        let process_action_route = ApiRoute::new(move |ctx: ActionContext| {
             let rules = pa_rules.clone();
             let node = pa_node.clone();
             async move {
                 let mut valid = true;
-                for rule_ref in rules.iter() {
-                    // Recreate a ReactiveRuleWithArgs for the current rule
-                    let rule_with_args = ReactiveRuleWithArgs {
-                        rule: rule_ref.key().rule.clone(),
-                        alias: rule_ref.key().alias.clone(),
-                        value: rule_ref.value().clone(),
-                    };
-
+                // Evaluate on a sorted copy, so rules may be added or removed while actions run
+                for rule_with_args in ordered_rules(&rules) {
                     // Log the rule being processed
                     let rule_desc = rule_with_args.rule.clone().to_string();
                     debug!("Attempting to process rule: {}", rule_desc);
@@ -94,9 +170,13 @@ impl EventHandler {
                         continue; // Skip rules that are false
                     }
                     debug!("Processing rule: {:?}", rule_with_args);
-                    let result =
-                        Self::process_rule_internal(node.clone(), rule_with_args.rule.clone())
-                            .await;
+                    let result = Self::process_rule_internal(
+                        node.clone(),
+                        rule_with_args.rule.clone(),
+                        rule_with_args.alias.clone(),
+                        ctx.clone(),
+                    )
+                    .await?;
                     valid &= result;
                 }
                 Ok(valid)
@@ -108,18 +188,14 @@ impl EventHandler {
         let get_rules_route = ApiRoute::new(move |all: bool| {
             let rules = gr_rules.clone();
             async move {
-                Ok(rules
-                    .iter()
-                    .filter_map(|entry| {
-                        if !all && entry.value().clone() == ActivationStatus::False {
-                            debug!("Skipping disabled rule: {:?}", entry.key());
-                            return None; // Skip rules that are false
+                Ok(ordered_rules(&rules)
+                    .into_iter()
+                    .filter(|rule| {
+                        if !all && rule.value == ActivationStatus::False {
+                            debug!("Skipping disabled rule: {:?}", rule);
+                            return false; // Skip rules that are false
                         }
-                        Some(ReactiveRuleWithArgs {
-                            rule: entry.key().rule.clone(),
-                            alias: entry.key().alias.clone(),
-                            value: entry.value().clone(),
-                        })
+                        true
                     })
                     .collect::<Vec<ReactiveRuleWithArgs>>())
             }
@@ -128,8 +204,10 @@ impl EventHandler {
         EventHandler {
             id,
             rules,
+            rule_count,
             api: EventHandlerApi {
                 new_rule: new_rule_route,
+                remove_rule: remove_rule_route,
                 process_action: process_action_route,
                 get_rules: get_rules_route,
             },
@@ -146,85 +224,134 @@ impl EventHandler {
         Ok(ok)
     }
 
-    /// Core rule evaluation logic: checks condition, and if true, emits the corresponding action.
-    #[instrument(skip(node, rule))]
-    async fn process_rule_internal(node: Arc<Node>, rule: ReactiveRule) -> bool {
+    /// Core rule evaluation logic: checks condition, and if true, emits the corresponding action,
+    /// otherwise the else action when the rule has one. Each action run is recorded in the
+    /// node's firing log under the rule's `alias`.
+    /// Failures are logged and reported as `false`, except trigger cycles and exceeded deadlines,
+    /// which abort the chain.
+    #[instrument(skip(node, rule, alias, ctx), fields(node_id = %node.id))]
+    async fn process_rule_internal(
+        node: Arc<Node>,
+        rule: ReactiveRule,
+        alias: Option<Vec<String>>,
+        ctx: ActionContext,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
         // Log the rule being processed
         let r = rule.clone().to_string();
         debug!("Processing rule: {}", r);
+        let fired = rule.clone();
 
-        // Decompose the rule into optional condition and action
-        let (condition, action) = match rule {
-            ReactiveRule::CA { condition, action } => (Some(condition), action),
+        // Decompose the rule into optional condition, action and else action
+        let (condition, action, else_action) = match rule {
+            ReactiveRule::CA {
+                condition,
+                action,
+                else_action,
+            } => (Some(condition), action, else_action),
             ReactiveRule::ECA {
                 event: _,
                 condition,
                 action,
-            } => (condition, action),
+                else_action,
+            } => (condition, action, else_action),
         };
 
         // Evaluate condition if provided
         let node_clone = node.clone();
         let condition_result = match condition {
-            Some(c) => node_clone.process_condition(&c).await,
+            Some(c) => node_clone.process_condition(&c, 0).await,
             None => Ok(true),
         };
 
         match condition_result {
             Err(e) => {
                 error!("Failed to process condition: {}", e);
-                false
+                Ok(false)
             }
             Ok(result) => {
-                if result {
-                    // Condition satisfied: fire the action as a new case rule
+                let chosen = if result {
                     debug!("Condition is true, processing action: {:?}", action);
-                    let case = RuleWithArgs::Case(CaseRule {
-                        action: action.clone(),
-                    });
-                    match node.api.new_rules.call(vec![case]).await {
-                        Ok(r) => {
-                            let r_val = r.get(0).cloned().unwrap_or(false);
+                    Some(action)
+                } else {
+                    if else_action.is_some() {
+                        debug!(
+                            "Condition is false, processing else action: {:?}",
+                            else_action
+                        );
+                    }
+                    else_action
+                };
+                if let Some(action) = chosen {
+                    node.record_firing(&fired, alias.as_ref());
+                    // Run the chosen action within the triggering chain
+                    match node.process_action(action.clone(), ctx, 0).await {
+                        Ok(r_val) => {
                             if r_val {
-                                info!(
-                                    "Successfully created new rule (case) for action: {:?}",
-                                    action
-                                );
+                                info!("Successfully processed action: {:?}", action);
                             } else {
-                                warn!("Failed to create new rule (case) for action: {:?}", action);
+                                warn!("Failed to process action: {:?}", action);
                             }
-                            r_val
+                            Ok(r_val)
                         }
+                        Err(e) if e.is::<TriggerCycle>() => Err(e),
+                        Err(e) if e.downcast_ref() == Some(&NodeError::DeadlineExceeded) => Err(e),
                         Err(e) => {
                             error!(
-                                "Failed to create new rule (case) for action: {:?}, error: {:?}",
+                                "Failed to process action: {:?}, error: {:?}",
                                 action, e
                             );
-                            false
+                            Ok(false)
                         }
                     }
                 } else {
-                    debug!("Condition is false, no action processed for: {:?}", action);
-                    true
+                    debug!("Condition is false, no action processed for: {}", r);
+                    Ok(true)
                 }
             }
         }
     }
 
-    /// Aggregate the statuses of all contained rules into a single effective state.
-    pub async fn state(&self) -> ActivationStatus {
+    /// Whether this handler holds `rule_with_args`'s rule under its alias, whatever its status.
+    pub fn has_rule(&self, rule_with_args: &ReactiveRuleWithArgs) -> bool {
+        self.rules.contains_key(&ReactiveRuleKey {
+            rule: rule_with_args.rule.clone(),
+            alias: rule_with_args.alias.clone(),
+        })
+    }
+
+    /// Whether this handler no longer holds any enabled rule, so it can be dropped.
+    pub fn is_idle(&self) -> bool {
+        self.rules
+            .iter()
+            .all(|entry| entry.value().status == ActivationStatus::False)
+    }
+
+    /// Aggregate the statuses of all contained rules into a single effective state:
+    /// - `None` when the handler holds no rules at all.
+    /// - `True` when any rule is enabled, since triggering the handler will run it.
+    /// - `False` when no rule is enabled but at least one is disabled.
+    /// - `Conflict` when every rule is in conflict.
+    pub async fn state(&self) -> Option<ActivationStatus> {
         let mut statuses: HashSet<ActivationStatus> = HashSet::new();
         for rule in self.rules.iter() {
-            statuses.insert(rule.value().clone());
+            statuses.insert(rule.value().status.clone());
         }
-        if statuses.len() == 1 {
-            statuses.into_iter().next().unwrap_or(ActivationStatus::Conflict)
+        if statuses.is_empty() {
+            None
         } else if statuses.contains(&ActivationStatus::True) {
-            ActivationStatus::True
+            Some(ActivationStatus::True)
         } else if statuses.contains(&ActivationStatus::False) {
-            ActivationStatus::False
+            Some(ActivationStatus::False)
         } else {
-            ActivationStatus::Conflict
+            Some(ActivationStatus::Conflict)
         }
     }
 }
+
+impl Drop for EventHandler {
+    /// The rules of a dropped handler no longer count towards the node's total.
+    fn drop(&mut self) {
+        self.rule_count
+            .fetch_sub(self.rules.len(), Ordering::Relaxed);
+    }
+}
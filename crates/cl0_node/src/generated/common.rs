@@ -39,6 +39,9 @@ pub struct ReactiveRule {
     pub condition: ::core::option::Option<Condition>,
     #[prost(message, optional, tag = "3")]
     pub action: ::core::option::Option<Action>,
+    /// Optional, empty = None
+    #[prost(message, optional, tag = "4")]
+    pub else_action: ::core::option::Option<Action>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct DeclarativeRule {
@@ -134,7 +137,7 @@ pub mod primitive_event {
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Condition {
-    #[prost(oneof = "condition::Kind", tags = "1, 2, 3, 4, 5")]
+    #[prost(oneof = "condition::Kind", tags = "1, 2, 3, 4, 5, 6")]
     pub kind: ::core::option::Option<condition::Kind>,
 }
 /// Nested message and enum types in `Condition`.
@@ -151,8 +154,17 @@ pub mod condition {
         Disjunction(super::Disjunction),
         #[prost(message, tag = "5")]
         Parentheses(::prost::alloc::boxed::Box<super::Condition>),
+        #[prost(message, tag = "6")]
+        AtLeast(super::AtLeast),
     }
 }
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct AtLeast {
+    #[prost(message, optional, tag = "1")]
+    pub var: ::core::option::Option<PrimitiveCondition>,
+    #[prost(int64, tag = "2")]
+    pub count: i64,
+}
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Conjunction {
     #[prost(message, repeated, tag = "1")]
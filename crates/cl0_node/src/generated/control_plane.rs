@@ -19,6 +19,16 @@ pub struct NodeHeartbeat {
     pub id: ::core::option::Option<super::common::NodeId>,
     #[prost(int64, tag = "2")]
     pub timestamp: i64,
+    /// Current status of the node, left unset by clients that only report liveness.
+    #[prost(message, optional, tag = "3")]
+    pub status: ::core::option::Option<NodeStatus>,
+}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct NodeStatus {
+    #[prost(uint32, tag = "1")]
+    pub rule_count: u32,
+    #[prost(uint32, tag = "2")]
+    pub var_count: u32,
 }
 #[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
 pub struct ControlMessage {
@@ -35,7 +45,13 @@ pub struct RuleExecutionRequest {
     pub rule: ::core::option::Option<super::common::Rule>,
 }
 #[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
-pub struct HeartbeatAck {}
+pub struct HeartbeatAck {
+    /// Set when the heartbeat came from an id the control plane doesn't know,
+    /// the node should register again to get a new id. Such a heartbeat succeeds
+    /// instead of failing with NOT_FOUND, as an error status can't carry this flag.
+    #[prost(bool, tag = "1")]
+    pub should_reregister: bool,
+}
 #[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
 pub struct Ack {}
 /// Generated client implementations.
@@ -66,6 +66,9 @@ pub struct Join {
     /// optional: resume from a known output id
     #[prost(string, tag = "3")]
     pub since_id: ::prost::alloc::string::String,
+    /// optional: resume from this sequence number, preferred over since_id
+    #[prost(uint64, tag = "4")]
+    pub since_seq: u64,
 }
 /// / Client sends a command to execute within a scope.
 #[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
@@ -92,6 +95,9 @@ pub struct Output {
     pub stdout: ::prost::alloc::string::String,
     #[prost(int64, tag = "5")]
     pub unix_ts: i64,
+    /// server-assigned, increases by one per output of the scope
+    #[prost(uint64, tag = "6")]
+    pub seq: u64,
 }
 /// / Batch of history items sent immediately after Join.
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -152,8 +158,11 @@ pub struct RuleStatus {
 pub struct VarStatus {
     #[prost(string, tag = "1")]
     pub name: ::prost::alloc::string::String,
+    /// status == ACTIVATION_STATUS_TRUE
     #[prost(bool, tag = "2")]
     pub enabled: bool,
+    #[prost(enumeration = "ActivationStatus", tag = "3")]
+    pub status: i32,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct StatusSnapshot {
@@ -188,6 +197,22 @@ pub struct SystemTree {
     #[prost(message, repeated, tag = "2")]
     pub node_pools: ::prost::alloc::vec::Vec<NodePoolDescriptor>,
 }
+/// / Adds a node to an existing node pool.
+#[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct CreateNodeRequest {
+    #[prost(string, tag = "1")]
+    pub pool_id: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub node: ::core::option::Option<NodeDescriptor>,
+}
+/// / Result of removing a node or pool; removed is false when the id was unknown.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RemoveResponse {
+    #[prost(bool, tag = "1")]
+    pub removed: bool,
+    #[prost(message, optional, tag = "2")]
+    pub tree: ::core::option::Option<SystemTree>,
+}
 #[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
 pub struct Presence {
     #[prost(string, tag = "1")]
@@ -195,6 +220,9 @@ pub struct Presence {
     /// where the user is currently looking
     #[prost(message, optional, tag = "2")]
     pub scope: ::core::option::Option<Scope>,
+    /// unix seconds of the last activity, server-assigned
+    #[prost(int64, tag = "3")]
+    pub last_seen: i64,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PresenceSnapshot {
@@ -270,6 +298,40 @@ pub mod presence_event {
         Update(super::PresenceUpdate),
     }
 }
+/// / Value of a variable or rule on a node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ActivationStatus {
+    Unspecified = 0,
+    True = 1,
+    False = 2,
+    /// set to both true and false at once
+    Conflict = 3,
+}
+impl ActivationStatus {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unspecified => "ACTIVATION_STATUS_UNSPECIFIED",
+            Self::True => "ACTIVATION_STATUS_TRUE",
+            Self::False => "ACTIVATION_STATUS_FALSE",
+            Self::Conflict => "ACTIVATION_STATUS_CONFLICT",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "ACTIVATION_STATUS_UNSPECIFIED" => Some(Self::Unspecified),
+            "ACTIVATION_STATUS_TRUE" => Some(Self::True),
+            "ACTIVATION_STATUS_FALSE" => Some(Self::False),
+            "ACTIVATION_STATUS_CONFLICT" => Some(Self::Conflict),
+            _ => None,
+        }
+    }
+}
 /// Generated client implementations.
 pub mod repl_service_client {
     #![allow(
@@ -407,6 +469,29 @@ pub mod repl_service_client {
                 .insert(GrpcMethod::new("web.ReplService", "SendCommand"));
             self.inner.unary(req, path, codec).await
         }
+        /// / Subscribe and send commands over one stream. The first Input picks the scope and user,
+        /// / the events are those of Subscribe plus an Ack per Input.
+        pub async fn session(
+            &mut self,
+            request: impl tonic::IntoStreamingRequest<Message = super::Input>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::ServerEvent>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic_prost::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/web.ReplService/Session");
+            let mut req = request.into_streaming_request();
+            req.extensions_mut().insert(GrpcMethod::new("web.ReplService", "Session"));
+            self.inner.streaming(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -438,6 +523,18 @@ pub mod repl_service_server {
             &self,
             request: tonic::Request<super::Input>,
         ) -> std::result::Result<tonic::Response<super::Ack>, tonic::Status>;
+        /// Server streaming response type for the Session method.
+        type SessionStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::ServerEvent, tonic::Status>,
+            >
+            + std::marker::Send
+            + 'static;
+        /// / Subscribe and send commands over one stream. The first Input picks the scope and user,
+        /// / the events are those of Subscribe plus an Ack per Input.
+        async fn session(
+            &self,
+            request: tonic::Request<tonic::Streaming<super::Input>>,
+        ) -> std::result::Result<tonic::Response<Self::SessionStream>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct ReplServiceServer<T> {
@@ -604,6 +701,50 @@ pub mod repl_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/web.ReplService/Session" => {
+                    #[allow(non_camel_case_types)]
+                    struct SessionSvc<T: ReplService>(pub Arc<T>);
+                    impl<T: ReplService> tonic::server::StreamingService<super::Input>
+                    for SessionSvc<T> {
+                        type Response = super::ServerEvent;
+                        type ResponseStream = T::SessionStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<tonic::Streaming<super::Input>>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ReplService>::session(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = SessionSvc(inner);
+                        let codec = tonic_prost::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         let mut response = http::Response::new(
@@ -757,6 +898,31 @@ pub mod status_service_client {
                 .insert(GrpcMethod::new("web.StatusService", "GetStatus"));
             self.inner.unary(req, path, codec).await
         }
+        /// / Stream status snapshots (initial snapshot + one per change).
+        pub async fn watch_status(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Scope>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::StatusSnapshot>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic_prost::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/web.StatusService/WatchStatus",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("web.StatusService", "WatchStatus"));
+            self.inner.server_streaming(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -777,6 +943,20 @@ pub mod status_service_server {
             &self,
             request: tonic::Request<super::Scope>,
         ) -> std::result::Result<tonic::Response<super::StatusSnapshot>, tonic::Status>;
+        /// Server streaming response type for the WatchStatus method.
+        type WatchStatusStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::StatusSnapshot, tonic::Status>,
+            >
+            + std::marker::Send
+            + 'static;
+        /// / Stream status snapshots (initial snapshot + one per change).
+        async fn watch_status(
+            &self,
+            request: tonic::Request<super::Scope>,
+        ) -> std::result::Result<
+            tonic::Response<Self::WatchStatusStream>,
+            tonic::Status,
+        >;
     }
     #[derive(Debug)]
     pub struct StatusServiceServer<T> {
@@ -897,6 +1077,52 @@ pub mod status_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/web.StatusService/WatchStatus" => {
+                    #[allow(non_camel_case_types)]
+                    struct WatchStatusSvc<T: StatusService>(pub Arc<T>);
+                    impl<
+                        T: StatusService,
+                    > tonic::server::ServerStreamingService<super::Scope>
+                    for WatchStatusSvc<T> {
+                        type Response = super::StatusSnapshot;
+                        type ResponseStream = T::WatchStatusStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::Scope>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as StatusService>::watch_status(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = WatchStatusSvc(inner);
+                        let codec = tonic_prost::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         let mut response = http::Response::new(
@@ -1054,6 +1280,94 @@ pub mod control_plane_service_client {
                 .insert(GrpcMethod::new("web.ControlPlaneService", "SubscribeTree"));
             self.inner.server_streaming(req, path, codec).await
         }
+        /// / Create a node pool (the nodes listed are ignored), returning the updated tree.
+        pub async fn create_pool(
+            &mut self,
+            request: impl tonic::IntoRequest<super::NodePoolDescriptor>,
+        ) -> std::result::Result<tonic::Response<super::SystemTree>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic_prost::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/web.ControlPlaneService/CreatePool",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("web.ControlPlaneService", "CreatePool"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// / Create a node in an existing pool, returning the updated tree.
+        pub async fn create_node(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CreateNodeRequest>,
+        ) -> std::result::Result<tonic::Response<super::SystemTree>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic_prost::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/web.ControlPlaneService/CreateNode",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("web.ControlPlaneService", "CreateNode"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// / Remove a node pool and its nodes (only the id is used).
+        pub async fn remove_pool(
+            &mut self,
+            request: impl tonic::IntoRequest<super::NodePoolDescriptor>,
+        ) -> std::result::Result<tonic::Response<super::RemoveResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic_prost::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/web.ControlPlaneService/RemovePool",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("web.ControlPlaneService", "RemovePool"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// / Remove a node from its pool (only the id is used).
+        pub async fn remove_node(
+            &mut self,
+            request: impl tonic::IntoRequest<super::NodeDescriptor>,
+        ) -> std::result::Result<tonic::Response<super::RemoveResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic_prost::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/web.ControlPlaneService/RemoveNode",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("web.ControlPlaneService", "RemoveNode"));
+            self.inner.unary(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -1084,6 +1398,26 @@ pub mod control_plane_service_server {
             tonic::Response<Self::SubscribeTreeStream>,
             tonic::Status,
         >;
+        /// / Create a node pool (the nodes listed are ignored), returning the updated tree.
+        async fn create_pool(
+            &self,
+            request: tonic::Request<super::NodePoolDescriptor>,
+        ) -> std::result::Result<tonic::Response<super::SystemTree>, tonic::Status>;
+        /// / Create a node in an existing pool, returning the updated tree.
+        async fn create_node(
+            &self,
+            request: tonic::Request<super::CreateNodeRequest>,
+        ) -> std::result::Result<tonic::Response<super::SystemTree>, tonic::Status>;
+        /// / Remove a node pool and its nodes (only the id is used).
+        async fn remove_pool(
+            &self,
+            request: tonic::Request<super::NodePoolDescriptor>,
+        ) -> std::result::Result<tonic::Response<super::RemoveResponse>, tonic::Status>;
+        /// / Remove a node from its pool (only the id is used).
+        async fn remove_node(
+            &self,
+            request: tonic::Request<super::NodeDescriptor>,
+        ) -> std::result::Result<tonic::Response<super::RemoveResponse>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct ControlPlaneServiceServer<T> {
@@ -1208,6 +1542,190 @@ pub mod control_plane_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/web.ControlPlaneService/CreatePool" => {
+                    #[allow(non_camel_case_types)]
+                    struct CreatePoolSvc<T: ControlPlaneService>(pub Arc<T>);
+                    impl<
+                        T: ControlPlaneService,
+                    > tonic::server::UnaryService<super::NodePoolDescriptor>
+                    for CreatePoolSvc<T> {
+                        type Response = super::SystemTree;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::NodePoolDescriptor>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ControlPlaneService>::create_pool(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = CreatePoolSvc(inner);
+                        let codec = tonic_prost::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/web.ControlPlaneService/CreateNode" => {
+                    #[allow(non_camel_case_types)]
+                    struct CreateNodeSvc<T: ControlPlaneService>(pub Arc<T>);
+                    impl<
+                        T: ControlPlaneService,
+                    > tonic::server::UnaryService<super::CreateNodeRequest>
+                    for CreateNodeSvc<T> {
+                        type Response = super::SystemTree;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CreateNodeRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ControlPlaneService>::create_node(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = CreateNodeSvc(inner);
+                        let codec = tonic_prost::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/web.ControlPlaneService/RemovePool" => {
+                    #[allow(non_camel_case_types)]
+                    struct RemovePoolSvc<T: ControlPlaneService>(pub Arc<T>);
+                    impl<
+                        T: ControlPlaneService,
+                    > tonic::server::UnaryService<super::NodePoolDescriptor>
+                    for RemovePoolSvc<T> {
+                        type Response = super::RemoveResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::NodePoolDescriptor>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ControlPlaneService>::remove_pool(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = RemovePoolSvc(inner);
+                        let codec = tonic_prost::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/web.ControlPlaneService/RemoveNode" => {
+                    #[allow(non_camel_case_types)]
+                    struct RemoveNodeSvc<T: ControlPlaneService>(pub Arc<T>);
+                    impl<
+                        T: ControlPlaneService,
+                    > tonic::server::UnaryService<super::NodeDescriptor>
+                    for RemoveNodeSvc<T> {
+                        type Response = super::RemoveResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::NodeDescriptor>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ControlPlaneService>::remove_node(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = RemoveNodeSvc(inner);
+                        let codec = tonic_prost::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         let mut response = http::Response::new(
@@ -1339,10 +1857,11 @@ pub mod presence_service_client {
             self.inner = self.inner.max_encoding_message_size(limit);
             self
         }
-        /// / Stream presence events (initial snapshot + live updates).
+        /// / Stream presence events (initial snapshot + live updates) for a scope.
+        /// / An unspecified scope kind streams the presence of every scope.
         pub async fn subscribe(
             &mut self,
-            request: impl tonic::IntoRequest<super::Empty>,
+            request: impl tonic::IntoRequest<super::Scope>,
         ) -> std::result::Result<
             tonic::Response<tonic::codec::Streaming<super::PresenceEvent>>,
             tonic::Status,
@@ -1425,10 +1944,11 @@ pub mod presence_service_server {
             >
             + std::marker::Send
             + 'static;
-        /// / Stream presence events (initial snapshot + live updates).
+        /// / Stream presence events (initial snapshot + live updates) for a scope.
+        /// / An unspecified scope kind streams the presence of every scope.
         async fn subscribe(
             &self,
-            request: tonic::Request<super::Empty>,
+            request: tonic::Request<super::Scope>,
         ) -> std::result::Result<tonic::Response<Self::SubscribeStream>, tonic::Status>;
         /// / User explicitly joins a scope.
         async fn join(
@@ -1522,7 +2042,7 @@ pub mod presence_service_server {
                     struct SubscribeSvc<T: PresenceService>(pub Arc<T>);
                     impl<
                         T: PresenceService,
-                    > tonic::server::ServerStreamingService<super::Empty>
+                    > tonic::server::ServerStreamingService<super::Scope>
                     for SubscribeSvc<T> {
                         type Response = super::PresenceEvent;
                         type ResponseStream = T::SubscribeStream;
@@ -1532,7 +2052,7 @@ pub mod presence_service_server {
                         >;
                         fn call(
                             &mut self,
-                            request: tonic::Request<super::Empty>,
+                            request: tonic::Request<super::Scope>,
                         ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
                             let fut = async move {
@@ -4,16 +4,24 @@ pub mod control_plane;
 mod logger;
 pub mod utils;
 pub mod types;
-mod api;
+pub mod api;
+pub mod policy;
+pub mod diff;
 pub mod visitor;
 pub mod node_client;
 pub mod server;
+pub mod auth;
+pub mod request_log;
+pub mod repl;
 
 pub mod generated {
     pub mod common;
     pub mod node;
     pub mod control_plane;
     pub mod web; 
+
+    /// Encoded `FileDescriptorSet` of every proto above, for gRPC server reflection.
+    pub const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!("generated/cl0_descriptor.bin");
 }
 
 // pub mod common_proto {
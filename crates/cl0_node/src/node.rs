@@ -1,23 +1,34 @@
 use async_recursion::async_recursion;
 use cl0_parser::ast::{
-    Action, ActionList, AtomicCondition, CaseRule, Compound, Condition, FactRule,
-    PrimitiveCondition, PrimitiveEvent, Rule,
+    Action, ActionList, AtomicCondition, CaseRule, Compound, Condition, DeclarativeRule, Directive,
+    FactRule, PrimitiveCondition, PrimitiveEvent, ReactiveRule, Rule,
 };
+use cl0_parser::{lex_and_parse_compound_safe, lex_and_parse_condition};
 use dashmap::{DashMap, DashSet};
-use rand::seq::IndexedRandom;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::{IndexedRandom, SliceRandom};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::error::Error;
-use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
 use std::vec;
-use tokio::sync::Barrier;
+use tokio::sync::{Barrier, broadcast};
+use tokio::task::JoinSet;
 use tracing::{debug, error, info, instrument, warn};
+use uuid::Uuid;
 // use tracing_subscriber::field::debug;
 
 use crate::api::ApiRoute;
+use crate::diff::RuleDiff;
 use crate::event_handler::EventHandler;
-use crate::types::{ActivationStatus, FactRuleWithArgs, ReactiveRuleWithArgs, RuleWithArgs};
+use crate::types::{
+    ActionContext, ActivationStatus, ConflictStrategy, DepthExceeded, FactRuleWithArgs, FiringRecord, NodeError, NodeMetrics, NodeSnapshot, ReactiveRuleWithArgs, RuleSnapshot,
+    RuleWithArgs, Value, VarSnapshot,
+};
 use crate::utils::{
-    AliasNamespace,
-    collect_conjunction, get_parts, overall_status_from_set,
+    AbortOnDrop, AliasNamespace, CreatedRules,
+    collect_conjunction, get_parts, overall_status_from_set_with,
 };
 use crate::visitor::AstVisitor;
 
@@ -26,20 +37,208 @@ use crate::visitor::AstVisitor;
 pub struct NodeApi {
     pub new_rules: ApiRoute<Vec<RuleWithArgs>, Vec<bool>>,
     pub get_rules: ApiRoute<bool, Vec<ReactiveRuleWithArgs>>,
+    /// Like `get_rules`, keeping only the rules under a namespace. `None` selects the top-level
+    /// rules, `Some(prefix)` the rules whose alias path starts with `prefix`.
+    pub get_rules_in: ApiRoute<(Option<Vec<String>>, bool), Vec<ReactiveRuleWithArgs>>,
+}
+
+/// Buffer size of the variable change channel; receivers further behind than this lag.
+const VAR_EVENTS_CAPACITY: usize = 1024;
+
+/// How many `new_rules` batches are handled at once, and how many more may queue up behind them.
+const NEW_RULES_CAPACITY: usize = 64;
+
+/// Default for `NodeConfig::max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Default for `NodeConfig::firing_log_capacity`.
+pub const DEFAULT_FIRING_LOG_CAPACITY: usize = 1024;
+
+/// Default for `NodeConfig::retired_handlers_capacity`.
+pub const DEFAULT_RETIRED_HANDLERS_CAPACITY: usize = 1024;
+
+/// Construction-time options for a Node. The defaults match `Node::new_with_rules`.
+#[derive(Debug, Clone)]
+pub struct NodeConfig {
+    /// Identity recorded on the node's tracing spans; a random UUID is used when `None`.
+    pub id: Option<String>,
+    /// Seed for picking alternative actions; thread-local randomness is used when `None`.
+    pub seed: Option<u64>,
+    /// Evaluate variables that were never set as `false` instead of failing the condition.
+    pub treat_unknown_as_false: bool,
+    /// How deep conditions, actions and compounds may nest before evaluation is refused.
+    pub max_depth: usize,
+    /// Run the branches of a parallel action one after another on the calling task instead of
+    /// concurrently. The order of the branches is shuffled with the node's RNG, so with a seed
+    /// every run picks the same schedule. Set by the `@interleaving` directive.
+    pub interleaving: bool,
+    /// Evaluate the operands of a disjunction concurrently, each in its own task, answering as
+    /// soon as an operand is true or fails after only false ones, and cancelling the rest. The
+    /// result, errors included, is the sequential one. Lowers the latency of disjunctions over
+    /// slow conditions, at the cost of the order operands are evaluated in.
+    pub concurrent_disjunction: bool,
+    /// Variables whose values come from outside the node, see `@external(var)`. They are not
+    /// initialized to `false`, so reading one before it is set fails.
+    pub external_vars: HashSet<String>,
+    /// Events only the outside world produces, see `@external(+var)`. Reactive rules may handle
+    /// them but not produce them.
+    pub external_events: HashSet<PrimitiveEvent>,
+    /// Variables that count instead of holding a status, see `@counter(var)`. Producing one adds
+    /// one and consuming it takes one off; as a plain condition it holds while the count is
+    /// above zero, and `var >= n` compares the count.
+    pub counter_vars: HashSet<String>,
+    /// How the statuses of a compound's rules combine into the compound's status.
+    pub conflict_strategy: ConflictStrategy,
+    /// How many reactive rules the node's handlers may hold together; adding a rule beyond the
+    /// limit fails with `NodeError::TooManyRules`. Unbounded when `None`.
+    pub max_rules: Option<usize>,
+    /// How many of the latest rule firings `Node::firing_log` keeps; older ones are dropped.
+    /// Nothing is recorded when 0.
+    pub firing_log_capacity: usize,
+    /// How many of the events whose handlers were pruned the node remembers, so triggering them
+    /// stays a no-op. Beyond that the longest-retired ones are forgotten, and triggering one of
+    /// them is an invalid action like any unknown event.
+    pub retired_handlers_capacity: usize,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            id: None,
+            seed: None,
+            treat_unknown_as_false: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            interleaving: false,
+            concurrent_disjunction: false,
+            external_vars: HashSet::new(),
+            external_events: HashSet::new(),
+            counter_vars: HashSet::new(),
+            conflict_strategy: ConflictStrategy::default(),
+            max_rules: None,
+            firing_log_capacity: DEFAULT_FIRING_LOG_CAPACITY,
+            retired_handlers_capacity: DEFAULT_RETIRED_HANDLERS_CAPACITY,
+        }
+    }
+}
+
+impl NodeConfig {
+    /// Applies the options requested by a program's directives, e.g. `@interleaving`. A node's
+    /// options are fixed once it is created, so the directives have to be applied here, before
+    /// `Node::new_with_config`. `@include`, `@exclude` and `@scale` shape the rules rather than the
+    /// node and are skipped, see `PolicyLoader`.
+    pub fn with_directives(mut self, directives: &[Directive]) -> Self {
+        for directive in directives {
+            match directive {
+                Directive::Interleaving => self.interleaving = true,
+                Directive::ExternalVar(var) => {
+                    self.external_vars.insert(var.clone());
+                }
+                Directive::ExternalEvent(event) => {
+                    self.external_events.insert(event.clone());
+                }
+                Directive::Counter(var) => {
+                    self.counter_vars.insert(var.clone());
+                }
+                _ => {}
+            }
+        }
+        self
+    }
+}
+
+/// A copy of a node's rules, variables and aliases, taken by `Node::snapshot` and put back by
+/// `Node::restore`.
+#[derive(Debug)]
+pub struct NodeCheckpoint {
+    vars: HashMap<PrimitiveCondition, ActivationStatus>,
+    counts: HashMap<PrimitiveCondition, i64>,
+    aliases: Vec<(String, AliasNamespace)>,
+    /// The rules of each handler, disabled ones included, in evaluation order.
+    handlers: Vec<Vec<ReactiveRuleWithArgs>>,
+    declarative_rules: Vec<DeclarativeRule>,
+}
+
+/// Lock-free counters behind `Node::metrics`.
+#[derive(Debug, Default)]
+struct NodeCounters {
+    rules_processed: AtomicU64,
+    actions_fired: AtomicU64,
+    conditions_evaluated: AtomicU64,
+    conflicts_detected: AtomicU64,
+}
+
+/// Writes recorded by `Node::dry_run_action`, with the values they leave behind.
+#[derive(Debug, Default)]
+struct DryRun {
+    vars: HashMap<PrimitiveCondition, ActivationStatus>,
+    writes: Vec<(PrimitiveCondition, ActivationStatus)>,
+}
+
+impl DryRun {
+    fn write(&mut self, var: PrimitiveCondition, value: ActivationStatus) {
+        self.vars.insert(var.clone(), value.clone());
+        self.writes.push((var, value));
+    }
 }
 
 /// Core node that maintains variable state, aliases, and event handlers.
 #[derive(Debug)]
 pub struct Node {
+    /// Identity of this node, recorded as `node_id` on its tracing spans.
+    pub id: String,
     pub vars: Arc<DashMap<PrimitiveCondition, ActivationStatus>>,
+    /// The counts of the counter variables, see `NodeConfig::counter_vars`. A counter that was
+    /// never produced or consumed counts zero.
+    pub counts: Arc<DashMap<PrimitiveCondition, i64>>,
     pub aliases: Arc<DashMap<String, Arc<AliasNamespace>>>,
     pub event_handlers: Arc<DashMap<String, Arc<EventHandler>>>,
+    /// Declarative rules still in force: every CC rule, and the CT rules that haven't fired yet.
+    pub declarative_rules: Arc<DashSet<DeclarativeRule>>,
     pub api: NodeApi,
+    /// The options this node was built with.
+    pub config: NodeConfig,
+    /// Seeded RNG for alternative actions; thread-local randomness is used when absent.
+    rng: Option<Arc<Mutex<StdRng>>>,
+    /// Broadcasts every change of a variable's value, see `subscribe_vars`.
+    var_tx: broadcast::Sender<(PrimitiveCondition, ActivationStatus)>,
+    /// Work counters, see `metrics`.
+    counters: NodeCounters,
+    /// How many reactive rules the handlers hold together, kept up to date by the handlers.
+    pub(crate) rule_count: Arc<AtomicUsize>,
+    /// The latest rule firings, oldest first, see `firing_log`.
+    firings: Mutex<VecDeque<FiringRecord>>,
+    /// Events whose handlers were dropped after their rules were retracted, oldest first, see
+    /// `retire_handler`.
+    retired_handlers: Mutex<VecDeque<String>>,
 }
 
 impl Node {
     /// Async constructor that builds the node and applies initial rules if provided.
     pub async fn new_with_rules(rules: Option<Vec<Rule>>) -> Arc<Self> {
+        Self::new_with_config(rules, NodeConfig::default()).await
+    }
+
+    /// Like `new_with_rules`, but with a fixed identity, e.g. the id a control plane assigned.
+    pub async fn with_id(id: impl Into<String>, rules: Option<Vec<Rule>>) -> Arc<Self> {
+        let config = NodeConfig {
+            id: Some(id.into()),
+            ..NodeConfig::default()
+        };
+        Self::new_with_config(rules, config).await
+    }
+
+    /// Like `new_with_rules`, but alternative actions are picked by an RNG seeded with `seed`,
+    /// so two nodes built with the same seed and rules make the same choices.
+    pub async fn new_with_rules_seeded(rules: Option<Vec<Rule>>, seed: u64) -> Arc<Self> {
+        let config = NodeConfig {
+            seed: Some(seed),
+            ..NodeConfig::default()
+        };
+        Self::new_with_config(rules, config).await
+    }
+
+    /// Like `new_with_rules`, with the behaviour adjusted by `config`.
+    pub async fn new_with_config(rules: Option<Vec<Rule>>, config: NodeConfig) -> Arc<Self> {
         // Use `Arc::new_cyclic` to get a self-referential structure safely
         let node = Arc::new_cyclic(|weak_node: &Weak<Node>| {
             // Shared internal state
@@ -49,22 +248,28 @@ impl Node {
 
             // Cloneable handles for closure capture
             let weak_node = weak_node.clone();
+            let weak_node_for_get = weak_node.clone();
             let handlers_for_get = event_handlers.clone();
 
-            // Route to add new rules; handles both reactive rules and immediate cases
-            let new_rules = ApiRoute::new(move |rules: Vec<RuleWithArgs>| {
+            // Route to add new rules; handles both reactive rules and immediate cases.
+            // Bounded, so callers feeding rules faster than they are handled get backpressure.
+            let new_rules = ApiRoute::bounded(NEW_RULES_CAPACITY, move |rules: Vec<RuleWithArgs>| {
                 let weak_node = weak_node.clone();
                 async move {
                     let node = weak_node
                         .upgrade()
                         .ok_or_else(|| Box::<dyn Error + Send + Sync>::from("Node dropped"))?;
 
-                    let results = Vec::with_capacity(rules.len());
+                    // One entry per rule: whether it was applied
+                    let mut results = Vec::with_capacity(rules.len());
 
                     for rule in rules.into_iter() {
-                        Self::process_rule(node.clone(), rule).await?;
+                        results.push(Self::process_rule(node.clone(), rule, 0).await?);
                     }
 
+                    // The batch may have changed premises of declarative rules
+                    Self::evaluate_declarative_rules(node.clone()).await?;
+
                     Ok(results)
                 }
             });
@@ -74,10 +279,13 @@ impl Node {
                 let handlers = handlers_for_get.clone();
                 async move {
                     let mut rules_accum: Vec<ReactiveRuleWithArgs> = Vec::new();
-                    for handler_ref in handlers.iter() {
-                        let id = handler_ref.key();
+                    // Snapshot the handlers so no map lock is held across the awaits below
+                    let snapshot: Vec<(String, Arc<EventHandler>)> = handlers
+                        .iter()
+                        .map(|entry| (entry.key().clone(), entry.value().clone()))
+                        .collect();
+                    for (id, handler) in snapshot {
                         debug!("Collecting rules from handler: {}", id);
-                        let handler = handler_ref.value().clone(); // Arc<EventHandler>
                         let mut handler_rules = handler.api.get_rules.call(all).await?;
                         rules_accum.append(&mut handler_rules);
                     }
@@ -85,81 +293,67 @@ impl Node {
                 }
             });
 
+            // Route to gather the reactive rules of one namespace, built on `get_rules`
+            let get_rules_in = ApiRoute::new(
+                move |(namespace, all): (Option<Vec<String>>, bool)| {
+                    let weak_node = weak_node_for_get.clone();
+                    async move {
+                        let node = weak_node
+                            .upgrade()
+                            .ok_or_else(|| Box::<dyn Error + Send + Sync>::from("Node dropped"))?;
+                        let rules = node.api.get_rules.call(all).await?;
+                        Ok(rules
+                            .into_iter()
+                            .filter(|rule| match (&namespace, &rule.alias) {
+                                (None, alias) => alias.is_none(),
+                                (Some(_), None) => false,
+                                (Some(prefix), Some(alias)) => alias.starts_with(prefix),
+                            })
+                            .collect())
+                    }
+                },
+            );
+
             Node {
+                id: config
+                    .id
+                    .clone()
+                    .unwrap_or_else(|| Uuid::new_v4().to_string()),
                 vars,
+                counts: Arc::new(DashMap::new()),
                 aliases,
                 event_handlers,
+                declarative_rules: Arc::new(DashSet::new()),
+                rng: config
+                    .seed
+                    .map(|seed| Arc::new(Mutex::new(StdRng::seed_from_u64(seed)))),
+                config,
+                var_tx: broadcast::channel(VAR_EVENTS_CAPACITY).0,
+                counters: NodeCounters::default(),
+                rule_count: Arc::new(AtomicUsize::new(0)),
+                firings: Mutex::new(VecDeque::new()),
+                retired_handlers: Mutex::new(VecDeque::new()),
                 api: NodeApi {
                     new_rules,
                     get_rules,
+                    get_rules_in,
                 },
             }
         });
 
+        // Subscribed before the initial rules are loaded, so their facts can trigger CA rules
+        tokio::spawn(Self::watch_condition_rules(
+            Arc::downgrade(&node),
+            node.var_tx.subscribe(),
+        ));
+
         // Apply initial rules in a controlled (awaited) fashion
         if let Some(initial_rules) = rules {
-            // Initialize all potential atomic conditions
-            let mut atomic_conditions = Vec::new();
-            for rule in initial_rules.clone() {
-                rule.visit(&mut |c| {
-                    if let Some(ac) = c.downcast_ref::<AtomicCondition>() {
-                        atomic_conditions.push(ac.clone());
-                    }
-                });
-            }
-            for ac in atomic_conditions {
-                // Store each atomic condition with an initial value of False
-                let _ = Self::store_atomic_condition(node.clone(), ac, ActivationStatus::False, None, true)
-                    .await;
-            }
-
-            // Execute all case rules at the end of initialization
-            debug!("Initializing Node with rules: {:?}", initial_rules.clone());
-            let other_rules: Vec<RuleWithArgs> = initial_rules
-                .clone()
-                .into_iter()
-                .filter_map(|rule| match rule {
-                    Rule::Reactive(r) => Some(RuleWithArgs::Reactive(ReactiveRuleWithArgs {
-                        rule: r,
-                        value: ActivationStatus::True,
-                        alias: None,
-                    })),
-                    Rule::Fact(FactRule { condition }) => {
-                        Some(RuleWithArgs::Fact(FactRuleWithArgs {
-                            rule: FactRule { condition },
-                            value: None,
-                        }))
-                    }
-                    Rule::Declarative(d) => Some(RuleWithArgs::Declarative(d)),
-                    _ => None,
-                })
-                .collect();
-            let case_rules: Vec<RuleWithArgs> = initial_rules
-                .clone()
-                .into_iter()
-                .filter(|rule| matches!(rule, Rule::Case { .. }))
-                .filter_map(|rule| match rule {
-                    Rule::Case(CaseRule { action }) => {
-                        Some(RuleWithArgs::Case(CaseRule { action }))
-                    }
-                    _ => None,
-                })
-                .collect();
-
-            // Apply non-case rules first
-            debug!("Applying initial rules: {:?}", other_rules);
-            let res = node.api.new_rules.call(other_rules).await;
-            if let Err(e) = res {
+            debug!("Initializing Node with rules: {:?}", initial_rules);
+            if let Err(e) = node.clone().load_rules(initial_rules).await {
                 error!("Failed to apply initial rules: {:?}", e);
             } else {
                 debug!("Initial rules applied successfully");
-                // Apply case rules next
-                debug!("Applying case rules: {:?}", case_rules);
-                if let Err(e) = node.api.new_rules.call(case_rules).await {
-                    error!("Failed to apply case rules: {:?}", e);
-                } else {
-                    debug!("Case rules applied successfully");
-                }
             }
         } else {
             debug!("Initializing Node without initial rules");
@@ -168,54 +362,199 @@ impl Node {
         node
     }
 
+    /// Evaluates condition-action rules whenever a variable their condition reads changes, since
+    /// no event ever triggers their handlers. Runs until the node is dropped.
+    ///
+    /// The changes a handler makes can re-trigger handlers in turn, so runs are counted for as
+    /// long as changes keep queueing up, and a handler running more than `max_depth` times
+    /// before the queue drains is stopped with `NodeError::Oscillation`.
+    async fn watch_condition_rules(
+        node: Weak<Node>,
+        mut changes: broadcast::Receiver<(PrimitiveCondition, ActivationStatus)>,
+    ) {
+        let mut runs: HashMap<String, usize> = HashMap::new();
+        loop {
+            let changed = match changes.recv().await {
+                Ok((var, _)) => Some(var),
+                // Changes were missed, so any of the rules may be due
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Missed {} variable changes, evaluating all CA rules", skipped);
+                    None
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            let Some(node) = node.upgrade() else {
+                return;
+            };
+            node.trigger_condition_rules(changed.as_ref(), &mut runs).await;
+            // Nothing queued up behind this change, so the cascade has settled
+            if changes.is_empty() {
+                runs.clear();
+            }
+        }
+    }
+
+    /// Runs the handlers of the condition-action rules whose condition reads `changed`, or of
+    /// all of them when `changed` is `None`. `runs` counts the runs of each handler in the
+    /// current cascade, see `watch_condition_rules`.
+    async fn trigger_condition_rules(
+        &self,
+        changed: Option<&PrimitiveCondition>,
+        runs: &mut HashMap<String, usize>,
+    ) {
+        // Clone the handlers out so no map lock is held across the awaits below
+        let handlers: Vec<Arc<EventHandler>> = self
+            .event_handlers
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        for handler in handlers {
+            let rules = match handler.api.get_rules.call(false).await {
+                Ok(rules) => rules,
+                Err(e) => {
+                    warn!("Failed to get rules of handler {}: {}", handler.id, e);
+                    continue;
+                }
+            };
+            let due = rules.iter().any(|rule| match &rule.rule {
+                ReactiveRule::CA { condition, .. } => {
+                    changed.is_none_or(|var| Self::reads_var(condition, var))
+                }
+                ReactiveRule::ECA { .. } => false,
+            });
+            if !due {
+                continue;
+            }
+            let count = runs.entry(handler.id.clone()).or_default();
+            *count += 1;
+            if *count > self.config.max_depth {
+                // Report once, then leave the handler be until the cascade settles
+                if *count == self.config.max_depth + 1 {
+                    let e = NodeError::Oscillation {
+                        handler: handler.id.clone(),
+                        limit: self.config.max_depth,
+                    };
+                    error!("{}", e);
+                }
+                continue;
+            }
+            debug!("Triggering CA handler: {}", handler.id);
+            if let Err(e) = handler.api.process_action.call(ActionContext::default()).await {
+                error!("Failed to process CA handler {}: {}", handler.id, e);
+            }
+        }
+    }
+
+    /// Whether `condition` reads the variable `var`.
+    fn reads_var(condition: &Condition, var: &PrimitiveCondition) -> bool {
+        let mut found = false;
+        condition.visit(&mut |node| {
+            if node.downcast_ref::<PrimitiveCondition>() == Some(var) {
+                found = true;
+            }
+        });
+        found
+    }
+
     /// Recursively evaluates complex conditions. Instrumented for tracing.
-    #[instrument(skip(self, condition))]
+    /// `depth` is the nesting level of `condition`; start at 0.
+    #[instrument(skip(self, condition, depth), fields(node_id = %self.id))]
     #[async_recursion]
     pub async fn process_condition(
         self: Arc<Self>,
         condition: &Condition,
+        depth: usize,
     ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        self.check_depth(depth)?;
+        self.counters.conditions_evaluated.fetch_add(1, Ordering::Relaxed);
         let cond = condition.clone().to_string();
         debug!("Processing condition: {}", cond);
         match condition {
             Condition::Atomic(val) => {
-                let ac = self.get_atomic_condition(val.clone(), None).await;
-                match ac {
-                    Ok(value) => value.to_bool().map_err(|e| {
-                        Box::<dyn Error + Send + Sync>::from(format!(
-                            "Failed to evaluate condition {:?}: {}",
-                            val, e
-                        ))
-                    }),
+                // Variables that were never set may count as false instead of an error
+                if self.config.treat_unknown_as_false
+                    && let AtomicCondition::Primitive(var) = val
+                    && !self.vars.contains_key(var)
+                {
+                    debug!("Treating unset variable as false: {}", var);
+                    return Ok(false);
+                }
+                let value = match self.clone().get_atomic_condition(val.clone(), None, depth + 1).await {
+                    Ok(value) => value,
                     Err(e) => {
-                        error!("Failed to get atomic condition: {}", e);
-                        Err(Box::<dyn Error + Send + Sync>::from(format!(
-                            "Failed to get atomic condition {:?}: {}",
-                            val, e
-                        )))
+                        error!("Failed to get atomic condition {}: {}", val, e);
+                        return Err(e);
                     }
+                };
+                match (value.to_bool(), val) {
+                    (Ok(b), _) => Ok(b),
+                    (Err(_), AtomicCondition::Primitive(var)) if !self.vars.contains_key(var) => {
+                        Err(NodeError::UnknownVariable(var.clone()).into())
+                    }
+                    (Err(e), _) => Err(e.into()),
                 }
             }
             Condition::Not(cond) => {
-                let result = self.process_condition(cond).await?;
+                let result = self.process_condition(cond, depth + 1).await?;
                 Ok(!result)
             }
-            Condition::Parentheses(cond) => self.process_condition(cond).await,
+            Condition::Parentheses(cond) => self.process_condition(cond, depth + 1).await,
+            Condition::AtLeast(var, count) => Ok(self.count(var)? >= *count),
+            // The parser never gives empty lists, but ASTs built in code may. They evaluate to
+            // the identity of their operator: nothing to contradict a conjunction, nothing to
+            // satisfy a disjunction.
+            Condition::Conjunction(conds) if conds.is_empty() => Ok(true),
+            Condition::Disjunction(conds) if conds.is_empty() => Ok(false),
             Condition::Conjunction(conds) => {
+                // Fast path: when every conjunct is a plain variable, read them all in one pass
+                let reads: Option<Vec<_>> =
+                    conds.iter().map(|cond| self.read_plain_variable(cond)).collect();
+                if let Some(reads) = reads {
+                    for read in reads {
+                        if !read? {
+                            return Ok(false);
+                        }
+                    }
+                    return Ok(true);
+                }
+
                 let node_clone = Arc::clone(&self);
                 for cond in conds {
                     let node_clone = node_clone.clone();
-                    if !node_clone.process_condition(cond).await? {
+                    if !node_clone.process_condition(cond, depth + 1).await? {
                         return Ok(false);
                     }
                 }
                 Ok(true)
             }
+            Condition::Disjunction(conds) if self.config.concurrent_disjunction && conds.len() > 1 => {
+                let mut tasks = JoinSet::new();
+                for (i, cond) in conds.iter().cloned().enumerate() {
+                    let node = self.clone();
+                    tasks.spawn(async move { (i, node.process_condition(&cond, depth + 1).await) });
+                }
+                // Like on the sequential path, the first operand in order that is true or fails
+                // decides, so results are only read once every operand before them is false
+                let mut results: Vec<Option<_>> = (0..conds.len()).map(|_| None).collect();
+                let mut next = 0;
+                while let Some(joined) = tasks.join_next().await {
+                    // Dropping the set aborts the operands still running
+                    let (i, result) = joined?;
+                    results[i] = Some(result);
+                    while let Some(result) = results.get_mut(next).and_then(Option::take) {
+                        if result? {
+                            return Ok(true);
+                        }
+                        next += 1;
+                    }
+                }
+                Ok(false)
+            }
             Condition::Disjunction(conds) => {
                 let node_clone = Arc::clone(&self);
                 for cond in conds {
                     let node_clone = node_clone.clone();
-                    if node_clone.process_condition(cond).await? {
+                    if node_clone.process_condition(cond, depth + 1).await? {
                         return Ok(true);
                     }
                 }
@@ -225,49 +564,99 @@ impl Node {
     }
 
     /// Entry point for processing an action. Handles triggers, productions, and consumptions.
-    #[instrument(skip(self, action))]
-    #[async_recursion]
+    /// `ctx` tracks the events triggered along the current chain; start new chains with the default.
+    /// `depth` is the nesting level of `action`; start at 0. Triggered handlers run in their own
+    /// task and start counting again.
+    ///
+    /// With a `ctx.deadline`, every chain started at depth 0 gives up once it passes, aborting
+    /// the parallel branches it spawned, and fails with `NodeError::DeadlineExceeded`.
+    #[instrument(skip(self, action, ctx, depth), fields(node_id = %self.id))]
     pub async fn process_action(
         self: Arc<Self>,
         action: Action,
+        ctx: ActionContext,
+        depth: usize,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        match ctx.deadline {
+            // Nested actions run within the chain's timeout, only its start sets one up
+            Some(deadline) if depth == 0 => {
+                tokio::time::timeout_at(deadline, self.run_action(action, ctx, depth))
+                    .await
+                    .map_err(|_| {
+                        error!("Action deadline exceeded");
+                        NodeError::DeadlineExceeded
+                    })?
+            }
+            _ => self.run_action(action, ctx, depth).await,
+        }
+    }
+
+    /// `process_action` without the deadline.
+    #[async_recursion]
+    async fn run_action(
+        self: Arc<Self>,
+        action: Action,
+        ctx: ActionContext,
+        depth: usize,
     ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        self.check_depth(depth)?;
         // Log the action being processed
         let a = action.clone().to_string();
         debug!("Processing action: {}", a);
 
+        if let Action::Primitive(_) = action {
+            self.counters.actions_fired.fetch_add(1, Ordering::Relaxed);
+        }
+
         // Match on the action type to determine how to process it
         match action {
             Action::Primitive(prim_event) => match prim_event {
-                PrimitiveEvent::Trigger(desc) => match self.event_handlers.get(&desc) {
+                PrimitiveEvent::Trigger(desc) => match self
+                    .event_handlers
+                    .get(&desc)
+                    .map(|entry| entry.value().clone())
+                {
+                    None if self.is_retired(&desc) => {
+                        info!("Retracted event was silently not executed: {}", desc);
+                        Ok(true)
+                    }
                     None => {
                         error!("Invalid action cannot be executed: {}", desc);
-                        Err(Box::<dyn Error + Send + Sync>::from(format!(
-                            "Invalid action: {}",
-                            desc
-                        )))
+                        Err(NodeError::InvalidAction {
+                            event: desc,
+                            span: ctx.span,
+                        }
+                        .into())
                     }
-                    Some(eh_entry) => {
-                        let handler = eh_entry.value();
+                    Some(handler) => {
                         match handler.state().await {
-                            ActivationStatus::True => {
+                            Some(ActivationStatus::True) => {
                                 debug!("Processing action for event handler: {}", desc);
-                                let action_res = handler.api.process_action.call(()).await;
-                                action_res.map_err(|e| e)
+                                // Refuse to handle an event that is already being handled up the chain
+                                let ctx = ctx.enter(&desc).inspect_err(|e| error!("{}", e))?;
+                                handler.api.process_action.call(ctx).await
                             }
-                            ActivationStatus::False => {
+                            Some(ActivationStatus::False) => {
                                 info!("Inactive variable was silently not executed: {}", desc);
                                 Ok(true)
                             }
-                            ActivationStatus::Conflict => Ok(true),
+                            Some(ActivationStatus::Conflict) => Ok(true),
+                            None => {
+                                debug!("Event handler has no rules left: {}", desc);
+                                Ok(true)
+                            }
                         }
                     }
                 },
                 PrimitiveEvent::Production(ac) => {
+                    if let Some(var) = self.counter_var(&ac) {
+                        return Ok(self.add_to_counter(var, 1));
+                    }
                     let alias_rules = self.get_alias_rules(ac.clone(), None).await;
                     match alias_rules {
                         Err(_) => {
                             debug!("No alias found for atomic condition: {:?}", ac);
-                            self.store_atomic_condition(ac, ActivationStatus::True, None, true)
+                            self.store_atomic_condition(ac, ActivationStatus::True, None, true, depth + 1)
                                 .await
                         }
                         Ok((rules, ns)) => {
@@ -284,22 +673,23 @@ impl Node {
                                     ActivationStatus::True,
                                     Some(ns),
                                     true,
+                                    depth + 1,
                                 )
                                 .await?;
 
-                            // Extract case rules from the alias rules
-                            let case_rules: Vec<RuleWithArgs> = rules
+                            // Extract the actions of the case rules from the alias rules
+                            let case_actions: Vec<Action> = rules
                                 .iter()
                                 .filter_map(|rule| match rule {
-                                    Rule::Case(cr) => Some(RuleWithArgs::Case(cr.clone())),
+                                    Rule::Case(cr) => Some(cr.action.clone()),
                                     _ => None,
                                 })
                                 .collect();
 
-                            // Process each case rule
-                            for case_rule in case_rules {
-                                debug!("Processing case rule: {:?}", case_rule);
-                                match self.clone().process_rule(case_rule).await {
+                            // Process each case rule within the current chain
+                            for case_action in case_actions {
+                                debug!("Processing case rule: {:?}", case_action);
+                                match self.clone().process_action(case_action, ctx.clone(), depth + 1).await {
                                     Ok(res) => r &= res,
                                     Err(e) => {
                                         error!("Failed to process case rule: {}", e);
@@ -313,17 +703,26 @@ impl Node {
                 }
 
                 PrimitiveEvent::Consumption(ac) => {
+                    if let Some(var) = self.counter_var(&ac) {
+                        return Ok(self.add_to_counter(var, -1));
+                    }
                     let alias_rules = self.get_alias_rules(ac.clone(), None).await;
                     match alias_rules {
                         Err(_) => {
                             debug!("No alias found for atomic condition: {:?}", ac);
-                            self.store_atomic_condition(ac, ActivationStatus::False, None, true)
-                                .await
+                            let r = self
+                                .clone()
+                                .store_atomic_condition(ac, ActivationStatus::False, None, true, depth + 1)
+                                .await;
+                            self.gc_handlers();
+                            r
                         }
                         Ok((rules, ns)) => {
                             debug!("Found alias rules for atomic condition: {:?}", ac);
+                            // Consuming the alias itself retracts the whole compound
+                            let fully_retracted = matches!(ac, AtomicCondition::Primitive(_));
                             // store_atomic_condition should handle everything except for case rules
-                            // Store the atomic condition as True after processing all rules
+                            // Store the atomic condition as False after processing all rules
                             let mut r = self
                                 .clone()
                                 .store_atomic_condition(
@@ -332,24 +731,31 @@ impl Node {
                                         alias: None,
                                     }),
                                     ActivationStatus::False,
-                                    Some(ns),
+                                    Some(ns.clone()),
                                     false,
+                                    depth + 1,
                                 )
                                 .await?;
 
-                            // Extract case rules from the alias rules
-                            let case_rules: Vec<RuleWithArgs> = rules
+                            // Drop the retracted reactive rules from their handlers
+                            if fully_retracted {
+                                self.clone().remove_compound_rules(&rules, ns).await?;
+                            }
+                            self.gc_handlers();
+
+                            // Extract the actions of the case rules from the alias rules
+                            let case_actions: Vec<Action> = rules
                                 .iter()
                                 .filter_map(|rule| match rule {
-                                    Rule::Case(cr) => Some(RuleWithArgs::Case(cr.clone())),
+                                    Rule::Case(cr) => Some(cr.action.clone()),
                                     _ => None,
                                 })
                                 .collect();
 
-                            // Process each case rule
-                            for case_rule in case_rules {
-                                debug!("Processing case rule: {:?}", case_rule);
-                                match self.clone().process_rule(case_rule).await {
+                            // Process each case rule within the current chain
+                            for case_action in case_actions {
+                                debug!("Processing case rule: {:?}", case_action);
+                                match self.clone().process_action(case_action, ctx.clone(), depth + 1).await {
                                     Ok(res) => r &= res,
                                     Err(e) => {
                                         error!("Failed to process case rule: {}", e);
@@ -365,18 +771,31 @@ impl Node {
             Action::List(list) => {
                 match list {
                     ActionList::Sequence(actions) => {
-                        // Sequential-start execution: fire each sub-action one after another without waiting for completion, but still collect their results
-                        let mut handles = Vec::with_capacity(actions.len());
+                        // Sequential execution: each sub-action completes before the next one starts, stopping at the first error
+                        let mut overall = true;
                         for sub in actions {
-                            let node_clone = Arc::clone(&self);
-                            let handle = tokio::spawn(async move {
-                                node_clone.process_action(sub.clone()).await
-                            });
-                            handles.push(handle);
+                            overall &= self.clone().process_action(sub, ctx.clone(), depth + 1).await?;
                         }
-                        collect_conjunction(handles).await
+                        Ok(overall)
                     }
                     ActionList::Parallel(actions) => {
+                        // Opposing writes to the same variable would race, mark those variables as conflicting instead
+                        let conflicts = self.opposing_writes(&actions);
+                        for var in conflicts.iter() {
+                            warn!("Conflicting writes to variable in parallel action: {}", var);
+                            self.set_var(var.clone(), ActivationStatus::Conflict);
+                        }
+                        let actions: Vec<Action> = actions
+                            .into_iter()
+                            .filter(|sub| {
+                                !Self::variable_write(sub).is_some_and(|(var, _)| conflicts.contains(&var))
+                            })
+                            .collect();
+
+                        if self.config.interleaving {
+                            return self.clone().process_interleaved(actions, ctx, depth).await;
+                        }
+
                         // Parallel execution: launch all sub-actions concurrently and await all their results
                         let barrier = Arc::new(Barrier::new(actions.len() + 1));
                         let mut handles = Vec::with_capacity(actions.len());
@@ -384,12 +803,15 @@ impl Node {
                         for sub in actions {
                             let node_clone = Arc::clone(&self);
                             let b = barrier.clone();
+                            let ctx = ctx.clone();
                             let handle = tokio::spawn(async move {
                                 b.wait().await;
-                                node_clone.process_action(sub.clone()).await
+                                node_clone.process_action(sub.clone(), ctx, depth + 1).await
                             });
                             handles.push(handle);
                         }
+                        // Abort the branches when this action is dropped, e.g. past its deadline
+                        let _abort = AbortOnDrop::new(&handles);
                         // release actions all simultaneously
                         barrier.wait().await;
                         collect_conjunction(handles).await
@@ -397,27 +819,677 @@ impl Node {
                     ActionList::Alternative(actions) => {
                         // Alternative execution: launch one random action from the list
                         if actions.is_empty() {
-                            return Err(Box::<dyn std::error::Error + Send + Sync>::from(
-                                "Cannot execute empty alternative action",
-                            ));
+                            return Err(NodeError::EmptyAlternative.into());
                         }
 
-                        // Get a random action
-                        let selected_action = {
-                            let mut rng = rand::rng(); // current thread-local RNG
-                            actions
-                                .choose(&mut rng)
-                                .expect("non-empty; just checked")
-                                .clone()
-                        };
+                        // Get a random action, from the seeded RNG if the node has one
+                        let selected_action = match &self.rng {
+                            Some(rng) => {
+                                let mut rng = rng.lock().unwrap_or_else(|e| e.into_inner());
+                                actions.choose(&mut *rng)
+                            }
+                            None => actions.choose(&mut rand::rng()), // current thread-local RNG
+                        }
+                        .expect("non-empty; just checked")
+                        .clone();
 
                         debug!("Executing alternative action: {:?}", selected_action);
 
-                        self.clone().process_action(selected_action).await
+                        self.clone().process_action(selected_action, ctx, depth + 1).await
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs the branches of a parallel action in interleaving mode: the branches are shuffled
+    /// with the node's RNG and each one runs to completion before the next starts. Like the
+    /// concurrent schedule, every branch runs and the result is their conjunction, with the
+    /// first error returned once all branches are done.
+    async fn process_interleaved(
+        self: Arc<Self>,
+        mut actions: Vec<Action>,
+        ctx: ActionContext,
+        depth: usize,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        match &self.rng {
+            Some(rng) => {
+                let mut rng = rng.lock().unwrap_or_else(|e| e.into_inner());
+                actions.shuffle(&mut *rng);
+            }
+            None => actions.shuffle(&mut rand::rng()),
+        }
+        debug!("Interleaved schedule: {:?}", actions);
+
+        let mut overall = Ok(true);
+        for sub in actions {
+            let result = self.clone().process_action(sub, ctx.clone(), depth + 1).await;
+            overall = match (overall, result) {
+                (Err(e), _) | (Ok(_), Err(e)) => Err(e),
+                (Ok(all), Ok(val)) => Ok(all && val),
+            };
+        }
+        overall
+    }
+
+    /// Works out the variable writes `action` would make, in order, without changing the node.
+    ///
+    /// The action is followed like `process_action` would: triggers run the active rules of
+    /// their handler whose conditions hold, reading the writes made so far. Branches of a parallel
+    /// action are taken in order, with opposing writes recorded as `Conflict`. Alternatives,
+    /// aliases and compounds are not covered and make the dry run fail.
+    pub async fn dry_run_action(
+        &self,
+        action: Action,
+    ) -> Result<Vec<(PrimitiveCondition, ActivationStatus)>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        let mut shadow = DryRun::default();
+        self.dry_run(action, ActionContext::default(), 0, &mut shadow).await?;
+        Ok(shadow.writes)
+    }
+
+    /// Follows one action of a dry run, see `dry_run_action`.
+    #[async_recursion]
+    async fn dry_run(
+        &self,
+        action: Action,
+        ctx: ActionContext,
+        depth: usize,
+        shadow: &mut DryRun,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.check_depth(depth)?;
+        match action {
+            Action::Primitive(PrimitiveEvent::Trigger(desc)) => {
+                if !self.event_handlers.contains_key(&desc) && self.is_retired(&desc) {
+                    return Ok(());
+                }
+                let handler = self
+                    .event_handlers
+                    .get(&desc)
+                    .map(|entry| entry.value().clone())
+                    .ok_or_else(|| NodeError::InvalidAction {
+                        event: desc.clone(),
+                        span: ctx.span,
+                    })?;
+                if handler.state().await != Some(ActivationStatus::True) {
+                    return Ok(());
+                }
+                let ctx = ctx.enter(&desc)?;
+                for rule in handler.api.get_rules.call(false).await? {
+                    let (condition, action, else_action) = match rule.rule {
+                        ReactiveRule::CA {
+                            condition,
+                            action,
+                            else_action,
+                        } => (Some(condition), action, else_action),
+                        ReactiveRule::ECA {
+                            condition,
+                            action,
+                            else_action,
+                            ..
+                        } => (condition, action, else_action),
+                    };
+                    let holds = match condition {
+                        Some(condition) => self.dry_run_condition(&condition, shadow)?,
+                        None => true,
+                    };
+                    if holds {
+                        self.dry_run(action, ctx.clone(), 0, shadow).await?;
+                    } else if let Some(else_action) = else_action {
+                        self.dry_run(else_action, ctx.clone(), 0, shadow).await?;
+                    }
+                }
+                Ok(())
+            }
+            Action::Primitive(event) => {
+                let write = Self::variable_write(&Action::Primitive(event.clone()));
+                match write {
+                    // Counters aren't simulated, their writes fail like those to aliases
+                    Some((var, value)) => {
+                        let PrimitiveCondition::Var(name) = &var;
+                        if self.aliases.contains_key(name) || self.count(&var).is_ok() {
+                            return Err(NodeError::DryRunUnsupported(event.to_string()).into());
+                        }
+                        shadow.write(var, ActivationStatus::from(value));
+                        Ok(())
+                    }
+                    None => Err(NodeError::DryRunUnsupported(event.to_string()).into()),
+                }
+            }
+            Action::List(ActionList::Sequence(actions)) => {
+                for sub in actions {
+                    self.dry_run(sub, ctx.clone(), depth + 1, shadow).await?;
+                }
+                Ok(())
+            }
+            Action::List(ActionList::Parallel(actions)) => {
+                let conflicts = self.opposing_writes(&actions);
+                for var in conflicts.iter() {
+                    shadow.write(var.clone(), ActivationStatus::Conflict);
+                }
+                for sub in actions {
+                    if Self::variable_write(&sub).is_some_and(|(var, _)| conflicts.contains(&var)) {
+                        continue;
+                    }
+                    self.dry_run(sub, ctx.clone(), depth + 1, shadow).await?;
+                }
+                Ok(())
+            }
+            // Which alternative is picked can't be predicted
+            Action::List(ActionList::Alternative(actions)) => Err(NodeError::DryRunUnsupported(
+                Action::List(ActionList::Alternative(actions)).to_string(),
+            )
+            .into()),
+        }
+    }
+
+    /// Evaluates a condition over plain variables, reading the writes of the dry run first.
+    fn dry_run_condition(
+        &self,
+        condition: &Condition,
+        shadow: &DryRun,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        match condition {
+            Condition::Atomic(AtomicCondition::Primitive(var)) => {
+                let value = match shadow.vars.get(var) {
+                    Some(value) => Some(value.clone()),
+                    None => self.vars.get(var).map(|entry| entry.value().clone()),
+                };
+                match value {
+                    None if self.config.treat_unknown_as_false => Ok(false),
+                    None => Err(NodeError::UnknownVariable(var.clone()).into()),
+                    Some(value) => Ok(value.to_bool()?),
+                }
+            }
+            Condition::Atomic(other) => Err(NodeError::DryRunUnsupported(other.to_string()).into()),
+            Condition::Not(inner) => Ok(!self.dry_run_condition(inner, shadow)?),
+            Condition::Parentheses(inner) => self.dry_run_condition(inner, shadow),
+            // Counters keep their count during a dry run, see `dry_run`
+            Condition::AtLeast(var, count) => Ok(self.count(var)? >= *count),
+            Condition::Conjunction(conds) => {
+                for cond in conds {
+                    if !self.dry_run_condition(cond, shadow)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            Condition::Disjunction(conds) => {
+                for cond in conds {
+                    if self.dry_run_condition(cond, shadow)? {
+                        return Ok(true);
                     }
                 }
+                Ok(false)
+            }
+        }
+    }
+
+    /// Fails once `depth` goes past the configured `max_depth`.
+    fn check_depth(&self, depth: usize) -> Result<(), DepthExceeded> {
+        if depth > self.config.max_depth {
+            error!("Maximum nesting depth exceeded at depth {}", depth);
+            return Err(DepthExceeded {
+                max_depth: self.config.max_depth,
+            });
+        }
+        Ok(())
+    }
+
+    /// Evaluates `var` or `not var` straight from the variable map, giving the same result as
+    /// `process_condition` would. Returns `None` for any other kind of condition.
+    fn read_plain_variable(
+        &self,
+        condition: &Condition,
+    ) -> Option<Result<bool, Box<dyn std::error::Error + Send + Sync>>> {
+        let (val, negated) = match condition {
+            Condition::Atomic(val) => (val, false),
+            Condition::Not(inner) => match inner.as_ref() {
+                Condition::Atomic(val) => (val, true),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let AtomicCondition::Primitive(var) = val else {
+            return None;
+        };
+
+        let value = match self.vars.get(var) {
+            None if self.config.treat_unknown_as_false => return Some(Ok(negated)),
+            None => return Some(Err(NodeError::UnknownVariable(var.clone()).into())),
+            Some(entry) => entry.value().clone(),
+        };
+        Some(value.to_bool().map(|b| b != negated).map_err(|e| e.into()))
+    }
+
+    /// Parses a condition such as `loaded and not busy` and evaluates it against the node.
+    /// Parse errors are returned rather than terminating the process.
+    pub async fn query(
+        self: Arc<Self>,
+        cond_src: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let condition = lex_and_parse_condition(cond_src)?;
+        self.process_condition(&condition, 0).await
+    }
+
+    /// Parses a compound such as `{#e => #a.} as r` and reports whether its rules are active on
+    /// the node, the same way a compound inside a condition is evaluated.
+    pub async fn is_compound_active(
+        self: Arc<Self>,
+        src: &str,
+    ) -> Result<ActivationStatus, Box<dyn std::error::Error + Send + Sync>> {
+        let compound = lex_and_parse_compound_safe(src)?;
+        self.get_atomic_condition(AtomicCondition::Compound(compound), None, 0)
+            .await
+    }
+
+    /// Captures the node's rules, variable bindings and alias namespaces, each sorted for stable output.
+    pub async fn observe(&self) -> NodeSnapshot {
+        // Clone the handlers out so no map lock is held across the awaits below
+        let handlers: Vec<Arc<EventHandler>> = self
+            .event_handlers
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        let mut rules = Vec::new();
+        for handler in handlers {
+            match handler.api.get_rules.call(true).await {
+                Ok(handler_rules) => rules.extend(handler_rules.into_iter().map(RuleSnapshot::from)),
+                Err(e) => error!("Failed to collect rules from handler {}: {}", handler.id, e),
             }
         }
+        rules.sort_by(|a, b| (&a.alias, &a.rule).cmp(&(&b.alias, &b.rule)));
+
+        let mut vars: Vec<VarSnapshot> = self
+            .vars
+            .iter()
+            .map(|entry| VarSnapshot {
+                name: entry.key().to_string(),
+                status: entry.value().clone(),
+            })
+            .collect();
+        vars.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let namespaces: Vec<(String, Arc<AliasNamespace>)> = self
+            .aliases
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        let mut aliases = Vec::with_capacity(namespaces.len());
+        for (name, namespace) in namespaces {
+            aliases.push(namespace.snapshot(name).await);
+        }
+        aliases.sort_by(|a, b| a.name.cmp(&b.name));
+
+        NodeSnapshot {
+            rules,
+            vars,
+            aliases,
+        }
+    }
+
+    /// Copies the node's rules, variables and aliases, see `restore`.
+    pub async fn snapshot(&self) -> Result<NodeCheckpoint, Box<dyn std::error::Error + Send + Sync>> {
+        // Clone the handlers and namespaces out so no map lock is held across the awaits below
+        let handlers: Vec<Arc<EventHandler>> = self
+            .event_handlers
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        let namespaces: Vec<(String, Arc<AliasNamespace>)> = self
+            .aliases
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+
+        let mut handler_rules = Vec::with_capacity(handlers.len());
+        for handler in handlers {
+            handler_rules.push(handler.api.get_rules.call(true).await?);
+        }
+        let mut aliases = Vec::with_capacity(namespaces.len());
+        for (name, namespace) in namespaces {
+            aliases.push((name, namespace.deep_clone().await));
+        }
+
+        Ok(NodeCheckpoint {
+            vars: self
+                .vars
+                .iter()
+                .map(|entry| (entry.key().clone(), entry.value().clone()))
+                .collect(),
+            counts: self
+                .counts
+                .iter()
+                .map(|entry| (entry.key().clone(), *entry.value()))
+                .collect(),
+            aliases,
+            handlers: handler_rules,
+            declarative_rules: self.declarative_rules.iter().map(|rule| rule.clone()).collect(),
+        })
+    }
+
+    /// Puts the node back in the state of `checkpoint`. Handlers are rebuilt from their rules,
+    /// and variables whose value changes are announced to `subscribe_vars` receivers.
+    pub async fn restore(
+        self: Arc<Self>,
+        checkpoint: NodeCheckpoint,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        info!("Restoring node state");
+        self.event_handlers.clear();
+        for mut rules in checkpoint.handlers.into_iter().filter(|rules| !rules.is_empty()) {
+            let rest = rules.split_off(1);
+            let first = rules.pop().expect("non-empty; just checked");
+            let handler = Arc::new(EventHandler::new(self.clone(), first));
+            for rule in rest {
+                handler.add_rule(rule).await?;
+            }
+            self.revive_handler(&handler.id);
+            self.event_handlers.insert(handler.id.clone(), handler);
+        }
+
+        self.declarative_rules.clear();
+        for rule in checkpoint.declarative_rules {
+            self.declarative_rules.insert(rule);
+        }
+
+        self.aliases.clear();
+        for (name, namespace) in checkpoint.aliases {
+            self.aliases.insert(name, Arc::new(namespace));
+        }
+
+        self.counts.clear();
+        for (var, count) in checkpoint.counts {
+            self.counts.insert(var, count);
+        }
+
+        self.vars.retain(|var, _| checkpoint.vars.contains_key(var));
+        for (var, value) in checkpoint.vars {
+            self.set_var(var, value);
+        }
+        Ok(())
+    }
+
+    /// Applies the changes of `diff` to the node: the `removed` rules are taken out, dropping
+    /// handlers and aliases left without rules, then the `added` rules are loaded. The
+    /// `unchanged` rules are left alone. When a step fails, or an added rule isn't applied,
+    /// the node is restored to its state from before the call and the error is returned.
+    pub async fn apply_diff(
+        self: Arc<Self>,
+        diff: RuleDiff,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let checkpoint = self.snapshot().await?;
+        let result = self.clone().apply_diff_steps(diff).await;
+        if let Err(e) = &result {
+            warn!("Rolling back rule diff: {}", e);
+            self.restore(checkpoint).await?;
+        }
+        result
+    }
+
+    /// The steps of `apply_diff`, without the rollback.
+    async fn apply_diff_steps(
+        self: Arc<Self>,
+        diff: RuleDiff,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        info!(
+            "Applying rule diff: {} removed, {} added, {} unchanged",
+            diff.removed.len(),
+            diff.added.len(),
+            diff.unchanged.len()
+        );
+        for rule in &diff.removed {
+            self.clone().unload_rule(rule).await?;
+        }
+        match self.load_rules(diff.added).await?.first() {
+            Some(rule) => Err(NodeError::RuleNotApplied(rule.to_string()).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Clears all rules, variables and aliases so the node starts over with an empty policy.
+    /// The node keeps its id, configuration and subscribers.
+    pub fn reset(&self) {
+        info!("Resetting node state");
+        self.event_handlers.clear();
+        self.declarative_rules.clear();
+        self.aliases.clear();
+        self.vars.clear();
+        self.counts.clear();
+        self.retired_handlers.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    /// Remembers that the handler of event `id` was dropped after its rules were retracted, so
+    /// triggering the event is a no-op, as it was while the handler still held the disabled
+    /// rules, rather than an invalid action. At most `NodeConfig::retired_handlers_capacity`
+    /// events are remembered; the longest-retired one makes room for a new one.
+    fn retire_handler(&self, id: String) {
+        let capacity = self.config.retired_handlers_capacity;
+        let mut retired = self.retired_handlers.lock().unwrap_or_else(|e| e.into_inner());
+        retired.retain(|other| *other != id);
+        if capacity == 0 {
+            return;
+        }
+        if retired.len() >= capacity {
+            retired.pop_front();
+        }
+        retired.push_back(id);
+    }
+
+    /// Whether the handler of event `id` was dropped, see `retire_handler`.
+    fn is_retired(&self, id: &str) -> bool {
+        let retired = self.retired_handlers.lock().unwrap_or_else(|e| e.into_inner());
+        retired.iter().any(|other| other == id)
+    }
+
+    /// Forgets that the handler of event `id` was dropped, once it has one again.
+    fn revive_handler(&self, id: &str) {
+        let mut retired = self.retired_handlers.lock().unwrap_or_else(|e| e.into_inner());
+        retired.retain(|other| other != id);
+    }
+
+    /// Drops event handlers that no longer hold any enabled rule, returning how many were removed.
+    /// Callers must not hold a reference into `event_handlers` while calling this.
+    pub fn gc_handlers(&self) -> usize {
+        let mut removed = 0;
+        self.event_handlers.retain(|id, handler| {
+            let idle = handler.is_idle();
+            if idle {
+                debug!("Removing idle handler: {}", id);
+                self.retire_handler(id.clone());
+                removed += 1;
+            }
+            !idle
+        });
+        removed
+    }
+
+    /// Loads the rules of a policy: atomic conditions the node doesn't know yet start out
+    /// false, then the rules are applied, with the case rules run last. Returns the rules
+    /// that weren't applied.
+    async fn load_rules(
+        self: Arc<Self>,
+        rules: Vec<Rule>,
+    ) -> Result<Vec<Rule>, Box<dyn std::error::Error + Send + Sync>> {
+        // Counters start out at zero, so they read as false
+        for name in self.config.counter_vars.iter() {
+            let var = PrimitiveCondition::Var(name.clone());
+            if !self.vars.contains_key(&var) {
+                self.set_var(var, ActivationStatus::False);
+            }
+        }
+
+        // Initialize all potential atomic conditions
+        let mut atomic_conditions = Vec::new();
+        for rule in &rules {
+            rule.visit(&mut |c| {
+                if let Some(ac) = c.downcast_ref::<AtomicCondition>() {
+                    atomic_conditions.push(ac.clone());
+                }
+            });
+        }
+        for ac in atomic_conditions {
+            match &ac {
+                // External variables are set from outside, leave them unset until then
+                AtomicCondition::Primitive(PrimitiveCondition::Var(var))
+                    if self.config.external_vars.contains(var) =>
+                {
+                    continue;
+                }
+                // Keep what the node already has, so loading more rules doesn't reset it
+                AtomicCondition::Primitive(var) if self.vars.contains_key(var) => continue,
+                AtomicCondition::Compound(Compound {
+                    alias: Some(alias), ..
+                }) if self.aliases.contains_key(alias) => continue,
+                _ => {}
+            }
+            // Store each atomic condition with an initial value of False
+            let _ = self
+                .clone()
+                .store_atomic_condition(ac, ActivationStatus::False, None, true, 0)
+                .await;
+        }
+
+        let (case_rules, other_rules): (Vec<Rule>, Vec<Rule>) = rules
+            .into_iter()
+            .partition(|rule| matches!(rule, Rule::Case { .. }));
+
+        // Apply non-case rules first, case rules next
+        let mut not_applied = Vec::new();
+        for batch in [other_rules, case_rules] {
+            debug!("Applying rules: {:?}", batch);
+            let args: Vec<RuleWithArgs> = batch.iter().cloned().map(RuleWithArgs::from).collect();
+            let results = self.api.new_rules.call(args).await?;
+            for (rule, applied) in batch.into_iter().zip(results) {
+                if !applied {
+                    warn!("Rule was not applied: {}", rule);
+                    not_applied.push(rule);
+                }
+            }
+        }
+        Ok(not_applied)
+    }
+
+    /// Takes a rule loaded by `load_rules` out of the node again. Handlers and aliases left
+    /// without rules are dropped, facts are made false again. Case rules already ran, so
+    /// there is nothing to take out for them.
+    #[async_recursion]
+    async fn unload_rule(
+        self: Arc<Self>,
+        rule: &Rule,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        debug!("Unloading rule: {}", rule);
+        match rule {
+            Rule::Reactive(rr) => {
+                let handler_id = rr.get_identifier();
+                let handler = self.event_handlers.get(&handler_id).map(|h| h.value().clone());
+                if let Some(handler) = handler {
+                    handler
+                        .api
+                        .remove_rule
+                        .call(ReactiveRuleWithArgs::new(rr.clone(), ActivationStatus::True, None))
+                        .await?;
+                    if handler.state().await.is_none() {
+                        debug!("Removing empty handler: {}", handler_id);
+                        self.event_handlers.remove(&handler_id);
+                        self.retire_handler(handler_id);
+                    }
+                }
+            }
+            Rule::Declarative(rule) => {
+                self.declarative_rules.remove(rule);
+            }
+            Rule::Fact(FactRule { condition }) => match condition {
+                AtomicCondition::Primitive(var) => {
+                    self.update_var(var.clone(), ActivationStatus::False)?;
+                }
+                AtomicCondition::Compound(Compound {
+                    rules,
+                    alias: Some(alias),
+                }) => {
+                    self.aliases.remove(alias);
+                    self.remove_compound_rules(rules, vec![alias.clone()]).await?;
+                }
+                // Without an alias the compound's rules went into the main namespace
+                AtomicCondition::Compound(Compound { rules, alias: None }) => {
+                    for rule in rules {
+                        self.clone().unload_rule(rule).await?;
+                    }
+                }
+                AtomicCondition::SubCompound { .. } => {
+                    debug!("Leaving sub-compound fact in place: {}", condition);
+                }
+            },
+            Rule::Case(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Removes the reactive rules of a retracted compound from their event handlers.
+    /// Returns whether every rule was still tracked by its handler.
+    async fn remove_compound_rules(
+        self: Arc<Self>,
+        rules: &[Rule],
+        namespace: Vec<String>,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let mut removed = true;
+        for rule in rules {
+            if let Rule::Reactive(rr) = rule {
+                let handler_id = rr.get_identifier();
+                let handler = self.event_handlers.get(&handler_id).map(|h| h.value().clone());
+                match handler {
+                    None => removed = false,
+                    Some(handler) => {
+                        removed &= handler
+                            .api
+                            .remove_rule
+                            .call(ReactiveRuleWithArgs::new(
+                                rr.clone(),
+                                ActivationStatus::False,
+                                Some(namespace.clone()),
+                            ))
+                            .await?;
+                    }
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Returns the plain variable written by a production or consumption, with the value written.
+    fn variable_write(action: &Action) -> Option<(PrimitiveCondition, bool)> {
+        match action {
+            Action::Primitive(PrimitiveEvent::Production(AtomicCondition::Primitive(var))) => {
+                Some((var.clone(), true))
+            }
+            Action::Primitive(PrimitiveEvent::Consumption(AtomicCondition::Primitive(var))) => {
+                Some((var.clone(), false))
+            }
+            _ => None,
+        }
+    }
+
+    /// Collects the variables that the direct sub-actions of a parallel action both produce and consume.
+    /// Aliases are left out, since producing or consuming them acts on their rules instead, and
+    /// so are counters, whose increments and decrements add up in any order.
+    fn opposing_writes(&self, actions: &[Action]) -> HashSet<PrimitiveCondition> {
+        let mut writes: HashMap<PrimitiveCondition, bool> = HashMap::new();
+        let mut conflicts = HashSet::new();
+        for (var, value) in actions.iter().filter_map(Self::variable_write) {
+            let PrimitiveCondition::Var(name) = &var;
+            if self.aliases.contains_key(name) || self.config.counter_vars.contains(name) {
+                continue;
+            }
+            match writes.get(&var) {
+                Some(previous) if *previous != value => {
+                    conflicts.insert(var);
+                }
+                Some(_) => {}
+                None => {
+                    writes.insert(var, value);
+                }
+            }
+        }
+        conflicts
     }
 
     /// Retrieves rules from the alias namespace based on the atomic condition and optional namespace.
@@ -446,9 +1518,7 @@ impl Node {
                         let an = match self.aliases.get(&var) {
                             // If the alias cannot be found, return an error
                             None => {
-                                return Err(Box::<dyn std::error::Error + Send + Sync>::from(
-                                    "No alias found",
-                                ));
+                                return Err(NodeError::AliasNotFound(var).into());
                             }
                             // Alias found, retrieve the rules
                             Some(alias_namespace_ref) => {
@@ -473,9 +1543,7 @@ impl Node {
                         let an = match self.aliases.get(&head) {
                             // If the alias cannot be found, return an error
                             None => {
-                                return Err(Box::<dyn std::error::Error + Send + Sync>::from(
-                                    "No alias found",
-                                ));
+                                return Err(NodeError::AliasNotFound(head).into());
                             }
                             // Alias found, retrieve the rules
                             Some(alias_namespace_ref) => {
@@ -493,9 +1561,7 @@ impl Node {
             AtomicCondition::Compound(Compound { rules, .. }) => {
                 match alias_namespace {
                     // No alias namespace provided, return an error telling that there are no alias rules
-                    None => Err(Box::<dyn std::error::Error + Send + Sync>::from(
-                        "No alias found",
-                    )),
+                    None => Err(NodeError::MainNamespace.into()),
                     // Alias namespace provided, return the overlapping rules and the namespace
                     Some(ns) => {
                         let tail_namespace = ns.clone()[ns.len() - 1].clone();
@@ -512,10 +1578,13 @@ impl Node {
                         // Get the rules from the main alias namespace
                         let (full_rules, full_namespace) = self.get_alias_rules(ac, ns).await?;
 
-                        // Get the matching rules
+                        // Get the stored rules matching the given ones, whatever their aliases
                         let matching_rules: Vec<Rule> = rules
-                            .into_iter()
-                            .filter(|rule| full_rules.contains(rule))
+                            .iter()
+                            .filter_map(|rule| {
+                                full_rules.iter().find(|stored| stored.structurally_eq(rule))
+                            })
+                            .cloned()
                             .collect();
 
                         // Return the matching rules and the namespace
@@ -545,8 +1614,9 @@ impl Node {
     /// Condition - the atomic condition to store,
     /// value - the value to associate with the condition,
     /// var_namespace - optional namespace to store the condition in,
-    /// override_entries - whether to override existing entries in the namespace.
-    #[instrument(skip(self, condition, value, var_namespace))]
+    /// override_entries - whether to override existing entries in the namespace,
+    /// depth - the nesting level of the condition, start at 0.
+    #[instrument(skip(self, condition, value, var_namespace, depth), fields(node_id = %self.id))]
     #[async_recursion]
     pub async fn store_atomic_condition(
         self: Arc<Self>,
@@ -554,7 +1624,9 @@ impl Node {
         value: ActivationStatus,
         var_namespace: Option<Vec<String>>,
         override_entries: bool,
+        depth: usize,
     ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        self.check_depth(depth)?;
         // Log the condition being processed
         let cond = condition.clone().to_string();
         debug!("Storing atomic condition: {} with value: {:?}", cond, value);
@@ -582,6 +1654,31 @@ impl Node {
                 // Copy of the namespace for future use
                 let var_namespace_copy = if n.len() > 0 { Some(n.clone()) } else { None };
 
+                // Convert the rules into RuleWithArgs format before any namespace is touched, so
+                // an unsupported rule leaves nothing half stored
+                let mut rules_with_args: Vec<RuleWithArgs> = Vec::with_capacity(rules.len());
+                for r in rules.iter() {
+                    match r {
+                        Rule::Reactive(rr) => {
+                            rules_with_args.push(RuleWithArgs::Reactive(ReactiveRuleWithArgs::new(
+                                rr.clone(),
+                                value.clone(),
+                                var_namespace_copy.clone(),
+                            )))
+                        }
+                        Rule::Fact(fact_rule) => {
+                            rules_with_args.push(RuleWithArgs::Fact(FactRuleWithArgs {
+                                rule: fact_rule.clone(),
+                                value: Some(value.clone()),
+                            }))
+                        }
+                        Rule::Case(_) => {} // Case rules are not processed here
+                        Rule::Declarative(_) => {
+                            return Err(NodeError::UnsupportedRule(r.to_string()).into());
+                        }
+                    }
+                }
+
                 // If the namespace is empty, we are in the main namespace
                 if n.is_empty() {
                     info!("Storing rules in the main namespace");
@@ -602,13 +1699,24 @@ impl Node {
                     };
 
                     // Create or update the rules in the namespace
-                    let prev_rules = child_ns
+                    let created = child_ns
                         .create_rules(n, rules.clone(), override_entries)
                         .await;
 
-                    match prev_rules {
-                        Ok(Some(existing)) => warn!("Overriding existing rules: {:?}", existing),
-                        Ok(None) => info!("No existing rules, inserted fresh."),
+                    match created {
+                        Ok(CreatedRules {
+                            previous: Some(existing),
+                            added,
+                        }) => {
+                            if override_entries {
+                                warn!("Overriding existing rules: {:?}", existing);
+                            } else {
+                                info!("Added {} new rules to the existing ones", added.len());
+                            }
+                        }
+                        Ok(CreatedRules { previous: None, .. }) => {
+                            info!("No existing rules, inserted fresh.")
+                        }
                         Err(e) => {
                             error!("Failed to create or update rules: {}", e);
                             return Err(e);
@@ -616,30 +1724,10 @@ impl Node {
                     }
                 }
 
-                // Convert the rules into RuleWithArgs format
-                let rules_with_args: Vec<RuleWithArgs> = rules
-                    .into_iter()
-                    .filter_map(|r| match r {
-                        Rule::Reactive(rr) => {
-                            Some(RuleWithArgs::Reactive(ReactiveRuleWithArgs::new(
-                                rr.clone(),
-                                value.clone(),
-                                var_namespace_copy.clone(),
-                            )))
-                        }
-                        Rule::Fact(fact_rule) => Some(RuleWithArgs::Fact(FactRuleWithArgs {
-                            rule: fact_rule.clone(),
-                            value: Some(value.clone()),
-                        })),
-                        Rule::Case(_) => None, // Case rules are not processed here
-                        _ => panic!("Unsupported rule type in compound condition: {:?}", r),
-                    })
-                    .collect();
-
                 // Process the rules with arguments
                 let mut r = true;
                 for rule in rules_with_args.iter() {
-                    match self.clone().process_rule(rule.clone()).await {
+                    match self.clone().process_rule(rule.clone(), depth + 1).await {
                         Ok(res) => r &= res,
                         Err(e) => {
                             error!("Failed to process rule: {}", e);
@@ -664,8 +1752,14 @@ impl Node {
                 };
 
                 // Handle sub-compound conditions
-                self.store_atomic_condition(*condition, ActivationStatus::False, Some(n), override_entries)
-                    .await
+                self.store_atomic_condition(
+                    *condition,
+                    ActivationStatus::False,
+                    Some(n),
+                    override_entries,
+                    depth + 1,
+                )
+                .await
             }
         }
     }
@@ -674,14 +1768,17 @@ impl Node {
     /// Condition - the atomic condition to store,
     /// value - the value to associate with the condition,
     /// var_namespace - optional namespace to store the condition in,
-    /// override_entries - whether to override existing entries in the namespace.
-    #[instrument(skip(self, condition, var_namespace))]
+    /// override_entries - whether to override existing entries in the namespace,
+    /// depth - the nesting level of the condition, start at 0.
+    #[instrument(skip(self, condition, var_namespace, depth), fields(node_id = %self.id))]
     #[async_recursion]
     pub async fn get_atomic_condition(
         self: Arc<Self>,
         condition: AtomicCondition,
         var_namespace: Option<Vec<String>>,
+        depth: usize,
     ) -> Result<ActivationStatus, Box<dyn std::error::Error + Send + Sync>> {
+        self.check_depth(depth)?;
         // Log the condition being processed
         let cond = condition.clone().to_string();
         debug!("Getting atomic condition: {}", cond);
@@ -714,9 +1811,7 @@ impl Node {
                 // If the namespace is empty, we are in the main namespace
                 if n.is_empty() {
                     info!("Rules are in the main namespace");
-                    Err(Box::<dyn Error + Send + Sync>::from(
-                        "Cannot get rules from the main namespace",
-                    ))
+                    Err(NodeError::MainNamespace.into())
                 } else {
                     info!("Rules are in namespace: {:?}", n);
                     // Split the namespace into the first alias and the rest
@@ -727,27 +1822,26 @@ impl Node {
                     let alias_namespace = if let Some(r) = self.aliases.get(&first_alias) {
                         r.value().clone()
                     } else {
-                        return Err(Box::<dyn Error + Send + Sync>::from(format!(
-                            "No matching namespace found for alias: {}",
-                            first_alias
-                        )));
+                        return Err(NodeError::AliasNotFound(first_alias).into());
                     };
                     // Get the previous rules
                     let prev_rules = alias_namespace.get_rules(n.clone()).await?;
 
-                    // Get the intersection of the rules with the previous rules
+                    // Get the intersection of the rules with the previous rules, ignoring aliases
                     let matching_rules = rules
                         .iter()
-                        .filter(|rule| prev_rules.contains(rule))
+                        .filter_map(|rule| {
+                            prev_rules.iter().find(|stored| stored.structurally_eq(rule))
+                        })
                         .cloned()
                         .collect::<Vec<Rule>>();
 
                     // Need to check every rule in the compound to determine the overall value (Only checking for reactive rules currently)
-                    let statuses: DashSet<ActivationStatus> = DashSet::new();
+                    let mut statuses: Vec<ActivationStatus> = Vec::new();
                     for rule in matching_rules {
                         match rule {
                             Rule::Reactive(rr) => {
-                                // Get the status of the reactive rule
+                                // Get the status of the reactive rule, a removed rule counts as disabled
                                 let s = self
                                     .clone()
                                     .get_rule_status(&ReactiveRuleWithArgs::new(
@@ -755,14 +1849,16 @@ impl Node {
                                         ActivationStatus::True,
                                         var_namespace_copy.clone(),
                                     ))
-                                    .await?;
-                                statuses.insert(s);
+                                    .await
+                                    .unwrap_or(ActivationStatus::False);
+                                statuses.push(s);
                             }
                             _ => {}
                         }
                     }
                     // Determine the overall value based on the statuses
-                    let overall = overall_status_from_set(&statuses)?;
+                    let overall =
+                        overall_status_from_set_with(&statuses, self.config.conflict_strategy)?;
                     Ok(overall)
                 }
             }
@@ -782,7 +1878,7 @@ impl Node {
                 };
 
                 // Handle sub-compound conditions
-                self.get_atomic_condition(*condition, Some(n)).await
+                self.get_atomic_condition(*condition, Some(n), depth + 1).await
             }
         }
     }
@@ -799,18 +1895,12 @@ impl Node {
         // Get the rule's identifier
         let handler_id = rule.rule.get_identifier();
         // Check if the handler exists
-        match self.event_handlers.get(&handler_id) {
+        match self.event_handlers.get(&handler_id).map(|h| h.value().clone()) {
             None => {
                 // If the handler does not exist
-                Err(Box::<dyn Error + Send + Sync>::from(format!(
-                    "Handler for rule {} not found",
-                    handler_id
-                )))
+                Err(NodeError::HandlerNotFound(handler_id).into())
             }
-            Some(handler_entry) => {
-                // Get the handler
-                let handler = handler_entry.value();
-
+            Some(handler) => {
                 // Get the rules from the handler
                 let rules = handler.api.get_rules.call(true).await?;
 
@@ -825,17 +1915,17 @@ impl Node {
                     }
                 }
                 // If no matching rule was found, return an error
-                Err(Box::<dyn Error + Send + Sync>::from(format!(
-                    "Rule {} not found in handler {}",
-                    rule.rule.get_identifier(),
-                    handler_id
-                )))
+                Err(NodeError::RuleNotFound {
+                    rule: rule.rule.to_string(),
+                    handler: handler_id,
+                }
+                .into())
             }
         }
     }
 
     /// Updates an atomic condition's value atomically.
-    #[instrument(skip(self, var, value))]
+    #[instrument(skip(self, var, value), fields(node_id = %self.id))]
     fn update_var(
         self: Arc<Self>,
         var: PrimitiveCondition,
@@ -845,24 +1935,203 @@ impl Node {
         let var_desc = var.to_string();
         debug!("Updating variable: {} to value: {:?}", var_desc, value);
 
-        // Check if the value is Unknown, which is not allowed
+        // Check if the value is Conflict, which is not allowed
         if value == ActivationStatus::Conflict {
-            return Err(Box::<dyn Error + Send + Sync>::from(
-                "Cannot update variable to Unknown",
-            ));
+            return Err(NodeError::ConflictingWrite(var).into());
+        }
+
+        // A counter's status follows its count: true makes it count at least one, false
+        // resets it to zero
+        if let Ok(count) = self.count(&var) {
+            let target = if value == ActivationStatus::True { count.max(1) } else { 0 };
+            if target != count {
+                return Ok(self.add_to_counter(var, target - count));
+            }
         }
 
-        self.vars.insert(var, value);
+        self.set_var(var, value);
         Ok(true)
     }
 
+    /// Returns the variable `ac` names when it is a counter.
+    fn counter_var(&self, ac: &AtomicCondition) -> Option<PrimitiveCondition> {
+        match ac {
+            AtomicCondition::Primitive(PrimitiveCondition::Var(name))
+                if self.config.counter_vars.contains(name) =>
+            {
+                Some(PrimitiveCondition::Var(name.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Reads the count of a counter variable.
+    fn count(&self, var: &PrimitiveCondition) -> Result<i64, NodeError> {
+        let PrimitiveCondition::Var(name) = var;
+        if !self.config.counter_vars.contains(name) {
+            return Err(NodeError::NotACounter(var.clone()));
+        }
+        Ok(self.counts.get(var).map_or(0, |count| *count))
+    }
+
+    /// Adds `delta` to a counter and stores whether it is above zero as the variable's status.
+    /// Condition-action rules may compare the count, so a change that leaves the status as it
+    /// was is announced all the same, and runs them through `watch_condition_rules` like any
+    /// other change.
+    fn add_to_counter(&self, var: PrimitiveCondition, delta: i64) -> bool {
+        let (previous, count) = {
+            let mut count = self.counts.entry(var.clone()).or_insert(0);
+            let previous = *count;
+            *count += delta;
+            (previous, *count)
+        };
+        debug!("Counter {} went from {} to {}", var, previous, count);
+        let status = ActivationStatus::from(count > 0);
+        self.set_var(var.clone(), status.clone());
+        if previous != count && (previous > 0) == (count > 0) {
+            // Sending only fails when nobody is subscribed
+            let _ = self.var_tx.send((var, status));
+        }
+        true
+    }
+
+    /// Reads the value of a variable: the count of a counter, the status of any other one.
+    pub fn value(&self, var: &PrimitiveCondition) -> Option<Value> {
+        match self.count(var) {
+            Ok(count) => Some(Value::Int(count)),
+            Err(_) => self.vars.get(var).map(|entry| Value::Status(entry.value().clone())),
+        }
+    }
+
+    /// Stores a variable's value, announcing it to `subscribe_vars` receivers if it changed.
+    fn set_var(&self, var: PrimitiveCondition, value: ActivationStatus) {
+        if value == ActivationStatus::Conflict {
+            self.counters.conflicts_detected.fetch_add(1, Ordering::Relaxed);
+        }
+        let previous = self.vars.insert(var.clone(), value.clone());
+        if previous.as_ref() != Some(&value) {
+            // Sending only fails when nobody is subscribed
+            let _ = self.var_tx.send((var, value));
+        }
+    }
+
+    /// Lists the identifiers of the node's event handlers in name order, e.g. `e` for `#e` or `:a`
+    /// for `: a => ...`. The keys are collected in one pass, so handlers added or dropped meanwhile
+    /// may be missed.
+    pub fn handler_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .event_handlers
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// Lists the paths of all alias namespaces, top-level aliases in name order, each followed by
+    /// the namespaces nested in it.
+    pub fn alias_tree(&self) -> Vec<Vec<String>> {
+        let mut roots: Vec<(String, Arc<AliasNamespace>)> = self
+            .aliases
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        roots.sort_by(|a, b| a.0.cmp(&b.0));
+        roots
+            .into_iter()
+            .flat_map(|(name, ns)| ns.list_namespaces(vec![name]))
+            .collect()
+    }
+
+    /// Reads the node's work counters. Each counter is read on its own, so a snapshot taken
+    /// while rules are running may mix slightly different moments.
+    pub fn metrics(&self) -> NodeMetrics {
+        NodeMetrics {
+            rules_processed: self.counters.rules_processed.load(Ordering::Relaxed),
+            actions_fired: self.counters.actions_fired.load(Ordering::Relaxed),
+            conditions_evaluated: self.counters.conditions_evaluated.load(Ordering::Relaxed),
+            conflicts_detected: self.counters.conflicts_detected.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Lists the latest times a reactive rule ran its action or else action, oldest first. At
+    /// most `NodeConfig::firing_log_capacity` firings are kept.
+    pub fn firing_log(&self) -> Vec<FiringRecord> {
+        let firings = self.firings.lock().unwrap_or_else(|e| e.into_inner());
+        firings.iter().cloned().collect()
+    }
+
+    /// Adds a firing of `rule` to the firing log, dropping the oldest one when it is full.
+    pub(crate) fn record_firing(&self, rule: &ReactiveRule, alias: Option<&Vec<String>>) {
+        let capacity = self.config.firing_log_capacity;
+        if capacity == 0 {
+            return;
+        }
+        let record = FiringRecord {
+            rule: rule.clone(),
+            alias: alias.cloned(),
+            timestamp: chrono::Utc::now().timestamp_millis(),
+        };
+        let mut firings = self.firings.lock().unwrap_or_else(|e| e.into_inner());
+        if firings.len() >= capacity {
+            firings.pop_front();
+        }
+        firings.push_back(record);
+    }
+
+    /// How many reactive rules the node's handlers hold, disabled ones included.
+    pub fn rule_count(&self) -> usize {
+        self.rule_count.load(Ordering::Relaxed)
+    }
+
+    /// Subscribes to variable changes. Each event carries the variable and its new value;
+    /// writes that leave the value unchanged are not announced. A counter is announced whenever
+    /// its count changes, even if its status stays the same.
+    pub fn subscribe_vars(&self) -> broadcast::Receiver<(PrimitiveCondition, ActivationStatus)> {
+        self.var_tx.subscribe()
+    }
+
+    /// Returns an event declared external that the action or else action of `rule` would
+    /// produce, if any.
+    fn produced_external_event(&self, rule: &ReactiveRule) -> Option<PrimitiveEvent> {
+        if self.config.external_events.is_empty() {
+            return None;
+        }
+        let (action, else_action) = match rule {
+            ReactiveRule::ECA {
+                action, else_action, ..
+            }
+            | ReactiveRule::CA {
+                action, else_action, ..
+            } => (action, else_action),
+        };
+        let mut found = None;
+        for action in std::iter::once(action).chain(else_action) {
+            action.visit(&mut |node| {
+                if let Some(event) = node.downcast_ref::<PrimitiveEvent>()
+                    && found.is_none()
+                    && self.config.external_events.contains(event)
+                {
+                    found = Some(event.clone());
+                }
+            });
+        }
+        found
+    }
+
     /// Processes a rule with arguments, handling reactive rules, case rules, and fact rules.
-    #[instrument(skip(self, rule_with_args))]
+    #[instrument(skip(self, rule_with_args, depth), fields(node_id = %self.id))]
     #[async_recursion]
     async fn process_rule(
         self: Arc<Self>,
         rule_with_args: RuleWithArgs,
+        depth: usize,
     ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        // Spanned rules are counted once, when their inner rule is processed
+        if !matches!(rule_with_args, RuleWithArgs::Spanned { .. }) {
+            self.counters.rules_processed.fetch_add(1, Ordering::Relaxed);
+        }
+
         // Log the rule being processed
         let rule_desc = Rule::from(rule_with_args.clone()).to_string();
         debug!("Processing rule: {}", rule_desc);
@@ -871,16 +2140,32 @@ impl Node {
         let result: Result<bool, Box<dyn std::error::Error + Send + Sync>> = match &rule_with_args {
             // Reactive rules: check if the handler already exists, create it if not, or add the rule to the existing handler
             RuleWithArgs::Reactive(reactive_rule) => {
+                if let Some(event) = self.produced_external_event(&reactive_rule.rule) {
+                    return Err(Box::new(NodeError::ExternalEvent {
+                        event: event.to_string(),
+                    }));
+                }
+
                 // Get the rule's identifier
                 let handler_id = reactive_rule.rule.get_identifier();
+                let handler = self.event_handlers.get(&handler_id).map(|h| h.value().clone());
+
+                // Only a rule the handlers don't hold yet counts against the limit
+                if let Some(max) = self.config.max_rules
+                    && !handler.as_ref().is_some_and(|h| h.has_rule(reactive_rule))
+                    && self.rule_count() >= max
+                {
+                    return Err(Box::new(NodeError::TooManyRules(max)));
+                }
 
                 // Check if the handler already exists
-                match self.event_handlers.get(&handler_id) {
+                match handler {
                     // If the handler does not exist, create a new one
                     None => {
                         debug!("Creating new handler: {}", handler_id);
                         let new_handler =
                             Arc::new(EventHandler::new(self.clone(), reactive_rule.clone()));
+                        self.revive_handler(&handler_id);
                         self.event_handlers.insert(handler_id.clone(), new_handler);
                         info!("Created new handler for rule: {}", handler_id.clone());
                         debug!("Current handlers size: {:?}", self.event_handlers.len());
@@ -889,44 +2174,186 @@ impl Node {
                     // If the handler exists, add the rule to it
                     Some(handler) => {
                         debug!("Adding rule to existing handler: {}", handler_id);
-                        let handler = handler.clone();
                         handler.add_rule(reactive_rule.clone()).await
                     }
                 }
             }
             // Case rules: process the action immediately
             RuleWithArgs::Case(CaseRule { action }) => {
-                let res = self.clone().process_action(action.clone()).await;
-                match res {
-                    Ok(val) => {
-                        debug!("Processed case rule with action: {:?}", action);
-                        Ok(val)
-                    }
-                    Err(e) => {
-                        error!("Failed to process action: {}", e);
-                        return Err(Box::<dyn std::error::Error + Send + Sync>::from(e));
-                    }
-                }
+                self.clone()
+                    .process_case_action(action.clone(), ActionContext::default(), depth)
+                    .await
             }
+            // Spanned rules: case actions carry the span along, so errors can point at the rule
+            RuleWithArgs::Spanned { rule, span } => match rule.as_ref() {
+                RuleWithArgs::Case(CaseRule { action }) => {
+                    let ctx = ActionContext {
+                        span: Some(*span),
+                        ..ActionContext::default()
+                    };
+                    self.clone().process_case_action(action.clone(), ctx, depth).await
+                }
+                _ => self.clone().process_rule(*rule.clone(), depth).await,
+            },
             // Fact rules: store the atomic condition with the provided value
             RuleWithArgs::Fact(FactRuleWithArgs { rule, value }) => {
                 match &rule.condition {
                     AtomicCondition::Primitive(_) => {
                         // By default, primitive conditions are set to True
                         let real_val = value.clone().map_or(ActivationStatus::True, |v| v.clone());
-                        self.store_atomic_condition(rule.condition.clone(), real_val, None, true)
+                        self.store_atomic_condition(rule.condition.clone(), real_val, None, true, depth)
                             .await
                     }
                     _ => {
                         // By default, compound and sub-compound rules are set to false
                         let real_val = value.clone().map_or(ActivationStatus::False, |v| v.clone());
-                        self.store_atomic_condition(rule.condition.clone(), real_val, None, true)
+                        self.store_atomic_condition(rule.condition.clone(), real_val, None, true, depth)
                             .await
                     }
                 }
             }
-            _ => Ok(false),
+            // Declarative rules: keep them in force, they are evaluated once the batch is processed
+            RuleWithArgs::Declarative(rule) => {
+                if let DeclarativeRule::CC {
+                    condition: AtomicCondition::Compound(Compound { alias: None, .. }),
+                    ..
+                } = &rule
+                {
+                    return Err(NodeError::UnaliasedImplication(rule.to_string()).into());
+                }
+                self.declarative_rules.insert(rule.clone());
+                Ok(true)
+            }
         };
         result
     }
+
+    /// Runs the action of a case rule as the start of a new chain.
+    async fn process_case_action(
+        self: Arc<Self>,
+        action: Action,
+        ctx: ActionContext,
+        depth: usize,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let res = self.process_action(action.clone(), ctx, depth).await;
+        match res {
+            Ok(val) => {
+                debug!("Processed case rule with action: {:?}", action);
+                Ok(val)
+            }
+            Err(e) => {
+                error!("Failed to process action: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Evaluates the declarative rules in force until nothing changes anymore.
+    ///
+    /// - CC (`premise -> condition`) is a persistent implication: whenever its premise holds,
+    ///   the condition is made true, and the rule stays in force for later changes.
+    /// - CT (`premise -o condition`) is a linear implication: the first time its premise holds,
+    ///   the condition is made true and the rule is consumed.
+    ///
+    /// A missing premise always holds, a premise over unknown variables doesn't hold yet.
+    async fn evaluate_declarative_rules(
+        self: Arc<Self>,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let mut fired_any = false;
+        loop {
+            let mut changed = false;
+            let rules: Vec<DeclarativeRule> =
+                self.declarative_rules.iter().map(|r| r.key().clone()).collect();
+
+            for rule in rules {
+                let premise = match &rule {
+                    DeclarativeRule::CC { premise, .. } | DeclarativeRule::CT { premise, .. } => {
+                        premise
+                    }
+                };
+                let holds = match premise {
+                    None => true,
+                    Some(p) => self.clone().process_condition(p, 0).await.unwrap_or_else(|e| {
+                        debug!("Premise of {} not evaluable yet: {}", rule, e);
+                        false
+                    }),
+                };
+                if !holds {
+                    continue;
+                }
+
+                match &rule {
+                    DeclarativeRule::CC { condition, .. } => {
+                        let current = self
+                            .clone()
+                            .get_atomic_condition(condition.clone(), None, 0)
+                            .await
+                            .unwrap_or(ActivationStatus::Conflict);
+                        if current != ActivationStatus::True {
+                            self.clone()
+                                .store_atomic_condition(
+                                    condition.clone(),
+                                    ActivationStatus::True,
+                                    None,
+                                    true,
+                                    0,
+                                )
+                                .await?;
+                            // Only a condition that now holds is a change, so one that can't be
+                            // read back doesn't keep the loop going
+                            changed |= self
+                                .clone()
+                                .get_atomic_condition(condition.clone(), None, 0)
+                                .await
+                                .is_ok_and(|status| status == ActivationStatus::True);
+                        }
+                    }
+                    DeclarativeRule::CT { condition, .. } => {
+                        if self.declarative_rules.remove(&rule).is_some() {
+                            debug!("Firing linear rule: {}", rule);
+                            self.clone().assert_condition(condition.clone(), true, 0).await?;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+
+            if !changed {
+                return Ok(fired_any);
+            }
+            fired_any = true;
+        }
+    }
+
+    /// Makes a condition hold by storing its atoms: conjunctions assert every part and `not`
+    /// flips the value. Disjunctions are rejected since it's not defined which part to assert.
+    #[async_recursion]
+    async fn assert_condition(
+        self: Arc<Self>,
+        condition: Condition,
+        value: bool,
+        depth: usize,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        self.check_depth(depth)?;
+        match condition {
+            Condition::Atomic(ac) => {
+                let status = if value {
+                    ActivationStatus::True
+                } else {
+                    ActivationStatus::False
+                };
+                self.store_atomic_condition(ac, status, None, true, depth + 1).await
+            }
+            Condition::Not(c) => self.assert_condition(*c, !value, depth + 1).await,
+            Condition::Parentheses(c) => self.assert_condition(*c, value, depth + 1).await,
+            Condition::Conjunction(conds) if value => {
+                let mut r = true;
+                for c in conds {
+                    r &= self.clone().assert_condition(c, value, depth + 1).await?;
+                }
+                Ok(r)
+            }
+            c => Err(NodeError::InvalidAssertion(c.to_string()).into()),
+        }
+    }
 }
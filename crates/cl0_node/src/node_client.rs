@@ -1,21 +1,40 @@
-use tonic::transport::Channel;
-use tonic::Request;
+use std::path::Path;
+use std::sync::Arc;
+
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint};
+use tonic::{Code, Request, Status};
 use tokio::time::{sleep, Duration};
 use chrono::Utc;
 
 use crate::generated::control_plane::{
     control_plane_client::ControlPlaneClient,
-    NodeRegistration, NodeHeartbeat, ControlMessage,
+    NodeRegistration, NodeHeartbeat, NodeStatus, ControlMessage,
 };
-use crate::generated::common::{NodeId, Rule};
+use crate::generated::common::{NodeId, Rule, RuleResult};
+use crate::node::Node;
+
+/// How often a registered node sends a heartbeat.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// First delay before retrying after the control plane couldn't be reached.
+pub const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+/// Upper bound of the retry delay, which doubles after every failed attempt.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(10);
 
 #[derive(Debug)]
 pub struct NodeClient {
     pub client: ControlPlaneClient<Channel>,
+    /// Control plane address, kept to reconnect after transport errors.
+    pub endpoint: String,
+    /// TLS settings of the connection, `None` for plaintext.
+    pub tls: Option<ClientTlsConfig>,
     pub node_id: Option<NodeId>,
     pub pool: Option<String>,
     pub hostname: String,
     pub version: String,
+    /// Node whose status is reported with each heartbeat, if any.
+    pub node: Option<Arc<Node>>,
+    /// Bearer token sent with every request, for control planes that require one.
+    pub auth_token: Option<String>,
 }
 
 impl NodeClient {
@@ -23,31 +42,90 @@ impl NodeClient {
         let channel = Channel::from_shared(endpoint.to_string())?
             .connect()
             .await?;
+        Ok(NodeClient::with_channel(endpoint, version, channel))
+    }
+
+    /// Like [`NodeClient::new`], but over TLS, trusting the control plane's certificate if
+    /// it is signed by the PEM encoded CA certificate at `ca_cert`.
+    pub async fn new_tls(
+        endpoint: &str,
+        version: &str,
+        ca_cert: &Path,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let ca_cert = Certificate::from_pem(std::fs::read(ca_cert)?);
+        let tls = ClientTlsConfig::new().ca_certificate(ca_cert);
+        let channel = Endpoint::from_shared(endpoint.to_string())?
+            .tls_config(tls.clone())?
+            .connect()
+            .await?;
+        let mut client = NodeClient::with_channel(endpoint, version, channel);
+        client.tls = Some(tls);
+        Ok(client)
+    }
 
+    /// Like [`NodeClient::new`], but doesn't connect until the first request, so the control
+    /// plane doesn't need to be up yet. Meant to be driven by [`NodeClient::run`].
+    pub fn new_lazy(endpoint: &str, version: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let channel = Channel::from_shared(endpoint.to_string())?.connect_lazy();
+        Ok(NodeClient::with_channel(endpoint, version, channel))
+    }
+
+    fn with_channel(endpoint: &str, version: &str, channel: Channel) -> Self {
         let hostname = gethostname::gethostname()
             .to_str()
             .unwrap_or("unknown")
             .to_string();
 
-        Ok(NodeClient {
+        NodeClient {
             client: ControlPlaneClient::new(channel),
+            endpoint: endpoint.to_string(),
+            tls: None,
             node_id: None,
             pool: None,
             hostname,
             version: version.to_string(),
-        })
+            node: None,
+            auth_token: None,
+        }
     }
     pub async fn new_with_pool(endpoint: &str, version: &str, pool: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let node = NodeClient::new(endpoint, version).await?;
         Ok(NodeClient {
             client: node.client,
+            endpoint: node.endpoint,
+            tls: node.tls,
             node_id: node.node_id,
             pool: Some(pool.to_string()),
             hostname: node.hostname,
             version: node.version,
+            node: node.node,
+            auth_token: node.auth_token,
         })
     }
 
+    /// Reports the status of `node` (rule and variable counts) with every heartbeat.
+    pub fn with_node(mut self, node: Arc<Node>) -> Self {
+        self.node = Some(node);
+        self
+    }
+
+    /// Sends `authorization: Bearer <token>` with every request to the control plane.
+    pub fn with_auth_token(mut self, token: impl Into<String>) -> Self {
+        self.auth_token = Some(token.into());
+        self
+    }
+
+    /// Wraps `message` in a request, carrying the auth token if there is one.
+    fn request<T>(&self, message: T) -> Result<Request<T>, Box<dyn std::error::Error>> {
+        let mut request = Request::new(message);
+        if let Some(token) = &self.auth_token {
+            request
+                .metadata_mut()
+                .insert("authorization", format!("Bearer {}", token).parse()?);
+        }
+        Ok(request)
+    }
+
     pub async fn register(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         let registration = NodeRegistration {
             hostname: self.hostname.clone(),
@@ -55,28 +133,101 @@ impl NodeClient {
             pool: self.pool.clone(),
         };
 
-        let response = self.client.register_node(Request::new(registration)).await?;
+        let request = self.request(registration)?;
+        let response = self.client.register_node(request).await?;
         self.node_id = response.into_inner().id;
 
         println!("✅ Registered as node {:?}", self.node_id);
         Ok(())
     }
 
+    /// Sends a single heartbeat, re-registering if the control plane no longer knows this node.
+    pub async fn send_heartbeat(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(ref node_id) = self.node_id else {
+            return Ok(());
+        };
+
+        let status = match &self.node {
+            Some(node) => {
+                let snapshot = node.observe().await;
+                Some(NodeStatus {
+                    rule_count: snapshot.rules.len() as u32,
+                    var_count: snapshot.vars.len() as u32,
+                })
+            }
+            None => None,
+        };
+        let heartbeat = NodeHeartbeat {
+            id: Some(node_id.clone()),
+            timestamp: Utc::now().timestamp(),
+            status,
+        };
+
+        let request = self.request(heartbeat)?;
+        let ack = self.client.heartbeat(request).await?;
+        if ack.get_ref().should_reregister {
+            println!("🔁 Control plane does not know this node, re-registering");
+            self.register().await?;
+        } else {
+            println!("❤️ Sent heartbeat");
+        }
+        Ok(())
+    }
+
     pub async fn send_heartbeat_loop(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         loop {
-            if let Some(ref node_id) = self.node_id {
-                let heartbeat = NodeHeartbeat {
-                    id: Some(node_id.clone()),
-                    timestamp: Utc::now().timestamp(),
-                };
-
-                match self.client.heartbeat(Request::new(heartbeat)).await {
-                    Ok(_) => println!("❤️ Sent heartbeat"),
-                    Err(e) => eprintln!("💥 Failed to send heartbeat: {:?}", e),
-                }
+            if let Err(e) = self.send_heartbeat().await {
+                eprintln!("💥 Failed to send heartbeat: {:?}", e);
             }
 
-            sleep(Duration::from_secs(5)).await;
+            sleep(HEARTBEAT_INTERVAL).await;
+        }
+    }
+
+    /// Replaces the channel to the control plane with a freshly connected one.
+    pub async fn reconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut endpoint = Channel::from_shared(self.endpoint.clone())?;
+        if let Some(tls) = &self.tls {
+            endpoint = endpoint.tls_config(tls.clone())?;
+        }
+        let channel = endpoint.connect().await?;
+        self.client = ControlPlaneClient::new(channel);
+        Ok(())
+    }
+
+    /// Keeps the node known to the control plane: registers, then sends heartbeats forever.
+    /// Whenever the control plane can't be reached, the client reconnects and tries again
+    /// after a delay that doubles from [`INITIAL_BACKOFF`] up to [`MAX_BACKOFF`]. Only returns
+    /// on an error reconnecting won't fix, such as a rejected token.
+    pub async fn run(mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut connected = true;
+        loop {
+            let result = if !connected {
+                self.reconnect().await
+            } else if self.node_id.is_none() {
+                self.register().await
+            } else {
+                self.send_heartbeat().await
+            };
+
+            match result.map_err(retryable) {
+                Ok(()) if !connected => connected = true,
+                Ok(()) => {
+                    backoff = INITIAL_BACKOFF;
+                    sleep(HEARTBEAT_INTERVAL).await;
+                }
+                Err(Ok(e)) => {
+                    eprintln!("💥 Control plane unreachable, retrying in {:?}: {}", backoff, e);
+                    connected = false;
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                Err(Err(e)) => {
+                    eprintln!("💥 Control plane refused the node: {}", e);
+                    return Err(e);
+                }
+            }
         }
     }
 
@@ -90,7 +241,8 @@ impl NodeClient {
                 message: message.to_string(),
             };
 
-            self.client.forward_message(Request::new(msg)).await?;
+            let request = self.request(msg)?;
+            self.client.forward_message(request).await?;
             println!("📨 Message forwarded");
         }
 
@@ -101,7 +253,7 @@ impl NodeClient {
         &mut self,
         target_node: NodeId,
         rule: Rule,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    ) -> Result<RuleResult, Box<dyn std::error::Error>> {
         use crate::generated::control_plane::RuleExecutionRequest;
 
         let req = RuleExecutionRequest {
@@ -109,9 +261,23 @@ impl NodeClient {
             rule: Some(rule),
         };
 
-        let res = self.client.request_rule_execution(Request::new(req)).await?;
-        println!("✅ Rule result: {:?}", res.into_inner());
+        let request = self.request(req)?;
+        let res = self.client.request_rule_execution(request).await?.into_inner();
+        println!("✅ Rule result: {:?}", res);
 
-        Ok(())
+        Ok(res)
+    }
+}
+
+/// Sorts out an error of [`NodeClient::run`]: `Ok` with its message when the control plane
+/// couldn't be reached, so reconnecting may help, and `Err` with the error otherwise.
+fn retryable(e: Box<dyn std::error::Error>) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    if e.is::<tonic::transport::Error>() {
+        return Ok(e.to_string());
+    }
+    match e.downcast::<Status>() {
+        Ok(status) if status.code() == Code::Unavailable => Ok(status.to_string()),
+        Ok(status) => Err(status),
+        Err(e) => Err(e.to_string().into()),
     }
 }
@@ -0,0 +1,96 @@
+//! Preprocessing of policy directives: composition out of named fragments with `@include` and
+//! `@exclude`, and replication with `@scale`.
+
+use std::collections::HashMap;
+
+use cl0_parser::ast::{AtomicCondition, Directive, FactRule, Rule};
+
+use crate::types::UnknownFragment;
+
+/// Expands `@include(name)` and `@exclude(name)` directives against a set of named fragments,
+/// before the resulting rules are given to a node.
+///
+/// Resolution order:
+/// 1. Every `@include(name)` inlines the rules of fragment `name` after the program's own
+///    rules, in the order of the directives. A fragment included twice is inlined once.
+/// 2. Every `@exclude(name)` then removes each rule equal to a rule of fragment `name`, whether
+///    it came from the program or from an included fragment.
+///
+/// Other directives are left to the node, see `NodeConfig::with_directives`.
+#[derive(Debug, Clone, Default)]
+pub struct PolicyLoader {
+    fragments: HashMap<String, Vec<Rule>>,
+}
+
+impl PolicyLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the rules of a fragment under `name`, replacing an earlier fragment of that name.
+    pub fn add_fragment(&mut self, name: impl Into<String>, rules: Vec<Rule>) -> &mut Self {
+        self.fragments.insert(name.into(), rules);
+        self
+    }
+
+    /// Resolves the `@include` and `@exclude` directives of a program into the rules to load.
+    /// Fails when a directive names a fragment that was never registered.
+    pub fn load(
+        &self,
+        directives: &[Directive],
+        mut rules: Vec<Rule>,
+    ) -> Result<Vec<Rule>, UnknownFragment> {
+        let mut included: Vec<&str> = Vec::new();
+        let mut excluded: Vec<&Rule> = Vec::new();
+
+        for directive in directives {
+            if let Directive::Include(name) = directive {
+                let fragment = self.fragment(name)?;
+                if !included.contains(&name.as_str()) {
+                    included.push(name);
+                    rules.extend(fragment.iter().cloned());
+                }
+            }
+        }
+        for directive in directives {
+            if let Directive::Exclude(name) = directive {
+                excluded.extend(self.fragment(name)?);
+            }
+        }
+
+        rules.retain(|rule| !excluded.contains(&rule));
+        Ok(rules)
+    }
+
+    fn fragment(&self, name: &str) -> Result<&Vec<Rule>, UnknownFragment> {
+        self.fragments.get(name).ok_or_else(|| UnknownFragment {
+            name: name.to_string(),
+        })
+    }
+}
+
+/// Namespace prefix used by `expand_scale`.
+pub const DEFAULT_REPLICA_PREFIX: &str = "replica";
+
+/// Expands `@scale(n) { ... }` into `n` copies of the compound, under the namespaces
+/// `replica_0` to `replica_{n-1}`. Any other directive expands to nothing.
+pub fn expand_scale(directive: &Directive) -> Vec<Rule> {
+    expand_scale_with_prefix(directive, DEFAULT_REPLICA_PREFIX)
+}
+
+/// Like `expand_scale`, with the namespaces named `{prefix}_0` to `{prefix}_{n-1}`.
+pub fn expand_scale_with_prefix(directive: &Directive, prefix: &str) -> Vec<Rule> {
+    let Directive::Scale { number, policy } = directive else {
+        return Vec::new();
+    };
+    (0..*number)
+        .map(|i| {
+            Rule::Fact(FactRule {
+                condition: AtomicCondition::SubCompound {
+                    namespace: format!("{}_{}", prefix, i),
+                    condition: Box::new(AtomicCondition::Compound(policy.clone())),
+                },
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,384 @@
+//! Helpers for the interactive node REPL (`cl0_node_repl`).
+
+use std::error::Error;
+use std::io::{self, IsTerminal, Write};
+
+use cl0_parser::ast::quote_name;
+use cl0_parser::format::format_program;
+
+use crate::node::Node;
+use crate::types::{ActivationStatus, AliasSnapshot, NodeSnapshot};
+
+/// Collects REPL input lines until they form complete rules.
+///
+/// Input is complete once every `{` is closed and the last line ends with the `.` of a rule,
+/// so a rule can be spread over several lines. An empty line hands over whatever was typed so
+/// far, letting the parser report what is missing.
+#[derive(Debug, Default)]
+pub struct InputBuffer {
+    pending: String,
+    bracket_depth: i64,
+}
+
+impl InputBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether no partial input is waiting for more lines.
+    pub fn is_empty(&self) -> bool {
+        self.pending.trim().is_empty()
+    }
+
+    /// Adds a line of input. Returns the buffered source once it is complete, emptying the buffer.
+    pub fn push_line(&mut self, line: &str) -> Option<String> {
+        if line.trim().is_empty() {
+            return (!self.is_empty()).then(|| self.take());
+        }
+
+        self.bracket_depth += line.chars().filter(|&c| c == '{').count() as i64;
+        self.bracket_depth -= line.chars().filter(|&c| c == '}').count() as i64;
+        if !self.pending.is_empty() {
+            self.pending.push('\n');
+        }
+        self.pending.push_str(line.trim_end());
+
+        (self.bracket_depth <= 0 && self.pending.ends_with('.')).then(|| self.take())
+    }
+
+    /// Drops the buffered input.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.bracket_depth = 0;
+    }
+
+    fn take(&mut self) -> String {
+        let src = std::mem::take(&mut self.pending);
+        self.clear();
+        src
+    }
+}
+
+/// ANSI styling for REPL output. When disabled every style is the empty string, so output sent
+/// to a file or pipe carries no escape codes.
+#[derive(Debug, Clone, Copy)]
+pub struct Style {
+    enabled: bool,
+}
+
+impl Style {
+    pub fn new(enabled: bool) -> Self {
+        Style { enabled }
+    }
+
+    /// Colors only when stdout is a terminal and the user didn't ask for `--no-color`.
+    pub fn detect(no_color: bool) -> Self {
+        Style::new(!no_color && std::io::stdout().is_terminal())
+    }
+
+    fn code(&self, code: &'static str) -> &'static str {
+        if self.enabled { code } else { "" }
+    }
+
+    pub fn reset(&self) -> &'static str {
+        self.code("\x1b[0m")
+    }
+
+    pub fn bold(&self) -> &'static str {
+        self.code("\x1b[1m")
+    }
+
+    pub fn blue(&self) -> &'static str {
+        self.code("\x1b[34m")
+    }
+
+    pub fn cyan(&self) -> &'static str {
+        self.code("\x1b[36m")
+    }
+
+    pub fn green(&self) -> &'static str {
+        self.code("\x1b[32m")
+    }
+
+    pub fn yellow(&self) -> &'static str {
+        self.code("\x1b[33m")
+    }
+
+    pub fn magenta(&self) -> &'static str {
+        self.code("\x1b[35m")
+    }
+
+    pub fn red(&self) -> &'static str {
+        self.code("\x1b[31m")
+    }
+
+    /// Green for `True`, red otherwise.
+    fn status(&self, status: &ActivationStatus) -> &'static str {
+        if *status == ActivationStatus::True {
+            self.green()
+        } else {
+            self.red()
+        }
+    }
+}
+
+/// Writes the welcome banner with the syntax guide.
+pub fn write_banner(out: &mut impl Write, style: Style) -> io::Result<()> {
+    writeln!(out, "{}{}Welcome to \"CL0 Node REPL\"!{}", style.bold(), style.cyan(), style.reset())?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{}This REPL resembles the engine behind a single node in the system.{}",
+        style.blue(), style.reset()
+    )?;
+    writeln!(out)?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{}Enter initial rules (optionally) by wrapping {{ ... }}{}",
+        style.blue(), style.reset()
+    )?;
+    writeln!(out)?;
+    writeln!(out, "{}For example:{}", style.green(), style.reset())?;
+    writeln!(out, "{}{{{}", style.yellow(), style.reset())?;
+    writeln!(out, "  {}#e: c => +a.{}", style.magenta(), style.reset())?;
+    writeln!(out, "  {}=> #e.{}", style.magenta(), style.reset())?;
+    writeln!(out, "  {}{{ #f => +v }} as alias.{}", style.magenta(), style.reset())?;
+    writeln!(out, "{}}}{}", style.yellow(), style.reset())?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{}Use {}{{}}{} for empty policy initialization.{}",
+        style.blue(), style.yellow(), style.blue(), style.reset()
+    )?;
+    writeln!(
+        out,
+        "{}Directives such as {}@interleaving{} go on their own lines before the policy.{}",
+        style.blue(), style.yellow(), style.blue(), style.reset()
+    )?;
+    writeln!(out)?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{}After initialization, run rules one by one. A rule may span several lines, it ends at its '.'.{}",
+        style.blue(), style.reset()
+    )?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{}Use the 'observe' command to view state, 'tree' to list the alias namespaces, and 'handlers' to list the event handlers.{}\n",
+        style.blue(), style.reset()
+    )?;
+    writeln!(
+        out,
+        "{}Use the 'reset' command to clear the node's state.{}\n",
+        style.blue(), style.reset()
+    )?;
+    writeln!(
+        out,
+        "{}Use 'save <path>' to write the rules to a file, and 'load <path>' to apply a file.{}\n",
+        style.blue(), style.reset()
+    )?;
+    writeln!(
+        out,
+        "{}Use 'history' to list the rule batches applied so far, and 'undo' to revert the last one.{}\n",
+        style.blue(), style.reset()
+    )?;
+    writeln!(
+        out,
+        "{}Use 'query <condition>' to evaluate a condition, e.g. {}query loaded and not busy{}.{}\n",
+        style.blue(), style.yellow(), style.blue(), style.reset()
+    )?;
+    writeln!(out)?;
+
+    // Basic syntax guide
+    writeln!(out, "{}{}Basic Syntax:{}\n", style.bold(), style.cyan(), style.reset())?;
+    writeln!(out, "{}Events:{}", style.bold(), style.reset())?;
+    writeln!(out, "  {}#event{}        Trigger event", style.green(), style.reset())?;
+    writeln!(out, "  {}+condition{}    Production event", style.green(), style.reset())?;
+    writeln!(out, "  {}-condition{}    Consumption event\n", style.green(), style.reset())?;
+    writeln!(out, "{}Conditions:{}", style.bold(), style.reset())?;
+    writeln!(out, "  {}foo{}           Atomic condition", style.yellow(), style.reset())?;
+    writeln!(out, "  {}not foo{}       Negation", style.yellow(), style.reset())?;
+    writeln!(out, "  {}( foo ){}       Priority", style.yellow(), style.reset())?;
+    writeln!(out, "  {}a and b{}       Conjunction", style.yellow(), style.reset())?;
+    writeln!(out, "  {}a or b{}        Disjunction", style.yellow(), style.reset())?;
+    writeln!(out, "  {}{{ ... }} as r{}  Compound alias", style.yellow(), style.reset())?;
+    writeln!(out, "\n{}Actions:{}", style.bold(), style.reset())?;
+    writeln!(out, "  {}#e{}            Trigger event", style.green(), style.reset())?;
+    writeln!(out, "  {}+v{}            Production action", style.green(), style.reset())?;
+    writeln!(out, "  {}-v{}            Consumption action", style.green(), style.reset())?;
+    writeln!(out, "  {}a, b{}          Parallel actions", style.green(), style.reset())?;
+    writeln!(out, "  {}a; b{}          Sequential actions", style.green(), style.reset())?;
+    writeln!(out, "  {}a alt b{}       Alternative actions\n", style.green(), style.reset())?;
+
+    Ok(())
+}
+
+/// Writes alias namespace paths, as returned by `Node::alias_tree`, one per line and indented by
+/// depth, as shown by the `tree` command.
+pub fn write_alias_tree(out: &mut impl Write, paths: &[Vec<String>], style: Style) -> io::Result<()> {
+    writeln!(out, "{}Aliases:{}", style.yellow(), style.reset())?;
+    if paths.is_empty() {
+        writeln!(out, "{}    No aliases defined.{}", style.yellow(), style.reset())?;
+    }
+    for path in paths {
+        let indent = "  ".repeat(path.len().saturating_sub(1));
+        let name = path.last().map(String::as_str).unwrap_or_default();
+        writeln!(out, "    {}{}{}{}", indent, style.blue(), name, style.reset())?;
+    }
+    Ok(())
+}
+
+/// Collects the identifier and aggregated state of each event handler of `node`, see
+/// `Node::handler_ids`. Handlers dropped while collecting are left out.
+pub async fn handler_states(node: &Node) -> Vec<(String, Option<ActivationStatus>)> {
+    let mut states = Vec::new();
+    for id in node.handler_ids() {
+        let handler = node.event_handlers.get(&id).map(|h| h.value().clone());
+        if let Some(handler) = handler {
+            states.push((id, handler.state().await));
+        }
+    }
+    states
+}
+
+/// Writes event handler identifiers with their state, as returned by `handler_states`, as shown
+/// by the `handlers` command.
+pub fn write_handlers(
+    out: &mut impl Write,
+    handlers: &[(String, Option<ActivationStatus>)],
+    style: Style,
+) -> io::Result<()> {
+    writeln!(out, "{}Handlers:{}", style.yellow(), style.reset())?;
+    if handlers.is_empty() {
+        writeln!(out, "{}    No handlers defined.{}", style.yellow(), style.reset())?;
+    }
+    for (id, state) in handlers {
+        match state {
+            Some(state) => writeln!(out, "{}    {}{}: {}{}{}", style.blue(), id, style.reset(), style.status(state), state, style.reset())?,
+            None => writeln!(out, "{}    {}{}: {}empty{}", style.blue(), id, style.reset(), style.yellow(), style.reset())?,
+        }
+    }
+    Ok(())
+}
+
+/// Writes the rules and variables of a node snapshot, as shown by the `observe` command.
+pub fn write_snapshot(out: &mut impl Write, snapshot: NodeSnapshot, style: Style) -> io::Result<()> {
+    writeln!(out, "{}Current state:{}", style.blue(), style.reset())?;
+    writeln!(out, "{}Rules:{}", style.yellow(), style.reset())?;
+    writeln!(out, "==========================")?;
+    // Print the rules
+    if snapshot.rules.is_empty() {
+        writeln!(out, "{}    No rules defined.{}", style.yellow(), style.reset())?;
+    } else {
+        for rule in snapshot.rules {
+            let namespace_string = match rule.alias {
+                Some(ns) => ns.join(".") + ".",
+                None => "".to_string(),
+            };
+            writeln!(out, "{}    {}{}{}{}: {}{}{}", style.blue(), namespace_string, style.reset(), rule.rule.trim_end_matches("."), style.yellow(), style.status(&rule.status), rule.status, style.reset())?;
+        }
+    }
+    writeln!(out, "==========================")?;
+    writeln!(out, "{}Variables:{}", style.yellow(), style.reset())?;
+    writeln!(out, "==========================")?;
+
+    // Print the variables
+    for var in snapshot.vars {
+        writeln!(out, "{}    {}: {}{}{}", style.blue(), var.name, style.status(&var.status), var.status, style.reset())?;
+    }
+    writeln!(out, "==========================")?;
+    Ok(())
+}
+
+/// Writes the node's policy out as canonical CL0 source, for the `save` command. Adding the
+/// source to an empty node as one batch gives it the same rules, aliases and variables:
+///
+/// 1. the status of each variable: `x.` when true, `=> -x.` when false and `=> +x, -x.` when
+///    conflicting, before any rule so the consumptions don't prune handlers (see
+///    `Node::gc_handlers`),
+/// 2. the enabled rules outside any alias, and the declarative rules,
+/// 3. every alias namespace as `{ ... } as name.`, nested ones as `parent.{ ... } as name.`,
+/// 4. `=> +name.` for each alias whose rules are enabled.
+pub async fn policy_source(node: &Node) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let snapshot = node.observe().await;
+    let mut src = String::new();
+
+    // The names are already quoted where needed. `-` is kept apart from them, `-off` would lex
+    // as `-o` followed by `ff`. Aliases are left to their definitions, consuming one would
+    // retract it
+    let aliases: Vec<String> = snapshot.aliases.iter().map(|alias| quote_name(&alias.name)).collect();
+    for var in snapshot.vars.iter() {
+        let name = &var.name;
+        if aliases.contains(name) {
+            continue;
+        }
+        match var.status {
+            ActivationStatus::True => src.push_str(&format!("{}.\n", name)),
+            ActivationStatus::False => src.push_str(&format!("=> - {}.\n", name)),
+            ActivationStatus::Conflict => src.push_str(&format!("=> +{}, - {}.\n", name, name)),
+        }
+    }
+
+    for rule in snapshot.rules.iter() {
+        if rule.alias.is_none() && rule.status == ActivationStatus::True {
+            src.push_str(&format!("{}\n", rule.rule));
+        }
+    }
+    let mut declarative: Vec<String> = node
+        .declarative_rules
+        .iter()
+        .map(|rule| rule.to_string())
+        .collect();
+    declarative.sort();
+    for rule in declarative {
+        src.push_str(&format!("{}\n", rule));
+    }
+
+    for alias in snapshot.aliases.iter() {
+        push_alias(&mut src, &[], alias);
+    }
+
+    let mut enabled: Vec<String> = Vec::new();
+    for rule in snapshot.rules.iter() {
+        if let Some(path) = &rule.alias
+            && rule.status == ActivationStatus::True
+        {
+            let name: Vec<String> = path.iter().map(|part| quote_name(part)).collect();
+            let name = name.join(".");
+            if !enabled.contains(&name) {
+                enabled.push(name);
+            }
+        }
+    }
+    for name in enabled {
+        src.push_str(&format!("=> +{}.\n", name));
+    }
+
+    format_program(&src).map_err(|diagnostics| {
+        let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+        Box::<dyn Error + Send + Sync>::from(format!(
+            "Saved policy doesn't parse: {}",
+            messages.join("; ")
+        ))
+    })
+}
+
+/// Appends the definition of an alias namespace under `parents`, then its sub-namespaces.
+fn push_alias(src: &mut String, parents: &[String], alias: &AliasSnapshot) {
+    if !alias.rules.is_empty() {
+        let prefix: String = parents.iter().map(|parent| format!("{}.", quote_name(parent))).collect();
+        src.push_str(&format!(
+            "{}{{ {} }} as {}.\n",
+            prefix,
+            alias.rules.join(" "),
+            quote_name(&alias.name)
+        ));
+    }
+    let mut path = parents.to_vec();
+    path.push(alias.name.clone());
+    for child in alias.children.iter() {
+        push_alias(src, &path, child);
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use tonic::{
+    codegen::http,
+    server::NamedService,
+    transport::server::{TcpConnectInfo, TlsConnectInfo},
+};
+use tower::{Layer, Service};
+use tracing::Level;
+
+/// Logs the method, peer address and latency of every call handled by the wrapped service.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestLogLayer {
+    level: Level,
+}
+
+impl RequestLogLayer {
+    /// Logs each call at `level`.
+    pub fn new(level: Level) -> Self {
+        RequestLogLayer { level }
+    }
+}
+
+impl Default for RequestLogLayer {
+    fn default() -> Self {
+        RequestLogLayer::new(Level::INFO)
+    }
+}
+
+impl<S> Layer<S> for RequestLogLayer {
+    type Service = RequestLog<S>;
+
+    fn layer(&self, inner: S) -> RequestLog<S> {
+        RequestLog {
+            inner,
+            level: self.level,
+        }
+    }
+}
+
+/// Service built by [`RequestLogLayer`].
+#[derive(Debug, Clone)]
+pub struct RequestLog<S> {
+    inner: S,
+    level: Level,
+}
+
+// Keeps the wrapped gRPC service routable by name
+impl<S: NamedService> NamedService for RequestLog<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S, B, R> Service<http::Request<B>> for RequestLog<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<R>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let method = request.uri().path().to_string();
+        let peer = peer_addr(&request).map_or_else(|| "unknown".to_string(), |a| a.to_string());
+        let level = self.level;
+        let start = Instant::now();
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = response.await;
+            let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+            // Failed calls usually answer with HTTP 200 and a `grpc-status` header instead
+            let grpc_status = response
+                .as_ref()
+                .ok()
+                .and_then(|r| r.headers().get("grpc-status"))
+                .and_then(|s| s.to_str().ok())
+                .unwrap_or("0")
+                .to_string();
+            match level {
+                Level::ERROR => tracing::error!(%method, %peer, %grpc_status, latency_ms, "rpc"),
+                Level::WARN => tracing::warn!(%method, %peer, %grpc_status, latency_ms, "rpc"),
+                Level::INFO => tracing::info!(%method, %peer, %grpc_status, latency_ms, "rpc"),
+                Level::DEBUG => tracing::debug!(%method, %peer, %grpc_status, latency_ms, "rpc"),
+                _ => tracing::trace!(%method, %peer, %grpc_status, latency_ms, "rpc"),
+            }
+            response
+        })
+    }
+}
+
+/// Remote address of the connection a request came in on, over plain TCP or TLS.
+fn peer_addr<B>(request: &http::Request<B>) -> Option<SocketAddr> {
+    let extensions = request.extensions();
+    extensions
+        .get::<TcpConnectInfo>()
+        .and_then(TcpConnectInfo::remote_addr)
+        .or_else(|| {
+            extensions
+                .get::<TlsConnectInfo<TcpConnectInfo>>()
+                .and_then(|info| info.get_ref().remote_addr())
+        })
+}
@@ -1,16 +1,44 @@
 use dashmap::DashMap;
 use futures::{Stream, StreamExt};
-use std::{net::SocketAddr, pin::Pin, sync::Arc};
-use tokio::sync::{RwLock, broadcast};
-use tokio_stream::wrappers::{BroadcastStream, ReceiverStream};
-use tonic::{Request, Response, Status};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::TcpListener,
+    sync::{RwLock, broadcast},
+    task::JoinHandle,
+};
+use tokio_stream::wrappers::{BroadcastStream, ReceiverStream, TcpListenerStream};
+use tonic::{
+    Request, Response, Status, Streaming,
+    server::NamedService,
+    transport::{Identity, ServerTlsConfig},
+};
 use tonic_web::GrpcWebLayer;
 use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer};
+use tracing::{Level, warn};
+
+use cl0_parser::{ast::Rule, json::parse_rules_json, lex_and_parse_safe};
+
+use crate::{
+    auth::AuthInterceptor, node::Node, request_log::RequestLogLayer, types::RuleWithArgs,
+};
 
 // ===== Generated types =====
 use crate::generated::web::{
     Ack,
+    CreateNodeRequest,
     Empty,
     HistoryChunk,
     Input,
@@ -22,11 +50,14 @@ use crate::generated::web::{
     PresenceEvent,
     PresenceSnapshot,
     PresenceUpdate,
+    RemoveResponse,
+    RuleStatus,
     Scope,
     ServerEvent,
     ServerNotice,
     StatusSnapshot,
     SystemTree,
+    VarStatus,
     // services
     control_plane_service_server::{ControlPlaneService, ControlPlaneServiceServer},
     presence_event,
@@ -42,34 +73,247 @@ use crate::generated::web::{
 // Core in-memory cluster
 // =========================
 
+/// Buffer sizes of the broadcast channels fanning events out to subscribers.
+///
+/// A subscriber that falls more than its channel's capacity behind *lags*: the oldest
+/// events are dropped for it. REPL subscribers then receive a resync `HistoryChunk` with
+/// the latest history and status subscribers the current snapshot, while tree and presence
+/// streams end so the client subscribes again and starts from a fresh snapshot. Raise these
+/// for many clients or bursty scopes, at the cost of memory per channel. A capacity of 0 is
+/// treated as 1, a channel can't be unbuffered.
+#[derive(Debug, Clone)]
+pub struct ChannelCapacities {
+    /// REPL events of each scope (control plane, pool, node).
+    pub scope_events: usize,
+    /// System tree updates.
+    pub tree: usize,
+    /// Status snapshots of each node.
+    pub node_status: usize,
+    /// Presence updates.
+    pub presence: usize,
+}
+
+impl Default for ChannelCapacities {
+    fn default() -> Self {
+        ChannelCapacities {
+            scope_events: 1024,
+            tree: 64,
+            node_status: 64,
+            presence: 256,
+        }
+    }
+}
+
 /// Minimal REPL session for any scope (control plane, pool, node).
 #[derive(Debug)]
 struct ScopeSession {
     history: RwLock<Vec<Output>>,
+    /// Sequence number of the next output, the first one gets 1.
+    next_seq: AtomicU64,
     tx: broadcast::Sender<ServerEvent>,
+    /// Append-only JSON lines log the history is persisted to, if enabled.
+    log: Option<PathBuf>,
 }
 
 impl ScopeSession {
-    fn new() -> Self {
-        let (tx, _rx) = broadcast::channel(1024);
+    fn new(log: Option<PathBuf>, capacity: usize) -> Self {
+        let (tx, _rx) = broadcast::channel(capacity.max(1));
+        let history = log.as_deref().map(load_history).unwrap_or_default();
+        let next_seq = history.last().map_or(1, |o| o.seq + 1);
         Self {
-            history: RwLock::new(Vec::new()),
+            history: RwLock::new(history),
+            next_seq: AtomicU64::new(next_seq),
             tx,
+            log,
+        }
+    }
+
+    /// Add an output to the history under the next sequence number, appending it to the log
+    /// when persistence is enabled.
+    async fn record(&self, out: &mut Output) {
+        {
+            // Numbered under the lock so the history stays ordered by `seq`
+            let mut history = self.history.write().await;
+            out.seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+            history.push(out.clone());
+        }
+
+        if let Some(path) = &self.log
+            && let Err(e) = append_history(path, out).await
+        {
+            warn!("Failed to persist output {} to {}: {}", out.id, path.display(), e);
         }
     }
+
+    /// The history from sequence number `since_seq` on or, when that is 0, from the output
+    /// with id `since_id` on. Everything is returned when neither is set or `since_id` is
+    /// unknown.
+    async fn history_since(&self, since_id: &str, since_seq: u64) -> Vec<Output> {
+        let g = self.history.read().await;
+        let idx = if since_seq > 0 {
+            g.partition_point(|o| o.seq < since_seq)
+        } else {
+            g.iter().position(|o| o.id == since_id).unwrap_or(0)
+        };
+        g[idx..].to_vec()
+    }
+
+    /// The latest history, resent to subscribers that lagged behind.
+    async fn recent_history(&self) -> Vec<Output> {
+        let g = self.history.read().await;
+        // last up to 200 items, for example
+        let take = g.len().saturating_sub(200);
+        g.iter().skip(take).cloned().collect()
+    }
 }
 
-/// Node session = REPL + node status
-#[derive(Debug)]
+// ==========================
+// History persistence
+// ==========================
+
+/// On-disk form of an `Output`, one JSON object per line.
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredOutput {
+    id: String,
+    scope_kind: i32,
+    scope_id: String,
+    user_id: String,
+    stdout: String,
+    unix_ts: i64,
+    /// Missing in logs written before outputs were numbered, see `load_history`.
+    #[serde(default)]
+    seq: u64,
+}
+
+impl From<&Output> for StoredOutput {
+    fn from(out: &Output) -> Self {
+        let scope = out.scope.clone().unwrap_or_default();
+        StoredOutput {
+            id: out.id.clone(),
+            scope_kind: scope.kind,
+            scope_id: scope.id,
+            user_id: out.user_id.clone(),
+            stdout: out.stdout.clone(),
+            unix_ts: out.unix_ts,
+            seq: out.seq,
+        }
+    }
+}
+
+impl From<StoredOutput> for Output {
+    fn from(stored: StoredOutput) -> Self {
+        Output {
+            id: stored.id,
+            scope: Some(Scope {
+                kind: stored.scope_kind,
+                id: stored.scope_id,
+            }),
+            user_id: stored.user_id,
+            stdout: stored.stdout,
+            unix_ts: stored.unix_ts,
+            seq: stored.seq,
+        }
+    }
+}
+
+/// Log file of a scope inside the persistence directory, e.g. `node-node-1.jsonl`.
+fn history_path(dir: &Path, kind: scope::Kind, id: &str) -> PathBuf {
+    let kind = match kind {
+        scope::Kind::ControlPlane => "control_plane",
+        scope::Kind::NodePool => "pool",
+        scope::Kind::Node => "node",
+        scope::Kind::Unspecified => "unspecified",
+    };
+    let id: String = id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    dir.join(format!("{kind}-{id}.jsonl"))
+}
+
+/// Reads a history log. A missing file means no history yet; an unreadable or corrupt one
+/// is logged and ignored so the scope starts empty.
+fn load_history(path: &Path) -> Vec<Output> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            warn!("Failed to read history {}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str::<StoredOutput>(line).map(Output::from))
+        .collect::<Result<Vec<_>, _>>()
+        .map(|mut history| {
+            // Older logs have no sequence numbers, number those outputs by position
+            let mut prev = 0;
+            for out in history.iter_mut() {
+                if out.seq <= prev {
+                    out.seq = prev + 1;
+                }
+                prev = out.seq;
+            }
+            history
+        })
+        .unwrap_or_else(|e| {
+            warn!("Ignoring corrupt history {}: {}", path.display(), e);
+            Vec::new()
+        })
+}
+
+/// Parses the code of a command: a JSON array of rules from structured clients, CL0 source otherwise.
+/// Directives are refused as syntax errors, since a session's node is created with the default
+/// options before any command arrives.
+fn parse_command(code: &str) -> Result<Vec<Rule>, Box<dyn std::error::Error + Send + Sync>> {
+    if code.trim_start().starts_with('[') {
+        Ok(parse_rules_json(code)?)
+    } else {
+        lex_and_parse_safe(code)
+    }
+}
+
+async fn append_history(path: &Path, out: &Output) -> Result<(), Box<dyn std::error::Error>> {
+    let mut line = serde_json::to_string(&StoredOutput::from(out))?;
+    line.push('\n');
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(line.as_bytes()).await?;
+    // tokio finishes file writes in the background; make sure the line is on disk
+    file.flush().await?;
+    Ok(())
+}
+
+/// Node session = REPL + node status (+ watchers) + the node executing the commands
 struct NodeSession {
     repl: Arc<ScopeSession>,
     status: RwLock<StatusSnapshot>,
+    status_tx: broadcast::Sender<StatusSnapshot>,
+    node: Arc<Node>,
+}
+
+impl std::fmt::Debug for NodeSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NodeSession")
+            .field("repl", &self.repl)
+            .field("status", &self.status)
+            .finish_non_exhaustive()
+    }
 }
 
 impl NodeSession {
-    fn new(node_id: &str) -> Self {
+    async fn new(node_id: &str, log: Option<PathBuf>, capacities: &ChannelCapacities) -> Self {
+        let (status_tx, _rx) = broadcast::channel(capacities.node_status.max(1));
         Self {
-            repl: Arc::new(ScopeSession::new()),
+            node: Node::with_id(node_id, None).await,
+            status_tx,
+            repl: Arc::new(ScopeSession::new(log, capacities.scope_events)),
             status: RwLock::new(StatusSnapshot {
                 scope: Some(Scope {
                     kind: scope::Kind::Node as i32,
@@ -80,6 +324,57 @@ impl NodeSession {
             }),
         }
     }
+
+    /// Apply parsed rules to the node and summarize the outcome.
+    async fn execute(&self, rules: Vec<Rule>) -> Result<String, Status> {
+        let count = rules.len();
+        let rules = rules.into_iter().map(RuleWithArgs::from).collect();
+        let result = match self.node.api.new_rules.call(rules).await {
+            Ok(results) => {
+                let applied = results.iter().filter(|ok| **ok).count();
+                if applied == count {
+                    format!("ok: {} rule(s) applied", count)
+                } else {
+                    format!("ok: {} of {} rule(s) applied", applied, count)
+                }
+            }
+            Err(e) => format!("error: {}", e),
+        };
+        self.refresh_status().await?;
+        Ok(result)
+    }
+
+    /// Rebuild the status from the node's rules and vars, notifying watchers if it changed.
+    async fn refresh_status(&self) -> Result<(), Status> {
+        // Held while reading the node, so a slower refresh can't overwrite a newer one
+        let mut status = self.status.write().await;
+        let mut rules: Vec<RuleStatus> = self
+            .node
+            .api
+            .get_rules
+            .call(true)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .into_iter()
+            .map(RuleStatus::from)
+            .collect();
+        rules.sort_by(|a, b| (&a.namespace, &a.name).cmp(&(&b.namespace, &b.name)));
+
+        let mut vars: Vec<VarStatus> = self
+            .node
+            .vars
+            .iter()
+            .map(|v| VarStatus::from((v.key().clone(), v.value().clone())))
+            .collect();
+        vars.sort_by(|a, b| a.name.cmp(&b.name));
+
+        if status.rules != rules || status.vars != vars {
+            status.rules = rules;
+            status.vars = vars;
+            let _ = self.status_tx.send(status.clone());
+        }
+        Ok(())
+    }
 }
 
 /// Pool session = REPL + nodes map
@@ -91,13 +386,40 @@ struct PoolSession {
 }
 
 impl PoolSession {
-    fn new(id: &str, name: &str) -> Self {
+    fn new(id: &str, name: &str, log: Option<PathBuf>, capacity: usize) -> Self {
         Self {
-            repl: Arc::new(ScopeSession::new()),
+            repl: Arc::new(ScopeSession::new(log, capacity)),
             nodes: DashMap::new(),
             name: name.to_string(),
         }
     }
+
+    /// Apply parsed rules to every node currently in the pool, one result line per node.
+    /// Nodes joining the pool afterwards don't receive them.
+    async fn execute(&self, rules: Vec<Rule>) -> Result<String, Status> {
+        let mut members: Vec<(String, Arc<NodeSession>)> = self
+            .nodes
+            .iter()
+            .map(|n| (n.key().clone(), Arc::clone(n.value())))
+            .collect();
+        if members.is_empty() {
+            return Ok("no nodes in pool".to_string());
+        }
+        members.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let results = futures::future::join_all(
+            members
+                .iter()
+                .map(|(_, node)| node.execute(rules.clone())),
+        )
+        .await;
+
+        let mut lines = Vec::with_capacity(members.len());
+        for ((id, _), result) in members.iter().zip(results) {
+            lines.push(format!("{}: {}", id, result?));
+        }
+        Ok(lines.join("\n"))
+    }
 }
 
 /// Entire cluster: control plane + pools (+ node index) + tree broadcast
@@ -110,25 +432,50 @@ struct Cluster {
     nodes_index: DashMap<String, Arc<NodeSession>>,
     // System tree broadcaster
     tree_tx: broadcast::Sender<SystemTree>,
+    // Where scope histories are persisted, if anywhere
+    persist_dir: Option<PathBuf>,
+    capacities: ChannelCapacities,
 }
 
 impl Cluster {
-    fn new(control_plane_id: impl Into<String>) -> Self {
-        let (tree_tx, _rx) = broadcast::channel(64);
+    /// Scope sessions rehydrate their history from `persist_dir` when they are created.
+    fn new(
+        control_plane_id: impl Into<String>,
+        persist_dir: Option<PathBuf>,
+        capacities: ChannelCapacities,
+    ) -> Self {
+        let (tree_tx, _rx) = broadcast::channel(capacities.tree.max(1));
+        let control_plane_id = control_plane_id.into();
+        let control_log = persist_dir
+            .as_deref()
+            .map(|dir| history_path(dir, scope::Kind::ControlPlane, &control_plane_id));
         Self {
-            control_plane_id: control_plane_id.into(),
-            control: Arc::new(ScopeSession::new()),
+            control: Arc::new(ScopeSession::new(control_log, capacities.scope_events)),
+            control_plane_id,
             pools: DashMap::new(),
             nodes_index: DashMap::new(),
             tree_tx,
+            persist_dir,
+            capacities,
         }
     }
 
+    fn history_log(&self, kind: scope::Kind, id: &str) -> Option<PathBuf> {
+        self.persist_dir
+            .as_deref()
+            .map(|dir| history_path(dir, kind, id))
+    }
+
     fn ensure_pool(&self, pool_id: &str) -> Arc<PoolSession> {
         if let Some(p) = self.pools.get(pool_id) {
             return Arc::clone(&*p);
         }
-        let pool = Arc::new(PoolSession::new(pool_id, pool_id));
+        let pool = Arc::new(PoolSession::new(
+            pool_id,
+            pool_id,
+            self.history_log(scope::Kind::NodePool, pool_id),
+            self.capacities.scope_events,
+        ));
         let entry = self
             .pools
             .entry(pool_id.to_string())
@@ -136,26 +483,49 @@ impl Cluster {
         Arc::clone(&*entry)
     }
 
-    fn ensure_node(&self, pool_id: &str, node_id: &str) -> Arc<NodeSession> {
-        if let Some(n) = self.nodes_index.get(node_id) {
-            return Arc::clone(&*n);
+    /// Get or create a node in `pool_id`. Node ids are unique across pools, so a node that
+    /// exists in another pool is moved here, keeping its state.
+    async fn ensure_node(&self, pool_id: &str, node_id: &str) -> Arc<NodeSession> {
+        let existing = self.nodes_index.get(node_id).map(|n| Arc::clone(&*n));
+        let node = match existing {
+            Some(node) => node,
+            None => {
+                let log = self.history_log(scope::Kind::Node, node_id);
+                let created = Arc::new(NodeSession::new(node_id, log, &self.capacities).await);
+                // Another join may have created the node while this one awaited, the first one
+                // stored wins so all subscribers share a single session
+                let stored = self
+                    .nodes_index
+                    .entry(node_id.to_string())
+                    .or_insert(created);
+                Arc::clone(&*stored)
+            }
+        };
+
+        let home = self
+            .pools
+            .iter()
+            .find(|p| p.nodes.contains_key(node_id))
+            .map(|p| p.key().clone());
+        if home.as_deref() != Some(pool_id) {
+            if let Some(old) = home.and_then(|id| self.pools.get(&id).map(|p| Arc::clone(&*p))) {
+                old.nodes.remove(node_id);
+            }
+            self.ensure_pool(pool_id)
+                .nodes
+                .insert(node_id.to_string(), Arc::clone(&node));
+            self.broadcast_tree(); // topology changed
         }
-        let pool = self.ensure_pool(pool_id);
-        let node = Arc::new(NodeSession::new(node_id));
-        pool.nodes.insert(node_id.to_string(), Arc::clone(&node));
-        self.nodes_index
-            .insert(node_id.to_string(), Arc::clone(&node));
-        self.broadcast_tree(); // topology changed
         node
     }
 
     /// If you only know a node_id (global-unique), fetch/create under a default pool.
-    fn ensure_node_global(&self, node_id: &str) -> Arc<NodeSession> {
+    async fn ensure_node_global(&self, node_id: &str) -> Arc<NodeSession> {
         if let Some(n) = self.nodes_index.get(node_id) {
             return Arc::clone(&*n);
         }
         // Default grouping (adjust if you have a real pool mapping)
-        self.ensure_node("default", node_id)
+        self.ensure_node("default", node_id).await
     }
 
     /// Convert current topology to a SystemTree snapshot.
@@ -185,37 +555,67 @@ impl Cluster {
         let _ = self.tree_tx.send(self.system_tree_snapshot());
     }
 
+    /// Remove a node from its pool. Returns `false` if no node has that id.
+    fn remove_node(&self, node_id: &str) -> bool {
+        if self.nodes_index.remove(node_id).is_none() {
+            return false;
+        }
+        for pool in self.pools.iter() {
+            pool.nodes.remove(node_id);
+        }
+        self.broadcast_tree(); // topology changed
+        true
+    }
+
+    /// Remove a pool together with its nodes. Returns `false` if no pool has that id.
+    fn remove_pool(&self, pool_id: &str) -> bool {
+        let Some((_, pool)) = self.pools.remove(pool_id) else {
+            return false;
+        };
+        for node in pool.nodes.iter() {
+            self.nodes_index.remove(node.key());
+        }
+        self.broadcast_tree(); // topology changed
+        true
+    }
+
     /// Get a REPL session for any scope.
-    fn get_scope_session(&self, scope: &Scope) -> Result<Arc<ScopeSession>, Status> {
+    async fn get_scope_session(&self, scope: &Scope) -> Result<Arc<ScopeSession>, Status> {
         match scope::Kind::try_from(scope.kind).unwrap_or(scope::Kind::Unspecified) {
             scope::Kind::ControlPlane => Ok(Arc::clone(&self.control)),
             scope::Kind::NodePool => Ok(self.ensure_pool(&scope.id).repl.clone()),
-            scope::Kind::Node => Ok(self.ensure_node_global(&scope.id).repl.clone()),
+            scope::Kind::Node => Ok(self.ensure_node_global(&scope.id).await.repl.clone()),
             scope::Kind::Unspecified => Err(Status::invalid_argument("scope.kind unspecified")),
         }
     }
 
     /// Get a NodeSession (node-level)
-    fn get_node_session(&self, scope: &Scope) -> Result<Arc<NodeSession>, Status> {
+    async fn get_node_session(&self, scope: &Scope) -> Result<Arc<NodeSession>, Status> {
         match scope::Kind::try_from(scope.kind).unwrap_or(scope::Kind::Unspecified) {
-            scope::Kind::Node => Ok(self.ensure_node_global(&scope.id)),
+            scope::Kind::Node => Ok(self.ensure_node_global(&scope.id).await),
             _ => Err(Status::invalid_argument("GetStatus requires NODE scope")),
         }
     }
 
-    /// Public helpers if your control-plane integration wants to upsert pools/nodes:
-    #[allow(dead_code)]
+    /// Create a pool unless it exists, then broadcast the tree. Used by `CreatePool`.
     fn upsert_pool(&self, pool_id: &str, name: &str) {
         let _ = self
             .pools
             .entry(pool_id.to_string())
-            .or_insert_with(|| Arc::new(PoolSession::new(pool_id, name)));
+            .or_insert_with(|| {
+                Arc::new(PoolSession::new(
+                    pool_id,
+                    name,
+                    self.history_log(scope::Kind::NodePool, pool_id),
+                    self.capacities.scope_events,
+                ))
+            });
         self.broadcast_tree();
     }
 
-    #[allow(dead_code)]
-    fn upsert_node(&self, pool_id: &str, node_id: &str, _name: &str) {
-        let _ = self.ensure_node(pool_id, node_id);
+    /// Create a node in a pool, or move it there from its current pool. Used by `CreateNode`.
+    async fn upsert_node(&self, pool_id: &str, node_id: &str, _name: &str) {
+        let _ = self.ensure_node(pool_id, node_id).await;
     }
 }
 
@@ -223,31 +623,91 @@ impl Cluster {
 // Presence hub (global)
 // ==========================
 
+/// Tracks which user looks at which scope and broadcasts the changes.
 #[derive(Debug)]
-struct PresenceHub {
+pub struct PresenceHub {
     users: DashMap<String, Presence>, // user_id -> Presence
     tx: broadcast::Sender<PresenceEvent>,
 }
 
 impl Default for PresenceHub {
     fn default() -> Self {
-        let (tx, _rx) = broadcast::channel(512); // capacity chosen by you
-        PresenceHub {
-            users: DashMap::new(),
-            tx,
-        }
+        PresenceHub::new(&ChannelCapacities::default())
     }
 }
 
 impl PresenceHub {
-    fn new() -> Self {
-        let (tx, _rx) = broadcast::channel(256);
+    pub fn new(capacities: &ChannelCapacities) -> Self {
+        let (tx, _rx) = broadcast::channel(capacities.presence.max(1));
         Self {
             users: DashMap::new(),
             tx,
         }
     }
 
+    /// Receiver of the live presence updates.
+    pub fn subscribe(&self) -> broadcast::Receiver<PresenceEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Marks a user as active now. Returns `false` if the user isn't present.
+    pub fn touch(&self, user_id: &str) -> bool {
+        match self.users.get_mut(user_id) {
+            Some(mut p) => {
+                p.last_seen = chrono::Utc::now().timestamp();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes every user whose last activity is more than `timeout` seconds before `now`,
+    /// announcing each one with a `Left` update. Returns the removed user ids.
+    pub fn remove_stale(&self, now: i64, timeout: i64) -> Vec<String> {
+        let stale: Vec<String> = self
+            .users
+            .iter()
+            .filter(|e| now - e.value().last_seen > timeout)
+            .map(|e| e.key().clone())
+            .collect();
+
+        let mut removed = Vec::new();
+        for user_id in stale {
+            // Re-check: the user may have been touched in the meantime
+            if let Some((_, p)) = self
+                .users
+                .remove_if(&user_id, |_, p| now - p.last_seen > timeout)
+            {
+                let _ = self.tx.send(PresenceEvent {
+                    kind: Some(presence_event::Kind::Update(PresenceUpdate {
+                        kind: presence_update::Kind::Left as i32,
+                        user: Some(p),
+                    })),
+                });
+                removed.push(user_id);
+            }
+        }
+        removed
+    }
+
+    /// Starts the background task that removes idle users every `interval`. The task stops
+    /// on its own once the hub has been dropped.
+    pub fn spawn_reaper(self: &Arc<Self>, interval: Duration, timeout: Duration) -> JoinHandle<()> {
+        let hub = Arc::downgrade(self);
+        let timeout = timeout.as_secs() as i64;
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let Some(hub) = hub.upgrade() else {
+                    break;
+                };
+                hub.remove_stale(chrono::Utc::now().timestamp(), timeout);
+            }
+        })
+    }
+
     fn users_in_scope(&self, target: &Scope) -> Vec<Presence> {
         self.users
             .iter()
@@ -256,7 +716,7 @@ impl PresenceHub {
             .collect()
     }
 
-    fn snapshot(&self) -> PresenceSnapshot {
+    pub fn snapshot(&self) -> PresenceSnapshot {
         let mut users = Vec::new();
         for u in self.users.iter() {
             users.push(u.value().clone());
@@ -264,7 +724,8 @@ impl PresenceHub {
         PresenceSnapshot { users }
     }
 
-    fn join(&self, p: Presence) {
+    pub fn join(&self, mut p: Presence) {
+        p.last_seen = chrono::Utc::now().timestamp();
         let user_id = p.user_id.clone();
         match self.users.insert(user_id.clone(), p.clone()) {
             None => {
@@ -290,7 +751,7 @@ impl PresenceHub {
         }
     }
 
-    fn leave(&self, p: Presence) {
+    pub fn leave(&self, p: Presence) {
         let user_id = p.user_id.clone();
         if self.users.remove(&user_id).is_some() {
             let _ = self.tx.send(PresenceEvent {
@@ -310,6 +771,32 @@ impl PresenceHub {
 #[derive(Clone)]
 struct ReplSvc {
     cluster: Arc<Cluster>,
+    presence: Arc<PresenceHub>,
+    history_page_size: usize,
+}
+
+/// Splits replayed history into chunks of at most `page_size` outputs. Only the last one is
+/// `done`; empty history still yields a single, empty chunk.
+fn history_chunks(scope: &Scope, items: Vec<Output>, page_size: usize) -> Vec<ServerEvent> {
+    let mut pages: Vec<Vec<Output>> = items
+        .chunks(page_size.max(1))
+        .map(|page| page.to_vec())
+        .collect();
+    if pages.is_empty() {
+        pages.push(Vec::new());
+    }
+    let last = pages.len() - 1;
+    pages
+        .into_iter()
+        .enumerate()
+        .map(|(i, items)| ServerEvent {
+            kind: Some(server_event::Kind::History(HistoryChunk {
+                scope: Some(scope.clone()),
+                items,
+                done: i == last,
+            })),
+        })
+        .collect()
 }
 
 #[tonic::async_trait]
@@ -324,9 +811,10 @@ impl ReplService for ReplSvc {
             user_id,
             scope: maybe_scope,
             since_id,
+            since_seq,
         } = request.into_inner();
         let scope = maybe_scope.ok_or_else(|| Status::invalid_argument("Join.scope required"))?;
-        let session = self.cluster.get_scope_session(&scope)?;
+        let session = self.cluster.get_scope_session(&scope).await?;
 
         let (out_tx, out_rx) = tokio::sync::mpsc::channel::<Result<ServerEvent, Status>>(256);
 
@@ -335,27 +823,14 @@ impl ReplService for ReplSvc {
             let session = Arc::clone(&session);
             let scope_clone = scope.clone();
             let user_id_clone = user_id.clone();
+            let page_size = self.history_page_size;
             async move {
-                // (a) history
-                let items = {
-                    let g = session.history.read().await;
-                    if since_id.is_empty() {
-                        g.clone()
-                    } else {
-                        // find first index with id > since_id
-                        let idx = g.iter().position(|o| o.id >= since_id).unwrap_or(g.len());
-                        g[idx..].to_vec()
+                // (a) history, in pages
+                let items = session.history_since(&since_id, since_seq).await;
+                for chunk in history_chunks(&scope_clone, items, page_size) {
+                    if out_tx.send(Ok(chunk)).await.is_err() {
+                        return;
                     }
-                };
-                let chunk = ServerEvent {
-                    kind: Some(server_event::Kind::History(HistoryChunk {
-                        scope: Some(scope_clone.clone()),
-                        items,
-                        done: true,
-                    })),
-                };
-                if out_tx.send(Ok(chunk)).await.is_err() {
-                    return;
                 }
 
                 // (b) join notice
@@ -379,16 +854,11 @@ impl ReplService for ReplSvc {
                             _n,
                         )) => {
                             // Optional: send a resync chunk (latest N history)
-                            let snapshot = {
-                                let g = session.history.read().await;
-                                // last up to 200 items, for example
-                                let take = g.len().saturating_sub(200);
-                                g.iter().skip(take).cloned().collect::<Vec<_>>()
-                            };
+                            let snapshot = session.recent_history().await;
                             let _ = out_tx
                                 .send(Ok(ServerEvent {
                                     kind: Some(server_event::Kind::History(HistoryChunk {
-                                        scope: Some(scope.clone()),
+                                        scope: Some(scope_clone.clone()),
                                         items: snapshot,
                                         done: true,
                                     })),
@@ -412,39 +882,180 @@ impl ReplService for ReplSvc {
     }
 
     async fn send_command(&self, request: Request<Input>) -> Result<Response<Ack>, Status> {
+        self.execute(request.into_inner()).await.map(Response::new)
+    }
+
+    type SessionStream = Pin<Box<dyn Stream<Item = Result<ServerEvent, Status>> + Send>>;
+
+    async fn session(
+        &self,
+        request: Request<Streaming<Input>>,
+    ) -> Result<Response<Self::SessionStream>, Status> {
+        let mut inputs = request.into_inner();
+        let first = inputs
+            .message()
+            .await?
+            .ok_or_else(|| Status::invalid_argument("Session needs a first Input"))?;
+        let scope = first
+            .scope
+            .clone()
+            .ok_or_else(|| Status::invalid_argument("Input.scope required"))?;
+        let user_id = first.user_id.clone();
+        let session = self.cluster.get_scope_session(&scope).await?;
+
+        let (out_tx, out_rx) = tokio::sync::mpsc::channel::<Result<ServerEvent, Status>>(256);
+
+        // subscribe before reading the history so no output is missed in between
+        let mut live = BroadcastStream::new(session.tx.subscribe());
+
+        // Pump: history -> join notice -> inputs and broadcast interleaved -> leave notice
+        let svc = self.clone();
+        tokio::spawn(async move {
+            let items = session.history_since("", 0).await;
+            for chunk in history_chunks(&scope, items, svc.history_page_size) {
+                if out_tx.send(Ok(chunk)).await.is_err() {
+                    return;
+                }
+            }
+
+            let _ = session.tx.send(ServerEvent {
+                kind: Some(server_event::Kind::Notice(ServerNotice {
+                    scope: Some(scope.clone()),
+                    text: format!("{user_id} joined"),
+                })),
+            });
+
+            // The first input may only open the session
+            let mut pending = Some(first).filter(|input| !input.code.is_empty());
+            loop {
+                let input = match pending.take() {
+                    Some(input) => Some(input),
+                    None => tokio::select! {
+                        input = inputs.message() => match input {
+                            Ok(Some(input)) => Some(input),
+                            // Client closed its side or the stream broke
+                            Ok(None) | Err(_) => break,
+                        },
+                        item = live.next() => {
+                            let evt = match item {
+                                Some(Ok(evt)) => evt,
+                                Some(Err(_lagged)) => ServerEvent {
+                                    kind: Some(server_event::Kind::History(HistoryChunk {
+                                        scope: Some(scope.clone()),
+                                        items: session.recent_history().await,
+                                        done: true,
+                                    })),
+                                },
+                                None => break,
+                            };
+                            if out_tx.send(Ok(evt)).await.is_err() {
+                                break;
+                            }
+                            None
+                        }
+                    },
+                };
+
+                if let Some(mut input) = input {
+                    // Commands run in the session's scope
+                    match &input.scope {
+                        None => input.scope = Some(scope.clone()),
+                        Some(s) if *s == scope => {}
+                        Some(_) => {
+                            let _ = out_tx
+                                .send(Err(Status::invalid_argument(
+                                    "Input.scope differs from the session's scope",
+                                )))
+                                .await;
+                            break;
+                        }
+                    }
+                    let evt = svc.execute(input).await.map(|ack| ServerEvent {
+                        kind: Some(server_event::Kind::Ack(ack)),
+                    });
+                    if out_tx.send(evt).await.is_err() {
+                        break;
+                    }
+                }
+            }
+
+            let _ = session.tx.send(ServerEvent {
+                kind: Some(server_event::Kind::Notice(ServerNotice {
+                    scope: Some(scope.clone()),
+                    text: format!("{user_id} left"),
+                })),
+            });
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(out_rx))))
+    }
+}
+
+impl ReplSvc {
+    /// Runs a command in its scope, records the output and fans it out to the subscribers.
+    async fn execute(&self, input: Input) -> Result<Ack, Status> {
         let Input {
             user_id,
             scope: maybe_scope,
             code,
-        } = request.into_inner();
+        } = input;
         let scope = maybe_scope.ok_or_else(|| Status::invalid_argument("Input.scope required"))?;
-        let session = self.cluster.get_scope_session(&scope)?;
-
-        // --- Execute your rule/REPL here ---
-        let result_text = format!(">> {}\n{}", code, "[result placeholder]");
+        self.presence.touch(&user_id);
+
+        let (session, result) =
+            match scope::Kind::try_from(scope.kind).unwrap_or(scope::Kind::Unspecified) {
+                scope::Kind::Node => {
+                    let node_session = self.cluster.get_node_session(&scope).await?;
+                    let result = match parse_command(&code) {
+                        Ok(rules) => node_session.execute(rules).await?,
+                        Err(e) => format!("error: {}", e),
+                    };
+                    (Arc::clone(&node_session.repl), result)
+                }
+                scope::Kind::NodePool => {
+                    let pool = self
+                        .cluster
+                        .pools
+                        .get(&scope.id)
+                        .map(|p| Arc::clone(&*p))
+                        .ok_or_else(|| Status::not_found(format!("Node pool not found: {}", scope.id)))?;
+                    let result = match parse_command(&code) {
+                        Ok(rules) => pool.execute(rules).await?,
+                        Err(e) => format!("error: {}", e),
+                    };
+                    (Arc::clone(&pool.repl), result)
+                }
+                scope::Kind::ControlPlane => {
+                    return Err(Status::unimplemented(
+                        "Executing commands on the control plane is not supported yet, target a node or pool instead",
+                    ));
+                }
+                scope::Kind::Unspecified => {
+                    return Err(Status::invalid_argument("scope.kind unspecified"));
+                }
+            };
+        let result_text = format!(">> {}\n{}", code, result);
 
-        let out = Output {
+        let mut out = Output {
             id: uuid::Uuid::now_v7().to_string(),
             scope: Some(scope.clone()),
             user_id: user_id.clone(),
             stdout: result_text,
             unix_ts: chrono::Utc::now().timestamp(),
+            seq: 0,
         };
 
-        {
-            let mut hist = session.history.write().await;
-            hist.push(out.clone());
-        }
+        session.record(&mut out).await;
 
         // fan-out
         let _ = session.tx.send(ServerEvent {
             kind: Some(server_event::Kind::Output(out.clone())),
         });
 
-        Ok(Response::new(Ack {
+        Ok(Ack {
             input_echo: code,
             output_id: out.id,
-        }))
+        })
     }
 }
 
@@ -460,10 +1071,48 @@ impl StatusService for StatusSvc {
         request: Request<Scope>,
     ) -> Result<Response<StatusSnapshot>, Status> {
         let scope = request.into_inner();
-        let node = self.cluster.get_node_session(&scope)?;
+        let node = self.cluster.get_node_session(&scope).await?;
         let snap = node.status.read().await.clone();
         Ok(Response::new(snap))
     }
+
+    type WatchStatusStream = Pin<Box<dyn Stream<Item = Result<StatusSnapshot, Status>> + Send>>;
+
+    async fn watch_status(
+        &self,
+        request: Request<Scope>,
+    ) -> Result<Response<Self::WatchStatusStream>, Status> {
+        let scope = request.into_inner();
+        let node = self.cluster.get_node_session(&scope).await?;
+        let (out_tx, out_rx) = tokio::sync::mpsc::channel::<Result<StatusSnapshot, Status>>(64);
+
+        // subscribe before reading the snapshot so no change is missed in between
+        let mut rx = BroadcastStream::new(node.status_tx.subscribe());
+
+        // send snapshot immediately
+        let snapshot = node.status.read().await.clone();
+        if out_tx.send(Ok(snapshot)).await.is_err() {
+            return Ok(Response::new(Box::pin(ReceiverStream::new(out_rx))));
+        }
+
+        // forward updates
+        tokio::spawn(async move {
+            while let Some(item) = rx.next().await {
+                let snapshot = match item {
+                    Ok(snapshot) => snapshot,
+                    // Updates were missed, the current status covers them all
+                    Err(tokio_stream::wrappers::errors::BroadcastStreamRecvError::Lagged(_n)) => {
+                        node.status.read().await.clone()
+                    }
+                };
+                if out_tx.send(Ok(snapshot)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(out_rx))))
+    }
 }
 
 #[derive(Clone)]
@@ -501,6 +1150,57 @@ impl ControlPlaneService for TreeSvc {
 
         Ok(Response::new(Box::pin(ReceiverStream::new(out_rx))))
     }
+
+    async fn create_pool(
+        &self,
+        request: Request<NodePoolDescriptor>,
+    ) -> Result<Response<SystemTree>, Status> {
+        let pool = request.into_inner();
+        if pool.id.is_empty() {
+            return Err(Status::invalid_argument("NodePoolDescriptor.id required"));
+        }
+        let name = if pool.name.is_empty() { &pool.id } else { &pool.name };
+        self.cluster.upsert_pool(&pool.id, name);
+        Ok(Response::new(self.cluster.system_tree_snapshot()))
+    }
+
+    async fn create_node(
+        &self,
+        request: Request<CreateNodeRequest>,
+    ) -> Result<Response<SystemTree>, Status> {
+        let CreateNodeRequest { pool_id, node } = request.into_inner();
+        let node = node.ok_or_else(|| Status::invalid_argument("CreateNodeRequest.node required"))?;
+        if node.id.is_empty() {
+            return Err(Status::invalid_argument("NodeDescriptor.id required"));
+        }
+        if !self.cluster.pools.contains_key(&pool_id) {
+            return Err(Status::not_found(format!("Node pool not found: {}", pool_id)));
+        }
+        self.cluster.upsert_node(&pool_id, &node.id, &node.name).await;
+        Ok(Response::new(self.cluster.system_tree_snapshot()))
+    }
+
+    async fn remove_pool(
+        &self,
+        request: Request<NodePoolDescriptor>,
+    ) -> Result<Response<RemoveResponse>, Status> {
+        let removed = self.cluster.remove_pool(&request.into_inner().id);
+        Ok(Response::new(RemoveResponse {
+            removed,
+            tree: Some(self.cluster.system_tree_snapshot()),
+        }))
+    }
+
+    async fn remove_node(
+        &self,
+        request: Request<NodeDescriptor>,
+    ) -> Result<Response<RemoveResponse>, Status> {
+        let removed = self.cluster.remove_node(&request.into_inner().id);
+        Ok(Response::new(RemoveResponse {
+            removed,
+            tree: Some(self.cluster.system_tree_snapshot()),
+        }))
+    }
 }
 
 #[derive(Clone)]
@@ -514,12 +1214,25 @@ impl PresenceService for PresenceSvc {
 
     async fn subscribe(
         &self,
-        _request: Request<Empty>,
+        request: Request<Scope>,
     ) -> Result<Response<Self::SubscribeStream>, Status> {
+        // An unspecified scope kind means no filter
+        let target =
+            Some(request.into_inner()).filter(|s| s.kind != scope::Kind::Unspecified as i32);
         let (out_tx, out_rx) = tokio::sync::mpsc::channel::<Result<PresenceEvent, Status>>(256);
 
+        // Subscribe before taking the snapshot so no update falls in between
+        let mut rx = BroadcastStream::new(self.hub.subscribe());
+
         // (1) send snapshot
-        let snap = self.hub.snapshot();
+        let snap = match &target {
+            Some(target) => PresenceSnapshot {
+                users: self.hub.users_in_scope(target),
+            },
+            None => self.hub.snapshot(),
+        };
+        // Users of the scope the subscriber currently knows about
+        let mut known: HashSet<String> = snap.users.iter().map(|p| p.user_id.clone()).collect();
         if out_tx
             .send(Ok(PresenceEvent {
                 kind: Some(presence_event::Kind::Snapshot(snap)),
@@ -531,14 +1244,28 @@ impl PresenceService for PresenceSvc {
         }
 
         // (2) forward updates
-        tokio::spawn({
-            let mut rx = BroadcastStream::new(self.hub.tx.subscribe());
-            async move {
-                while let Some(Ok(evt)) = rx.next().await {
-                    if out_tx.send(Ok(evt)).await.is_err() {
-                        break;
+        tokio::spawn(async move {
+            while let Some(Ok(mut evt)) = rx.next().await {
+                if let Some(target) = &target
+                    && let Some(presence_event::Kind::Update(update)) = &mut evt.kind
+                    && let Some(user) = &update.user
+                {
+                    if update.kind == presence_update::Kind::Left as i32 {
+                        if !known.remove(&user.user_id) {
+                            continue;
+                        }
+                    } else if user.scope.as_ref() == Some(target) {
+                        known.insert(user.user_id.clone());
+                    } else if known.remove(&user.user_id) {
+                        // Moved out of the scope: to this subscriber, the user left
+                        update.kind = presence_update::Kind::Left as i32;
+                    } else {
+                        continue;
                     }
                 }
+                if out_tx.send(Ok(evt)).await.is_err() {
+                    break;
+                }
             }
         });
 
@@ -572,17 +1299,98 @@ impl PresenceService for PresenceSvc {
 // Server bootstrap
 // ==========================
 
-pub async fn serve(socket_address: Option<SocketAddr>) -> Result<(), Box<dyn std::error::Error>> {
+/// Outputs per `HistoryChunk` when history is replayed to a new subscriber.
+pub const DEFAULT_HISTORY_PAGE_SIZE: usize = 100;
+
+/// How often idle presence entries are looked for.
+pub const DEFAULT_PRESENCE_REAP_INTERVAL: Duration = Duration::from_secs(10);
+/// How long a user may stay inactive before being removed from presence.
+pub const DEFAULT_PRESENCE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Options for the web gRPC server.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// When set, every request must carry `authorization: Bearer <token>`.
+    pub auth_token: Option<String>,
+    /// When set, REPL history is appended to one JSON lines file per scope in this
+    /// directory and replayed from there on startup.
+    pub persist_dir: Option<PathBuf>,
+    /// Buffer sizes of the event broadcasts.
+    pub channel_capacities: ChannelCapacities,
+    /// How often idle users are removed from presence.
+    pub presence_reap_interval: Duration,
+    /// Inactivity (no join or command) after which a user counts as gone.
+    pub presence_timeout: Duration,
+    /// When set, the server only accepts TLS connections.
+    pub tls: Option<TlsConfig>,
+    /// Level at which every handled call is logged.
+    pub log_level: Level,
+    /// Outputs per `HistoryChunk` when history is replayed; only the last chunk is `done`.
+    pub history_page_size: usize,
+}
+
+/// PEM encoded certificate (chain) and private key the server identifies itself with.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl TlsConfig {
+    fn load(&self) -> Result<ServerTlsConfig, Box<dyn std::error::Error>> {
+        let cert = std::fs::read(&self.cert)?;
+        let key = std::fs::read(&self.key)?;
+        Ok(ServerTlsConfig::new().identity(Identity::from_pem(cert, key)))
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            auth_token: None,
+            persist_dir: None,
+            channel_capacities: ChannelCapacities::default(),
+            presence_reap_interval: DEFAULT_PRESENCE_REAP_INTERVAL,
+            presence_timeout: DEFAULT_PRESENCE_TIMEOUT,
+            tls: None,
+            log_level: Level::INFO,
+            history_page_size: DEFAULT_HISTORY_PAGE_SIZE,
+        }
+    }
+}
+
+pub async fn serve(
+    socket_address: Option<SocketAddr>,
+    config: ServerConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
     let addr = socket_address.unwrap_or(([127, 0, 0, 1], 50051).into());
+    let listener = TcpListener::bind(addr).await?;
+    serve_with_listener(listener, config).await
+}
 
-    let cluster = Arc::new(Cluster::new("cp-1"));
+/// Same as [`serve`], on an already bound listener (e.g. port 0 in tests).
+pub async fn serve_with_listener(
+    listener: TcpListener,
+    config: ServerConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(dir) = &config.persist_dir {
+        std::fs::create_dir_all(dir)?;
+    }
+    let cluster = Arc::new(Cluster::new(
+        "cp-1",
+        config.persist_dir.clone(),
+        config.channel_capacities.clone(),
+    ));
     cluster.upsert_pool("default", "default");
-    cluster.upsert_node("default", "node-1", "node-1");
+    cluster.upsert_node("default", "node-1", "node-1").await;
 
-    let presence = Arc::new(PresenceHub::new());
+    let presence = Arc::new(PresenceHub::new(&config.channel_capacities));
+    presence.spawn_reaper(config.presence_reap_interval, config.presence_timeout);
 
     let repl = ReplSvc {
         cluster: Arc::clone(&cluster),
+        presence: Arc::clone(&presence),
+        history_page_size: config.history_page_size,
     };
     let status = StatusSvc {
         cluster: Arc::clone(&cluster),
@@ -594,19 +1402,51 @@ pub async fn serve(socket_address: Option<SocketAddr>) -> Result<(), Box<dyn std
         hub: Arc::clone(&presence),
     };
 
+    let auth = AuthInterceptor::new(config.auth_token);
+
+    // Health checks stay unauthenticated so probes don't need the token
+    let (health_reporter, health) = tonic_health::server::health_reporter();
+    health_reporter.set_serving::<ReplServiceServer<ReplSvc>>().await;
+    health_reporter.set_serving::<StatusServiceServer<StatusSvc>>().await;
+    health_reporter.set_serving::<ControlPlaneServiceServer<TreeSvc>>().await;
+    health_reporter.set_serving::<PresenceServiceServer<PresenceSvc>>().await;
+
+    // Lets tools like grpcurl discover the services without the .proto files
+    let reflection = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(crate::generated::FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .with_service_name(<ReplServiceServer<ReplSvc> as NamedService>::NAME)
+        .with_service_name(<StatusServiceServer<StatusSvc> as NamedService>::NAME)
+        .with_service_name(<ControlPlaneServiceServer<TreeSvc> as NamedService>::NAME)
+        .with_service_name(<PresenceServiceServer<PresenceSvc> as NamedService>::NAME)
+        .with_service_name("grpc.health.v1.Health")
+        .build_v1()?;
+
     let cors = CorsLayer::new()
         .allow_methods(Any)
         .allow_headers(Any)
         .allow_origin(Any);
 
-    tonic::transport::Server::builder()
+    let mut builder = tonic::transport::Server::builder();
+    if let Some(tls) = &config.tls {
+        builder = builder.tls_config(tls.load()?)?;
+    }
+
+    builder
         .accept_http1(true)
-        .layer(ServiceBuilder::new().layer(cors).layer(GrpcWebLayer::new()))
-        .add_service(ReplServiceServer::new(repl))
-        .add_service(StatusServiceServer::new(status))
-        .add_service(ControlPlaneServiceServer::new(tree))
-        .add_service(PresenceServiceServer::new(pres))
-        .serve(addr)
+        .layer(
+            ServiceBuilder::new()
+                .layer(RequestLogLayer::new(config.log_level))
+                .layer(cors)
+                .layer(GrpcWebLayer::new()),
+        )
+        .add_service(ReplServiceServer::with_interceptor(repl, auth.clone()))
+        .add_service(StatusServiceServer::with_interceptor(status, auth.clone()))
+        .add_service(ControlPlaneServiceServer::with_interceptor(tree, auth.clone()))
+        .add_service(PresenceServiceServer::with_interceptor(pres, auth))
+        .add_service(health)
+        .add_service(reflection)
+        .serve_with_incoming(TcpListenerStream::new(listener))
         .await?;
 
     Ok(())
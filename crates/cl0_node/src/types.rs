@@ -2,7 +2,9 @@ use cl0_parser::ast::{
     Action, ActionList, AtomicCondition, CaseRule, Compound, Condition, DeclarativeRule, FactRule,
     PrimitiveCondition, PrimitiveEvent, ReactiveRule, Rule,
 };
-use std::{error::Error, fmt};
+use cl0_parser::Span;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 use crate::generated;
 
@@ -18,10 +20,24 @@ pub struct ReactiveRuleWithArgs {
     pub rule: ReactiveRule,
     pub value: ActivationStatus,
     pub alias: Option<Vec<String>>,
+    /// Rules of the same handler are evaluated from the highest priority down, ties in the
+    /// order they were added.
+    pub priority: i32,
 }
 impl ReactiveRuleWithArgs {
     pub fn new(rule: ReactiveRule, value: ActivationStatus, alias: Option<Vec<String>>) -> Self {
-        ReactiveRuleWithArgs { rule, value, alias }
+        ReactiveRuleWithArgs {
+            rule,
+            value,
+            alias,
+            priority: 0,
+        }
+    }
+
+    /// Sets the evaluation priority of this rule within its handler.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
     }
 }
 
@@ -44,10 +60,13 @@ pub enum RuleWithArgs {
     Case(CaseRule),
     Fact(FactRuleWithArgs),
     Reactive(ReactiveRuleWithArgs),
+    /// A rule together with the source range it was parsed from, so errors can point back at it.
+    Spanned { rule: Box<RuleWithArgs>, span: Span },
 }
 impl From<RuleWithArgs> for Rule {
     fn from(rwa: RuleWithArgs) -> Rule {
         match rwa {
+            RuleWithArgs::Spanned { rule, .. } => Rule::from(*rule),
             RuleWithArgs::Declarative(d) => Rule::Declarative(d),
             RuleWithArgs::Case(c) => Rule::Case(c),
             RuleWithArgs::Fact(FactRuleWithArgs { rule, .. }) => Rule::Fact(rule),
@@ -68,23 +87,45 @@ impl From<Rule> for RuleWithArgs {
                 rule: rr,
                 value: ActivationStatus::True, // Default value for reactive rules
                 alias: None,                   // Default alias
+                priority: 0,                   // Default priority
             }),
         }
     }
 }
+impl From<(Rule, Span)> for RuleWithArgs {
+    fn from((rule, span): (Rule, Span)) -> RuleWithArgs {
+        RuleWithArgs::Spanned {
+            rule: Box::new(RuleWithArgs::from(rule)),
+            span,
+        }
+    }
+}
 
 /// The possible values a condition variable can take in the system.
 ///
 /// - `True` and `False` are concrete boolean states.
-/// - `Unknown` represents a value that is not yet determined; callers can choose to
-///   treat it differently (e.g., continue or fail) depending on context.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// - `Conflict` represents a value that is not yet determined or could not be settled;
+///   callers can choose to treat it differently (e.g., continue or fail) depending on context.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ActivationStatus {
     True,
     False, // Inactive
     Conflict,
 }
 
+/// How the statuses of several rules, e.g. those of a compound, combine into one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ConflictStrategy {
+    /// Any `Conflict` is an error, otherwise any `False` makes the result `False`.
+    #[default]
+    Pessimistic,
+    /// Any `True` makes the result `True`, otherwise any `Conflict` is an error.
+    Optimistic,
+    /// Whichever of `True` and `False` occurs more often, a tie is an error. `Conflict`s don't
+    /// count.
+    Majority,
+}
+
 impl ActivationStatus {
     /// Returns `Some(bool)` for concrete values, or `None` for `Conflict`.
     pub fn as_option_bool(&self) -> Option<bool> {
@@ -96,12 +137,36 @@ impl ActivationStatus {
     }
 
     /// Converts into a boolean, returning an error for ambiguous states.
-    pub fn to_bool(&self) -> Result<bool, Box<dyn Error + Send + Sync>> {
-        self.as_option_bool()
-            .ok_or(Box::<dyn Error + Send + Sync>::from(format!(
-                "Not a boolean value: {}",
-                self
-            )))
+    pub fn to_bool(&self) -> Result<bool, NodeError> {
+        self.as_option_bool().ok_or(NodeError::Conflict)
+    }
+}
+
+/// The value of a variable: the status of a boolean variable, or the count of a counter declared
+/// with `@counter(var)`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Value {
+    Status(ActivationStatus),
+    Int(i64),
+}
+
+impl From<bool> for ActivationStatus {
+    fn from(value: bool) -> Self {
+        if value {
+            ActivationStatus::True
+        } else {
+            ActivationStatus::False
+        }
+    }
+}
+
+/// The inverse of `ActivationStatus::as_option_bool`: an unknown value, `None`, is a `Conflict`.
+impl From<Option<bool>> for ActivationStatus {
+    fn from(value: Option<bool>) -> Self {
+        match value {
+            Some(value) => value.into(),
+            None => ActivationStatus::Conflict,
+        }
     }
 }
 
@@ -110,7 +175,7 @@ impl fmt::Display for ActivationStatus {
         match self {
             ActivationStatus::True => write!(f, "True"),
             ActivationStatus::False => write!(f, "False"),
-            ActivationStatus::Conflict => write!(f, "Unknown"),
+            ActivationStatus::Conflict => write!(f, "Conflict"),
         }
     }
 }
@@ -170,10 +235,15 @@ impl TryFrom<generated::common::ReactiveRule> for ReactiveRule {
             None => None,
         };
         let action = Action::try_from(r.action.ok_or("Missing action")?)?;
+        let else_action = match r.else_action {
+            Some(a) => Some(Action::try_from(a)?),
+            None => None,
+        };
         Ok(ReactiveRule::ECA {
             event,
             condition,
             action,
+            else_action,
         })
     }
 }
@@ -185,15 +255,22 @@ impl From<ReactiveRule> for generated::common::ReactiveRule {
                 event,
                 condition,
                 action,
+                else_action,
             } => generated::common::ReactiveRule {
                 event: Some(event.into()),
                 condition: condition.map(Into::into),
                 action: Some(action.into()),
+                else_action: else_action.map(Into::into),
             },
-            ReactiveRule::CA { condition, action } => generated::common::ReactiveRule {
+            ReactiveRule::CA {
+                condition,
+                action,
+                else_action,
+            } => generated::common::ReactiveRule {
                 event: None, // CA maps to missing event
                 condition: Some(condition.into()),
                 action: Some(action.into()),
+                else_action: else_action.map(Into::into),
             },
         }
     }
@@ -427,6 +504,10 @@ impl TryFrom<generated::common::Condition> for Condition {
             Kind::Atomic(ac) => Ok(Condition::Atomic(AtomicCondition::try_from(ac)?)),
             Kind::Not(sub) => Ok(Condition::Not(Box::new(Condition::try_from(*sub)?))),
             Kind::Conjunction(c) => {
+                // The parser never produces an empty one, so neither may the wire
+                if c.conditions.is_empty() {
+                    return Err("Conjunction without conditions".to_string());
+                }
                 let items = c
                     .conditions
                     .into_iter()
@@ -435,6 +516,10 @@ impl TryFrom<generated::common::Condition> for Condition {
                 Ok(Condition::Conjunction(items))
             }
             Kind::Disjunction(d) => {
+                // The parser never produces an empty one, so neither may the wire
+                if d.conditions.is_empty() {
+                    return Err("Disjunction without conditions".to_string());
+                }
                 let items = d
                     .conditions
                     .into_iter()
@@ -445,6 +530,10 @@ impl TryFrom<generated::common::Condition> for Condition {
             Kind::Parentheses(inner) => Ok(Condition::Parentheses(Box::new(Condition::try_from(
                 *inner,
             )?))),
+            Kind::AtLeast(a) => Ok(Condition::AtLeast(
+                PrimitiveCondition::try_from(a.var.ok_or("Missing counter")?)?,
+                a.count,
+            )),
         }
     }
 }
@@ -463,6 +552,10 @@ impl From<Condition> for generated::common::Condition {
                 conditions: items.into_iter().map(Into::into).collect(),
             }),
             Condition::Parentheses(inner) => Kind::Parentheses(Box::new((*inner).into())),
+            Condition::AtLeast(var, count) => Kind::AtLeast(generated::common::AtLeast {
+                var: Some(var.into()),
+                count,
+            }),
         };
 
         generated::common::Condition { kind: Some(kind) }
@@ -545,3 +638,249 @@ impl From<Compound> for generated::common::Compound {
         }
     }
 }
+
+/// Status view of a reactive rule: the alias path becomes the dot-separated `namespace`
+/// (empty for top-level rules) and the rule text its `name`. Only `True` counts as enabled,
+/// so both `False` and `Conflict` rules are reported as disabled.
+impl From<ReactiveRuleWithArgs> for generated::web::RuleStatus {
+    fn from(rule: ReactiveRuleWithArgs) -> Self {
+        generated::web::RuleStatus {
+            namespace: rule.alias.map(|a| a.join(".")).unwrap_or_default(),
+            name: rule.rule.to_string(),
+            enabled: rule.value == ActivationStatus::True,
+        }
+    }
+}
+
+/// Status view of a variable, with the same `True`-only notion of enabled as rules. The exact
+/// value is kept in `status`.
+impl From<(PrimitiveCondition, ActivationStatus)> for generated::web::VarStatus {
+    fn from((var, value): (PrimitiveCondition, ActivationStatus)) -> Self {
+        generated::web::VarStatus {
+            name: var.name().to_string(),
+            enabled: value == ActivationStatus::True,
+            status: generated::web::ActivationStatus::from(value).into(),
+        }
+    }
+}
+
+impl From<ActivationStatus> for generated::web::ActivationStatus {
+    fn from(value: ActivationStatus) -> Self {
+        match value {
+            ActivationStatus::True => generated::web::ActivationStatus::True,
+            ActivationStatus::False => generated::web::ActivationStatus::False,
+            ActivationStatus::Conflict => generated::web::ActivationStatus::Conflict,
+        }
+    }
+}
+
+impl TryFrom<generated::web::ActivationStatus> for ActivationStatus {
+    type Error = String;
+
+    fn try_from(value: generated::web::ActivationStatus) -> Result<Self, Self::Error> {
+        match value {
+            generated::web::ActivationStatus::True => Ok(ActivationStatus::True),
+            generated::web::ActivationStatus::False => Ok(ActivationStatus::False),
+            generated::web::ActivationStatus::Conflict => Ok(ActivationStatus::Conflict),
+            generated::web::ActivationStatus::Unspecified => {
+                Err("Unspecified activation status".to_string())
+            }
+        }
+    }
+}
+
+/// Point-in-time view of a node, as returned by `Node::observe`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeSnapshot {
+    /// Every reactive rule held by the node's handlers, disabled ones included.
+    pub rules: Vec<RuleSnapshot>,
+    /// Every variable binding.
+    pub vars: Vec<VarSnapshot>,
+    /// The top-level alias namespaces with their nested namespaces.
+    pub aliases: Vec<AliasSnapshot>,
+}
+
+/// Counters of the work a node did since it was created, as returned by `Node::metrics`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeMetrics {
+    /// Rules handed to the node, including those inside activated compounds.
+    pub rules_processed: u64,
+    /// Primitive actions (triggers, productions and consumptions) executed.
+    pub actions_fired: u64,
+    /// Conditions evaluated, counting each nested condition visited.
+    pub conditions_evaluated: u64,
+    /// Variables that were marked as conflicting.
+    pub conflicts_detected: u64,
+}
+
+/// A reactive rule running its action, as recorded in `Node::firing_log`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FiringRecord {
+    /// The rule that fired.
+    pub rule: ReactiveRule,
+    /// The alias path of the compound the rule belongs to, `None` for top-level rules.
+    pub alias: Option<Vec<String>>,
+    /// When the action started, in milliseconds since the Unix epoch.
+    pub timestamp: i64,
+}
+
+/// A reactive rule in a `NodeSnapshot`, with its alias path and status.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleSnapshot {
+    pub alias: Option<Vec<String>>,
+    pub rule: String,
+    pub status: ActivationStatus,
+}
+
+impl From<ReactiveRuleWithArgs> for RuleSnapshot {
+    fn from(rule: ReactiveRuleWithArgs) -> Self {
+        RuleSnapshot {
+            alias: rule.alias,
+            rule: rule.rule.to_string(),
+            status: rule.value,
+        }
+    }
+}
+
+/// A variable binding in a `NodeSnapshot`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VarSnapshot {
+    pub name: String,
+    pub status: ActivationStatus,
+}
+
+/// An alias namespace in a `NodeSnapshot`: the rules stored under it and its sub-namespaces.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AliasSnapshot {
+    pub name: String,
+    pub rules: Vec<String>,
+    pub children: Vec<AliasSnapshot>,
+}
+
+/// State carried along one chain of actions by `Node::process_action`: the events currently
+/// being handled, outermost first, so an event that re-triggers itself can be caught.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ActionContext {
+    pub trigger_stack: Vec<String>,
+    /// Source range of the rule that started the chain, when it is known.
+    pub span: Option<Span>,
+    /// When the chain has to be done by, see `Node::process_action`.
+    pub deadline: Option<tokio::time::Instant>,
+}
+
+impl ActionContext {
+    /// Returns the context for handling `event` within this chain, or a `TriggerCycle` error if
+    /// the event is already being handled further up.
+    pub fn enter(&self, event: &str) -> Result<ActionContext, TriggerCycle> {
+        let mut trigger_stack = self.trigger_stack.clone();
+        trigger_stack.push(event.to_string());
+        if self.trigger_stack.iter().any(|e| e == event) {
+            return Err(TriggerCycle {
+                chain: trigger_stack,
+            });
+        }
+        Ok(ActionContext {
+            trigger_stack,
+            span: self.span,
+            deadline: self.deadline,
+        })
+    }
+}
+
+/// Error raised when an event re-triggers itself within the same chain of actions.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Trigger cycle detected: {}", chain.join(" -> "))]
+pub struct TriggerCycle {
+    /// The events of the chain, ending with the one that closed the cycle.
+    pub chain: Vec<String>,
+}
+
+/// Error raised when conditions, actions or compounds are nested deeper than a node allows,
+/// see `NodeConfig::max_depth`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Maximum nesting depth of {max_depth} exceeded")]
+pub struct DepthExceeded {
+    pub max_depth: usize,
+}
+
+/// Error raised when `@include` or `@exclude` names a fragment the `PolicyLoader` doesn't know.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Unknown policy fragment: {name}")]
+pub struct UnknownFragment {
+    pub name: String,
+}
+
+/// Errors raised by a node while running rules.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum NodeError {
+    /// An event was triggered that no rule handles. `span` is the source range of the rule
+    /// that started the chain, when it is known.
+    #[error("Invalid action: {event}{}", at_span(span))]
+    InvalidAction { event: String, span: Option<Span> },
+    /// A rule produces an event declared `@external`, which only the outside world may produce.
+    #[error("Cannot produce external event {event} locally")]
+    ExternalEvent { event: String },
+    /// A compound or production names an alias the node has no namespace for.
+    #[error("No matching namespace found for alias: {0}")]
+    AliasNotFound(String),
+    /// The rules of a compound without an alias were asked for; those live in the main namespace.
+    #[error("Cannot get rules from the main namespace")]
+    MainNamespace,
+    /// A condition reads a variable that was never set.
+    #[error("Unknown variable: {0}")]
+    UnknownVariable(PrimitiveCondition),
+    /// A value is in conflict where a boolean is needed.
+    #[error("Not a boolean value: Conflict")]
+    Conflict,
+    /// A variable was to be set to `Conflict`, which only ever results from evaluation.
+    #[error("Cannot update variable {0} to Conflict")]
+    ConflictingWrite(PrimitiveCondition),
+    /// No handler exists for the event of a rule.
+    #[error("Handler for rule {0} not found")]
+    HandlerNotFound(String),
+    /// The handler for the event of a rule does not hold the rule.
+    #[error("Rule {rule} not found in handler {handler}")]
+    RuleNotFound { rule: String, handler: String },
+    /// An action chain was still running when the deadline of its `ActionContext` passed.
+    #[error("Action deadline exceeded")]
+    DeadlineExceeded,
+    /// A counter comparison reads a variable that isn't declared a counter.
+    #[error("Not a counter: {0}")]
+    NotACounter(PrimitiveCondition),
+    /// A reactive rule was added while the handlers already hold `NodeConfig::max_rules` rules.
+    #[error("Rule limit of {0} reached")]
+    TooManyRules(usize),
+    /// An alternative action without any choices was run.
+    #[error("Cannot execute empty alternative action")]
+    EmptyAlternative,
+    /// A compound holds a kind of rule that can't be stored from a condition, such as `a -> c.`.
+    #[error("Unsupported rule type in compound condition: {0}")]
+    UnsupportedRule(String),
+    /// A condition-action rule kept re-triggering itself through the variables it changes, like
+    /// `: a => -a. : not a => +a.`, and was stopped after `limit` runs.
+    #[error("Condition-action handler {handler} re-ran {limit} times without settling")]
+    Oscillation { handler: String, limit: usize },
+    /// A persistent implication makes a compound without an alias hold, like `a -> { #e => +b. }.`.
+    /// Its rules land in the main namespace, so whether it holds can't be read back.
+    #[error("Cannot keep an unaliased compound in force: {0}")]
+    UnaliasedImplication(String),
+    /// A dry run met something it can't simulate, such as a write to an alias or a counter, an
+    /// alternative action, or a condition over a compound.
+    #[error("Dry run can't simulate {0}")]
+    DryRunUnsupported(String),
+    /// Only conjunctions of variables can be asserted.
+    #[error("Cannot assert condition: {0}")]
+    InvalidAssertion(String),
+    /// A rule of a batch that had to be applied in full reported that it wasn't, e.g. a case
+    /// rule whose action failed.
+    #[error("Rule was not applied: {0}")]
+    RuleNotApplied(String),
+}
+
+/// Formats an optional source range as a suffix for error messages.
+fn at_span(span: &Option<Span>) -> String {
+    match span {
+        Some(span) => format!(" (at {}..{})", span.start, span.end),
+        None => String::new(),
+    }
+}
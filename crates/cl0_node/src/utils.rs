@@ -2,33 +2,80 @@ use cl0_parser::ast::Rule;
 use dashmap::{DashMap, DashSet};
 use futures::future::join_all;
 use std::{error::Error, sync::Arc};
-use tokio::{sync::RwLock, task::JoinHandle};
+use tokio::{
+    sync::RwLock,
+    task::{AbortHandle, JoinHandle},
+};
 use async_recursion::async_recursion;
 
-use crate::types::ActivationStatus;
+use crate::types::{ActivationStatus, AliasSnapshot, ConflictStrategy};
 
 /// From a set of status values, return what the overall status is:
-/// - If any are `Unknown`, return an error.
+/// - If any are `Conflict`, return an error.
 /// - If all are `True`, return `True`.
 /// - If any are `False`, return `False`.
 /// If no valid status is found, return an error.
 pub fn overall_status_from_set(
     statuses: &DashSet<ActivationStatus>,
 ) -> Result<ActivationStatus, Box<dyn Error + Send + Sync>> {
-    if statuses.contains(&ActivationStatus::Conflict) {
+    let statuses: Vec<ActivationStatus> = statuses.iter().map(|s| s.clone()).collect();
+    overall_status_from_set_with(&statuses, ConflictStrategy::Pessimistic)
+}
+
+/// Like `overall_status_from_set`, combining the statuses as `strategy` describes. Repeated
+/// statuses count once per occurrence, which only matters for `ConflictStrategy::Majority`.
+/// An empty input is an error whatever the strategy.
+pub fn overall_status_from_set_with(
+    statuses: &[ActivationStatus],
+    strategy: ConflictStrategy,
+) -> Result<ActivationStatus, Box<dyn Error + Send + Sync>> {
+    let count = |status: ActivationStatus| statuses.iter().filter(|s| **s == status).count();
+    let (trues, falses, conflicts) = (
+        count(ActivationStatus::True),
+        count(ActivationStatus::False),
+        count(ActivationStatus::Conflict),
+    );
+    if statuses.is_empty() {
         return Err(Box::<dyn Error + Send + Sync>::from(
-            "Overall status is unknown due to at least one Unknown value",
+            "No valid status found",
         ));
     }
-    if statuses.contains(&ActivationStatus::False) {
-        return Ok(ActivationStatus::False);
-    }
-    if statuses.contains(&ActivationStatus::True) {
-        return Ok(ActivationStatus::True);
+    match strategy {
+        ConflictStrategy::Pessimistic => {
+            if conflicts > 0 {
+                Err(Box::<dyn Error + Send + Sync>::from(
+                    "Overall status is undetermined due to at least one Conflict value",
+                ))
+            } else if falses > 0 {
+                Ok(ActivationStatus::False)
+            } else {
+                Ok(ActivationStatus::True)
+            }
+        }
+        ConflictStrategy::Optimistic => {
+            if trues > 0 {
+                Ok(ActivationStatus::True)
+            } else if conflicts > 0 {
+                Err(Box::<dyn Error + Send + Sync>::from(
+                    "Overall status is undetermined due to Conflict values and no True value",
+                ))
+            } else {
+                Ok(ActivationStatus::False)
+            }
+        }
+        ConflictStrategy::Majority => {
+            if trues > falses {
+                Ok(ActivationStatus::True)
+            } else if falses > trues {
+                Ok(ActivationStatus::False)
+            } else {
+                Err(Box::<dyn Error + Send + Sync>::from(format!(
+                    "Overall status is undetermined, {} True against {} False",
+                    trues, falses
+                )))
+            }
+        }
     }
-    Err(Box::<dyn Error + Send + Sync>::from(
-        "No valid status found",
-    ))
 }
 
 /// Awaits a collection of `JoinHandle<Result<bool, E>>`, returns the conjunction
@@ -49,6 +96,34 @@ pub async fn collect_conjunction(
     Ok(overall)
 }
 
+/// Aborts a set of spawned tasks when dropped, so they don't outlive the future that awaits
+/// them. Tasks that already finished are unaffected.
+#[derive(Debug)]
+pub struct AbortOnDrop(Vec<AbortHandle>);
+
+impl AbortOnDrop {
+    pub fn new<T>(handles: &[JoinHandle<T>]) -> Self {
+        AbortOnDrop(handles.iter().map(JoinHandle::abort_handle).collect())
+    }
+}
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        for handle in &self.0 {
+            handle.abort();
+        }
+    }
+}
+
+/// Outcome of `AliasNamespace::create_rules`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreatedRules {
+    /// The rules the namespace held before, `None` if it held none.
+    pub previous: Option<Vec<Rule>>,
+    /// The given rules the namespace didn't hold yet, without duplicates.
+    pub added: Vec<Rule>,
+}
+
 /// Represents a namespace for aliases, which can contain sub-namespaces and rules.
 #[derive(Debug)]
 pub struct AliasNamespace {
@@ -83,6 +158,25 @@ impl AliasNamespace {
         }
     }
 
+    /// Lists the fully-qualified paths of this namespace, reached through `prefix`, and of every
+    /// namespace below it. Children are visited in name order.
+    pub fn list_namespaces(&self, prefix: Vec<String>) -> Vec<Vec<String>> {
+        let mut children: Vec<(String, Arc<AliasNamespace>)> = self
+            .sub_namespaces
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        children.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut paths = vec![prefix.clone()];
+        for (name, child) in children {
+            let mut path = prefix.clone();
+            path.push(name);
+            paths.extend(child.list_namespaces(path));
+        }
+        paths
+    }
+
     /// Creates (or replaces) rules in this namespace or a descendant.
     #[async_recursion]
     pub async fn create_rules(
@@ -90,28 +184,29 @@ impl AliasNamespace {
         mut aliases: Vec<String>,
         new_rules: Vec<Rule>,
         override_entries: bool,
-    ) -> Result<Option<Vec<Rule>>, Box<dyn Error + Send + Sync>> {
+    ) -> Result<CreatedRules, Box<dyn Error + Send + Sync>> {
         if aliases.is_empty() {
             let mut guard = self.rules.write().await;
             let old = guard.clone();
+            let mut added: Vec<Rule> = Vec::new();
+            for rule in new_rules.iter() {
+                if !old.contains(rule) && !added.contains(rule) {
+                    added.push(rule.clone());
+                }
+            }
             if override_entries {
                 // Replace all rules
                 *guard = new_rules;
             } else {
                 // Union: combine old and new, remove duplicates
                 let mut combined = old.clone();
-                for rule in new_rules {
-                    if !combined.contains(&rule) {
-                        combined.push(rule);
-                    }
-                }
+                combined.extend(added.iter().cloned());
                 *guard = combined;
             }
-            return if old.is_empty() {
-                Ok(None)
-            } else {
-                Ok(Some(old))
-            };
+            return Ok(CreatedRules {
+                previous: if old.is_empty() { None } else { Some(old) },
+                added,
+            });
         }
 
         let first = aliases.remove(0);
@@ -122,6 +217,56 @@ impl AliasNamespace {
             .clone();
         child.create_rules(aliases, new_rules, override_entries).await
     }
+
+    /// Copies this namespace and its sub-namespaces, so later changes to either leave the other
+    /// untouched.
+    #[async_recursion]
+    pub async fn deep_clone(&self) -> AliasNamespace {
+        // Clone the children out so no map lock is held across the awaits below
+        let subs: Vec<(String, Arc<AliasNamespace>)> = self
+            .sub_namespaces
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        let sub_namespaces = DashMap::new();
+        for (name, sub) in subs {
+            sub_namespaces.insert(name, Arc::new(sub.deep_clone().await));
+        }
+        AliasNamespace {
+            sub_namespaces,
+            rules: RwLock::new(self.rules.read().await.clone()),
+        }
+    }
+
+    /// Captures this namespace under `name`, with its sub-namespaces sorted by name.
+    #[async_recursion]
+    pub async fn snapshot(&self, name: String) -> AliasSnapshot {
+        let rules = self
+            .rules
+            .read()
+            .await
+            .iter()
+            .map(|rule| rule.to_string())
+            .collect();
+
+        // Clone the children out so no map lock is held across the awaits below
+        let subs: Vec<(String, Arc<AliasNamespace>)> = self
+            .sub_namespaces
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        let mut children = Vec::with_capacity(subs.len());
+        for (sub_name, sub) in subs {
+            children.push(sub.snapshot(sub_name).await);
+        }
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+
+        AliasSnapshot {
+            name,
+            rules,
+            children,
+        }
+    }
 }
 
 
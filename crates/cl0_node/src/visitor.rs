@@ -64,6 +64,9 @@ impl AstVisitor for Condition {
             Condition::Parentheses(condition) => {
                 condition.visit(f);
             }
+            Condition::AtLeast(var, _) => {
+                var.visit(f);
+            }
         }
     }
 }
@@ -154,16 +157,22 @@ impl AstVisitor for ReactiveRule {
     fn visit<F: FnMut(&dyn Any)>(&self, f: &mut F) {
         f(self);
         match self {
-            ReactiveRule::ECA { event, condition, action } => {
+            ReactiveRule::ECA { event, condition, action, else_action } => {
                 event.visit(f);
                 if let Some(cond) = condition {
                     cond.visit(f);
                 }
                 action.visit(f);
+                if let Some(else_action) = else_action {
+                    else_action.visit(f);
+                }
             }
-            ReactiveRule::CA { condition, action } => {
+            ReactiveRule::CA { condition, action, else_action } => {
                 condition.visit(f);
                 action.visit(f);
+                if let Some(else_action) = else_action {
+                    else_action.visit(f);
+                }
             }
         }
     }
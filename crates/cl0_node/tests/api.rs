@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use cl0_node::api::ApiRoute;
+use tokio::sync::Semaphore;
+
+/// Test that a full bounded route rejects notifications and that calls wait for room.
+#[tokio::test]
+async fn bounded_route_rejects_when_full() {
+    // Handlers block until the gate is opened
+    let gate = Arc::new(Semaphore::new(0));
+    let handler_gate = gate.clone();
+    let route = ApiRoute::bounded(1, move |n: u32| {
+        let gate = handler_gate.clone();
+        async move {
+            let _permit = gate.acquire().await?;
+            Ok(n)
+        }
+    });
+
+    // One request is handled, one waits for the dispatcher and one is queued; the rest is rejected
+    let mut accepted = 0;
+    while route.try_notify(accepted) {
+        accepted += 1;
+        assert!(accepted <= 3, "the bounded route should fill up");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(!route.try_notify(100));
+
+    // A call waits until the queue drains
+    let call = tokio::spawn({
+        let route = route.clone();
+        async move { route.call(42).await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!call.is_finished());
+
+    gate.add_permits(Semaphore::MAX_PERMITS);
+    let result = tokio::time::timeout(Duration::from_secs(5), call)
+        .await
+        .expect("the call should complete once the route drains")
+        .unwrap();
+    assert_eq!(result.unwrap(), 42);
+}
+
+/// Test that unbounded routes accept every notification.
+#[tokio::test]
+async fn unbounded_route_accepts_notifications() {
+    let route = ApiRoute::new(|n: u32| async move { Ok(n) });
+    for n in 0..1000 {
+        assert!(route.try_notify(n));
+    }
+    assert_eq!(route.call(7).await.unwrap(), 7);
+}
+
+/// Test that a panicking handler is reported as an error and the route keeps working.
+#[tokio::test]
+async fn handler_panic_is_an_error() {
+    let route = ApiRoute::new(|n: u32| async move {
+        if n == 0 {
+            panic!("zero is not allowed");
+        }
+        Ok(n)
+    });
+
+    let err = tokio::time::timeout(Duration::from_secs(5), route.call(0))
+        .await
+        .expect("a panicking handler should not hang the caller")
+        .expect_err("a panicking handler should be an error");
+    let message = err.to_string();
+    assert!(message.contains("terminated unexpectedly"), "unexpected error: {}", message);
+    assert!(message.contains("zero is not allowed"), "unexpected error: {}", message);
+
+    assert_eq!(route.call(1).await.unwrap(), 1);
+}
+
+/// Test that a call to a slow handler times out and the route stays usable.
+#[tokio::test]
+async fn call_timeout_expires() {
+    let route = ApiRoute::new(|delay_ms: u64| async move {
+        tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+        Ok(delay_ms)
+    });
+
+    let err = route
+        .call_timeout(10_000, Duration::from_millis(50))
+        .await
+        .expect_err("the slow call should time out");
+    assert!(err.to_string().contains("timed out"), "unexpected error: {}", err);
+
+    assert_eq!(route.call_timeout(0, Duration::from_secs(5)).await.unwrap(), 0);
+}
@@ -0,0 +1,49 @@
+use cl0_node::diff::{RuleDiff, diff_rules};
+use cl0_parser::lex_and_parse;
+
+/// Test that rules only present in the new version are reported as added.
+#[test]
+fn pure_additions() {
+    let old = lex_and_parse("#e => +a.");
+    let new = lex_and_parse("#f => +b. #e => +a.");
+    assert_eq!(
+        diff_rules(&old, &new),
+        RuleDiff {
+            added: lex_and_parse("#f => +b."),
+            removed: vec![],
+            unchanged: lex_and_parse("#e => +a."),
+        }
+    );
+}
+
+/// Test that rules missing from the new version are reported as removed.
+#[test]
+fn pure_removals() {
+    let old = lex_and_parse("#e => +a. #f => +b. #g => +c.");
+    let new = lex_and_parse("#f => +b.");
+    assert_eq!(
+        diff_rules(&old, &new),
+        RuleDiff {
+            added: vec![],
+            removed: lex_and_parse("#e => +a. #g => +c."),
+            unchanged: lex_and_parse("#f => +b."),
+        }
+    );
+}
+
+/// Test a diff with additions and removals, where reordering and renaming a compound alias
+/// count as unchanged.
+#[test]
+fn mixed_diff() {
+    let old = lex_and_parse("#e => +a. #f => +{ #g => +b. } as r. #h => +c.");
+    let new = lex_and_parse("#i => +d. #f => +{ #g => +b. } as s. #e => +a.");
+    let diff = diff_rules(&old, &new);
+    assert_eq!(diff.added, lex_and_parse("#i => +d."));
+    assert_eq!(diff.removed, lex_and_parse("#h => +c."));
+    assert_eq!(
+        diff.unchanged,
+        lex_and_parse("#f => +{ #g => +b. } as s. #e => +a.")
+    );
+    assert!(!diff.is_empty());
+    assert!(diff_rules(&old, &old).is_empty());
+}
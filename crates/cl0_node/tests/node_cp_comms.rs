@@ -1,29 +1,69 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
+use http_body_util::Full;
+use prost::Message;
+use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
 use tokio::time::sleep;
 use tokio_stream::wrappers::TcpListenerStream;
-use tonic::transport::Server;
+use tonic::{
+    Request,
+    body::Body,
+    codegen::{Bytes, http},
+    transport::{Channel, Identity, Server, ServerTlsConfig, server::Router},
+};
+use tonic_health::pb::{
+    HealthCheckRequest, health_check_response::ServingStatus, health_client::HealthClient,
+};
+use tonic_reflection::pb::v1::{
+    ServerReflectionRequest, server_reflection_client::ServerReflectionClient,
+    server_reflection_request::MessageRequest, server_reflection_response::MessageResponse,
+};
+use tower::ServiceExt;
+use tracing_test::traced_test;
 
-use cl0_node::{control_plane::new_service_instance, node_client::NodeClient};
+use cl0_node::{
+    control_plane::{
+        ControlPlaneGrpcService, ControlPlaneService, new_health_service, new_reflection_service,
+        new_service_instance, new_service_instance_with,
+    },
+    generated,
+    node::Node,
+    node_client::NodeClient,
+    types::ActivationStatus,
+};
+use cl0_parser::{
+    ast::{PrimitiveCondition, Rule},
+    lex_and_parse,
+};
 
-#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-async fn test_node_registration_and_heartbeat() -> Result<(), Box<dyn std::error::Error>> {
-    let (service, shared_state) = new_service_instance();
+async fn start_control_plane(
+    service: ControlPlaneGrpcService,
+) -> Result<SocketAddr, Box<dyn std::error::Error>> {
+    serve_in_background(Server::builder().add_service(service)).await
+}
 
+async fn serve_in_background(router: Router) -> Result<SocketAddr, Box<dyn std::error::Error>> {
     // Dynamically pick a port
     let addr: SocketAddr = "[::1]:0".parse()?;
     let listener = tokio::net::TcpListener::bind(addr).await?;
     let local_addr = listener.local_addr()?;
 
-    // Start the server in background
     tokio::spawn(async move {
-        Server::builder()
-            .add_service(service)
+        router
             .serve_with_incoming(TcpListenerStream::new(listener))
             .await
             .unwrap();
     });
 
+    Ok(local_addr)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_node_registration_and_heartbeat() -> Result<(), Box<dyn std::error::Error>> {
+    let (service, shared_state) = new_service_instance();
+
+    let local_addr = start_control_plane(service).await?;
+
     // Create and register client
     let mut client = NodeClient::new(&format!("http://{}", local_addr), "test-version").await?;
     client.register().await?;
@@ -53,19 +93,7 @@ async fn test_node_registration_and_heartbeat() -> Result<(), Box<dyn std::error
 async fn test_multi_node_registration_and_heartbeat() -> Result<(), Box<dyn std::error::Error>> {
     let (service, shared_state) = new_service_instance();
 
-    // Dynamically pick a port
-    let addr: SocketAddr = "[::1]:0".parse()?;
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    let local_addr = listener.local_addr()?;
-
-    // Start the server in background
-    tokio::spawn(async move {
-        Server::builder()
-            .add_service(service)
-            .serve_with_incoming(TcpListenerStream::new(listener))
-            .await
-            .unwrap();
-    });
+    let local_addr = start_control_plane(service).await?;
 
     // Create and register first client
     let mut client1 = NodeClient::new(&format!("http://{}", local_addr), "test-version-1").await?;
@@ -105,3 +133,365 @@ async fn test_multi_node_registration_and_heartbeat() -> Result<(), Box<dyn std:
 
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_rule_execution_through_control_plane() -> Result<(), Box<dyn std::error::Error>> {
+    let (service, shared_state) = new_service_instance();
+
+    let local_addr = start_control_plane(service).await?;
+
+    // Create and register client
+    let mut client = NodeClient::new(&format!("http://{}", local_addr), "test-version").await?;
+    client.register().await?;
+    let node_id = client.node_id.clone().expect("node should be registered");
+
+    // Send the reactive rule, then trigger it
+    for src in ["#e => +a.", "=> #e."] {
+        let rule: generated::common::Rule = lex_and_parse(src)
+            .into_iter()
+            .next()
+            .map(Rule::into)
+            .expect("rule should parse");
+        let result = client.request_rule_execution(node_id.clone(), rule).await?;
+        assert!(result.success, "rule {} failed: {}", src, result.output);
+    }
+
+    // Check the variable was created on the registered node
+    let state = shared_state.read().await;
+    let node = &state.nodes.get(&node_id.id).expect("node should exist").node;
+    let value = node
+        .vars
+        .get(&PrimitiveCondition::Var("a".to_string()))
+        .map(|v| v.value().clone());
+    assert_eq!(value, Some(ActivationStatus::True));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_stale_node_is_reaped() -> Result<(), Box<dyn std::error::Error>> {
+    let (service, shared_state) = new_service_instance_with(ControlPlaneService::with_config(
+        Duration::from_millis(100),
+        Duration::from_secs(1),
+    ));
+
+    let local_addr = start_control_plane(service).await?;
+
+    // Register without ever sending a heartbeat
+    let mut client = NodeClient::new(&format!("http://{}", local_addr), "test-version").await?;
+    client.register().await?;
+    assert_eq!(shared_state.read().await.nodes.len(), 1);
+
+    // Wait past the timeout for the reaper to run
+    sleep(Duration::from_secs(3)).await;
+
+    let state = shared_state.read().await;
+    assert!(state.nodes.is_empty(), "The stale node should have been removed");
+    assert!(state.pools.is_empty(), "The stale node's pool should have been removed");
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_unknown_node_heartbeat_reregisters() -> Result<(), Box<dyn std::error::Error>> {
+    let (service, shared_state) = new_service_instance();
+
+    let local_addr = start_control_plane(service).await?;
+
+    // A heartbeat for an id that was never registered asks for re-registration
+    let mut client = NodeClient::new(&format!("http://{}", local_addr), "test-version").await?;
+    let ack = client
+        .client
+        .heartbeat(Request::new(generated::control_plane::NodeHeartbeat {
+            id: Some(generated::common::NodeId {
+                id: "missing".into(),
+            }),
+            timestamp: 0,
+            status: None,
+        }))
+        .await?
+        .into_inner();
+    assert!(ack.should_reregister);
+
+    // Register, then forget the node on the control plane side
+    client.register().await?;
+    let old_id = client.node_id.clone().expect("node should be registered");
+    shared_state.write().await.nodes.remove(&old_id.id);
+
+    // The heartbeat loop should notice and register again
+    tokio::spawn(async move {
+        client.send_heartbeat_loop().await.unwrap();
+    });
+    sleep(Duration::from_secs(1)).await;
+
+    let state = shared_state.read().await;
+    assert_eq!(state.nodes.len(), 1, "The node should have re-registered");
+    assert!(!state.nodes.contains_key(&old_id.id));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_auth_token_required() -> Result<(), Box<dyn std::error::Error>> {
+    let (service, shared_state) =
+        new_service_instance_with(ControlPlaneService::default().with_auth_token("secret"));
+
+    let local_addr = start_control_plane(service).await?;
+
+    // Without a token the registration is rejected
+    let mut client = NodeClient::new(&format!("http://{}", local_addr), "test-version").await?;
+    let err = client
+        .client
+        .register_node(Request::new(generated::control_plane::NodeRegistration {
+            hostname: "test".into(),
+            version: "test-version".into(),
+            pool: None,
+        }))
+        .await
+        .expect_err("registration without a token should fail");
+    assert_eq!(err.code(), tonic::Code::Unauthenticated);
+    assert!(shared_state.read().await.nodes.is_empty());
+
+    // With the right token it goes through
+    let mut request = Request::new(generated::control_plane::NodeRegistration {
+        hostname: "test".into(),
+        version: "test-version".into(),
+        pool: None,
+    });
+    request
+        .metadata_mut()
+        .insert("authorization", "Bearer secret".parse()?);
+    client.client.register_node(request).await?;
+    assert_eq!(shared_state.read().await.nodes.len(), 1);
+
+    // A client configured with the token registers and sends heartbeats on its own
+    let mut authed = NodeClient::new(&format!("http://{}", local_addr), "test-version")
+        .await?
+        .with_auth_token("secret");
+    authed.register().await?;
+    authed.send_heartbeat().await?;
+    assert_eq!(shared_state.read().await.nodes.len(), 2);
+
+    // A wrong token is rejected the same way as a missing one
+    let mut wrong = NodeClient::new(&format!("http://{}", local_addr), "test-version")
+        .await?
+        .with_auth_token("secreT");
+    let err = wrong.register().await.expect_err("a wrong token should be rejected");
+    let status = err.downcast_ref::<tonic::Status>().expect("a gRPC status");
+    assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    assert_eq!(shared_state.read().await.nodes.len(), 2);
+
+    // Running with a wrong token gives up instead of reconnecting forever
+    let err = tokio::time::timeout(Duration::from_secs(5), wrong.run())
+        .await?
+        .expect_err("run should stop on a rejected token");
+    let status = err.downcast_ref::<tonic::Status>().expect("a gRPC status");
+    assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    assert_eq!(shared_state.read().await.nodes.len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_heartbeat_reports_node_status() -> Result<(), Box<dyn std::error::Error>> {
+    let (service, shared_state) = new_service_instance();
+
+    let local_addr = start_control_plane(service).await?;
+
+    // Two reactive rules, with `a`, `b` and `c` as variables
+    let node = Node::new_with_rules(Some(lex_and_parse("#e => +a. #f => +b. => +c."))).await;
+    let expected = node.observe().await;
+
+    let mut client = NodeClient::new(&format!("http://{}", local_addr), "test-version")
+        .await?
+        .with_node(node);
+    client.register().await?;
+    let id = client.node_id.clone().expect("node should be registered").id;
+    assert_eq!(shared_state.read().await.nodes[&id].status, None);
+
+    client.send_heartbeat().await?;
+
+    let state = shared_state.read().await;
+    let status = state.nodes[&id].status.expect("status should have been reported");
+    assert_eq!(status.rule_count as usize, expected.rules.len());
+    assert_eq!(status.var_count as usize, expected.vars.len());
+    assert_eq!((status.rule_count, status.var_count), (2, 3));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_run_registers_once_control_plane_is_up() -> Result<(), Box<dyn std::error::Error>> {
+    let (service, shared_state) = new_service_instance();
+
+    // Pick a free port, but don't listen on it yet
+    let addr: SocketAddr = "[::1]:0".parse()?;
+    let local_addr = tokio::net::TcpListener::bind(addr).await?.local_addr()?;
+
+    // The client starts retrying before the control plane exists
+    let client = NodeClient::new_lazy(&format!("http://{}", local_addr), "test-version")?;
+    tokio::spawn(client.run());
+    sleep(Duration::from_millis(500)).await;
+    assert!(shared_state.read().await.nodes.is_empty());
+
+    // Start the server in background
+    let listener = tokio::net::TcpListener::bind(local_addr).await?;
+    tokio::spawn(async move {
+        Server::builder()
+            .add_service(service)
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+
+    // The next retry should register the node
+    let registered = tokio::time::timeout(Duration::from_secs(5), async {
+        while shared_state.read().await.nodes.is_empty() {
+            sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+    assert!(registered.is_ok(), "The node should have registered after the server started");
+    assert_eq!(shared_state.read().await.nodes.len(), 1);
+
+    Ok(())
+}
+
+/// Writes a CA certificate and a certificate for `127.0.0.1` signed by it, with its key, into a
+/// fresh temporary directory. Returns the directory and the CA certificate, certificate and key.
+fn write_test_certs() -> Result<(PathBuf, String, String, String), Box<dyn std::error::Error>> {
+    let ca_key = KeyPair::generate()?;
+    let mut ca_params = CertificateParams::new(Vec::<String>::new())?;
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key)?;
+
+    let key = KeyPair::generate()?;
+    let cert =
+        CertificateParams::new(vec!["127.0.0.1".to_string()])?.signed_by(&key, &ca, &ca_key)?;
+
+    let dir = std::env::temp_dir().join(format!("cl0-tls-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir)?;
+    Ok((dir, ca.pem(), cert.pem(), key.serialize_pem()))
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_registration_over_tls() -> Result<(), Box<dyn std::error::Error>> {
+    let (service, shared_state) = new_service_instance();
+    let (dir, ca, cert, key) = write_test_certs()?;
+    let ca_path = dir.join("ca.pem");
+    std::fs::write(&ca_path, ca)?;
+
+    // Dynamically pick a port
+    let addr: SocketAddr = "127.0.0.1:0".parse()?;
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let local_addr = listener.local_addr()?;
+
+    // Start the TLS server in background
+    let tls = ServerTlsConfig::new().identity(Identity::from_pem(cert, key));
+    tokio::spawn(async move {
+        Server::builder()
+            .tls_config(tls)
+            .unwrap()
+            .add_service(service)
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+
+    // A plaintext client can't talk to it
+    let mut plain = NodeClient::new(&format!("http://{}", local_addr), "test-version").await?;
+    assert!(plain.register().await.is_err());
+
+    // A client trusting the CA registers
+    let mut client =
+        NodeClient::new_tls(&format!("https://{}", local_addr), "test-version", &ca_path).await?;
+    client.register().await?;
+    client.send_heartbeat().await?;
+    assert_eq!(shared_state.read().await.nodes.len(), 1);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+#[traced_test]
+async fn test_register_node_call_is_logged() -> Result<(), Box<dyn std::error::Error>> {
+    let (service, shared_state) = new_service_instance();
+
+    // A gRPC frame: no compression flag, then the big endian message length
+    let message = generated::control_plane::NodeRegistration {
+        hostname: "test".into(),
+        version: "test-version".into(),
+        pool: None,
+    }
+    .encode_to_vec();
+    let mut frame = vec![0];
+    frame.extend((message.len() as u32).to_be_bytes());
+    frame.extend(message);
+
+    let request = http::Request::builder()
+        .method("POST")
+        .uri("/control_plane.ControlPlane/RegisterNode")
+        .header("content-type", "application/grpc")
+        .body(Body::new(Full::new(Bytes::from(frame))))?;
+    let response = service.oneshot(request).await?;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert_eq!(shared_state.read().await.nodes.len(), 1);
+
+    assert!(logs_contain("method=/control_plane.ControlPlane/RegisterNode"));
+    assert!(logs_contain("grpc_status=0"));
+    assert!(logs_contain("New node registered"));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_health_reports_serving() -> Result<(), Box<dyn std::error::Error>> {
+    let (service, _shared_state) = new_service_instance();
+    let health = new_health_service().await;
+
+    let router = Server::builder().add_service(service).add_service(health);
+    let local_addr = serve_in_background(router).await?;
+
+    let channel = Channel::from_shared(format!("http://{}", local_addr))?.connect().await?;
+    let mut client = HealthClient::new(channel);
+    for service in ["", "control_plane.ControlPlane"] {
+        let response = client
+            .check(HealthCheckRequest {
+                service: service.into(),
+            })
+            .await?
+            .into_inner();
+        assert_eq!(response.status(), ServingStatus::Serving, "service {:?}", service);
+    }
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_reflection_lists_control_plane() -> Result<(), Box<dyn std::error::Error>> {
+    let (service, _shared_state) = new_service_instance();
+
+    let router = Server::builder().add_service(service).add_service(new_reflection_service());
+    let local_addr = serve_in_background(router).await?;
+
+    let channel = Channel::from_shared(format!("http://{}", local_addr))?.connect().await?;
+    let mut client = ServerReflectionClient::new(channel);
+    let request = ServerReflectionRequest {
+        host: String::new(),
+        message_request: Some(MessageRequest::ListServices(String::new())),
+    };
+    let mut responses = client
+        .server_reflection_info(tokio_stream::once(request))
+        .await?
+        .into_inner();
+    match responses.message().await?.and_then(|r| r.message_response) {
+        Some(MessageResponse::ListServicesResponse(list)) => {
+            let names: Vec<_> = list.service.iter().map(|s| s.name.as_str()).collect();
+            assert_eq!(names, vec!["control_plane.ControlPlane"]);
+        }
+        other => panic!("Expected a service list, got {:?}", other),
+    }
+
+    Ok(())
+}
@@ -1,9 +1,11 @@
-use cl0_node::node::Node;
-use cl0_node::types::{ReactiveRuleWithArgs, RuleWithArgs, ActivationStatus};
-use cl0_parser::ast::{Action, Compound, PrimitiveEvent, ReactiveRule, Rule};
+use cl0_node::diff::diff_rules;
+use cl0_node::generated;
+use cl0_node::node::{Node, NodeConfig};
+use cl0_node::types::{ActionContext, FactRuleWithArgs, ReactiveRuleWithArgs, RuleWithArgs, ActivationStatus, NodeError, NodeMetrics, Value};
+use cl0_parser::ast::{Action, ActionList, CaseRule, Compound, FactRule, PrimitiveEvent, ReactiveRule, Rule};
 use cl0_parser::{
     ast::{AtomicCondition, Condition, PrimitiveCondition},
-    lex_and_parse,
+    lex_and_parse, lex_and_parse_condition, lex_and_parse_directives, lex_and_parse_span,
 };
 
 /// Test that a node can be initialized without any rules.
@@ -146,7 +148,7 @@ async fn process_condition_check1() {
         "loaded".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_ok());
     let res = res.unwrap();
     assert!(res);
@@ -167,7 +169,7 @@ async fn process_condition_check_error1() {
         "loaded".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_err());
 }
 
@@ -185,7 +187,7 @@ async fn process_condition_check2() {
         "loaded".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_ok());
     let res = res.unwrap();
     assert!(!res);
@@ -205,7 +207,7 @@ async fn process_action_check1() {
         "loaded".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_ok());
     let res = res.unwrap();
     assert!(res);
@@ -225,7 +227,7 @@ async fn process_action_check2() {
         "loaded".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_ok());
     let res = res.unwrap();
     assert!(!res);
@@ -245,7 +247,7 @@ async fn process_action_check_error1() {
         "loaded".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_err());
 }
 
@@ -263,7 +265,7 @@ async fn process_complex_atomic_condition1() {
         "loaded".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_ok());
     let res = res.unwrap();
     assert!(!res);
@@ -283,7 +285,7 @@ async fn process_complex_atomic_condition2() {
         "loaded".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_ok());
     let res = res.unwrap();
     assert!(res);
@@ -303,7 +305,7 @@ async fn process_complex_atomic_condition_alias1() {
         "loaded".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_ok());
     let res = res.unwrap();
     assert!(res);
@@ -323,7 +325,7 @@ async fn process_complex_atomic_condition_alias2() {
         "loaded".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_ok());
     let res = res.unwrap();
     assert!(!res);
@@ -343,7 +345,7 @@ async fn process_complex_atomic_condition_alias3_1() {
         "action".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_ok());
     let res = res.unwrap();
     assert!(res);
@@ -363,7 +365,7 @@ async fn process_complex_atomic_condition_alias3_2() {
         "action".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_ok());
     let res = res.unwrap();
     assert!(!res);
@@ -383,7 +385,7 @@ async fn process_complex_atomic_condition_alias_err1() {
         "loaded".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_ok());
     let res = res.unwrap();
     assert!(!res);
@@ -403,7 +405,7 @@ async fn process_complex_atomic_condition_alias4() {
         "loaded".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_ok());
     let res = res.unwrap();
     assert!(!res);
@@ -423,7 +425,7 @@ async fn process_complex_atomic_condition_alias5() {
         "loaded".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_ok());
     let res = res.unwrap();
     assert!(res);
@@ -443,7 +445,7 @@ async fn test_fact_primitive() {
         "loaded".to_string(),
     )));
 
-    let res = node.process_condition(&condition).await;
+    let res = node.process_condition(&condition, 0).await;
     assert!(res.is_ok());
     let res = res.unwrap();
     assert!(res);
@@ -464,11 +466,12 @@ async fn test_fact_compound() {
             event: PrimitiveEvent::Trigger("e".to_string()),
             condition: None,
             action: Action::Primitive(PrimitiveEvent::Trigger("a".to_string())),
+            else_action: None,
         })],
         alias: Some("r".to_string()),
     });
 
-    let res = node.get_atomic_condition(condition, None).await;
+    let res = node.get_atomic_condition(condition, None, 0).await;
     assert!(res.is_ok());
     let res = res.unwrap();
     assert_eq!(res, ActivationStatus::False);
@@ -500,6 +503,7 @@ async fn test_fact_sub_compound1() {
             event: PrimitiveEvent::Trigger("e".to_string()),
             condition: None,
             action: Action::Primitive(PrimitiveEvent::Trigger("a1".to_string())),
+            else_action: None,
         })],
         alias: Some("r".to_string()),
     });
@@ -510,13 +514,14 @@ async fn test_fact_sub_compound1() {
             event: PrimitiveEvent::Trigger("e".to_string()),
             condition: None,
             action: Action::Primitive(PrimitiveEvent::Trigger("a2".to_string())),
+            else_action: None,
         })],
         alias: Some("r".to_string()),
     });
 
     let res = node
         .clone()
-        .get_atomic_condition(condition1.clone(), None)
+        .get_atomic_condition(condition1.clone(), None, 0)
         .await;
     assert!(res.is_ok());
 
@@ -525,7 +530,7 @@ async fn test_fact_sub_compound1() {
 
     let res = node
         .clone()
-        .get_atomic_condition(condition2.clone(), None)
+        .get_atomic_condition(condition2.clone(), None, 0)
         .await;
     assert!(res.is_ok());
 
@@ -536,7 +541,7 @@ async fn test_fact_sub_compound1() {
 
     let res = node
         .clone()
-        .get_atomic_condition(condition1.clone(), None)
+        .get_atomic_condition(condition1.clone(), None, 0)
         .await;
     assert!(res.is_ok());
 
@@ -545,7 +550,7 @@ async fn test_fact_sub_compound1() {
 
     let res = node
         .clone()
-        .get_atomic_condition(condition2.clone(), None)
+        .get_atomic_condition(condition2.clone(), None, 0)
         .await;
     assert!(res.is_ok());
 
@@ -553,6 +558,50 @@ async fn test_fact_sub_compound1() {
     assert_eq!(res, ActivationStatus::False);
 }
 
+/// Test that compounds can be checked from source, as in `test_fact_sub_compound1`.
+#[tokio::test]
+async fn is_compound_active_from_source() {
+    let rules = lex_and_parse("=> +{#e => #a1. #e => #a2.} as r.");
+    let node = Node::new_with_rules(Some(rules)).await;
+
+    let more_rules = lex_and_parse("=> -r.{#e => #a2.}.")
+        .into_iter()
+        .filter_map(|r| {
+            if let Rule::Case(cr) = r {
+                Some(RuleWithArgs::Case(cr))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let first = "{#e => #a1.} as r";
+    let second = "{#e => #a2.} as r";
+    assert_eq!(
+        node.clone().is_compound_active(first).await.unwrap(),
+        ActivationStatus::True
+    );
+    assert_eq!(
+        node.clone().is_compound_active(second).await.unwrap(),
+        ActivationStatus::True
+    );
+
+    node.api.new_rules.notify(more_rules);
+
+    assert_eq!(
+        node.clone().is_compound_active(first).await.unwrap(),
+        ActivationStatus::True
+    );
+    assert_eq!(
+        node.clone().is_compound_active(second).await.unwrap(),
+        ActivationStatus::False
+    );
+
+    // Bad sources are reported rather than ending the process
+    assert!(node.clone().is_compound_active("{#e => .}").await.is_err());
+    assert!(node.is_compound_active("{#e => #a1.} trailing").await.is_err());
+}
+
 #[tokio::test]
 async fn test_fact_sub_compound2() {
     // Define new rules to init the node with
@@ -579,6 +628,7 @@ async fn test_fact_sub_compound2() {
             event: PrimitiveEvent::Trigger("e".to_string()),
             condition: None,
             action: Action::Primitive(PrimitiveEvent::Trigger("a1".to_string())),
+            else_action: None,
         })],
         alias: Some("r".to_string()),
     });
@@ -589,13 +639,14 @@ async fn test_fact_sub_compound2() {
             event: PrimitiveEvent::Trigger("e".to_string()),
             condition: None,
             action: Action::Primitive(PrimitiveEvent::Trigger("a2".to_string())),
+            else_action: None,
         })],
         alias: Some("r".to_string()),
     });
 
     let res = node
         .clone()
-        .get_atomic_condition(condition1.clone(), None)
+        .get_atomic_condition(condition1.clone(), None, 0)
         .await;
     assert!(res.is_ok());
 
@@ -604,7 +655,7 @@ async fn test_fact_sub_compound2() {
 
     let res = node
         .clone()
-        .get_atomic_condition(condition2.clone(), None)
+        .get_atomic_condition(condition2.clone(), None, 0)
         .await;
     assert!(res.is_ok());
 
@@ -615,7 +666,7 @@ async fn test_fact_sub_compound2() {
 
     let res = node
         .clone()
-        .get_atomic_condition(condition1.clone(), None)
+        .get_atomic_condition(condition1.clone(), None, 0)
         .await;
     assert!(res.is_ok());
 
@@ -624,7 +675,7 @@ async fn test_fact_sub_compound2() {
 
     let res = node
         .clone()
-        .get_atomic_condition(condition2.clone(), None)
+        .get_atomic_condition(condition2.clone(), None, 0)
         .await;
     assert!(res.is_ok());
 
@@ -696,17 +747,17 @@ async fn test_action_list_parallel() {
         "a3".to_string(),
     )));
 
-    let res = node.clone().process_condition(&condition1).await;
+    let res = node.clone().process_condition(&condition1, 0).await;
     assert!(res.is_ok());
     let res1 = res.unwrap();
 
 
-    let res = node.clone().process_condition(&condition2).await;
+    let res = node.clone().process_condition(&condition2, 0).await;
     assert!(res.is_ok());
     let res2 = res.unwrap();
 
 
-    let res = node.clone().process_condition(&condition3).await;
+    let res = node.clone().process_condition(&condition3, 0).await;
     assert!(res.is_ok());
     let res3 = res.unwrap();
 
@@ -726,3 +777,1323 @@ async fn test_action_list_alternate_simple() {
 
     assert_eq!(node_rules.len(), 1);
 }
+
+/// Test that a CC rule derives its condition from a premise that already holds.
+#[tokio::test]
+async fn declarative_cc_rule() {
+    let rules = lex_and_parse("a. a -> b.");
+    let node = Node::new_with_rules(Some(rules)).await;
+
+    let b = PrimitiveCondition::Var("b".to_string());
+    assert_eq!(node.vars.get(&b).map(|v| v.clone()), Some(ActivationStatus::True));
+}
+
+/// Test that a CC rule stays in force and fires once its premise becomes true later.
+#[tokio::test]
+async fn declarative_cc_rule_persists() {
+    let node = Node::new_with_rules(Some(lex_and_parse("a -> b."))).await;
+    let b = PrimitiveCondition::Var("b".to_string());
+    assert_eq!(node.vars.get(&b).map(|v| v.clone()), Some(ActivationStatus::False));
+
+    let more_rules = lex_and_parse("a.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(more_rules).await.unwrap();
+    assert_eq!(node.vars.get(&b).map(|v| v.clone()), Some(ActivationStatus::True));
+
+    // Consuming b re-derives it as long as a holds
+    let more_rules = lex_and_parse("=> -b.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(more_rules).await.unwrap();
+    assert_eq!(node.vars.get(&b).map(|v| v.clone()), Some(ActivationStatus::True));
+}
+
+/// Test that a CT rule fires only once.
+#[tokio::test]
+async fn declarative_ct_rule_fires_once() {
+    let node = Node::new_with_rules(Some(lex_and_parse("a. a -o b."))).await;
+    let b = PrimitiveCondition::Var("b".to_string());
+    assert_eq!(node.vars.get(&b).map(|v| v.clone()), Some(ActivationStatus::True));
+    assert!(node.declarative_rules.is_empty());
+
+    // The rule was consumed, so b stays consumed
+    let more_rules = lex_and_parse("=> -b.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(more_rules).await.unwrap();
+    assert_eq!(node.vars.get(&b).map(|v| v.clone()), Some(ActivationStatus::False));
+}
+
+/// Test that a CC rule over a compound without an alias is refused instead of being stored
+/// again on every evaluation.
+#[tokio::test]
+async fn declarative_cc_rule_over_unaliased_compound() {
+    let node = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        Node::new_with_rules(Some(lex_and_parse("a. a -> { #e => +b. }."))),
+    )
+    .await
+    .expect("creating the node should not hang");
+    assert!(node.declarative_rules.is_empty());
+
+    let rules = lex_and_parse("a -> { #e => +b. }.").into_iter().map(RuleWithArgs::from).collect();
+    let res = tokio::time::timeout(std::time::Duration::from_secs(5), node.api.new_rules.call(rules))
+        .await
+        .expect("adding the rule should not hang");
+    let err = res.expect_err("the rule should be refused");
+    assert!(
+        matches!(err.downcast_ref::<NodeError>(), Some(NodeError::UnaliasedImplication(rule)) if rule == "a -> { #e => +b. }."),
+        "unexpected error: {}",
+        err
+    );
+    assert!(node.declarative_rules.is_empty());
+
+    // With an alias the compound can be read back, so the rule settles
+    let rules = lex_and_parse("a -> { #e => +b. } as r.").into_iter().map(RuleWithArgs::from).collect();
+    tokio::time::timeout(std::time::Duration::from_secs(5), node.api.new_rules.call(rules))
+        .await
+        .expect("adding the rule should not hang")
+        .unwrap();
+    assert!(node.aliases.contains_key("r"));
+}
+
+/// Test that a rule removed from its handler is no longer listed.
+#[tokio::test]
+async fn remove_rule_from_handler() {
+    let node = Node::new_with_rules(Some(lex_and_parse("#e => +a. #e => +b."))).await;
+    let handler = node.event_handlers.get("e").map(|h| h.value().clone()).unwrap();
+    let rule = lex_and_parse("#e => +a.")
+        .into_iter()
+        .find_map(|r| match r {
+            Rule::Reactive(rr) => Some(rr),
+            _ => None,
+        })
+        .unwrap();
+
+    let removed = handler
+        .api
+        .remove_rule
+        .call(ReactiveRuleWithArgs::new(rule.clone(), ActivationStatus::False, None))
+        .await
+        .unwrap();
+    assert!(removed);
+
+    let rules = node.api.get_rules.call(true).await.unwrap();
+    assert_eq!(rules.len(), 1);
+    assert!(rules.iter().all(|r| r.rule != rule));
+
+    // Removing it again reports that nothing was there
+    let removed = handler
+        .api
+        .remove_rule
+        .call(ReactiveRuleWithArgs::new(rule, ActivationStatus::False, None))
+        .await
+        .unwrap();
+    assert!(!removed);
+}
+
+/// Test that consuming an alias removes its reactive rules from the handlers.
+#[tokio::test]
+async fn consume_alias_removes_rules() {
+    let node = Node::new_with_rules(Some(lex_and_parse("=> +{#e => +a.} as r."))).await;
+    assert_eq!(node.api.get_rules.call(true).await.unwrap().len(), 1);
+
+    let more_rules = lex_and_parse("=> -r.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(more_rules).await.unwrap();
+    assert!(node.api.get_rules.call(true).await.unwrap().is_empty());
+
+    // The compound reads as disabled and can be activated again
+    let more_rules = lex_and_parse("=> +r. => #e.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(more_rules).await.unwrap();
+    let a = PrimitiveCondition::Var("a".to_string());
+    assert_eq!(node.vars.get(&a).map(|v| v.clone()), Some(ActivationStatus::True));
+}
+
+/// Test that handlers left without enabled rules are pruned after a consumption.
+#[tokio::test]
+async fn gc_handlers_after_retraction() {
+    let node = Node::new_with_rules(Some(lex_and_parse("=> +{#e => +a.} as r."))).await;
+    assert_eq!(node.event_handlers.len(), 1);
+
+    let more_rules = lex_and_parse("=> -r.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(more_rules).await.unwrap();
+    assert_eq!(node.event_handlers.len(), 0);
+    assert_eq!(node.gc_handlers(), 0);
+}
+
+/// Test that triggering the event of a pruned handler is still a no-op, not an invalid action.
+#[tokio::test]
+async fn trigger_pruned_handler() {
+    let node = Node::new_with_rules(Some(lex_and_parse("=> +{#e => +a.} as r."))).await;
+
+    let retract = lex_and_parse("=> -r.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(retract).await.unwrap();
+    assert_eq!(node.event_handlers.len(), 0);
+
+    let trigger = lex_and_parse("=> #e.").into_iter().map(RuleWithArgs::from).collect();
+    assert_eq!(node.api.new_rules.call(trigger).await.unwrap(), vec![true]);
+    assert_eq!(node.value(&"a".into()), Some(Value::Status(ActivationStatus::False)));
+
+    // Events that never had a handler are still invalid
+    let trigger = lex_and_parse("=> #f.").into_iter().map(RuleWithArgs::from).collect();
+    assert!(node.api.new_rules.call(trigger).await.is_err());
+
+    // Adding the rule back brings the handler back to life
+    let restore = lex_and_parse("=> +{#e => +a.} as r. => #e.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(restore).await.unwrap();
+    assert_eq!(node.value(&"a".into()), Some(Value::Status(ActivationStatus::True)));
+}
+
+/// Test that only the latest pruned handlers are remembered, so retracting many distinct rules
+/// doesn't grow the node without bound.
+#[tokio::test]
+async fn retired_handlers_are_bounded() {
+    let config = NodeConfig {
+        retired_handlers_capacity: 2,
+        ..NodeConfig::default()
+    };
+    let rules = lex_and_parse("=> +{#e1 => +a.} as r1. => +{#e2 => +a.} as r2. => +{#e3 => +a.} as r3.");
+    let node = Node::new_with_config(Some(rules), config).await;
+
+    for alias in ["r1", "r2", "r3"] {
+        let retract = lex_and_parse(&format!("=> -{}.", alias)).into_iter().map(RuleWithArgs::from).collect();
+        node.api.new_rules.call(retract).await.unwrap();
+    }
+    assert_eq!(node.event_handlers.len(), 0);
+
+    // The oldest one was forgotten and reads as unknown again
+    let trigger = lex_and_parse("=> #e1.").into_iter().map(RuleWithArgs::from).collect();
+    assert!(node.api.new_rules.call(trigger).await.is_err());
+    for event in ["e2", "e3"] {
+        let trigger = lex_and_parse(&format!("=> #{}.", event)).into_iter().map(RuleWithArgs::from).collect();
+        assert_eq!(node.api.new_rules.call(trigger).await.unwrap(), vec![true], "{}", event);
+    }
+}
+
+/// Test that activation statuses print with the same names as their variants.
+#[test]
+fn activation_status_display() {
+    assert_eq!(ActivationStatus::True.to_string(), "True");
+    assert_eq!(ActivationStatus::False.to_string(), "False");
+    assert_eq!(ActivationStatus::Conflict.to_string(), "Conflict");
+}
+
+/// Test that seeded nodes pick the same branches of alternative actions.
+#[tokio::test]
+async fn seeded_alternative_is_deterministic() {
+    let src = "=> +a1 alt +a2 alt +a3 alt +a4. => +b1 alt +b2 alt +b3 alt +b4. => +c1 alt +c2 alt +c3 alt +c4.";
+    let picked = |node: std::sync::Arc<Node>| {
+        let mut vars: Vec<String> = node
+            .vars
+            .iter()
+            .filter(|v| *v.value() == ActivationStatus::True)
+            .map(|v| v.key().to_string())
+            .collect();
+        vars.sort();
+        vars
+    };
+
+    for seed in [1, 7, 42] {
+        let first = Node::new_with_rules_seeded(Some(lex_and_parse(src)), seed).await;
+        let second = Node::new_with_rules_seeded(Some(lex_and_parse(src)), seed).await;
+
+        let picked_first = picked(first);
+        assert_eq!(picked_first.len(), 3);
+        assert_eq!(picked_first, picked(second));
+    }
+}
+
+/// Test that each step of a sequence sees the effects of the previous ones.
+#[tokio::test]
+async fn sequence_runs_in_order() {
+    let rules = lex_and_parse("#e: a => +b. #f: b => +c. => +a; #e; #f. => #g; +d.");
+    let node = Node::new_with_rules(Some(rules)).await;
+
+    for var in ["a", "b", "c"] {
+        let var = PrimitiveCondition::Var(var.to_string());
+        assert_eq!(node.vars.get(&var).map(|v| v.clone()), Some(ActivationStatus::True));
+    }
+
+    // A failing step stops the sequence before the next one starts
+    let d = PrimitiveCondition::Var("d".to_string());
+    assert_ne!(node.vars.get(&d).map(|v| v.clone()), Some(ActivationStatus::True));
+}
+
+/// Test that a snapshot lists the node's rules, variables and alias namespaces.
+#[tokio::test]
+async fn observe_snapshot() {
+    let node = Node::new_with_rules(Some(lex_and_parse("#e => +a. {#f => +b.} as r. c."))).await;
+    let snapshot = node.observe().await;
+
+    let rule_text = |src: &str| lex_and_parse(src)[0].to_string();
+    let rules: Vec<(Option<Vec<String>>, String, ActivationStatus)> = snapshot
+        .rules
+        .iter()
+        .map(|r| (r.alias.clone(), r.rule.clone(), r.status.clone()))
+        .collect();
+    assert_eq!(
+        rules,
+        vec![
+            (None, rule_text("#e => +a."), ActivationStatus::True),
+            (Some(vec!["r".to_string()]), rule_text("#f => +b."), ActivationStatus::False),
+        ]
+    );
+
+    let vars: Vec<(String, ActivationStatus)> = snapshot
+        .vars
+        .iter()
+        .map(|v| (v.name.clone(), v.status.clone()))
+        .collect();
+    assert!(vars.contains(&("c".to_string(), ActivationStatus::True)));
+    assert!(vars.contains(&("a".to_string(), ActivationStatus::False)));
+
+    assert_eq!(snapshot.aliases.len(), 1);
+    assert_eq!(snapshot.aliases[0].name, "r");
+    assert_eq!(snapshot.aliases[0].rules, vec![rule_text("#f => +b.")]);
+    assert!(snapshot.aliases[0].children.is_empty());
+
+    // The snapshot survives a JSON round trip
+    let json = serde_json::to_string(&snapshot).unwrap();
+    assert_eq!(serde_json::from_str::<cl0_node::types::NodeSnapshot>(&json).unwrap(), snapshot);
+}
+
+/// Test that opposing writes in one parallel action leave the variable in conflict.
+#[tokio::test]
+async fn parallel_opposing_writes_conflict() {
+    let node = Node::new_with_rules(Some(lex_and_parse("=> +x, -x, +y."))).await;
+
+    let x = PrimitiveCondition::Var("x".to_string());
+    assert_eq!(node.vars.get(&x).map(|v| v.clone()), Some(ActivationStatus::Conflict));
+
+    // Unrelated writes in the same action still go through
+    let y = PrimitiveCondition::Var("y".to_string());
+    assert_eq!(node.vars.get(&y).map(|v| v.clone()), Some(ActivationStatus::True));
+}
+
+/// Test that a condition string can be queried against a node.
+#[tokio::test]
+async fn query_condition() {
+    let node = Node::new_with_rules(Some(lex_and_parse("#e => +loaded. ready."))).await;
+
+    assert!(node.clone().query("not loaded").await.unwrap());
+    assert!(!node.clone().query("loaded and ready").await.unwrap());
+    assert!(node.clone().query("loaded or ready").await.unwrap());
+
+    // Parse errors come back as errors
+    assert!(node.clone().query("loaded and").await.is_err());
+}
+
+/// Test that unset variables fail conditions by default and read as false when configured to.
+#[tokio::test]
+async fn treat_unknown_as_false() {
+    let condition = Condition::Not(Box::new(Condition::Atomic(AtomicCondition::Primitive(
+        PrimitiveCondition::Var("loaded".to_string()),
+    ))));
+
+    let node = Node::new_with_rules(Some(lex_and_parse("#e => +a."))).await;
+    assert!(node.process_condition(&condition, 0).await.is_err());
+
+    let config = NodeConfig {
+        treat_unknown_as_false: true,
+        ..NodeConfig::default()
+    };
+    let node = Node::new_with_config(Some(lex_and_parse("#e => +a.")), config).await;
+    assert!(node.clone().process_condition(&condition, 0).await.unwrap());
+
+    // Variables that are set keep their value
+    assert!(!node.clone().query("a").await.unwrap());
+}
+
+/// Test that a wide conjunction of variables gives the same results as the recursive evaluation.
+#[tokio::test]
+async fn wide_conjunction_matches_recursive_path() {
+    let count = 2000;
+    let facts: String = (0..count).map(|i| format!("v{}. ", i)).collect();
+    let node = Node::new_with_rules(Some(lex_and_parse(&format!("{} down. => -down.", facts)))).await;
+
+    let var = |name: String| Condition::Atomic(AtomicCondition::Primitive(PrimitiveCondition::Var(name)));
+    // Wrapping each conjunct in parentheses forces the recursive path
+    let both = |conds: Vec<Condition>| {
+        let recursive = conds.iter().cloned().map(|c| Condition::Parentheses(Box::new(c))).collect();
+        (Condition::Conjunction(conds), Condition::Conjunction(recursive))
+    };
+
+    let all_true: Vec<Condition> = (0..count).map(|i| var(format!("v{}", i))).collect();
+    let mut one_false = all_true.clone();
+    one_false.insert(count / 2, var("down".to_string()));
+    let mut negated = all_true.clone();
+    negated.push(Condition::Not(Box::new(var("down".to_string()))));
+    let mut unset = all_true.clone();
+    unset.push(var("missing".to_string()));
+    let mut false_before_unset = vec![var("down".to_string())];
+    false_before_unset.extend(unset.clone());
+
+    for (conds, expected) in [
+        (all_true, Some(true)),
+        (one_false, Some(false)),
+        (negated, Some(true)),
+        (unset, None),
+        (false_before_unset, Some(false)),
+    ] {
+        let (fast, recursive) = both(conds);
+        let fast = node.clone().process_condition(&fast, 0).await.ok();
+        let recursive = node.clone().process_condition(&recursive, 0).await.ok();
+        assert_eq!(fast, expected);
+        assert_eq!(fast, recursive);
+    }
+}
+
+/// Test that variable changes are announced once, and unchanged writes not at all.
+#[tokio::test]
+async fn subscribe_vars_reports_changes() {
+    let node = Node::new_with_rules(None).await;
+    let mut rx = node.subscribe_vars();
+
+    let rules = lex_and_parse("=> +loaded.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(rules).await.unwrap();
+    assert_eq!(
+        rx.try_recv().unwrap(),
+        (PrimitiveCondition::Var("loaded".to_string()), ActivationStatus::True)
+    );
+    assert!(rx.try_recv().is_err());
+
+    // Producing it again changes nothing
+    let rules = lex_and_parse("=> +loaded.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(rules).await.unwrap();
+    assert!(rx.try_recv().is_err());
+}
+
+/// Test that resetting a node clears its rules, variables and aliases.
+#[tokio::test]
+async fn reset_clears_state() {
+    let node = Node::new_with_rules(Some(lex_and_parse("#e => +a. {#f => +b.} as r. c. c -> d."))).await;
+    assert!(!node.api.get_rules.call(true).await.unwrap().is_empty());
+
+    node.reset();
+    assert!(node.api.get_rules.call(true).await.unwrap().is_empty());
+    assert!(node.vars.is_empty());
+    assert!(node.aliases.is_empty());
+    assert!(node.declarative_rules.is_empty());
+
+    // The node keeps working afterwards
+    let rules = lex_and_parse("=> +loaded.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(rules).await.unwrap();
+    assert!(node.clone().query("loaded").await.unwrap());
+}
+
+/// Test that distinct CA rules are kept in distinct handlers.
+#[tokio::test]
+async fn ca_rules_get_distinct_handlers() {
+    let node = Node::new_with_rules(Some(lex_and_parse(": a => +x. : b and c => +y. : a => +z."))).await;
+
+    let mut ids: Vec<String> = node.event_handlers.iter().map(|h| h.key().clone()).collect();
+    ids.sort();
+    assert_eq!(ids, vec![":a".to_string(), ":b and c".to_string()]);
+    assert_eq!(node.api.get_rules.call(true).await.unwrap().len(), 3);
+}
+
+/// Test how a handler aggregates the statuses of its rules, including when it has none.
+#[tokio::test]
+async fn handler_state_aggregation() {
+    let node = Node::new_with_rules(Some(lex_and_parse("#e => +a."))).await;
+    let handler = node.event_handlers.get("e").map(|h| h.value().clone()).unwrap();
+    let rule = |src: &str| match lex_and_parse(src).remove(0) {
+        Rule::Reactive(rr) => rr,
+        _ => unreachable!(),
+    };
+
+    // Single rule
+    assert_eq!(handler.state().await, Some(ActivationStatus::True));
+
+    // Disabled and conflicting rules without an enabled one
+    handler
+        .add_rule(ReactiveRuleWithArgs::new(rule("#e => +a."), ActivationStatus::False, None))
+        .await
+        .unwrap();
+    assert_eq!(handler.state().await, Some(ActivationStatus::False));
+    handler
+        .add_rule(ReactiveRuleWithArgs::new(rule("#e => +b."), ActivationStatus::Conflict, None))
+        .await
+        .unwrap();
+    assert_eq!(handler.state().await, Some(ActivationStatus::False));
+
+    // Any enabled rule wins
+    handler
+        .add_rule(ReactiveRuleWithArgs::new(rule("#e => +c."), ActivationStatus::True, None))
+        .await
+        .unwrap();
+    assert_eq!(handler.state().await, Some(ActivationStatus::True));
+
+    // Only conflicting rules
+    for src in ["#e => +a.", "#e => +c."] {
+        handler
+            .api
+            .remove_rule
+            .call(ReactiveRuleWithArgs::new(rule(src), ActivationStatus::True, None))
+            .await
+            .unwrap();
+    }
+    assert_eq!(handler.state().await, Some(ActivationStatus::Conflict));
+
+    // No rules at all
+    handler
+        .api
+        .remove_rule
+        .call(ReactiveRuleWithArgs::new(rule("#e => +b."), ActivationStatus::True, None))
+        .await
+        .unwrap();
+    assert_eq!(handler.state().await, None);
+
+    // Triggering an empty handler does nothing
+    let rules = lex_and_parse("=> #e.").into_iter().map(RuleWithArgs::from).collect();
+    assert!(node.api.new_rules.call(rules).await.is_ok());
+}
+
+/// Test that a handler evaluates its rules from the highest priority down, ties in insertion order.
+#[tokio::test]
+async fn handler_evaluates_rules_by_priority() {
+    let node = Node::new_with_rules(Some(lex_and_parse("#e: b => +c."))).await;
+    let handler = node.event_handlers.get("e").map(|h| h.value().clone()).unwrap();
+    let rule = |src: &str| match lex_and_parse(src).remove(0) {
+        Rule::Reactive(rr) => rr,
+        _ => unreachable!(),
+    };
+
+    // Each rule only fires when the previous one already ran
+    handler
+        .add_rule(ReactiveRuleWithArgs::new(rule("#e: a => +b."), ActivationStatus::True, None).with_priority(5))
+        .await
+        .unwrap();
+    handler
+        .add_rule(ReactiveRuleWithArgs::new(rule("#e => +a."), ActivationStatus::True, None).with_priority(10))
+        .await
+        .unwrap();
+    handler
+        .add_rule(ReactiveRuleWithArgs::new(rule("#e: c => +d."), ActivationStatus::True, None))
+        .await
+        .unwrap();
+
+    let order: Vec<(String, i32)> = handler
+        .api
+        .get_rules
+        .call(true)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.rule.to_string(), r.priority))
+        .collect();
+    assert_eq!(
+        order,
+        vec![
+            (rule("#e => +a.").to_string(), 10),
+            (rule("#e: a => +b.").to_string(), 5),
+            (rule("#e: b => +c.").to_string(), 0),
+            (rule("#e: c => +d.").to_string(), 0),
+        ]
+    );
+
+    let rules = lex_and_parse("=> #e.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(rules).await.unwrap();
+    for var in ["a", "b", "c", "d"] {
+        assert!(node.clone().query(var).await.unwrap(), "{} should be set", var);
+    }
+}
+
+/// Test that events triggering each other in a cycle fail instead of running forever.
+#[tokio::test]
+async fn trigger_cycle_is_an_error() {
+    let node = Node::new_with_rules(Some(lex_and_parse("#a => #b. #b => #a. #c => +done."))).await;
+
+    let rules = lex_and_parse("=> #a.").into_iter().map(RuleWithArgs::from).collect();
+    let res = tokio::time::timeout(std::time::Duration::from_secs(5), node.api.new_rules.call(rules))
+        .await
+        .expect("a trigger cycle should not hang");
+    let err = res.expect_err("a trigger cycle should be an error");
+    assert!(err.to_string().contains("a -> b -> a"), "unexpected error: {}", err);
+
+    // Triggering the same event twice in a row is not a cycle
+    let rules = lex_and_parse("=> #c; #c.").into_iter().map(RuleWithArgs::from).collect();
+    assert!(node.api.new_rules.call(rules).await.is_ok());
+}
+
+/// Test that conditions and actions nested beyond the configured depth fail instead of recursing further.
+#[tokio::test]
+async fn nesting_depth_limit() {
+    let nested = |levels: usize| {
+        let mut condition = Condition::Atomic(AtomicCondition::Primitive(PrimitiveCondition::Var(
+            "a".to_string(),
+        )));
+        for _ in 0..levels {
+            condition = Condition::Parentheses(Box::new(condition));
+        }
+        condition
+    };
+
+    let node = Node::new_with_rules(Some(lex_and_parse("a."))).await;
+    assert!(node.clone().process_condition(&nested(10), 0).await.unwrap());
+    let err = node
+        .clone()
+        .process_condition(&nested(300), 0)
+        .await
+        .expect_err("nesting beyond the default limit should fail");
+    assert!(err.to_string().contains("Maximum nesting depth of 256 exceeded"), "unexpected error: {}", err);
+
+    // The limit is configurable, and applies to actions as well
+    let config = NodeConfig {
+        max_depth: 4,
+        ..NodeConfig::default()
+    };
+    let node = Node::new_with_config(Some(lex_and_parse("a.")), config).await;
+    assert!(node.clone().process_condition(&nested(10), 0).await.is_err());
+
+    let mut action = Action::Primitive(PrimitiveEvent::Production(AtomicCondition::Primitive(
+        PrimitiveCondition::Var("b".to_string()),
+    )));
+    for _ in 0..10 {
+        action = Action::List(ActionList::Sequence(vec![action]));
+    }
+    let rules = vec![RuleWithArgs::Case(CaseRule { action })];
+    assert!(node.api.new_rules.call(rules).await.is_err());
+}
+
+/// Test that `new_rules` reports for each rule whether it was applied.
+#[tokio::test]
+async fn new_rules_reports_each_result() {
+    let node = Node::new_with_rules(None).await;
+
+    let rules = lex_and_parse("#e : missing => +a. #f => +b. ready.")
+        .into_iter()
+        .map(RuleWithArgs::from)
+        .collect();
+    let results = node.api.new_rules.call(rules).await.unwrap();
+    assert_eq!(results, vec![true, true, true]);
+
+    // The condition of #e can't be evaluated, so that rule is not applied
+    let rules = lex_and_parse("=> #e. => #f.").into_iter().map(RuleWithArgs::from).collect();
+    let results = node.api.new_rules.call(rules).await.unwrap();
+    assert_eq!(results, vec![false, true]);
+
+    assert!(node.api.new_rules.call(vec![]).await.unwrap().is_empty());
+}
+
+/// Test that an invalid action points back at the source range of the rule that ran it.
+#[tokio::test]
+async fn invalid_action_error_has_span() {
+    let node = Node::new_with_rules(None).await;
+
+    let src = "#e => +a. => #missing.";
+    let rules = lex_and_parse_span(src).into_iter().map(RuleWithArgs::from).collect();
+    let err = node.api.new_rules.call(rules).await.unwrap_err();
+    match err.downcast_ref::<NodeError>() {
+        Some(NodeError::InvalidAction { event, span: Some(span) }) => {
+            assert_eq!(event, "missing");
+            assert_eq!(&src[span.start..span.end], "=> #missing.");
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    // Without a span the error still names the event
+    let rules = lex_and_parse("=> #missing.").into_iter().map(RuleWithArgs::from).collect();
+    let err = node.api.new_rules.call(rules).await.unwrap_err();
+    assert_eq!(err.to_string(), "Invalid action: missing");
+}
+
+/// Test that `@interleaving` runs parallel branches in a schedule fixed by the seed.
+#[tokio::test]
+async fn interleaving_is_deterministic_given_seed() {
+    let schedule = |seed: u64| async move {
+        let config = NodeConfig {
+            seed: Some(seed),
+            ..NodeConfig::default()
+        }
+        .with_directives(&lex_and_parse_directives("@interleaving").unwrap());
+        assert!(config.interleaving);
+
+        let node = Node::new_with_config(None, config).await;
+        let mut rx = node.subscribe_vars();
+        let rules = lex_and_parse("=> +a, +b.").into_iter().map(RuleWithArgs::from).collect();
+        assert_eq!(node.api.new_rules.call(rules).await.unwrap(), vec![true]);
+
+        let mut order = Vec::new();
+        while let Ok((var, value)) = rx.try_recv() {
+            assert_eq!(value, ActivationStatus::True);
+            order.push(var.to_string());
+        }
+        order
+    };
+
+    let mut seen = std::collections::HashSet::new();
+    for seed in 0..20 {
+        let first = schedule(seed).await;
+        assert_eq!(first.len(), 2);
+        assert_eq!(first, schedule(seed).await);
+        seen.insert(first);
+    }
+    // Different seeds pick different schedules
+    assert_eq!(seen.len(), 2);
+}
+
+/// Test that `@external` variables are left unset and external events can't be produced locally.
+#[tokio::test]
+async fn external_directives() {
+    let directives = lex_and_parse_directives("@external(sensor) @external(#alarm)").unwrap();
+    let config = NodeConfig::default().with_directives(&directives);
+    let node = Node::new_with_config(Some(lex_and_parse("#e: sensor => +a.")), config).await;
+
+    // Local variables start out false, the external one is unknown until it is set
+    let a = PrimitiveCondition::Var("a".to_string());
+    let sensor = PrimitiveCondition::Var("sensor".to_string());
+    assert_eq!(node.vars.get(&a).map(|v| v.clone()), Some(ActivationStatus::False));
+    assert!(node.vars.get(&sensor).is_none());
+    assert!(node.clone().query("sensor").await.is_err());
+
+    // Handling an external event is fine, producing one is not
+    let rules = lex_and_parse("#alarm => +a.").into_iter().map(RuleWithArgs::from).collect();
+    assert_eq!(node.api.new_rules.call(rules).await.unwrap(), vec![true]);
+    let rules = lex_and_parse("#e => +a, #alarm.").into_iter().map(RuleWithArgs::from).collect();
+    let err = node.api.new_rules.call(rules).await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<NodeError>(),
+        Some(&NodeError::ExternalEvent {
+            event: "#alarm".to_string()
+        })
+    );
+}
+
+/// Test that the metrics count the rules, actions, conditions and conflicts a node handled.
+#[tokio::test]
+async fn metrics_count_work() {
+    let node = Node::new_with_rules(None).await;
+    assert_eq!(node.metrics(), NodeMetrics::default());
+
+    let rules = lex_and_parse("#e: a => +b, -c. => +a; #e.")
+        .into_iter()
+        .map(RuleWithArgs::from)
+        .collect();
+    node.api.new_rules.call(rules).await.unwrap();
+    let metrics = node.metrics();
+    assert_eq!(metrics.rules_processed, 2);
+    // `+a` and `#e` from the case rule, then `+b` and `-c` from the handler
+    assert_eq!(metrics.actions_fired, 4);
+    assert!(metrics.conditions_evaluated >= 1);
+    assert_eq!(metrics.conflicts_detected, 0);
+
+    let before = node.metrics().conditions_evaluated;
+    assert!(node.clone().query("a").await.unwrap());
+    assert_eq!(node.metrics().conditions_evaluated, before + 1);
+
+    let rules = lex_and_parse("=> +x, -x.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(rules).await.unwrap();
+    assert_eq!(node.metrics().conflicts_detected, 1);
+}
+
+/// Test that a dry run reports the writes of an action without changing the node.
+#[tokio::test]
+async fn dry_run_records_writes() {
+    let node = Node::new_with_rules(Some(lex_and_parse("b. #e: a => +c."))).await;
+    let var = |name: &str| PrimitiveCondition::Var(name.to_string());
+    let action = |src: &str| match lex_and_parse(src).remove(0) {
+        Rule::Case(CaseRule { action }) => action,
+        other => panic!("expected a case rule, got {}", other),
+    };
+
+    let writes = node.dry_run_action(action("=> +a, -b.")).await.unwrap();
+    assert_eq!(
+        writes,
+        vec![(var("a"), ActivationStatus::True), (var("b"), ActivationStatus::False)]
+    );
+    assert_eq!(node.vars.get(&var("a")).map(|v| v.clone()), Some(ActivationStatus::False));
+    assert_eq!(node.vars.get(&var("b")).map(|v| v.clone()), Some(ActivationStatus::True));
+
+    // Triggered rules see the writes made before them
+    let writes = node.dry_run_action(action("=> +a; #e.")).await.unwrap();
+    assert_eq!(
+        writes,
+        vec![(var("a"), ActivationStatus::True), (var("c"), ActivationStatus::True)]
+    );
+    assert_eq!(node.vars.get(&var("c")).map(|v| v.clone()), Some(ActivationStatus::False));
+
+    let err = node.dry_run_action(action("=> +a alt +b.")).await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<NodeError>(), Some(NodeError::DryRunUnsupported(_))), "{}", err);
+
+    // Writes to an alias act on its rules, also when its name needs quoting
+    let rules = lex_and_parse("{ #f => +d. } as `x y`.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(rules).await.unwrap();
+    let err = node.dry_run_action(action("=> -`x y`.")).await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<NodeError>(),
+        Some(&NodeError::DryRunUnsupported("-`x y`".to_string()))
+    );
+}
+
+/// Test that restoring a snapshot undoes the rules, variables and aliases added since.
+#[tokio::test]
+async fn snapshot_and_restore() {
+    let node = Node::new_with_rules(Some(lex_and_parse("#e: a => +b. a."))).await;
+    let before = node.observe().await;
+    let checkpoint = node.snapshot().await.unwrap();
+
+    let rules = lex_and_parse("#e => +c. #f => -a. => #e; #f. { #g => +d. } as r. r.")
+        .into_iter()
+        .map(RuleWithArgs::from)
+        .collect();
+    node.api.new_rules.call(rules).await.unwrap();
+    assert_ne!(node.observe().await, before);
+
+    node.clone().restore(checkpoint).await.unwrap();
+    assert_eq!(node.observe().await, before);
+    assert!(!node.event_handlers.contains_key("#f"));
+
+    // The restored handlers still run
+    let rules = lex_and_parse("=> #e.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(rules).await.unwrap();
+    assert!(node.clone().query("b").await.unwrap());
+}
+
+/// Test that `get_rules_in` returns only the rules of the requested namespace.
+#[tokio::test]
+async fn get_rules_in_filters_by_namespace() {
+    let node = Node::new_with_rules(Some(lex_and_parse(
+        "#a => +x. => +{ #e => +b. } as r. => +{ #f => +c. #g => +d. } as q.",
+    )))
+    .await;
+    let triggers = |rules: Vec<ReactiveRuleWithArgs>| {
+        let mut names: Vec<String> = rules
+            .into_iter()
+            .map(|r| match r.rule {
+                ReactiveRule::ECA { event, .. } => event.to_string(),
+                ReactiveRule::CA { condition, .. } => condition.to_string(),
+            })
+            .collect();
+        names.sort();
+        names
+    };
+
+    let top = node.api.get_rules_in.call((None, false)).await.unwrap();
+    assert_eq!(triggers(top), vec!["#a"]);
+
+    let r = node
+        .api
+        .get_rules_in
+        .call((Some(vec!["r".to_string()]), false))
+        .await
+        .unwrap();
+    assert_eq!(triggers(r), vec!["#e"]);
+
+    let q = node
+        .api
+        .get_rules_in
+        .call((Some(vec!["q".to_string()]), false))
+        .await
+        .unwrap();
+    assert_eq!(triggers(q), vec!["#f", "#g"]);
+}
+
+/// Test that the alias tree lists nested namespaces under their parents.
+#[tokio::test]
+async fn alias_tree_lists_nested_namespaces() {
+    let node = Node::new_with_rules(None).await;
+    assert!(node.alias_tree().is_empty());
+
+    let rules = lex_and_parse(
+        "{ #e => +b. } as r. r.{ #f => +c. } as s. r.{ #g => +d. } as a. { #h => +e. } as q.",
+    )
+    .into_iter()
+    .map(RuleWithArgs::from)
+    .collect();
+    node.api.new_rules.call(rules).await.unwrap();
+
+    let path = |p: &[&str]| p.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+    assert_eq!(
+        node.alias_tree(),
+        vec![
+            path(&["q"]),
+            path(&["r"]),
+            path(&["r", "a"]),
+            path(&["r", "s"]),
+        ]
+    );
+}
+
+/// Test that a compound is matched against the stored rules even when the compounds nested in its
+/// rules carry other aliases than the stored ones.
+#[tokio::test]
+async fn compound_matching_ignores_nested_aliases() {
+    let node = Node::new_with_rules(Some(lex_and_parse(
+        "=> +{ #e => +{ #f => +b. } as p. #g => +c. } as r.",
+    )))
+    .await;
+
+    let condition = match lex_and_parse("{ #e => +{ #f => +b. } as q. } as r.").remove(0) {
+        Rule::Fact(fact) => fact.condition,
+        other => panic!("Expected a fact, got {:?}", other),
+    };
+    let res = node.get_atomic_condition(condition, None, 0).await.unwrap();
+    assert_eq!(res, ActivationStatus::True);
+}
+
+/// Test that applying a diff removes and adds rules while keeping the unchanged ones and the
+/// variables they use.
+#[tokio::test]
+async fn apply_diff_updates_rules() {
+    let old = lex_and_parse("#e => +a. #f => +b.");
+    let new = lex_and_parse("#f => +b. #g => +c.");
+    let node = Node::new_with_rules(Some(old.clone())).await;
+    let fire = lex_and_parse("=> #f.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(fire).await.unwrap();
+
+    node.clone().apply_diff(diff_rules(&old, &new)).await.unwrap();
+
+    let rules: Vec<Rule> = node
+        .api
+        .get_rules
+        .call(true)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| Rule::Reactive(r.rule))
+        .collect();
+    assert_eq!(rules.len(), 2);
+    assert!(new.iter().all(|rule| rules.contains(rule)));
+    // The handler of the removed rule is gone, `b` kept its value
+    assert!(node.event_handlers.get("#e").is_none());
+    assert!(node.clone().query("b").await.unwrap());
+    assert!(!node.clone().query("c").await.unwrap());
+}
+
+/// Test that a failing add in a diff reverts the removes that were already applied.
+#[tokio::test]
+async fn apply_diff_rolls_back_on_failure() {
+    let directives = lex_and_parse_directives("@external(#alarm)").unwrap();
+    let config = NodeConfig::default().with_directives(&directives);
+    let old = lex_and_parse("#e => +a. #f => +b.");
+    let new = lex_and_parse("#f => +b. #g => +c, #alarm.");
+    let node = Node::new_with_config(Some(old.clone()), config).await;
+
+    let err = node.clone().apply_diff(diff_rules(&old, &new)).await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<NodeError>(),
+        Some(&NodeError::ExternalEvent {
+            event: "#alarm".to_string()
+        })
+    );
+
+    let rules: Vec<Rule> = node
+        .api
+        .get_rules
+        .call(true)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| Rule::Reactive(r.rule))
+        .collect();
+    assert_eq!(rules.len(), 2);
+    assert!(old.iter().all(|rule| rules.contains(rule)));
+    assert!(node.vars.get(&PrimitiveCondition::Var("c".to_string())).is_none());
+}
+
+/// Test that an added rule reported as not applied also rolls the diff back.
+#[tokio::test]
+async fn apply_diff_rolls_back_on_rule_not_applied() {
+    let old = lex_and_parse("#e => +a. #f => +b.");
+    // Triggering `#g` fails to read the conflicting `w`, so the case rule isn't applied
+    let new = lex_and_parse("#f => +b. #g: w => +c. => +w, -w. => #g.");
+    let node = Node::new_with_rules(Some(old.clone())).await;
+
+    let err = node.clone().apply_diff(diff_rules(&old, &new)).await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<NodeError>(),
+        Some(&NodeError::RuleNotApplied("=> #g.".to_string()))
+    );
+
+    let rules: Vec<Rule> = node
+        .api
+        .get_rules
+        .call(true)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| Rule::Reactive(r.rule))
+        .collect();
+    assert_eq!(rules.len(), 2);
+    assert!(old.iter().all(|rule| rules.contains(rule)));
+    assert!(node.vars.get(&PrimitiveCondition::Var("w".to_string())).is_none());
+}
+
+/// Test that a CA rule fires once a variable its condition reads becomes true.
+#[tokio::test]
+async fn ca_rule_fires_on_condition_change() {
+    let node = Node::new_with_rules(Some(lex_and_parse(": loaded => +ready."))).await;
+    assert!(!node.clone().query("ready").await.unwrap());
+
+    let rules = lex_and_parse("=> +loaded.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(rules).await.unwrap();
+
+    // CA rules are evaluated by the node in the background
+    let ready = PrimitiveCondition::Var("ready".to_string());
+    let mut changes = node.subscribe_vars();
+    if !node.clone().query("ready").await.unwrap() {
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while changes.recv().await.unwrap() != (ready.clone(), ActivationStatus::True) {}
+        })
+        .await
+        .unwrap();
+    }
+    assert!(node.clone().query("ready").await.unwrap());
+}
+
+/// Test that the handlers of rules for different events are listed by their identifiers.
+#[tokio::test]
+async fn handler_ids_lists_events() {
+    let node = Node::new_with_rules(Some(lex_and_parse("#e => +a. #f: a => +b. #e => +c."))).await;
+    assert_eq!(node.handler_ids(), vec!["e".to_string(), "f".to_string()]);
+}
+
+/// Test that node errors can be told apart by matching on their `NodeError` kind.
+#[tokio::test]
+async fn errors_have_kinds() {
+    let node = Node::new_with_rules(Some(lex_and_parse("#e => +a."))).await;
+
+    let err = node
+        .clone()
+        .process_action(Action::Primitive(PrimitiveEvent::Trigger("missing".to_string())), Default::default(), 0)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<NodeError>(),
+        Some(NodeError::InvalidAction { event, span: None }) if event == "missing"
+    ));
+
+    let err = node.clone().query("unset").await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<NodeError>(),
+        Some(&NodeError::UnknownVariable(PrimitiveCondition::Var("unset".to_string())))
+    );
+
+    let condition = match lex_and_parse("{ #e => +a. } as r.").remove(0) {
+        Rule::Fact(fact) => fact.condition,
+        other => panic!("Expected a fact, got {:?}", other),
+    };
+    let err = node.clone().get_atomic_condition(condition, None, 0).await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<NodeError>(),
+        Some(&NodeError::AliasNotFound("r".to_string()))
+    );
+}
+
+/// Test that concurrent disjunctions give the same results as the sequential evaluation.
+#[tokio::test]
+async fn concurrent_disjunction_matches_sequential_path() {
+    let rules = lex_and_parse("a. b. => -b. c. => -c.");
+    let sequential = Node::new_with_rules(Some(rules.clone())).await;
+    let config = NodeConfig {
+        concurrent_disjunction: true,
+        ..NodeConfig::default()
+    };
+    let concurrent = Node::new_with_config(Some(rules), config).await;
+
+    for src in [
+        "a or b",
+        "b or a",
+        "b or c",
+        "not a or b or c",
+        "(b or c) or (c or not b)",
+        "b and a or c",
+        "b or unset",
+    ] {
+        let condition = lex_and_parse_condition(src).unwrap();
+        let want = sequential.clone().process_condition(&condition, 0).await.ok();
+        let got = concurrent.clone().process_condition(&condition, 0).await.ok();
+        assert_eq!(got, want, "{}", src);
+    }
+
+    // An operand failing before a true one fails the whole disjunction on both paths, one
+    // failing after a true one is never looked at
+    for src in ["unset or a", "b or unset or a", "a or unset", "b or a or unset"] {
+        let condition = lex_and_parse_condition(src).unwrap();
+        let want = sequential.clone().process_condition(&condition, 0).await.map_err(|e| e.to_string());
+        let got = concurrent.clone().process_condition(&condition, 0).await.map_err(|e| e.to_string());
+        assert_eq!(got, want, "{}", src);
+    }
+}
+
+/// Test that an empty conjunction is true and an empty disjunction false, and that conditions
+/// received over the wire may not be empty.
+#[tokio::test]
+async fn empty_condition_lists() {
+    let node = Node::new_with_rules(None).await;
+    assert!(node.clone().process_condition(&Condition::Conjunction(vec![]), 0).await.unwrap());
+    assert!(!node.clone().process_condition(&Condition::Disjunction(vec![]), 0).await.unwrap());
+    let negated = Condition::Not(Box::new(Condition::Disjunction(vec![])));
+    assert!(node.clone().process_condition(&negated, 0).await.unwrap());
+
+    for empty in [Condition::Conjunction(vec![]), Condition::Disjunction(vec![])] {
+        let wire = generated::common::Condition::from(empty.clone());
+        let err = Condition::try_from(wire).unwrap_err();
+        assert!(err.contains("without conditions"), "{}: {}", empty, err);
+    }
+}
+
+/// Test that an action chain that would run practically forever stops at its deadline.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn action_deadline_bounds_runaway_chain() {
+    // Every event triggers the next one twice in parallel, 2^40 handler runs in total
+    let rules: String = (0..40).map(|i| format!("#e{} => #e{}, #e{}. ", i, i + 1, i + 1)).collect();
+    let node = Node::new_with_rules(Some(lex_and_parse(&format!("{} #e40 => +done.", rules)))).await;
+
+    let action = Action::Primitive(PrimitiveEvent::Trigger("e0".to_string()));
+    let ctx = ActionContext {
+        deadline: Some(tokio::time::Instant::now() + std::time::Duration::from_millis(200)),
+        ..ActionContext::default()
+    };
+    let started = std::time::Instant::now();
+    let err = node.clone().process_action(action, ctx, 0).await.unwrap_err();
+    assert_eq!(err.downcast_ref::<NodeError>(), Some(&NodeError::DeadlineExceeded));
+    assert!(started.elapsed() < std::time::Duration::from_secs(5), "{:?}", started.elapsed());
+
+    // Chains that finish in time are unaffected
+    let action = Action::Primitive(PrimitiveEvent::Trigger("e40".to_string()));
+    let ctx = ActionContext {
+        deadline: Some(tokio::time::Instant::now() + std::time::Duration::from_secs(5)),
+        ..ActionContext::default()
+    };
+    assert!(node.clone().process_action(action, ctx, 0).await.unwrap());
+    assert!(node.clone().query("done").await.unwrap());
+}
+
+/// Test that rules past `max_rules` are refused, while known rules can still be updated and
+/// removing rules frees room again.
+#[tokio::test]
+async fn max_rules_bounds_handlers() {
+    let config = NodeConfig {
+        max_rules: Some(3),
+        ..NodeConfig::default()
+    };
+    let node = Node::new_with_config(Some(lex_and_parse("#a => +x. #a => +y.")), config).await;
+    assert_eq!(node.rule_count(), 2);
+
+    let more_rules = lex_and_parse("#b => +z.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(more_rules).await.unwrap();
+    assert_eq!(node.rule_count(), 3);
+
+    let more_rules = lex_and_parse("#c => +w.").into_iter().map(RuleWithArgs::from).collect();
+    let err = node.api.new_rules.call(more_rules).await.unwrap_err();
+    assert_eq!(err.downcast_ref::<NodeError>(), Some(&NodeError::TooManyRules(3)));
+    assert_eq!(node.rule_count(), 3);
+    assert!(node.event_handlers.get("c").is_none());
+
+    // Rules the node already holds don't count again
+    let more_rules = lex_and_parse("#a => +x.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(more_rules).await.unwrap();
+    assert_eq!(node.rule_count(), 3);
+
+    let old = lex_and_parse("#a => +x. #a => +y. #b => +z.");
+    let new = lex_and_parse("#a => +x. #c => +w.");
+    node.clone().apply_diff(diff_rules(&old, &new)).await.unwrap();
+    assert_eq!(node.rule_count(), 2);
+
+    node.reset();
+    assert_eq!(node.rule_count(), 0);
+}
+
+/// Test that a reactive rule runs its else action when the condition doesn't hold, and that the
+/// else action survives the trip through the protobuf types.
+#[tokio::test]
+async fn else_action_runs_when_condition_is_false() {
+    let rules = lex_and_parse("c. => -c. #e: c => +a | +b.");
+    let node = Node::new_with_rules(Some(rules.clone())).await;
+    let var = |name: &str| node.vars.get(&PrimitiveCondition::Var(name.to_string())).map(|v| v.clone());
+
+    let fire = lex_and_parse("=> #e.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(fire).await.unwrap();
+    assert_eq!(var("a"), Some(ActivationStatus::False));
+    assert_eq!(var("b"), Some(ActivationStatus::True));
+
+    let fire = lex_and_parse("=> -b, +c. => #e.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(fire).await.unwrap();
+    assert_eq!(var("a"), Some(ActivationStatus::True));
+    assert_eq!(var("b"), Some(ActivationStatus::False));
+
+    let Rule::Reactive(rule) = rules[2].clone() else {
+        panic!("expected a reactive rule");
+    };
+    let wire = generated::common::ReactiveRule::from(rule.clone());
+    assert!(wire.else_action.is_some());
+    assert_eq!(ReactiveRule::try_from(wire).unwrap(), rule);
+}
+
+/// Test that `@counter` variables count their productions and consumptions, read as true while
+/// above zero, and can be compared with `>=`, also by CA rules.
+#[tokio::test]
+async fn counters_count_and_compare() {
+    let directives = lex_and_parse_directives("@counter(hits)").unwrap();
+    let config = NodeConfig::default().with_directives(&directives);
+    let rules = lex_and_parse("#hit => +hits. #miss => -hits. : hits >= 3 => +alarm.");
+    let node = Node::new_with_config(Some(rules), config).await;
+    let hits = PrimitiveCondition::Var("hits".to_string());
+    let alarm = PrimitiveCondition::Var("alarm".to_string());
+    assert_eq!(node.value(&hits), Some(Value::Int(0)));
+    assert!(!node.clone().query("hits").await.unwrap());
+
+    let mut changes = node.subscribe_vars();
+    for _ in 0..2 {
+        let fire = lex_and_parse("=> #hit.").into_iter().map(RuleWithArgs::from).collect();
+        node.api.new_rules.call(fire).await.unwrap();
+    }
+    assert_eq!(node.value(&hits), Some(Value::Int(2)));
+    assert!(node.clone().query("hits and not hits >= 3").await.unwrap());
+
+    // Parallel increments add up instead of conflicting
+    let fire = lex_and_parse("=> +hits, +hits. => #miss.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(fire).await.unwrap();
+    assert_eq!(node.value(&hits), Some(Value::Int(3)));
+    assert!(node.clone().query("hits >= 3").await.unwrap());
+
+    // The status of `hits` didn't change, the CA rule runs because the count did
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while changes.recv().await.unwrap() != (alarm.clone(), ActivationStatus::True) {}
+    })
+    .await
+    .unwrap();
+    assert_eq!(node.value(&alarm), Some(Value::Status(ActivationStatus::True)));
+
+    let err = node.clone().query("alarm >= 1").await.unwrap_err();
+    assert_eq!(err.downcast_ref::<NodeError>(), Some(&NodeError::NotACounter(alarm)));
+}
+
+/// Test that a CA rule increasing the counter it compares is stopped like any other oscillation
+/// instead of counting forever.
+#[tokio::test]
+async fn self_incrementing_counter_stops() {
+    let directives = lex_and_parse_directives("@counter(c)").unwrap();
+    let config = NodeConfig { max_depth: 16, ..NodeConfig::default().with_directives(&directives) };
+    let node = Node::new_with_config(Some(lex_and_parse(": c >= 1 => +c.")), config).await;
+    let c = PrimitiveCondition::Var("c".to_string());
+
+    let fire = lex_and_parse("=> +c.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(fire).await.unwrap();
+
+    // Let the cascade run out, then make sure the count stays put
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    let Some(Value::Int(settled)) = node.value(&c) else {
+        panic!("c should be a counter");
+    };
+    assert!(settled > 1 && settled <= 18, "unexpected count: {}", settled);
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    assert_eq!(node.value(&c), Some(Value::Int(settled)));
+}
+
+/// Test that asserting and retracting a counter as a fact sets its count, so its status keeps
+/// following the count, and that comparisons go beyond a byte.
+#[tokio::test]
+async fn counter_facts_set_the_count() {
+    let directives = lex_and_parse_directives("@counter(hits)").unwrap();
+    let config = NodeConfig::default().with_directives(&directives);
+    let rules = lex_and_parse("hits. #hit => +hits. #reset => -hits.");
+    let node = Node::new_with_config(Some(rules), config).await;
+    let hits = PrimitiveCondition::Var("hits".to_string());
+    assert_eq!(node.value(&hits), Some(Value::Int(1)));
+    assert!(node.clone().query("hits and hits >= 1").await.unwrap());
+
+    for _ in 0..299 {
+        let fire = lex_and_parse("=> #hit.").into_iter().map(RuleWithArgs::from).collect();
+        node.api.new_rules.call(fire).await.unwrap();
+    }
+    assert_eq!(node.value(&hits), Some(Value::Int(300)));
+    assert!(node.clone().query("hits >= 300 and not hits >= 301").await.unwrap());
+
+    // Asserting it again keeps the count, it is already true
+    let fact = lex_and_parse("hits.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(fact).await.unwrap();
+    assert_eq!(node.value(&hits), Some(Value::Int(300)));
+
+    // Retracting it resets the count
+    let retract = vec![RuleWithArgs::Fact(FactRuleWithArgs {
+        rule: FactRule {
+            condition: AtomicCondition::Primitive(hits.clone()),
+        },
+        value: Some(ActivationStatus::False),
+    })];
+    node.api.new_rules.call(retract).await.unwrap();
+    assert_eq!(node.value(&hits), Some(Value::Int(0)));
+    assert!(!node.clone().query("hits").await.unwrap());
+}
+
+/// Test that the firing log records the rules that ran their action, with their alias, and keeps
+/// only the latest firings.
+#[tokio::test]
+async fn firing_log_records_fired_rules() {
+    let config = NodeConfig {
+        firing_log_capacity: 3,
+        ..NodeConfig::default()
+    };
+    let rules = lex_and_parse("c. => -c. #e => +a. #e: c => +b. #f => #e. { #g => +x. } as r.");
+    let node = Node::new_with_config(Some(rules), config).await;
+    let log = |node: &Node| {
+        node.firing_log()
+            .into_iter()
+            .map(|record| (record.rule.to_string(), record.alias))
+            .collect::<Vec<_>>()
+    };
+
+    let fire = lex_and_parse("=> #f.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(fire).await.unwrap();
+    assert_eq!(
+        log(&node),
+        vec![("#f => #e.".to_string(), None), ("#e => +a.".to_string(), None)]
+    );
+
+    let fire = lex_and_parse("=> +r. => #g.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(fire).await.unwrap();
+    assert_eq!(
+        log(&node),
+        vec![
+            ("#f => #e.".to_string(), None),
+            ("#e => +a.".to_string(), None),
+            ("#g => +x.".to_string(), Some(vec!["r".to_string()])),
+        ]
+    );
+
+    let fire = lex_and_parse("=> #e.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(fire).await.unwrap();
+    let firings = node.firing_log();
+    assert_eq!(firings.len(), 3);
+    assert_eq!(firings[2].rule.to_string(), "#e => +a.");
+    assert!(firings.windows(2).all(|pair| pair[0].timestamp <= pair[1].timestamp));
+}
+
+/// Test that a declarative rule inside a stored compound is reported as an error, not a panic.
+#[tokio::test]
+async fn declarative_rule_in_compound_is_an_error() {
+    let node = Node::new_with_rules(None).await;
+
+    let action = match lex_and_parse("=> +{a -> c.} as r.").pop() {
+        Some(Rule::Case(CaseRule { action })) => action,
+        other => panic!("expected a case rule, got {:?}", other),
+    };
+    let err = node.clone().process_action(action, Default::default(), 0).await.unwrap_err();
+    assert!(
+        matches!(err.downcast_ref::<NodeError>(), Some(NodeError::UnsupportedRule(rule)) if rule == "a -> c."),
+        "{}",
+        err
+    );
+
+    // Nothing of the compound was stored
+    assert!(node.alias_tree().is_empty());
+}
+
+/// Test that condition-action rules undoing each other are stopped instead of looping forever.
+#[tokio::test]
+async fn oscillating_condition_rules_are_stopped() {
+    let config = NodeConfig {
+        max_depth: 8,
+        ..Default::default()
+    };
+    let rules = lex_and_parse(": a => -a. : not a => +a.");
+    let node = Node::new_with_config(Some(rules), config).await;
+    let start = lex_and_parse("=> +a.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(start).await.unwrap();
+
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    let settled = node.metrics().actions_fired;
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    assert_eq!(node.metrics().actions_fired, settled);
+    // The `+a` above, then each of the two handlers at most `max_depth` times
+    assert!(settled <= 1 + 2 * 8, "fired {} actions", settled);
+    assert!(settled > 1);
+}
@@ -0,0 +1,26 @@
+use cl0_node::node::Node;
+use cl0_node::types::RuleWithArgs;
+use cl0_parser::lex_and_parse;
+use tracing_test::traced_test;
+
+/// Test that the node's id is recorded on its `process_action` spans.
+#[tokio::test]
+#[traced_test]
+async fn process_action_span_has_node_id() {
+    let node = Node::with_id("traced-node", None).await;
+    assert_eq!(node.id, "traced-node");
+
+    let rules = lex_and_parse("=> +loaded.").into_iter().map(RuleWithArgs::from).collect();
+    node.api.new_rules.call(rules).await.unwrap();
+
+    assert!(logs_contain("process_action{node_id=traced-node}"));
+}
+
+/// Test that nodes built without an id get distinct generated ones.
+#[tokio::test]
+async fn generated_node_ids_differ() {
+    let first = Node::new_with_rules(None).await;
+    let second = Node::new_with_rules(None).await;
+    assert!(!first.id.is_empty());
+    assert_ne!(first.id, second.id);
+}
@@ -0,0 +1,59 @@
+use cl0_node::policy::{PolicyLoader, expand_scale, expand_scale_with_prefix};
+use cl0_node::types::UnknownFragment;
+use cl0_parser::ast::{AtomicCondition, FactRule, Rule};
+use cl0_parser::{lex_and_parse, lex_and_parse_directives};
+
+/// Test that a program can include a fragment and exclude one of its rules.
+#[test]
+fn include_then_exclude() {
+    let mut loader = PolicyLoader::new();
+    loader
+        .add_fragment("base", lex_and_parse("#start => +ready. #stop => -ready."))
+        .add_fragment("no_stop", lex_and_parse("#stop => -ready."));
+
+    let directives = lex_and_parse_directives("@exclude(no_stop) @include(base)").unwrap();
+    let rules = loader.load(&directives, lex_and_parse("#e => +a.")).unwrap();
+    assert_eq!(rules, lex_and_parse("#e => +a. #start => +ready."));
+}
+
+/// Test that naming an unregistered fragment is an error.
+#[test]
+fn unknown_fragment_is_an_error() {
+    let loader = PolicyLoader::new();
+    for src in ["@include(missing)", "@exclude(missing)"] {
+        let directives = lex_and_parse_directives(src).unwrap();
+        assert_eq!(
+            loader.load(&directives, vec![]),
+            Err(UnknownFragment {
+                name: "missing".to_string()
+            })
+        );
+    }
+}
+
+/// Test that `@scale` replicates its compound under numbered namespaces.
+#[test]
+fn scale_expansion() {
+    let directives = lex_and_parse_directives("@scale(3){#e => +a.}").unwrap();
+    let rules = expand_scale(&directives[0]);
+    assert_eq!(
+        rules,
+        lex_and_parse("replica_0.{#e => +a.}. replica_1.{#e => +a.}. replica_2.{#e => +a.}.")
+    );
+
+    let rules = expand_scale_with_prefix(&directives[0], "worker");
+    let namespaces: Vec<String> = rules
+        .iter()
+        .map(|rule| match rule {
+            Rule::Fact(FactRule {
+                condition: AtomicCondition::SubCompound { namespace, .. },
+            }) => namespace.clone(),
+            other => panic!("expected a sub-compound fact, got {}", other),
+        })
+        .collect();
+    assert_eq!(namespaces, ["worker_0", "worker_1", "worker_2"]);
+
+    let directives = lex_and_parse_directives("@scale(0){#e => +a.} @interleaving").unwrap();
+    assert!(expand_scale(&directives[0]).is_empty());
+    assert!(expand_scale(&directives[1]).is_empty());
+}
@@ -0,0 +1,72 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::{sync::broadcast::error::RecvError, time::sleep};
+
+use cl0_node::{
+    generated::web::{Presence, PresenceEvent, Scope, presence_event, presence_update, scope},
+    server::{ChannelCapacities, PresenceHub},
+};
+
+fn presence(user_id: &str) -> Presence {
+    Presence {
+        user_id: user_id.to_string(),
+        scope: Some(Scope {
+            kind: scope::Kind::Node as i32,
+            id: "node-1".to_string(),
+        }),
+        last_seen: 0,
+    }
+}
+
+/// A subscriber that falls behind a tiny presence buffer lags, while the hub keeps every user.
+#[tokio::test]
+async fn test_tiny_presence_capacity_lags() {
+    let hub = PresenceHub::new(&ChannelCapacities {
+        presence: 1,
+        ..Default::default()
+    });
+    let mut rx = hub.subscribe();
+
+    hub.join(presence("a"));
+    hub.join(presence("b"));
+    hub.join(presence("c"));
+
+    assert!(matches!(rx.recv().await, Err(RecvError::Lagged(2))));
+    assert!(rx.recv().await.is_ok(), "The latest update is still delivered");
+    assert_eq!(hub.snapshot().users.len(), 3);
+}
+
+/// A user that stops sending anything is removed and announced as having left.
+#[tokio::test]
+async fn test_stale_user_is_reaped() {
+    let hub = Arc::new(PresenceHub::default());
+    hub.join(presence("idle"));
+    let mut rx = hub.subscribe();
+
+    let _reaper = hub.spawn_reaper(Duration::from_millis(100), Duration::from_secs(1));
+    sleep(Duration::from_secs(3)).await;
+
+    match rx.try_recv() {
+        Ok(PresenceEvent {
+            kind: Some(presence_event::Kind::Update(update)),
+        }) => {
+            assert_eq!(update.kind, presence_update::Kind::Left as i32);
+            assert_eq!(update.user.unwrap().user_id, "idle");
+        }
+        other => panic!("Expected a Left update, got {:?}", other),
+    }
+    assert!(hub.snapshot().users.is_empty());
+}
+
+/// Touching a user keeps it from being considered stale.
+#[test]
+fn test_touch_keeps_user_alive() {
+    let hub = PresenceHub::default();
+    hub.join(presence("active"));
+    assert!(hub.touch("active"));
+    assert!(!hub.touch("unknown"));
+
+    let now = hub.snapshot().users[0].last_seen;
+    assert!(hub.remove_stale(now, 60).is_empty());
+    assert_eq!(hub.remove_stale(now + 61, 60), vec!["active".to_string()]);
+}
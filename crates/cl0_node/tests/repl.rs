@@ -0,0 +1,123 @@
+use cl0_node::node::Node;
+use cl0_node::repl::{
+    InputBuffer, Style, handler_states, policy_source, write_alias_tree, write_banner,
+    write_handlers, write_snapshot,
+};
+use cl0_node::types::RuleWithArgs;
+use cl0_parser::lex_and_parse;
+
+/// Test that a rule typed over several lines is handed over once it ends.
+#[test]
+fn rule_spanning_lines() {
+    let mut buffer = InputBuffer::new();
+    assert_eq!(buffer.push_line("#e =>"), None);
+    assert!(!buffer.is_empty());
+    assert_eq!(buffer.push_line("+a."), Some("#e =>\n+a.".to_string()));
+    assert!(buffer.is_empty());
+
+    // Compounds stay open until their brackets are closed
+    assert_eq!(buffer.push_line("{ #f => +b."), None);
+    assert_eq!(buffer.push_line("c. } as r."), Some("{ #f => +b.\nc. } as r.".to_string()));
+}
+
+/// Test that complete lines are handed over at once and blank lines flush partial input.
+#[test]
+fn single_lines_and_blank_lines() {
+    let mut buffer = InputBuffer::new();
+    assert_eq!(
+        buffer.push_line("#e => +a. #f => +b.\n"),
+        Some("#e => +a. #f => +b.".to_string())
+    );
+    assert_eq!(buffer.push_line(""), None);
+
+    assert_eq!(buffer.push_line("#e => +a"), None);
+    assert_eq!(buffer.push_line("  "), Some("#e => +a".to_string()));
+    assert!(buffer.is_empty());
+}
+
+/// Test that the banner and `observe` output carry no escape codes when colors are off.
+#[tokio::test]
+async fn plain_style_has_no_escape_codes() {
+    let node = Node::new_with_rules(Some(lex_and_parse("#e: a => +b. { #f => +c. } as r."))).await;
+
+    for enabled in [false, true] {
+        let mut out = Vec::new();
+        write_banner(&mut out, Style::new(enabled)).unwrap();
+        write_snapshot(&mut out, node.observe().await, Style::new(enabled)).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert_eq!(out.contains('\x1b'), enabled, "{}", out);
+        if !enabled {
+            assert!(out.contains("Welcome to \"CL0 Node REPL\"!"));
+            assert!(out.contains("    #e: a => +b: True"), "{}", out);
+        }
+    }
+}
+
+/// Test that loading a saved policy into an empty node gives the same rules and state.
+#[tokio::test]
+async fn save_and_load_round_trip() {
+    let node = Node::new_with_rules(Some(lex_and_parse(
+        "#e: a and not b => +c, -d. { #f => +g. } as r. => +r. q.{ #k => +z. } as t. x. a -> y.",
+    )))
+    .await;
+    let saved = policy_source(&node).await.unwrap();
+
+    let loaded = Node::new_with_rules(None).await;
+    let rules = lex_and_parse(&saved).into_iter().map(RuleWithArgs::from).collect();
+    loaded.api.new_rules.call(rules).await.unwrap();
+
+    let (want, got) = (node.observe().await, loaded.observe().await);
+    assert_eq!(got.rules, want.rules, "saved policy:\n{}", saved);
+    assert_eq!(got.aliases, want.aliases, "saved policy:\n{}", saved);
+    assert!(loaded.clone().query("x").await.unwrap());
+
+    // Saving the loaded node gives the same source again
+    assert_eq!(policy_source(&loaded).await.unwrap(), saved);
+}
+
+/// Test that a saved policy keeps nested action lists, quoted names, and false and conflicting
+/// variables when loaded again.
+#[tokio::test]
+async fn save_and_load_keeps_structure_and_statuses() {
+    let node = Node::new_with_rules(None).await;
+    let rules = lex_and_parse(
+        "=> - off. => +w, -w. `x y`. #e => seq(+a par(+b -c) alt(+d seq(+f -g))) | +h. \
+         { #`go now` => +`a b`. } as `my rules`. => +`my rules`. q.{ #k => +z. } as `and`.",
+    );
+    node.api.new_rules.call(rules.into_iter().map(RuleWithArgs::from).collect()).await.unwrap();
+    let saved = policy_source(&node).await.unwrap();
+
+    let loaded = Node::new_with_rules(None).await;
+    let rules = lex_and_parse(&saved).into_iter().map(RuleWithArgs::from).collect();
+    loaded.api.new_rules.call(rules).await.unwrap();
+
+    let (want, got) = (node.observe().await, loaded.observe().await);
+    assert_eq!(got.rules, want.rules, "saved policy:\n{}", saved);
+    assert_eq!(got.aliases, want.aliases, "saved policy:\n{}", saved);
+    assert_eq!(got.vars, want.vars, "saved policy:\n{}", saved);
+    assert_eq!(policy_source(&loaded).await.unwrap(), saved);
+}
+
+/// Test that the alias tree is written with nested namespaces indented under their parents.
+#[test]
+fn alias_tree_is_indented() {
+    let paths = vec![
+        vec!["r".to_string()],
+        vec!["r".to_string(), "s".to_string()],
+    ];
+    let mut out = Vec::new();
+    write_alias_tree(&mut out, &paths, Style::new(false)).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "Aliases:\n    r\n      s\n");
+}
+
+/// Test that the `handlers` listing shows each handler with its state.
+#[tokio::test]
+async fn handlers_listing() {
+    let node = Node::new_with_rules(Some(lex_and_parse("#e => +a. { #f => +b. } as r."))).await;
+
+    let mut out = Vec::new();
+    write_handlers(&mut out, &handler_states(&node).await, Style::new(false)).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out, "Handlers:\n    e: True\n    f: False\n");
+}
@@ -0,0 +1,113 @@
+use cl0_node::types::{ActivationStatus, ConflictStrategy};
+use cl0_node::utils::{
+    AliasNamespace, CreatedRules, overall_status_from_set, overall_status_from_set_with,
+};
+use dashmap::DashSet;
+use cl0_parser::lex_and_parse;
+
+/// Test that adding rules to a namespace reports only the ones it didn't hold yet.
+#[tokio::test]
+async fn create_rules_reports_added_rules() {
+    let ns = AliasNamespace::new();
+    let path = vec!["r".to_string()];
+
+    let created = ns
+        .create_rules(path.clone(), lex_and_parse("#e => +a. #f => +b."), false)
+        .await
+        .unwrap();
+    assert_eq!(
+        created,
+        CreatedRules {
+            previous: None,
+            added: lex_and_parse("#e => +a. #f => +b."),
+        }
+    );
+
+    // Rules already held, or repeated in the batch, are only kept once
+    let created = ns
+        .create_rules(path.clone(), lex_and_parse("#f => +b. #g => +c. #g => +c."), false)
+        .await
+        .unwrap();
+    assert_eq!(created.previous, Some(lex_and_parse("#e => +a. #f => +b.")));
+    assert_eq!(created.added, lex_and_parse("#g => +c."));
+    assert_eq!(
+        ns.get_rules(path.clone()).await.unwrap(),
+        lex_and_parse("#e => +a. #f => +b. #g => +c.")
+    );
+
+    // Overriding replaces the rules, reporting those that weren't there before
+    let created = ns
+        .create_rules(path.clone(), lex_and_parse("#e => +a. #h => +d."), true)
+        .await
+        .unwrap();
+    assert_eq!(created.added, lex_and_parse("#h => +d."));
+    assert_eq!(
+        ns.get_rules(path).await.unwrap(),
+        lex_and_parse("#e => +a. #h => +d.")
+    );
+}
+
+/// The mixed statuses every conflict strategy is tested against.
+fn mixed_statuses() -> Vec<ActivationStatus> {
+    vec![
+        ActivationStatus::True,
+        ActivationStatus::False,
+        ActivationStatus::True,
+        ActivationStatus::Conflict,
+    ]
+}
+
+/// Test that the pessimistic strategy fails on any conflict and otherwise lets `False` win.
+#[test]
+fn pessimistic_strategy() {
+    let strategy = ConflictStrategy::Pessimistic;
+    assert!(overall_status_from_set_with(&mixed_statuses(), strategy).is_err());
+    assert_eq!(
+        overall_status_from_set_with(&mixed_statuses()[..3], strategy).unwrap(),
+        ActivationStatus::False
+    );
+
+    // The set based function behaves the same
+    let set: DashSet<ActivationStatus> = mixed_statuses()[..3].iter().cloned().collect();
+    assert_eq!(overall_status_from_set(&set).unwrap(), ActivationStatus::False);
+}
+
+/// Test that the optimistic strategy lets any `True` win, even over conflicts.
+#[test]
+fn optimistic_strategy() {
+    let strategy = ConflictStrategy::Optimistic;
+    assert_eq!(
+        overall_status_from_set_with(&mixed_statuses(), strategy).unwrap(),
+        ActivationStatus::True
+    );
+    assert_eq!(
+        overall_status_from_set_with(&mixed_statuses()[1..2], strategy).unwrap(),
+        ActivationStatus::False
+    );
+    assert!(overall_status_from_set_with(&mixed_statuses()[3..], strategy).is_err());
+}
+
+/// Test that the majority strategy counts `True` against `False` and fails on a tie.
+#[test]
+fn majority_strategy() {
+    let strategy = ConflictStrategy::Majority;
+    assert_eq!(
+        overall_status_from_set_with(&mixed_statuses(), strategy).unwrap(),
+        ActivationStatus::True
+    );
+    assert!(overall_status_from_set_with(&mixed_statuses()[1..], strategy).is_err());
+    assert!(overall_status_from_set_with(&[], strategy).is_err());
+}
+
+/// Test that every status survives a round trip through its optional boolean.
+#[test]
+fn activation_status_option_round_trip() {
+    for status in [
+        ActivationStatus::True,
+        ActivationStatus::False,
+        ActivationStatus::Conflict,
+    ] {
+        assert_eq!(ActivationStatus::from(status.as_option_bool()), status);
+    }
+    assert_eq!(ActivationStatus::from(None), ActivationStatus::Conflict);
+}
@@ -0,0 +1,1078 @@
+use std::{net::SocketAddr, time::Duration};
+
+use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+use tokio::time::timeout;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig};
+use tonic_health::pb::{
+    HealthCheckRequest, health_check_response::ServingStatus, health_client::HealthClient,
+};
+use tonic_reflection::pb::v1::{
+    ServerReflectionRequest, server_reflection_client::ServerReflectionClient,
+    server_reflection_request::MessageRequest, server_reflection_response::MessageResponse,
+};
+
+use cl0_node::{
+    generated,
+    generated::web::{
+        CreateNodeRequest, Input, Join, NodeDescriptor, NodePoolDescriptor, Output, Presence,
+        RuleStatus, Scope, SystemTree, VarStatus,
+        control_plane_service_client::ControlPlaneServiceClient, presence_event,
+        presence_service_client::PresenceServiceClient, presence_update,
+        repl_service_client::ReplServiceClient, scope, server_event,
+        status_service_client::StatusServiceClient,
+    },
+    server::{ChannelCapacities, ServerConfig, TlsConfig, serve_with_listener},
+    types::{ActivationStatus, ReactiveRuleWithArgs},
+};
+use cl0_parser::{
+    ast::{PrimitiveCondition, Rule},
+    json::rules_to_json,
+    lex_and_parse,
+};
+
+/// Starts the web gRPC server on a free port and returns its address.
+async fn start_server() -> Result<SocketAddr, Box<dyn std::error::Error>> {
+    start_server_with(ServerConfig::default()).await
+}
+
+async fn start_server_with(config: ServerConfig) -> Result<SocketAddr, Box<dyn std::error::Error>> {
+    let addr: SocketAddr = "[::1]:0".parse()?;
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let local_addr = listener.local_addr()?;
+
+    tokio::spawn(async move {
+        serve_with_listener(listener, config).await.unwrap();
+    });
+
+    Ok(local_addr)
+}
+
+/// Reads the history chunk sent first on a fresh subscription.
+async fn first_history(
+    client: &mut ReplServiceClient<tonic::transport::Channel>,
+    scope: Scope,
+) -> Result<Vec<Output>, Box<dyn std::error::Error>> {
+    let mut stream = client
+        .subscribe(Join {
+            user_id: "tester".into(),
+            scope: Some(scope),
+            since_id: String::new(),
+            since_seq: 0,
+        })
+        .await?
+        .into_inner();
+    match stream.message().await?.and_then(|e| e.kind) {
+        Some(server_event::Kind::History(chunk)) => Ok(chunk.items),
+        other => Err(format!("Expected history, got {:?}", other).into()),
+    }
+}
+
+fn node_scope(id: &str) -> Scope {
+    Scope {
+        kind: scope::Kind::Node as i32,
+        id: id.to_string(),
+    }
+}
+
+/// Sending a rule to a node scope executes it and broadcasts the result.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_send_command_executes_on_node() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server().await?;
+    let mut client = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+
+    let mut stream = client
+        .subscribe(Join {
+            user_id: "tester".into(),
+            scope: Some(node_scope("node-1")),
+            since_id: String::new(),
+            since_seq: 0,
+        })
+        .await?
+        .into_inner();
+
+    let ack = client
+        .send_command(Input {
+            user_id: "tester".into(),
+            scope: Some(node_scope("node-1")),
+            code: "#e => +a.".into(),
+        })
+        .await?
+        .into_inner();
+
+    // Skip history and notices until our output shows up
+    let output = timeout(Duration::from_secs(5), async {
+        loop {
+            let event = stream.message().await.unwrap().expect("stream closed");
+            if let Some(server_event::Kind::Output(out)) = event.kind {
+                return out;
+            }
+        }
+    })
+    .await?;
+
+    assert_eq!(output.id, ack.output_id);
+    assert!(output.stdout.contains("ok: 1 rule(s) applied"), "{}", output.stdout);
+
+    Ok(())
+}
+
+/// A session stream starts with the history, then carries the outputs of its commands in order.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_session_runs_commands_in_order() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server().await?;
+    let mut client = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+
+    let input = |code: &str| Input {
+        user_id: "tester".into(),
+        scope: Some(node_scope("node-1")),
+        code: code.into(),
+    };
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    tx.send(input("#e => +a.")).await?;
+    tx.send(input("=> #e.")).await?;
+    let mut stream = client
+        .session(tokio_stream::wrappers::ReceiverStream::new(rx))
+        .await?
+        .into_inner();
+
+    let (acks, outputs) = timeout(Duration::from_secs(5), async {
+        let first = stream.message().await.unwrap().expect("stream closed");
+        assert!(matches!(first.kind, Some(server_event::Kind::History(_))));
+
+        let (mut acks, mut outputs) = (Vec::new(), Vec::new());
+        while outputs.len() < 2 {
+            let event = stream.message().await.unwrap().expect("stream closed");
+            match event.kind {
+                Some(server_event::Kind::Ack(ack)) => acks.push(ack),
+                Some(server_event::Kind::Output(out)) => outputs.push(out),
+                _ => {}
+            }
+        }
+        (acks, outputs)
+    })
+    .await?;
+    drop(tx);
+
+    assert_eq!(acks.len(), 2);
+    assert_eq!(acks[0].output_id, outputs[0].id);
+    assert_eq!(acks[1].output_id, outputs[1].id);
+    assert!(outputs[0].stdout.starts_with(">> #e => +a."), "{}", outputs[0].stdout);
+    assert!(outputs[1].stdout.starts_with(">> => #e."), "{}", outputs[1].stdout);
+
+    Ok(())
+}
+
+/// Commands that can't be parsed report the error instead of failing the call.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_send_command_reports_parse_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server().await?;
+    let mut client = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+
+    client
+        .send_command(Input {
+            user_id: "tester".into(),
+            scope: Some(node_scope("node-1")),
+            code: "#e => => .".into(),
+        })
+        .await?;
+
+    // The output lands in the node history
+    let mut stream = client
+        .subscribe(Join {
+            user_id: "tester".into(),
+            scope: Some(node_scope("node-1")),
+            since_id: String::new(),
+            since_seq: 0,
+        })
+        .await?
+        .into_inner();
+    let event = stream.message().await?.expect("stream closed");
+    match event.kind {
+        Some(server_event::Kind::History(chunk)) => {
+            assert_eq!(chunk.items.len(), 1);
+            assert!(chunk.items[0].stdout.contains("error:"), "{}", chunk.items[0].stdout);
+        }
+        other => panic!("Expected history, got {:?}", other),
+    }
+
+    Ok(())
+}
+
+/// The control plane scope can't execute commands yet.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_send_command_rejects_control_plane_scope() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server().await?;
+    let mut client = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+
+    let err = client
+        .send_command(Input {
+            user_id: "tester".into(),
+            scope: Some(Scope {
+                kind: scope::Kind::ControlPlane as i32,
+                id: "cp-1".into(),
+            }),
+            code: "#e => +a.".into(),
+        })
+        .await
+        .expect_err("control plane scope should be rejected");
+    assert_eq!(err.code(), tonic::Code::Unimplemented);
+
+    Ok(())
+}
+
+/// A command sent to a pool is applied to every node in it.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_send_command_fans_out_to_pool() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server().await?;
+    let mut repl = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+    let mut status = StatusServiceClient::connect(format!("http://{}", addr)).await?;
+
+    // node-2 joins the default pool next to node-1
+    status.get_status(node_scope("node-2")).await?;
+
+    let pool = Scope {
+        kind: scope::Kind::NodePool as i32,
+        id: "default".into(),
+    };
+    repl.send_command(Input {
+        user_id: "tester".into(),
+        scope: Some(pool.clone()),
+        code: "#e => +a.".into(),
+    })
+    .await?;
+
+    for node in ["node-1", "node-2"] {
+        let snapshot = status.get_status(node_scope(node)).await?.into_inner();
+        assert_eq!(snapshot.rules.len(), 1, "Rule missing on {}", node);
+    }
+
+    let history = first_history(&mut repl, pool).await?;
+    assert_eq!(history.len(), 1);
+    assert!(history[0].stdout.contains("node-1: ok: 1 rule(s) applied"));
+    assert!(history[0].stdout.contains("node-2: ok: 1 rule(s) applied"));
+
+    // Nodes joining later don't get earlier pool commands
+    let snapshot = status.get_status(node_scope("node-3")).await?.into_inner();
+    assert!(snapshot.rules.is_empty());
+
+    Ok(())
+}
+
+/// Watching a node's status yields the current snapshot, then an update once a rule is added.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_watch_status_observes_new_rule() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server().await?;
+    let mut repl = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+    let mut status = StatusServiceClient::connect(format!("http://{}", addr)).await?;
+
+    let mut stream = status.watch_status(node_scope("node-1")).await?.into_inner();
+    let initial = stream.message().await?.expect("stream closed");
+    assert!(initial.rules.is_empty());
+
+    repl.send_command(Input {
+        user_id: "tester".into(),
+        scope: Some(node_scope("node-1")),
+        code: "#e => +a.".into(),
+    })
+    .await?;
+
+    let update = timeout(Duration::from_secs(5), stream.message())
+        .await??
+        .expect("stream closed");
+    assert_eq!(update.rules.len(), 1);
+    assert!(update.rules[0].enabled);
+
+    Ok(())
+}
+
+/// Commands racing to create the same node all end up on a single node.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_commands_share_new_node() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server().await?;
+
+    let mut sends = tokio::task::JoinSet::new();
+    for i in 0..16 {
+        sends.spawn(async move {
+            let mut repl = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+            repl.send_command(Input {
+                user_id: "tester".into(),
+                scope: Some(node_scope("racy")),
+                code: format!("#e{} => +a.", i),
+            })
+            .await?;
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+        });
+    }
+    while let Some(sent) = sends.join_next().await {
+        sent?.map_err(|e| e.to_string())?;
+    }
+
+    let mut status = StatusServiceClient::connect(format!("http://{}", addr)).await?;
+    let snapshot = status.get_status(node_scope("racy")).await?.into_inner();
+    assert_eq!(snapshot.rules.len(), 16);
+
+    Ok(())
+}
+
+/// The status of a node with one reactive rule and one fact lists both.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_status_reflects_rules_and_vars() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server().await?;
+    let mut repl = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+    let mut status = StatusServiceClient::connect(format!("http://{}", addr)).await?;
+
+    repl.send_command(Input {
+        user_id: "tester".into(),
+        scope: Some(node_scope("node-1")),
+        code: "#e => +a. b.".into(),
+    })
+    .await?;
+
+    let snapshot = status.get_status(node_scope("node-1")).await?.into_inner();
+    assert_eq!(
+        snapshot.rules,
+        vec![RuleStatus {
+            namespace: String::new(),
+            name: "#e => +a.".into(),
+            enabled: true,
+        }]
+    );
+    assert!(snapshot.vars.contains(&VarStatus {
+        name: "b".into(),
+        enabled: true,
+        status: generated::web::ActivationStatus::True.into(),
+    }));
+
+    Ok(())
+}
+
+/// Aliased rules report their namespace, and conflicting values count as disabled.
+#[test]
+fn test_status_mapping() {
+    let rule = match lex_and_parse("#e => +a.").remove(0) {
+        Rule::Reactive(rule) => rule,
+        other => panic!("Expected a reactive rule, got {:?}", other),
+    };
+
+    let status = RuleStatus::from(ReactiveRuleWithArgs::new(
+        rule,
+        ActivationStatus::Conflict,
+        Some(vec!["r1".into(), "r2".into()]),
+    ));
+    assert_eq!(status.namespace, "r1.r2");
+    assert!(!status.enabled);
+
+    let var = VarStatus::from((PrimitiveCondition::Var("a".into()), ActivationStatus::True));
+    assert_eq!(var.name, "a");
+    assert!(var.enabled);
+
+    let var = VarStatus::from((PrimitiveCondition::Var("c".into()), ActivationStatus::Conflict));
+    assert!(!var.enabled);
+    assert_eq!(var.status(), generated::web::ActivationStatus::Conflict);
+}
+
+/// Every activation status survives a round trip through the generated type, and the
+/// unspecified default is rejected.
+#[test]
+fn test_activation_status_round_trip() {
+    for status in [
+        ActivationStatus::True,
+        ActivationStatus::False,
+        ActivationStatus::Conflict,
+    ] {
+        let proto = generated::web::ActivationStatus::from(status.clone());
+        let raw: i32 = proto.into();
+        let decoded = generated::web::ActivationStatus::try_from(raw).unwrap();
+        assert_eq!(ActivationStatus::try_from(decoded), Ok(status));
+    }
+    assert!(ActivationStatus::try_from(generated::web::ActivationStatus::Unspecified).is_err());
+}
+
+/// History written by one server is replayed by the next one using the same directory.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_history_persistence_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("cl0-history-{}", uuid::Uuid::new_v4()));
+    let config = ServerConfig {
+        persist_dir: Some(dir.clone()),
+        ..Default::default()
+    };
+
+    let addr = start_server_with(config.clone()).await?;
+    let mut client = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+    let ack = client
+        .send_command(Input {
+            user_id: "tester".into(),
+            scope: Some(node_scope("node-1")),
+            code: "#e => +a.".into(),
+        })
+        .await?
+        .into_inner();
+
+    // A fresh server replays the output
+    let addr = start_server_with(config.clone()).await?;
+    let mut client = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+    let history = first_history(&mut client, node_scope("node-1")).await?;
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].id, ack.output_id);
+    assert_eq!(history[0].scope, Some(node_scope("node-1")));
+    assert_eq!(history[0].user_id, "tester");
+
+    // A corrupt log is ignored instead of failing the startup
+    std::fs::write(dir.join("node-node-1.jsonl"), "not json\n")?;
+    let addr = start_server_with(config).await?;
+    let mut client = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+    assert!(first_history(&mut client, node_scope("node-1")).await?.is_empty());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+/// A subscriber that falls behind gets a resync history chunk tagged with the scope it joined.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_lagged_subscriber_resyncs_with_its_scope() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server_with(ServerConfig {
+        channel_capacities: ChannelCapacities {
+            scope_events: 16,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .await?;
+    let mut client = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+
+    // Tiny flow control window so the server can't buffer our unread events
+    let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", addr))?
+        .initial_stream_window_size(Some(1024))
+        .initial_connection_window_size(Some(1024))
+        .connect()
+        .await?;
+    let mut slow = ReplServiceClient::new(channel);
+    let mut stream = slow
+        .subscribe(Join {
+            user_id: "slow".into(),
+            scope: Some(node_scope("node-1")),
+            since_id: String::new(),
+            since_seq: 0,
+        })
+        .await?
+        .into_inner();
+
+    // Overflow the forwarding queue and the scope broadcast buffer
+    for _ in 0..500 {
+        client
+            .send_command(Input {
+                user_id: "tester".into(),
+                scope: Some(node_scope("node-1")),
+                code: "a.".into(),
+            })
+            .await?;
+    }
+
+    // Skip the initial history, then look for the resync
+    let resync = timeout(Duration::from_secs(10), async {
+        let mut histories = 0;
+        loop {
+            let event = stream.message().await.unwrap().expect("stream closed");
+            if let Some(server_event::Kind::History(chunk)) = event.kind {
+                histories += 1;
+                if histories == 2 {
+                    return chunk;
+                }
+            }
+        }
+    })
+    .await?;
+
+    assert_eq!(resync.scope, Some(node_scope("node-1")));
+    assert!(!resync.items.is_empty());
+
+    Ok(())
+}
+
+/// Zero capacities are raised to one instead of bringing the server down.
+#[tokio::test]
+async fn test_zero_channel_capacities() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server_with(ServerConfig {
+        channel_capacities: ChannelCapacities {
+            scope_events: 0,
+            tree: 0,
+            node_status: 0,
+            presence: 0,
+        },
+        ..Default::default()
+    })
+    .await?;
+    let mut repl = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+    let mut status = StatusServiceClient::connect(format!("http://{}", addr)).await?;
+
+    let mut stream = status.watch_status(node_scope("node-1")).await?.into_inner();
+    repl.send_command(Input {
+        user_id: "tester".into(),
+        scope: Some(node_scope("node-1")),
+        code: "a.".into(),
+    })
+    .await?;
+
+    let updated = timeout(Duration::from_secs(5), async {
+        loop {
+            let snapshot = stream.message().await.unwrap().expect("stream closed");
+            if !snapshot.vars.is_empty() {
+                return snapshot;
+            }
+        }
+    })
+    .await?;
+    assert_eq!(updated.vars.len(), 1);
+
+    Ok(())
+}
+
+/// A status watcher that falls behind gets the current snapshot instead of losing its stream.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_lagged_status_watcher_resyncs() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server_with(ServerConfig {
+        channel_capacities: ChannelCapacities {
+            node_status: 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+    .await?;
+    let mut repl = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+
+    // Tiny flow control window so the server can't buffer our unread snapshots
+    let channel = tonic::transport::Endpoint::from_shared(format!("http://{}", addr))?
+        .initial_stream_window_size(Some(1024))
+        .initial_connection_window_size(Some(1024))
+        .connect()
+        .await?;
+    let mut status = StatusServiceClient::new(channel);
+    let mut stream = status.watch_status(node_scope("node-1")).await?.into_inner();
+
+    // Every fact changes the status, overflowing the status broadcast buffer
+    for i in 0..300 {
+        repl.send_command(Input {
+            user_id: "tester".into(),
+            scope: Some(node_scope("node-1")),
+            code: format!("v{}.", i),
+        })
+        .await?;
+    }
+
+    // The stream stays open and catches up with the latest status
+    let caught_up = timeout(Duration::from_secs(10), async {
+        loop {
+            let snapshot = stream.message().await.unwrap().expect("stream closed");
+            if snapshot.vars.len() == 300 {
+                return snapshot;
+            }
+        }
+    })
+    .await?;
+    assert!(caught_up.vars.iter().all(|v| v.enabled));
+
+    Ok(())
+}
+
+/// Commands may also be sent as a JSON array of rules.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_send_command_accepts_json_rules() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server().await?;
+    let mut client = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+
+    let code = rules_to_json(&lex_and_parse("#e => +a. => #e."))?;
+    client
+        .send_command(Input {
+            user_id: "tester".into(),
+            scope: Some(node_scope("node-1")),
+            code,
+        })
+        .await?;
+
+    let history = first_history(&mut client, node_scope("node-1")).await?;
+    assert_eq!(history.len(), 1);
+    assert!(history[0].stdout.contains("ok: 2 rule(s) applied"), "{}", history[0].stdout);
+
+    Ok(())
+}
+
+/// Pools and nodes created over the control plane service show up in the streamed tree.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_create_pool_and_node_updates_tree() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server().await?;
+    let mut client = ControlPlaneServiceClient::connect(format!("http://{}", addr)).await?;
+    let mut trees = client
+        .subscribe_tree(Scope {
+            kind: scope::Kind::ControlPlane as i32,
+            id: "cp-1".into(),
+        })
+        .await?
+        .into_inner();
+    let initial = trees.message().await?.expect("stream closed");
+    assert!(!initial.node_pools.iter().any(|p| p.id == "edge"));
+
+    // Nodes need an existing pool
+    let missing = client
+        .create_node(CreateNodeRequest {
+            pool_id: "edge".into(),
+            node: Some(NodeDescriptor {
+                id: "edge-1".into(),
+                name: "edge-1".into(),
+            }),
+        })
+        .await;
+    assert_eq!(missing.unwrap_err().code(), tonic::Code::NotFound);
+
+    client
+        .create_pool(NodePoolDescriptor {
+            id: "edge".into(),
+            name: "Edge".into(),
+            nodes: vec![],
+        })
+        .await?;
+    let tree = client
+        .create_node(CreateNodeRequest {
+            pool_id: "edge".into(),
+            node: Some(NodeDescriptor {
+                id: "edge-1".into(),
+                name: "edge-1".into(),
+            }),
+        })
+        .await?
+        .into_inner();
+    let edge_nodes = |tree: &SystemTree| {
+        tree.node_pools
+            .iter()
+            .find(|p| p.id == "edge")
+            .map(|p| (p.name.clone(), p.nodes.iter().map(|n| n.id.clone()).collect::<Vec<_>>()))
+    };
+    assert_eq!(edge_nodes(&tree), Some(("Edge".to_string(), vec!["edge-1".to_string()])));
+
+    // Subscribers receive the updated tree as well
+    let streamed = timeout(Duration::from_secs(5), async {
+        loop {
+            let tree = trees.message().await.unwrap().expect("stream closed");
+            if edge_nodes(&tree).is_some_and(|(_, nodes)| !nodes.is_empty()) {
+                return tree;
+            }
+        }
+    })
+    .await?;
+    assert_eq!(edge_nodes(&streamed), edge_nodes(&tree));
+
+    Ok(())
+}
+
+/// Creating an existing node in another pool moves it there, keeping its rules.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_create_node_moves_between_pools() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server().await?;
+    let mut repl = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+    let mut status = StatusServiceClient::connect(format!("http://{}", addr)).await?;
+    let mut client = ControlPlaneServiceClient::connect(format!("http://{}", addr)).await?;
+
+    repl.send_command(Input {
+        user_id: "tester".into(),
+        scope: Some(node_scope("node-1")),
+        code: "#e => +a.".into(),
+    })
+    .await?;
+
+    client
+        .create_pool(NodePoolDescriptor {
+            id: "poolB".into(),
+            name: "poolB".into(),
+            nodes: vec![],
+        })
+        .await?;
+    let tree = client
+        .create_node(CreateNodeRequest {
+            pool_id: "poolB".into(),
+            node: Some(NodeDescriptor {
+                id: "node-1".into(),
+                name: "node-1".into(),
+            }),
+        })
+        .await?
+        .into_inner();
+
+    let nodes_of = |pool: &str| {
+        tree.node_pools
+            .iter()
+            .find(|p| p.id == pool)
+            .map(|p| p.nodes.iter().map(|n| n.id.clone()).collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+    assert!(nodes_of("default").is_empty());
+    assert_eq!(nodes_of("poolB"), vec!["node-1".to_string()]);
+
+    let snapshot = status.get_status(node_scope("node-1")).await?.into_inner();
+    assert_eq!(snapshot.rules.len(), 1);
+
+    Ok(())
+}
+
+/// Removing a pool drops it and its nodes from the tree, unknown ids are reported as not removed.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_remove_pool_and_node_shrinks_tree() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server().await?;
+    let mut client = ControlPlaneServiceClient::connect(format!("http://{}", addr)).await?;
+
+    let pool = |id: &str| NodePoolDescriptor {
+        id: id.into(),
+        name: id.into(),
+        nodes: vec![],
+    };
+    let node = |id: &str| NodeDescriptor {
+        id: id.into(),
+        name: id.into(),
+    };
+    client.create_pool(pool("edge")).await?;
+    for id in ["edge-1", "edge-2"] {
+        client
+            .create_node(CreateNodeRequest {
+                pool_id: "edge".into(),
+                node: Some(node(id)),
+            })
+            .await?;
+    }
+
+    let mut trees = client
+        .subscribe_tree(Scope {
+            kind: scope::Kind::ControlPlane as i32,
+            id: "cp-1".into(),
+        })
+        .await?
+        .into_inner();
+    trees.message().await?.expect("stream closed");
+
+    let pool_nodes = |tree: &SystemTree, pool: &str| {
+        tree.node_pools
+            .iter()
+            .find(|p| p.id == pool)
+            .map(|p| p.nodes.iter().map(|n| n.id.clone()).collect::<Vec<_>>())
+    };
+
+    let res = client.remove_node(node("edge-1")).await?.into_inner();
+    assert!(res.removed);
+    assert_eq!(
+        pool_nodes(res.tree.as_ref().unwrap(), "edge"),
+        Some(vec!["edge-2".to_string()])
+    );
+    let streamed = trees.message().await?.expect("stream closed");
+    assert_eq!(pool_nodes(&streamed, "edge"), Some(vec!["edge-2".to_string()]));
+
+    let res = client.remove_pool(pool("edge")).await?.into_inner();
+    assert!(res.removed);
+    assert_eq!(pool_nodes(res.tree.as_ref().unwrap(), "edge"), None);
+    let streamed = trees.message().await?.expect("stream closed");
+    assert_eq!(pool_nodes(&streamed, "edge"), None);
+
+    // Unknown ids change nothing
+    assert!(!client.remove_pool(pool("edge")).await?.into_inner().removed);
+    assert!(!client.remove_node(node("edge-2")).await?.into_inner().removed);
+
+    Ok(())
+}
+
+/// A subscriber with a scope only sees the presence of that scope, and users moving away leave.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_presence_subscribe_filters_by_scope() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server().await?;
+    let mut client = PresenceServiceClient::connect(format!("http://{}", addr)).await?;
+    let presence = |user_id: &str, node: &str| Presence {
+        user_id: user_id.into(),
+        scope: Some(node_scope(node)),
+        last_seen: 0,
+    };
+
+    client.join(presence("alice", "node-1")).await?;
+    client.join(presence("bob", "node-2")).await?;
+
+    let mut scoped = client.subscribe(node_scope("node-1")).await?.into_inner();
+    match scoped.message().await?.and_then(|e| e.kind) {
+        Some(presence_event::Kind::Snapshot(snap)) => {
+            let users: Vec<_> = snap.users.iter().map(|p| p.user_id.as_str()).collect();
+            assert_eq!(users, vec!["alice"]);
+        }
+        other => panic!("Expected a snapshot, got {:?}", other),
+    }
+
+    let mut all = client
+        .subscribe(Scope {
+            kind: scope::Kind::Unspecified as i32,
+            id: String::new(),
+        })
+        .await?
+        .into_inner();
+    match all.message().await?.and_then(|e| e.kind) {
+        Some(presence_event::Kind::Snapshot(snap)) => assert_eq!(snap.users.len(), 2),
+        other => panic!("Expected a snapshot, got {:?}", other),
+    }
+
+    client.join(presence("carol", "node-2")).await?;
+    client.join(presence("dave", "node-1")).await?;
+    client.join(presence("alice", "node-2")).await?;
+
+    let mut updates = Vec::new();
+    for _ in 0..2 {
+        let event = timeout(Duration::from_secs(5), scoped.message())
+            .await??
+            .expect("stream closed");
+        if let Some(presence_event::Kind::Update(update)) = event.kind {
+            updates.push((update.kind, update.user.unwrap().user_id));
+        }
+    }
+    assert_eq!(
+        updates,
+        vec![
+            (presence_update::Kind::Joined as i32, "dave".to_string()),
+            (presence_update::Kind::Left as i32, "alice".to_string()),
+        ]
+    );
+
+    // The unscoped subscriber also sees the update for the other scope
+    let event = timeout(Duration::from_secs(5), all.message())
+        .await??
+        .expect("stream closed");
+    match event.kind {
+        Some(presence_event::Kind::Update(update)) => {
+            assert_eq!(update.user.unwrap().user_id, "carol")
+        }
+        other => panic!("Expected an update, got {:?}", other),
+    }
+
+    Ok(())
+}
+
+/// With a certificate configured, the server is reachable over TLS by clients trusting its CA.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_tls_server_accepts_trusting_client() -> Result<(), Box<dyn std::error::Error>> {
+    let ca_key = KeyPair::generate()?;
+    let mut ca_params = CertificateParams::new(Vec::<String>::new())?;
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key)?;
+    let key = KeyPair::generate()?;
+    let cert =
+        CertificateParams::new(vec!["localhost".to_string()])?.signed_by(&key, &ca, &ca_key)?;
+
+    let dir = std::env::temp_dir().join(format!("cl0-tls-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("cert.pem"), cert.pem())?;
+    std::fs::write(dir.join("key.pem"), key.serialize_pem())?;
+
+    let addr = start_server_with(ServerConfig {
+        tls: Some(TlsConfig {
+            cert: dir.join("cert.pem"),
+            key: dir.join("key.pem"),
+        }),
+        ..Default::default()
+    })
+    .await?;
+
+    let tls = ClientTlsConfig::new()
+        .ca_certificate(Certificate::from_pem(ca.pem()))
+        .domain_name("localhost");
+    let channel = Channel::from_shared(format!("https://{}", addr))?
+        .tls_config(tls)?
+        .connect()
+        .await?;
+    let mut client = ReplServiceClient::new(channel);
+    assert!(first_history(&mut client, node_scope("node-1")).await?.is_empty());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+/// Every service reports as serving, without requiring the auth token.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_health_reports_every_service_serving() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server_with(ServerConfig {
+        auth_token: Some("secret".into()),
+        ..Default::default()
+    })
+    .await?;
+
+    let channel = Channel::from_shared(format!("http://{}", addr))?.connect().await?;
+    let mut client = HealthClient::new(channel);
+    for service in [
+        "",
+        "web.ReplService",
+        "web.StatusService",
+        "web.ControlPlaneService",
+        "web.PresenceService",
+    ] {
+        let response = client
+            .check(HealthCheckRequest {
+                service: service.into(),
+            })
+            .await?
+            .into_inner();
+        assert_eq!(response.status(), ServingStatus::Serving, "service {:?}", service);
+    }
+
+    Ok(())
+}
+
+/// Reflection lists the web services, so tools don't need the .proto files.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_reflection_lists_services() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server().await?;
+    let channel = Channel::from_shared(format!("http://{}", addr))?.connect().await?;
+    let mut client = ServerReflectionClient::new(channel);
+
+    let request = ServerReflectionRequest {
+        host: String::new(),
+        message_request: Some(MessageRequest::ListServices(String::new())),
+    };
+    let mut responses = client
+        .server_reflection_info(tokio_stream::once(request))
+        .await?
+        .into_inner();
+    let services = match responses.message().await?.and_then(|r| r.message_response) {
+        Some(MessageResponse::ListServicesResponse(list)) => {
+            list.service.into_iter().map(|s| s.name).collect::<Vec<_>>()
+        }
+        other => panic!("Expected a service list, got {:?}", other),
+    };
+
+    for name in [
+        "web.ReplService",
+        "web.StatusService",
+        "web.ControlPlaneService",
+        "web.PresenceService",
+        "grpc.health.v1.Health",
+    ] {
+        assert!(services.iter().any(|s| s == name), "{} missing from {:?}", name, services);
+    }
+
+    Ok(())
+}
+
+/// Long history is replayed in pages, only the last one marked `done`, starting at `since_id`.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_history_is_replayed_in_pages() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server_with(ServerConfig {
+        history_page_size: 100,
+        ..Default::default()
+    })
+    .await?;
+    let mut client = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+
+    let mut ids = Vec::new();
+    for _ in 0..500 {
+        let ack = client
+            .send_command(Input {
+                user_id: "tester".into(),
+                scope: Some(node_scope("node-1")),
+                code: "=> +a.".into(),
+            })
+            .await?
+            .into_inner();
+        ids.push(ack.output_id);
+    }
+
+    // Reads history chunks until the one marked done
+    async fn read_pages(
+        client: &mut ReplServiceClient<tonic::transport::Channel>,
+        since_id: &str,
+    ) -> Result<Vec<(usize, bool, String)>, Box<dyn std::error::Error>> {
+        let mut stream = client
+            .subscribe(Join {
+                user_id: "reader".into(),
+                scope: Some(node_scope("node-1")),
+                since_id: since_id.into(),
+                since_seq: 0,
+            })
+            .await?
+            .into_inner();
+        let mut pages = Vec::new();
+        loop {
+            match stream.message().await?.and_then(|e| e.kind) {
+                Some(server_event::Kind::History(chunk)) => {
+                    let first = chunk.items.first().map(|o| o.id.clone()).unwrap_or_default();
+                    pages.push((chunk.items.len(), chunk.done, first));
+                    if chunk.done {
+                        return Ok(pages);
+                    }
+                }
+                other => return Err(format!("Expected history, got {:?}", other).into()),
+            }
+        }
+    }
+
+    let pages = read_pages(&mut client, "").await?;
+    assert_eq!(pages.len(), 5);
+    assert!(pages.iter().all(|(len, _, _)| *len == 100));
+    let done: Vec<_> = pages.iter().map(|(_, done, _)| *done).collect();
+    assert_eq!(done, vec![false, false, false, false, true]);
+    assert_eq!(pages[0].2, ids[0]);
+
+    // The first page starts at `since_id`
+    let pages = read_pages(&mut client, &ids[250]).await?;
+    let lens: Vec<_> = pages.iter().map(|(len, _, _)| *len).collect();
+    assert_eq!(lens, vec![100, 100, 50]);
+    assert_eq!(pages[0].2, ids[250]);
+
+    Ok(())
+}
+
+/// Outputs of a scope are numbered 1, 2, 3, ... and `since_seq` resumes from such a number.
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_history_resumes_from_since_seq() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = start_server().await?;
+    let mut client = ReplServiceClient::connect(format!("http://{}", addr)).await?;
+
+    let mut ids = Vec::new();
+    for _ in 0..5 {
+        let ack = client
+            .send_command(Input {
+                user_id: "tester".into(),
+                scope: Some(node_scope("node-1")),
+                code: "=> +a.".into(),
+            })
+            .await?
+            .into_inner();
+        ids.push(ack.output_id);
+    }
+
+    async fn read_history(
+        client: &mut ReplServiceClient<tonic::transport::Channel>,
+        since_id: &str,
+        since_seq: u64,
+    ) -> Result<Vec<(u64, String)>, Box<dyn std::error::Error>> {
+        let mut stream = client
+            .subscribe(Join {
+                user_id: "reader".into(),
+                scope: Some(node_scope("node-1")),
+                since_id: since_id.into(),
+                since_seq,
+            })
+            .await?
+            .into_inner();
+        match stream.message().await?.and_then(|e| e.kind) {
+            Some(server_event::Kind::History(chunk)) => {
+                Ok(chunk.items.into_iter().map(|o| (o.seq, o.id)).collect())
+            }
+            other => Err(format!("Expected history, got {:?}", other).into()),
+        }
+    }
+
+    let all = read_history(&mut client, "", 0).await?;
+    let seqs: Vec<_> = all.iter().map(|(seq, _)| *seq).collect();
+    assert_eq!(seqs, vec![1, 2, 3, 4, 5]);
+
+    let resumed = read_history(&mut client, "", 3).await?;
+    let resumed_ids: Vec<_> = resumed.into_iter().map(|(_, id)| id).collect();
+    assert_eq!(resumed_ids, ids[2..]);
+
+    // `since_seq` wins over `since_id`
+    let resumed = read_history(&mut client, &ids[0], 4).await?;
+    assert_eq!(resumed.first().map(|(seq, _)| *seq), Some(4));
+
+    // Still supported on its own
+    let resumed = read_history(&mut client, &ids[3], 0).await?;
+    assert_eq!(resumed.first().map(|(seq, _)| *seq), Some(4));
+
+    Ok(())
+}
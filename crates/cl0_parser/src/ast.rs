@@ -1,7 +1,9 @@
 use std::fmt::{self};
+use std::hash::{DefaultHasher, Hash, Hasher};
 
 /// Logical condition type.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Condition {
     /// A plain variable (e.g., `loaded`)
     Atomic(AtomicCondition),
@@ -13,17 +15,31 @@ pub enum Condition {
     Disjunction(Vec<Self>),
     /// Parenthesized condition (e.g., `(loaded AND ready)`)
     Parentheses(Box<Self>),
+    /// A counter compared to a number, holding once it reached it (e.g., `retries >= 3`)
+    AtLeast(PrimitiveCondition, i64),
 }
 /// Implements the Display trait for Condition, allowing it to be formatted as a string.
+/// Operands are wrapped in parentheses where the precedence of `not` over `and` over `or` would
+/// otherwise parse them back into a different tree.
 impl fmt::Display for Condition {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Condition::Atomic(atomic_condition) => write!(f, "{}", atomic_condition.to_string()),
-            Condition::Not(condition) => write!(f, "not {}", condition.to_string()),
+            Condition::Not(condition) => {
+                let nested = matches!(
+                    condition.as_ref(),
+                    Condition::Conjunction(_) | Condition::Disjunction(_)
+                );
+                write!(f, "not {}", condition.operand_string(nested))
+            }
             Condition::Conjunction(conditions) => {
                 let joined = conditions
                     .iter()
-                    .map(|c| c.to_string())
+                    .map(|c| {
+                        let nested =
+                            matches!(c, Condition::Conjunction(_) | Condition::Disjunction(_));
+                        c.operand_string(nested)
+                    })
                     .collect::<Vec<_>>()
                     .join(" and ");
                 write!(f, "{}", joined)
@@ -31,32 +47,89 @@ impl fmt::Display for Condition {
             Condition::Disjunction(conditions) => {
                 let joined = conditions
                     .iter()
-                    .map(|c| c.to_string())
+                    .map(|c| c.operand_string(matches!(c, Condition::Disjunction(_))))
                     .collect::<Vec<_>>()
                     .join(" or ");
                 write!(f, "{}", joined)
             }
             Condition::Parentheses(condition) => write!(f, "({})", condition.to_string()),
+            Condition::AtLeast(var, count) => write!(f, "{} >= {}", var, count),
+        }
+    }
+}
+impl Condition {
+    /// See `Rule::without_aliases`.
+    fn without_aliases(&self) -> Condition {
+        match self {
+            Condition::Atomic(atomic) => Condition::Atomic(atomic.without_aliases()),
+            Condition::Not(inner) => Condition::Not(Box::new(inner.without_aliases())),
+            Condition::Conjunction(conditions) => {
+                Condition::Conjunction(conditions.iter().map(Self::without_aliases).collect())
+            }
+            Condition::Disjunction(conditions) => {
+                Condition::Disjunction(conditions.iter().map(Self::without_aliases).collect())
+            }
+            Condition::Parentheses(inner) => {
+                Condition::Parentheses(Box::new(inner.without_aliases()))
+            }
+            Condition::AtLeast(..) => self.clone(),
+        }
+    }
+
+    /// Formats the condition as an operand, in parentheses when `wrap` is set.
+    fn operand_string(&self, wrap: bool) -> String {
+        if wrap {
+            format!("({})", self)
+        } else {
+            self.to_string()
         }
     }
 }
 
 /// A primitive condition is a basic variable or identifier used in conditions.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PrimitiveCondition {
     Var(String),
 }
+impl PrimitiveCondition {
+    /// Returns the name of the variable as written, without any backticks.
+    pub fn name(&self) -> &str {
+        match self {
+            PrimitiveCondition::Var(v) => v,
+        }
+    }
+}
 /// Implements the Display trait for PrimitiveCondition, allowing it to be formatted as a string.
 impl fmt::Display for PrimitiveCondition {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            PrimitiveCondition::Var(v) => write!(f, "{}", v.to_string()),
-        }
+        write_name(f, self.name())
+    }
+}
+
+/// Quotes a name so that the lexer reads it back the same: bare when it is a plain identifier,
+/// and between backticks otherwise, e.g. `` `user logged in` `` or `` `and` ``.
+pub fn quote_name(name: &str) -> String {
+    let mut chars = name.chars();
+    let plain = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !matches!(name, "seq" | "par" | "alt" | "and" | "or" | "not" | "as");
+    if plain {
+        name.to_string()
+    } else {
+        format!("`{}`", name)
     }
 }
 
+fn write_name(f: &mut fmt::Formatter<'_>, name: &str) -> fmt::Result {
+    write!(f, "{}", quote_name(name))
+}
+
 /// An atomic condition can be either a compound condition or a primitive condition.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AtomicCondition {
     Primitive(PrimitiveCondition),
     Compound(Compound),
@@ -67,6 +140,45 @@ pub enum AtomicCondition {
         condition: Box<Self>,
     },
 }
+impl AtomicCondition {
+    /// A compact identifier for keying handlers: the variable's name, or for a compound a hash of
+    /// its rules that ignores their aliases, so compounds differing only in aliases share one.
+    /// Sub-compounds keep their namespace in front, as in `ns.{…}`.
+    pub fn identifier(&self) -> String {
+        match self {
+            AtomicCondition::Primitive(var) => var.name().to_string(),
+            AtomicCondition::Compound(_) => {
+                let mut hasher = DefaultHasher::new();
+                self.without_aliases().hash(&mut hasher);
+                format!("{{{:016x}}}", hasher.finish())
+            }
+            AtomicCondition::SubCompound {
+                namespace,
+                condition,
+            } => format!("{}.{}", namespace, condition.identifier()),
+        }
+    }
+
+    /// See `Rule::without_aliases`.
+    fn without_aliases(&self) -> AtomicCondition {
+        match self {
+            AtomicCondition::Primitive(_) => self.clone(),
+            AtomicCondition::Compound(Compound { rules, .. }) => {
+                AtomicCondition::Compound(Compound {
+                    rules: rules.iter().map(Rule::without_aliases).collect(),
+                    alias: None,
+                })
+            }
+            AtomicCondition::SubCompound {
+                namespace,
+                condition,
+            } => AtomicCondition::SubCompound {
+                namespace: namespace.clone(),
+                condition: Box::new(condition.without_aliases()),
+            },
+        }
+    }
+}
 /// Implements the Display trait for AtomicCondition, allowing it to be formatted as a string.
 impl fmt::Display for AtomicCondition {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -79,7 +191,8 @@ impl fmt::Display for AtomicCondition {
                 namespace,
                 condition,
             } => {
-                write!(f, "{}.{}", namespace.to_string(), condition.to_string())
+                write_name(f, namespace)?;
+                write!(f, ".{}", condition)
             }
         }
     }
@@ -87,6 +200,7 @@ impl fmt::Display for AtomicCondition {
 
 /// A sequence of actions can be a sequence, parallel, or alternative execution.    
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ActionList {
     /// A sequence of actions (e.g., `a seq b` or `a; b`)
     Sequence(Vec<Action>),
@@ -95,40 +209,75 @@ pub enum ActionList {
     /// An alternative choice of actions (e.g., `a alt b`)
     Alternative(Vec<Action>),
 }
+impl ActionList {
+    /// Returns the actions of the list, whatever its kind.
+    fn actions(&self) -> &[Action] {
+        match self {
+            ActionList::Sequence(list) | ActionList::Parallel(list) | ActionList::Alternative(list) => {
+                list
+            }
+        }
+    }
+
+    /// How loosely the infix form binds: `,` binds tightest, then `alt`, then `;`.
+    fn looseness(&self) -> u8 {
+        match self {
+            ActionList::Parallel(_) => 0,
+            ActionList::Alternative(_) => 1,
+            ActionList::Sequence(_) => 2,
+        }
+    }
+
+    /// Writes the list as an operand of `parent`, or as a whole action when there is none.
+    /// The infix form is only used where it reads back the same, that is for at least two
+    /// actions binding tighter than the parent. Otherwise the list is written as a call, e.g.
+    /// `seq(#a #b), #c` rather than `#a; #b, #c`, which would read as `#a; (#b, #c)`.
+    fn fmt_within(&self, f: &mut fmt::Formatter<'_>, parent: Option<&ActionList>) -> fmt::Result {
+        let actions = self.actions();
+        let infix = actions.len() >= 2
+            && parent.is_none_or(|parent| self.looseness() < parent.looseness());
+        if infix {
+            let separator = match self {
+                ActionList::Sequence(_) => "; ",
+                ActionList::Parallel(_) => ", ",
+                ActionList::Alternative(_) => " alt ",
+            };
+            for (i, action) in actions.iter().enumerate() {
+                if i > 0 {
+                    write!(f, "{}", separator)?;
+                }
+                action.fmt_within(f, Some(self))?;
+            }
+            return Ok(());
+        }
+
+        let name = match self {
+            ActionList::Sequence(_) => "seq",
+            ActionList::Parallel(_) => "par",
+            ActionList::Alternative(_) => "alt",
+        };
+        write!(f, "{}(", name)?;
+        for (i, action) in actions.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            // Every argument of a call is a whole action again
+            action.fmt_within(f, None)?;
+        }
+        write!(f, ")")
+    }
+}
+
 /// Implements the Display trait for ActionList, allowing it to be formatted as a string.
 impl fmt::Display for ActionList {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ActionList::Sequence(list) => write!(
-                f,
-                "{}",
-                list.into_iter()
-                    .map(|s| { s.to_string() })
-                    .collect::<Vec<_>>()
-                    .join("; ")
-            ),
-            ActionList::Parallel(list) => write!(
-                f,
-                "{}",
-                list.into_iter()
-                    .map(|s| { s.to_string() })
-                    .collect::<Vec<_>>()
-                    .join(", ")
-            ),
-            ActionList::Alternative(list) => write!(
-                f,
-                "{}",
-                list.into_iter()
-                    .map(|s| { s.to_string() })
-                    .collect::<Vec<_>>()
-                    .join("alt ")
-            ),
-        }
+        self.fmt_within(f, None)
     }
 }
 
 /// Represents a primitive event, which can be a trigger, production, or consumption event.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PrimitiveEvent {
     Trigger(String),              // #Identifier
     Production(AtomicCondition),  // +Identifier
@@ -139,8 +288,20 @@ impl PrimitiveEvent {
     pub fn get_identifier(&self) -> String {
         match self {
             PrimitiveEvent::Trigger(id) => id.to_string(),
-            PrimitiveEvent::Production(cond) => cond.to_string(),
-            PrimitiveEvent::Consumption(cond) => cond.to_string(),
+            PrimitiveEvent::Production(cond) => cond.identifier(),
+            PrimitiveEvent::Consumption(cond) => cond.identifier(),
+        }
+    }
+}
+impl PrimitiveEvent {
+    /// See `Rule::without_aliases`.
+    fn without_aliases(&self) -> PrimitiveEvent {
+        match self {
+            PrimitiveEvent::Trigger(_) => self.clone(),
+            PrimitiveEvent::Production(cond) => PrimitiveEvent::Production(cond.without_aliases()),
+            PrimitiveEvent::Consumption(cond) => {
+                PrimitiveEvent::Consumption(cond.without_aliases())
+            }
         }
     }
 }
@@ -148,21 +309,56 @@ impl PrimitiveEvent {
 impl fmt::Display for PrimitiveEvent {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            PrimitiveEvent::Trigger(id) => write!(f, "#{}", id.to_string()),
+            PrimitiveEvent::Trigger(id) => {
+                write!(f, "#")?;
+                write_name(f, id)
+            }
             PrimitiveEvent::Production(cond) => write!(f, "+{}", cond.to_string()),
-            PrimitiveEvent::Consumption(cond) => write!(f, "-{}", cond.to_string()),
+            PrimitiveEvent::Consumption(cond) => {
+                // `-off` would lex as `-o` followed by `ff`
+                let cond = cond.to_string();
+                let gap = if cond.starts_with('o') { " " } else { "" };
+                write!(f, "-{}{}", gap, cond)
+            }
         }
     }
 }
 
 /// Represents an action, which can be a primitive event or a sequence of actions.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Action {
     /// A single event action, like `#click`, `+load`, or `-submit`
     Primitive(PrimitiveEvent),
     /// A sequence of actions, like `seq a seq b seq c` seq(test, test2, par(test3, test4), etc)
     List(ActionList),
 }
+impl Action {
+    /// See `Rule::without_aliases`.
+    fn without_aliases(&self) -> Action {
+        match self {
+            Action::Primitive(event) => Action::Primitive(event.without_aliases()),
+            Action::List(ActionList::Sequence(actions)) => Action::List(ActionList::Sequence(
+                actions.iter().map(Self::without_aliases).collect(),
+            )),
+            Action::List(ActionList::Parallel(actions)) => Action::List(ActionList::Parallel(
+                actions.iter().map(Self::without_aliases).collect(),
+            )),
+            Action::List(ActionList::Alternative(actions)) => Action::List(
+                ActionList::Alternative(actions.iter().map(Self::without_aliases).collect()),
+            ),
+        }
+    }
+}
+impl Action {
+    /// See `ActionList::fmt_within`.
+    fn fmt_within(&self, f: &mut fmt::Formatter<'_>, parent: Option<&ActionList>) -> fmt::Result {
+        match self {
+            Action::Primitive(event) => write!(f, "{}", event),
+            Action::List(action_list) => action_list.fmt_within(f, parent),
+        }
+    }
+}
 /// Implements the Display trait for Action, allowing it to be formatted as a string.
 impl fmt::Display for Action {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -174,47 +370,79 @@ impl fmt::Display for Action {
 }
 
 /// Represents a reactive rule, which can be either an ECA (Event-Condition-Action) or CA (Condition-Action).
+/// The optional `else_action`, written `| action` after the action, runs instead of the action
+/// when the condition doesn't hold.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ReactiveRule {
     ECA {
         event: PrimitiveEvent,
         condition: Option<Condition>,
         action: Action,
+        #[cfg_attr(feature = "serde", serde(default))]
+        else_action: Option<Action>,
     },
     CA {
         condition: Condition,
         action: Action,
+        #[cfg_attr(feature = "serde", serde(default))]
+        else_action: Option<Action>,
     },
 }
 impl ReactiveRule {
     /// Returns the identifier of the reactive rule, which is the identifier of what triggers the event.
+    /// CA rules have no event, so they are identified by their condition, prefixed with `:` as in
+    /// their textual form so they never collide with event identifiers.
     pub fn get_identifier(&self) -> String {
         match self {
             ReactiveRule::ECA { event, .. } => event.get_identifier(),
-            ReactiveRule::CA { .. } => "".to_string(),
+            ReactiveRule::CA { condition, .. } => format!(":{}", condition),
         }
     }
 }
 /// Implements the Display trait for ReactiveRule, allowing it to be formatted as a string.
 impl fmt::Display for ReactiveRule {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let else_action = match self {
+            ReactiveRule::ECA { else_action, .. } | ReactiveRule::CA { else_action, .. } => {
+                else_action
+                    .as_ref()
+                    .map_or(String::new(), |a| format!(" | {}", a))
+            }
+        };
         match self {
             ReactiveRule::ECA {
                 event,
                 condition,
                 action,
+                ..
             } => match condition {
                 Some(c) => write!(
                     f,
-                    "{}: {} => {}.",
+                    "{}: {} => {}{}.",
                     event.to_string(),
                     c.to_string(),
-                    action.to_string()
+                    action.to_string(),
+                    else_action
+                ),
+                None => write!(
+                    f,
+                    "{} => {}{}.",
+                    event.to_string(),
+                    action.to_string(),
+                    else_action
                 ),
-                None => write!(f, "{} => {}.", event.to_string(), action.to_string()),
             },
-            ReactiveRule::CA { condition, action } => {
-                write!(f, ":{} => {}.", condition.to_string(), action.to_string())
+            ReactiveRule::CA {
+                condition, action, ..
+            } => {
+                write!(
+                    f,
+                    ":{} => {}{}.",
+                    condition.to_string(),
+                    action.to_string(),
+                    else_action
+                )
             }
         }
     }
@@ -222,6 +450,7 @@ impl fmt::Display for ReactiveRule {
 
 /// Represents a declarative rule, which can be either a CC (->) or CT (-o).
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DeclarativeRule {
     CC {
         premise: Option<Condition>,
@@ -256,14 +485,15 @@ impl fmt::Display for DeclarativeRule {
                 None => write!(f, "-> {}.", condition.to_string()),
             },
             DeclarativeRule::CT { premise, condition } => match premise {
-                Some(p) => write!(f, "{} -> {}.", p.to_string(), condition.to_string()),
-                None => write!(f, "-> {}.", condition.to_string()),
+                Some(p) => write!(f, "{} -o {}.", p.to_string(), condition.to_string()),
+                None => write!(f, "-o {}.", condition.to_string()),
             },
         }
     }
 }
 /// Represents a case rule, which is a rule that only contains an action.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CaseRule {
     /// The action to be taken when the case is triggered.
     pub action: Action,
@@ -271,6 +501,7 @@ pub struct CaseRule {
 
 /// Represents a fact rule, which is a rule that only contains a condition.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FactRule {
     /// The condition that must be satisfied for the fact to hold.
     pub condition: AtomicCondition,
@@ -278,6 +509,7 @@ pub struct FactRule {
 
 /// Represents a rule in the system, which can be reactive, declarative, case-based, or fact-based.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Rule {
     Reactive(ReactiveRule),
     Declarative(DeclarativeRule),
@@ -306,10 +538,61 @@ impl Rule {
             Rule::Fact { .. } => None,
         }
     }
+
+    /// Compares two rules while ignoring the aliases of the compounds nested in them, so
+    /// `+{ #e => +a. } as r` and `+{ #e => +a. } as s` count as the same.
+    pub fn structurally_eq(&self, other: &Rule) -> bool {
+        self.without_aliases() == other.without_aliases()
+    }
+
+    /// Returns a copy of the rule with the aliases of all nested compounds removed.
+    pub fn without_aliases(&self) -> Rule {
+        match self {
+            Rule::Reactive(ReactiveRule::ECA {
+                event,
+                condition,
+                action,
+                else_action,
+            }) => Rule::Reactive(ReactiveRule::ECA {
+                event: event.without_aliases(),
+                condition: condition.as_ref().map(Condition::without_aliases),
+                action: action.without_aliases(),
+                else_action: else_action.as_ref().map(Action::without_aliases),
+            }),
+            Rule::Reactive(ReactiveRule::CA {
+                condition,
+                action,
+                else_action,
+            }) => Rule::Reactive(ReactiveRule::CA {
+                condition: condition.without_aliases(),
+                action: action.without_aliases(),
+                else_action: else_action.as_ref().map(Action::without_aliases),
+            }),
+            Rule::Declarative(DeclarativeRule::CC { premise, condition }) => {
+                Rule::Declarative(DeclarativeRule::CC {
+                    premise: premise.as_ref().map(Condition::without_aliases),
+                    condition: condition.without_aliases(),
+                })
+            }
+            Rule::Declarative(DeclarativeRule::CT { premise, condition }) => {
+                Rule::Declarative(DeclarativeRule::CT {
+                    premise: premise.as_ref().map(Condition::without_aliases),
+                    condition: condition.without_aliases(),
+                })
+            }
+            Rule::Case(CaseRule { action }) => Rule::Case(CaseRule {
+                action: action.without_aliases(),
+            }),
+            Rule::Fact(FactRule { condition }) => Rule::Fact(FactRule {
+                condition: condition.without_aliases(),
+            }),
+        }
+    }
 }
 
 // Represents a compound rule, which can contain multiple rules and an optional alias to refer to
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Compound {
     pub rules: Vec<Rule>,
     pub alias: Option<String>,
@@ -325,7 +608,10 @@ impl fmt::Display for Compound {
                     .collect::<Vec<_>>()
                     .join(" ");
                 return match alias {
-                    Some(alias) => write!(f, "{{ {} }} as {}", rules_string, alias),
+                    Some(alias) => {
+                        write!(f, "{{ {} }} as ", rules_string)?;
+                        write_name(f, alias)
+                    }
                     None => write!(f, "{{ {} }}", rules_string),
                 };
             }
@@ -335,12 +621,14 @@ impl fmt::Display for Compound {
 
 // Represents a directive meaning different things
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Directive {
     Scale { number: u8, policy: Compound },
     Include(String),
     Exclude(String),
     Interleaving,
     ExternalVar(String),
+    Counter(String),
     ExternalEvent(PrimitiveEvent),
 }
 
@@ -352,16 +640,27 @@ impl fmt::Display for Directive {
                 write!(f, "@scale({})\n{}", number, policy)
             }
             Directive::Include(s) => {
-                write!(f, "@include({})", s)
+                write!(f, "@include(")?;
+                write_name(f, s)?;
+                write!(f, ")")
             }
             Directive::Exclude(s) => {
-                write!(f, "@exclude({})", s)
+                write!(f, "@exclude(")?;
+                write_name(f, s)?;
+                write!(f, ")")
             }
             Directive::Interleaving => {
                 write!(f, "@interleaving")
             }
             Directive::ExternalVar(s) => {
-                write!(f, "@external({})", s)
+                write!(f, "@external(")?;
+                write_name(f, s)?;
+                write!(f, ")")
+            }
+            Directive::Counter(s) => {
+                write!(f, "@counter(")?;
+                write_name(f, s)?;
+                write!(f, ")")
             }
             Directive::ExternalEvent(pe) => {
                 write!(f, "@external({})", pe)
@@ -369,3 +668,172 @@ impl fmt::Display for Directive {
         }
     }
 }
+
+/// A top-level item of a program, which can be either a rule or a directive.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProgramItem {
+    Rule(Rule),
+    Directive(Directive),
+}
+
+/// Implements the Display trait for ProgramItem, allowing it to be formatted as a string.
+impl fmt::Display for ProgramItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgramItem::Rule(rule) => write!(f, "{}", rule),
+            ProgramItem::Directive(directive) => write!(f, "{}", directive),
+        }
+    }
+}
+
+impl From<&str> for PrimitiveCondition {
+    fn from(name: &str) -> Self {
+        PrimitiveCondition::Var(name.to_string())
+    }
+}
+
+impl From<String> for PrimitiveCondition {
+    fn from(name: String) -> Self {
+        PrimitiveCondition::Var(name)
+    }
+}
+
+impl From<PrimitiveCondition> for AtomicCondition {
+    fn from(var: PrimitiveCondition) -> Self {
+        AtomicCondition::Primitive(var)
+    }
+}
+
+impl From<&str> for AtomicCondition {
+    fn from(name: &str) -> Self {
+        AtomicCondition::Primitive(name.into())
+    }
+}
+
+impl From<Compound> for AtomicCondition {
+    fn from(compound: Compound) -> Self {
+        AtomicCondition::Compound(compound)
+    }
+}
+
+impl From<AtomicCondition> for Condition {
+    fn from(condition: AtomicCondition) -> Self {
+        Condition::Atomic(condition)
+    }
+}
+
+impl From<PrimitiveCondition> for Condition {
+    fn from(var: PrimitiveCondition) -> Self {
+        Condition::Atomic(var.into())
+    }
+}
+
+impl From<&str> for Condition {
+    fn from(name: &str) -> Self {
+        Condition::Atomic(name.into())
+    }
+}
+
+impl From<PrimitiveEvent> for Action {
+    fn from(event: PrimitiveEvent) -> Self {
+        Action::Primitive(event)
+    }
+}
+
+impl From<ActionList> for Action {
+    fn from(list: ActionList) -> Self {
+        Action::List(list)
+    }
+}
+
+/// The trigger event `#name`.
+pub fn event_trigger(name: impl Into<String>) -> PrimitiveEvent {
+    PrimitiveEvent::Trigger(name.into())
+}
+
+/// The production `+var`, which may also produce a compound.
+pub fn produce(var: impl Into<AtomicCondition>) -> PrimitiveEvent {
+    PrimitiveEvent::Production(var.into())
+}
+
+/// The consumption `-var`, which may also consume a compound.
+pub fn consume(var: impl Into<AtomicCondition>) -> PrimitiveEvent {
+    PrimitiveEvent::Consumption(var.into())
+}
+
+/// Builds reactive rules in code instead of parsing them, e.g.
+/// `RuleBuilder::eca(event_trigger("e")).when("c").then(produce("a")).build()` for `#e: c => +a.`
+#[derive(Debug, Clone)]
+pub struct RuleBuilder {
+    event: Option<PrimitiveEvent>,
+    condition: Option<Condition>,
+    action: Option<Action>,
+    else_action: Option<Action>,
+}
+
+impl RuleBuilder {
+    /// Starts an event-condition-action rule handling `event`.
+    pub fn eca(event: impl Into<PrimitiveEvent>) -> Self {
+        RuleBuilder {
+            event: Some(event.into()),
+            condition: None,
+            action: None,
+            else_action: None,
+        }
+    }
+
+    /// Starts a condition-action rule, which runs whenever `condition` holds.
+    pub fn ca(condition: impl Into<Condition>) -> Self {
+        RuleBuilder {
+            event: None,
+            condition: Some(condition.into()),
+            action: None,
+            else_action: None,
+        }
+    }
+
+    /// Adds a condition the rule requires. Calling it again requires both, as a conjunction.
+    pub fn when(mut self, condition: impl Into<Condition>) -> Self {
+        let condition = condition.into();
+        self.condition = Some(match self.condition.take() {
+            None => condition,
+            Some(Condition::Conjunction(mut conditions)) => {
+                conditions.push(condition);
+                Condition::Conjunction(conditions)
+            }
+            Some(previous) => Condition::Conjunction(vec![previous, condition]),
+        });
+        self
+    }
+
+    /// Sets the action the rule runs, replacing an earlier one.
+    pub fn then(mut self, action: impl Into<Action>) -> Self {
+        self.action = Some(action.into());
+        self
+    }
+
+    /// Sets the action the rule runs when its condition doesn't hold, replacing an earlier one.
+    pub fn otherwise(mut self, action: impl Into<Action>) -> Self {
+        self.else_action = Some(action.into());
+        self
+    }
+
+    /// Returns the rule. Panics when `then` was never called, since every rule needs an action.
+    pub fn build(self) -> Rule {
+        let action = self.action.expect("RuleBuilder::then must be called before build");
+        match self.event {
+            Some(event) => Rule::Reactive(ReactiveRule::ECA {
+                event,
+                condition: self.condition,
+                action,
+                else_action: self.else_action,
+            }),
+            None => Rule::Reactive(ReactiveRule::CA {
+                condition: self.condition.expect("CA rules start out with a condition"),
+                action,
+                else_action: self.else_action,
+            }),
+        }
+    }
+}
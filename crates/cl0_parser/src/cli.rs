@@ -0,0 +1,90 @@
+//! Command line of the `cl0_parser` binary.
+//!
+//! ```text
+//! cl0_parser [--check] <source>
+//! cl0_parser [--check] --file <path>
+//! ```
+//!
+//! Without `--check` the parsed rules are printed; with it nothing is printed on success, so the
+//! exit code can gate policy files in CI.
+
+use crate::{lex_and_parse_safe, parse_program};
+
+/// Exit code for a successful run.
+pub const EXIT_OK: i32 = 0;
+/// Exit code when the source doesn't parse.
+pub const EXIT_PARSE_ERROR: i32 = 1;
+/// Exit code for bad arguments or an unreadable file.
+pub const EXIT_USAGE: i32 = 2;
+
+const USAGE: &str = "Usage: cl0_parser [--check] (<source> | --file <path>)";
+
+/// Runs the command line on `args`, without the program name, and returns the exit code.
+pub fn run(args: impl IntoIterator<Item = String>) -> i32 {
+    let mut check = false;
+    let mut file = None;
+    let mut source = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--file" => match args.next() {
+                Some(path) => file = Some(path),
+                None => {
+                    eprintln!("--file needs a path\n{}", USAGE);
+                    return EXIT_USAGE;
+                }
+            },
+            _ if source.is_none() && !arg.starts_with("--") => source = Some(arg),
+            _ => {
+                eprintln!("Unexpected argument: {}\n{}", arg, USAGE);
+                return EXIT_USAGE;
+            }
+        }
+    }
+
+    let src = match (file, source) {
+        (Some(path), None) => match std::fs::read_to_string(&path) {
+            Ok(src) => src,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path, e);
+                return EXIT_USAGE;
+            }
+        },
+        // A single source argument keeps the recovering parser, which reports every error
+        (None, Some(src)) if !check => {
+            return match parse_program(&src) {
+                Ok(rules) => {
+                    let rules: Vec<_> = rules.into_iter().map(|(rule, _span)| rule).collect();
+                    println!("{:#?}", rules);
+                    EXIT_OK
+                }
+                Err(diagnostics) => {
+                    for diagnostic in diagnostics {
+                        diagnostic.print("input", &src);
+                    }
+                    EXIT_PARSE_ERROR
+                }
+            };
+        }
+        (None, Some(src)) => src,
+        _ => {
+            eprintln!("{}", USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    match lex_and_parse_safe(&src) {
+        Ok(rules) => {
+            if !check {
+                println!("{:#?}", rules);
+            }
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            EXIT_PARSE_ERROR
+        }
+    }
+}
@@ -0,0 +1,16 @@
+//! Canonical formatting of CL0 source, in the spirit of `rustfmt`.
+//!
+//! The source is parsed and printed back through the AST's `Display`, so the output has one rule
+//! per line, a single space around `=>`, `->`, `-o` and the keywords, a space after `:` and `,`,
+//! and no space before the `.` ending a rule. Formatting formatted source gives the same text.
+
+use crate::{ParseDiagnostic, parse_program};
+
+/// Formats a program, or returns what kept it from parsing.
+pub fn format_program(src: &str) -> Result<String, Vec<ParseDiagnostic>> {
+    let rules = parse_program(src)?;
+    Ok(rules
+        .iter()
+        .map(|(rule, _span)| format!("{}\n", rule))
+        .collect())
+}
@@ -0,0 +1,25 @@
+//! JSON frontend for CL0 rules, for clients that build policies programmatically instead of
+//! writing CL0 source.
+//!
+//! The JSON mirrors the AST in `crate::ast`, with enum variants written as externally tagged
+//! objects. For example `#e => +a.` is:
+//!
+//! ```json
+//! [{"Reactive": {"ECA": {
+//!     "event": {"Trigger": "e"},
+//!     "condition": null,
+//!     "action": {"Primitive": {"Production": {"Primitive": {"Var": "a"}}}}
+//! }}}]
+//! ```
+
+use crate::ast::Rule;
+
+/// Parses a JSON array of rules.
+pub fn parse_rules_json(src: &str) -> Result<Vec<Rule>, serde_json::Error> {
+    serde_json::from_str(src)
+}
+
+/// Serializes rules into the JSON accepted by `parse_rules_json`.
+pub fn rules_to_json(rules: &[Rule]) -> Result<String, serde_json::Error> {
+    serde_json::to_string(rules)
+}
@@ -6,30 +6,128 @@ pub type Spanned<T> = (T, SimpleSpan);
 /// Constructs the lexer that transforms raw input characters into a vector of `Token`s.
 ///
 /// This lexer handles:
-/// - Multi-character symbols: `=>`, `->`, `-o`
-/// - Single-character symbols: `#`, `:`, `;`, `+`, `-`, `.`, `(`, `)`, `,`
-/// - Keywords: `seq`, `par`, `alt`, `and`, `or`, `not`
-/// - Identifiers: any other alphanumeric word
-/// - Line comments starting with `%`, which are ignored
+/// - Multi-character symbols: `=>`, `->`, `-o`, `>=`
+/// - Single-character symbols: `#`, `:`, `;`, `+`, `-`, `.`, `(`, `)`, `,`, `|`
+/// - Keywords: `seq`, `par`, `alt`, `and`, `or`, `not`, and the directive names `@scale`,
+///   `@include`, `@exclude`, `@interleaving`, `@external` and `@counter`; any other `@` is
+///   lexed on its own
+/// - Identifiers: any other word made of an ASCII letter or `_`, followed by ASCII letters,
+///   digits and `_`, e.g. `my_var`, `_internal` or `state1`
+/// - Quoted identifiers: any text between backticks, e.g. `` `user logged in` `` or `` `and` ``,
+///   for names that aren't plain identifiers. They can't be empty or span lines.
+/// - Numbers: a run of decimal digits fitting in an `i64`; a word starting with a digit is never
+///   an identifier
+/// - Line comments starting with `%`, which are ignored; see `lexer_with_comments` to keep them
 ///
+/// On a character that starts no token, the lexer reports an error, skips the character and
+/// carries on, so the output may still contain tokens. See `lexer_strict` to stop instead.
 pub fn lexer<'src>()
 -> impl Parser<'src, &'src str, Vec<Spanned<Token<'src>>>, extra::Err<Rich<'src, char, SimpleSpan>>>
+{
+    token_lexer()
+        // If we encounter an error, skip and attempt to lex the next character as a token instead
+        .recover_with(skip_then_retry_until(any().ignored(), end()))
+        .repeated()
+        .collect()
+}
+
+/// Like `lexer`, but without error recovery: lexing stops at the first character that starts
+/// no token, and that character is the one reported.
+pub fn lexer_strict<'src>()
+-> impl Parser<'src, &'src str, Vec<Spanned<Token<'src>>>, extra::Err<Rich<'src, char, SimpleSpan>>>
+{
+    token_lexer().repeated().collect()
+}
+
+/// Like `lexer`, but the `%` comments are kept in a second channel instead of being dropped:
+/// the text after each `%` up to the end of its line, with the span of the whole comment.
+pub fn lexer_with_comments<'src>()
+-> impl Parser<'src, &'src str, LexedWithComments<'src>, extra::Err<Rich<'src, char, SimpleSpan>>> {
+    let comment = just('%')
+        .ignore_then(any().and_is(just('\n').not()).repeated().to_slice())
+        .map_with(|text, e| Lexeme::Comment((text, e.span())));
+    bare_token()
+        .map_with(|tok, e| Lexeme::Token((tok, e.span())))
+        .or(comment)
+        .padded()
+        .recover_with(skip_then_retry_until(any().ignored(), end()))
+        .repeated()
+        .collect::<Vec<_>>()
+        .padded()
+        .map(|lexemes| {
+            let mut tokens = Vec::new();
+            let mut comments = Vec::new();
+            for lexeme in lexemes {
+                match lexeme {
+                    Lexeme::Token(token) => tokens.push(token),
+                    Lexeme::Comment(comment) => comments.push(comment),
+                }
+            }
+            (tokens, comments)
+        })
+}
+
+/// The tokens and the comments of a source, each in source order.
+pub type LexedWithComments<'src> = (Vec<Spanned<Token<'src>>>, Vec<Spanned<&'src str>>);
+
+/// A token or a comment, as lexed by `lexer_with_comments`.
+#[derive(Debug, Clone)]
+enum Lexeme<'src> {
+    Token(Spanned<Token<'src>>),
+    Comment(Spanned<&'src str>),
+}
+
+/// Lexes a single token with its span, skipping the whitespace and comments around it.
+fn token_lexer<'src>()
+-> impl Parser<'src, &'src str, Spanned<Token<'src>>, extra::Err<Rich<'src, char, SimpleSpan>>> + Clone
+{
+    // Comments: skip lines beginning with `%`
+    let comment = just("%")
+        .then(any().and_is(just('\n').not()).repeated())
+        .padded();
+
+    bare_token()
+        .map_with(|tok, e| (tok, e.span()))
+        .padded_by(comment.repeated())
+        .padded()
+}
+
+/// Lexes a single token, without any whitespace or comments around it.
+fn bare_token<'src>()
+-> impl Parser<'src, &'src str, Token<'src>, extra::Err<Rich<'src, char, SimpleSpan>>> + Clone
 {
     // Multi-character symbols must be matched before single-character and identifiers
     let multi_symbol = choice((
         just("=>").to(Token::FatArrow),
         just("->").to(Token::ThinArrow),
         just("-o").to(Token::DashO),
+        just(">=").to(Token::GreaterEqual),
     ));
 
-    // Distinction between `.` as a dot and `.` as an end of rule
+    // Distinction between `.` as a dot and `.` as an end of rule, by looking at the next
+    // character only: a dot directly followed by an identifier, a quoted identifier or `{`
+    // separates namespaces (`r.s`, `` r.`my ns` ``, `r.{ ... }`), any other dot ends a rule. So
+    // `a. b.` is two facts, and a namespace dot can't be followed by whitespace.
     let dot_or_endrule = just('.')
         .then(any().rewind().or_not())
         .map(|(_, next): (char, Option<char>)| match next {
-            Some(c) if c.is_alphanumeric() || c == '{' => Token::Dot,
+            Some(c) if c.is_ascii_alphanumeric() || matches!(c, '_' | '`' | '{') => Token::Dot,
             _ => Token::EndRule,
         });
 
+    // Directive names, written right after the `@`. Other names fall through to a plain `@`
+    let directive = just('@')
+        .ignore_then(text::ascii::ident())
+        .try_map(|name: &str, span| match name {
+            "scale" => Ok(Token::AtScale),
+            "include" => Ok(Token::AtInclude),
+            "exclude" => Ok(Token::AtExclude),
+            "interleaving" => Ok(Token::AtInterleaving),
+            "external" => Ok(Token::AtExternal),
+            "counter" => Ok(Token::AtCounter),
+            _ => Err(Rich::custom(span, format!("unknown directive @{}", name))),
+        });
+
     // Single-character symbols
     let symbol = choice((
         just("#").to(Token::Hash),
@@ -42,10 +140,11 @@ pub fn lexer<'src>()
         just("{").to(Token::LeftCBracket),
         just("}").to(Token::RightCBracket),
         just(",").to(Token::Comma),
+        just("|").to(Token::Pipe),
         just("@").to(Token::At),
     ));
 
-    // Reserved words and identifiers
+    // Reserved words and identifiers: [A-Za-z_][A-Za-z0-9_]*
     let ident = text::ascii::ident().map(|identifier: &str| match identifier {
         "seq" => Token::Seq,
         "par" => Token::Par,
@@ -57,26 +156,30 @@ pub fn lexer<'src>()
         _ => Token::Descriptor(identifier),
     });
 
+    // Numbers are directive arguments, like `@scale(3)`, and comparison bounds, like
+    // `hits >= 300`. They must not run into a word, so `3d` is an error instead of the
+    // number 3 followed by the identifier `d`
     let number = text::int(10)
+        .then_ignore(any().filter(|c: &char| c.is_ascii_alphanumeric() || *c == '_').not())
         .try_map(|s: &str, span| {
-            s.parse::<u8>()
+            s.parse::<i64>()
                 .map(Token::Number)
-                .map_err(|_| Rich::custom(span, "number out of range for u8"))
+                .map_err(|_| Rich::custom(span, "number out of range for i64"))
         });
 
-    let token = multi_symbol.or(dot_or_endrule).or(symbol).or(number).or(ident);
-
-    // Comments: skip lines beginning with `%`
-    let comment = just("%")
-        .then(any().and_is(just('\n').not()).repeated())
-        .padded();
-
-    return token
-        .map_with(|tok, e| (tok, e.span()))
-        .padded_by(comment.repeated())
-        .padded()
-        // If we encounter an error, skip and attempt to lex the next character as a token instead
-        .recover_with(skip_then_retry_until(any().ignored(), end()))
+    // Backtick-quoted identifiers, which may contain spaces or be reserved words
+    let quoted = none_of("`\n")
         .repeated()
-        .collect();
+        .at_least(1)
+        .to_slice()
+        .delimited_by(just('`'), just('`'))
+        .map(Token::Descriptor);
+
+    multi_symbol
+        .or(dot_or_endrule)
+        .or(directive)
+        .or(symbol)
+        .or(number)
+        .or(quoted)
+        .or(ident)
 }
@@ -1,47 +1,178 @@
 pub mod ast;
+pub mod cli;
+pub mod format;
+#[cfg(feature = "serde")]
+pub mod json;
 pub mod lexer;
 pub mod parser;
 pub mod token;
+pub mod validate;
 
 use std::error::Error;
-
-use chumsky::{Parser, span::SimpleSpan};
-
-use crate::ast::{Compound, Rule};
-use crate::parser::{compound_parser, program_parser};
-use crate::{lexer::lexer, token::Token};
+use std::fmt;
+
+use chumsky::{
+    IterParser, ParseResult, Parser,
+    error::{Rich, RichReason},
+    prelude::end,
+    span::SimpleSpan,
+};
+
+use crate::ast::{Compound, Condition, Directive, ProgramItem, Rule};
+use crate::parser::{
+    compound_parser, condition_parser, directive_parser, program_items_parser, program_parser,
+};
+use crate::{
+    lexer::{lexer, lexer_strict, lexer_with_comments},
+    token::Token,
+};
 
 use ariadne::{Color, Fmt, Label, Report, ReportKind, Source};
 
 pub type Span = SimpleSpan;
 pub type Spanned<T> = (T, Span);
 
+/// A problem found while lexing or parsing, at a byte range of the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDiagnostic {
+    pub message: String,
+    pub span: Span,
+    /// 1-based line of the start of `span`
+    pub line: usize,
+    /// 1-based column of the start of `span`, counted in characters
+    pub column: usize,
+}
+
+/// Maps a byte offset into `src` to its 1-based line and column. Columns count characters, so
+/// multi-byte UTF-8 characters take up a single column. Offsets past the end map to the end,
+/// and offsets inside a character map to that character.
+pub fn position(src: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(src.len());
+    while !src.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &src[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
+
+impl ParseDiagnostic {
+    fn new(src: &str, message: String, span: Span) -> Self {
+        let (line, column) = position(src, span.start);
+        ParseDiagnostic {
+            message,
+            span,
+            line,
+            column,
+        }
+    }
+
+    /// Prints the diagnostic as a report pointing into `src`.
+    pub fn print(&self, file_id: &str, src: &str) {
+        let range = self.span.into_range();
+        // Printing is best effort, a closed stdout is no reason to panic
+        let _ = Report::build(ReportKind::Error, (file_id, range.clone()))
+            .with_message(&self.message)
+            .with_label(Label::new((file_id, range)).with_color(Color::Red))
+            .finish()
+            .print((file_id, Source::from(src)));
+    }
+}
+
+impl fmt::Display for ParseDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}..{}", self.message, self.span.start, self.span.end)
+    }
+}
+
+impl Error for ParseDiagnostic {}
+
+/// Lexes `src` into tokens with their spans in character offsets, without parsing them, so a
+/// multi-byte character like `é` takes up a single offset. Lexing carries on past bad
+/// characters, so every one of them is reported; the diagnostics point at byte ranges like all
+/// others, along with their line and column.
+pub fn tokenize(src: &str) -> Result<Vec<(Token<'_>, Span)>, Vec<ParseDiagnostic>> {
+    // Spans come in source order, so the characters are counted in a single pass
+    let (mut byte, mut chars) = (0, 0);
+    let mut char_offset = |offset: usize| {
+        chars += src[byte..offset].chars().count();
+        byte = offset;
+        chars
+    };
+    Ok(lex(src)?
+        .into_iter()
+        .map(|(token, span)| {
+            let start = char_offset(span.start);
+            let end = char_offset(span.end);
+            (token, SimpleSpan::from(start..end))
+        })
+        .collect())
+}
+
+/// Lexes `src` into tokens with their byte spans, reporting every bad character.
+fn lex(src: &str) -> Result<Vec<(Token<'_>, Span)>, Vec<ParseDiagnostic>> {
+    let (tokens, errors) = lexer().parse(src).into_output_errors();
+    if !errors.is_empty() {
+        return Err(errors
+            .into_iter()
+            .map(|err| ParseDiagnostic::new(src, err.to_string(), *err.span()))
+            .collect());
+    }
+    Ok(tokens.unwrap_or_default())
+}
+
+/// Lexes and parses a program into its rules with their byte spans. Nothing is printed,
+/// every problem found is returned as a diagnostic instead.
+pub fn parse_program(src: &str) -> Result<Vec<Spanned<Rule>>, Vec<ParseDiagnostic>> {
+    let spanned = lex(src)?;
+    if let Some(span) = too_deep(&spanned) {
+        return Err(vec![ParseDiagnostic::new(src, nesting_message(), span)]);
+    }
+    let tokens: Vec<Token> = spanned.iter().map(|(t, _)| t.clone()).collect();
+
+    let (rules, errors) = program_parser().parse(tokens.as_slice()).into_output_errors();
+    if !errors.is_empty() {
+        return Err(errors
+            .into_iter()
+            .map(|err| {
+                let expected: Vec<String> = err.expected().map(|t| t.to_string()).collect();
+                let found = err
+                    .found()
+                    .map(|t| t.to_string())
+                    .unwrap_or_else(|| "end of input".into());
+                let message = if expected.is_empty() {
+                    format!("Unexpected {}", found)
+                } else {
+                    format!("found {} expected {}", found, expected.join(", "))
+                };
+                ParseDiagnostic::new(src, message, char_span(&spanned, *err.span()))
+            })
+            .collect());
+    }
+
+    Ok(rules
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(rule, tok_span)| (rule, char_span(&spanned, tok_span)))
+        .collect())
+}
+
 pub fn lex_and_parse_span(src: &str) -> Vec<(Rule, SimpleSpan)> {
     // Placeholder when parsing from files
     let file_id: &'static str = "input";
 
     // Lex all the tokens with their character spans
-    let lex_result = lexer().parse(src);
-    if lex_result.has_errors() {
-        for err in lex_result.errors() {
-            let span = err.span().clone();
-            let SimpleSpan { start, end, .. } = span;
-            let r1 = start..end;
-            let r2 = r1.clone();
-
-            // Create lex error report if lexing fails
-            Report::build(ReportKind::Error, (file_id, r1))
-                .with_message(format!("{:?}", err))
-                .with_label(Label::new((file_id, r2)).with_color(Color::Red))
-                .finish()
-                .print((file_id, Source::from(src)))
-                .unwrap();
+    let spanned: Vec<(Token, SimpleSpan)> = match lex(src) {
+        Ok(spanned) => spanned,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                diagnostic.print(file_id, src);
+            }
+            std::process::exit(1);
         }
-        std::process::exit(1);
-    }
-
-    // Get the lexer output: vector of tokens with their spans
-    let spanned: Vec<(Token, SimpleSpan)> = lex_result.output().cloned().expect("no tokens");
+    };
     let tokens: Vec<Token> = spanned.iter().map(|(t, _)| t.clone()).collect();
 
     // Parse the tokens into an AST
@@ -99,9 +230,145 @@ pub fn lex_and_parse_span(src: &str) -> Vec<(Rule, SimpleSpan)> {
         std::process::exit(1);
     }
 
-    // If parsing was successful, print the AST
-    let output: &Vec<(Rule, SimpleSpan)> = parse_result.output().expect("No output from parser");
-    output.clone()
+    // The parser spans count tokens, map them back to character ranges in the source
+    let Some(output) = parse_result.output() else {
+        eprintln!("No output from parser");
+        std::process::exit(1);
+    };
+    output
+        .iter()
+        .map(|(rule, tok_span)| (rule.clone(), char_span(&spanned, *tok_span)))
+        .collect()
+}
+
+/// Like `lex_and_parse_span`, also returning the documentation of each rule: the `%` comments
+/// on the lines right above it, without their `%` and joined by newlines. Only comments on a
+/// line of their own count, and a blank line between a comment and the rule ends the
+/// documentation, so
+///
+/// ```text
+/// % Marks the node ready
+/// % once it is loaded.
+/// #loaded => +ready.
+/// ```
+///
+/// documents `#loaded => +ready.` with `Marks the node ready\nonce it is loaded.`
+pub fn lex_and_parse_with_docs(src: &str) -> Vec<(Rule, Option<String>, SimpleSpan)> {
+    let rules = lex_and_parse_span(src);
+    // Lexing succeeded above, so the comments are all there
+    let comments = lexer_with_comments()
+        .parse(src)
+        .into_output()
+        .map(|(_, comments)| comments)
+        .unwrap_or_default();
+
+    let line_of = |pos: usize| src[..pos].matches('\n').count();
+    let on_own_line = |span: SimpleSpan| {
+        src[..span.start]
+            .rsplit('\n')
+            .next()
+            .is_none_or(|before| before.trim().is_empty())
+    };
+
+    let mut previous_end = 0;
+    rules
+        .into_iter()
+        .map(|(rule, span)| {
+            // Walk up from the rule through the comments since the previous rule
+            let mut lines = Vec::new();
+            let mut line = line_of(span.start);
+            for (text, comment_span) in comments
+                .iter()
+                .rev()
+                .filter(|(_, c)| c.start >= previous_end && c.end <= span.start)
+            {
+                if !on_own_line(*comment_span) || line_of(comment_span.start) + 1 != line {
+                    break;
+                }
+                lines.push(text.trim());
+                line -= 1;
+            }
+            previous_end = span.end;
+            lines.reverse();
+            let doc = (!lines.is_empty()).then(|| lines.join("\n"));
+            (rule, doc, span)
+        })
+        .collect()
+}
+
+/// Deepest nesting of parentheses and braces the entry points returning errors accept. The
+/// parser recurses on every level, so deeper input would overflow the stack and abort. Chains
+/// of prefix operators nest the same way: every `not` and every namespace dot, as in
+/// `not not a` or `a.b.c`, counts as a level.
+pub const MAX_NESTING: usize = 256;
+
+/// Returns the span of the first bracket or prefix operator nested deeper than `MAX_NESTING`,
+/// if any.
+fn too_deep(tokens: &[(Token, SimpleSpan)]) -> Option<SimpleSpan> {
+    // Prefix operators in a row before the current token, and those before each open bracket
+    let mut chain = 0usize;
+    let mut outer: Vec<usize> = Vec::new();
+    let mut depth = 0usize;
+    for (token, span) in tokens {
+        match token {
+            Token::LeftParenthesis | Token::LeftCBracket => {
+                outer.push(chain);
+                chain = 0;
+                depth += 1;
+            }
+            Token::RightParenthesis | Token::RightCBracket => {
+                // The chain in front of the bracket ends with it
+                depth = depth.saturating_sub(1 + chain + outer.pop().unwrap_or(0));
+                chain = 0;
+                continue;
+            }
+            Token::Not | Token::Dot => {
+                chain += 1;
+                depth += 1;
+            }
+            // The names of a namespace chain sit between its dots
+            Token::Descriptor(_) => continue,
+            _ => {
+                depth -= chain;
+                chain = 0;
+                continue;
+            }
+        }
+        if depth > MAX_NESTING {
+            return Some(*span);
+        }
+    }
+    None
+}
+
+fn nesting_message() -> String {
+    format!("Nesting deeper than {} levels", MAX_NESTING)
+}
+
+/// Fails if the tokens nest deeper than `MAX_NESTING`.
+fn check_nesting(tokens: &[(Token, SimpleSpan)]) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match too_deep(tokens) {
+        Some(span) => Err(Box::<dyn Error + Send + Sync>::from(format!(
+            "{} at {:?}",
+            nesting_message(),
+            span.into_range()
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Converts a span over the lexed tokens into the character range those tokens cover.
+fn char_span(tokens: &[(Token, SimpleSpan)], tok_span: SimpleSpan) -> SimpleSpan {
+    let start = tokens.get(tok_span.start).map(|(_, sp)| sp.start).unwrap_or(0);
+    let end = if tok_span.end == 0 {
+        start
+    } else {
+        tokens
+            .get(tok_span.end - 1)
+            .map(|(_, sp)| sp.end)
+            .unwrap_or(start)
+    };
+    SimpleSpan::from(start..end)
 }
 
 pub fn lex_and_parse(src: &str) -> Vec<Rule> {
@@ -123,27 +390,15 @@ pub fn lex_and_parse_compound(src: &str) -> Compound {
     let file_id: &'static str = "input";
 
     // Lex all the tokens with their character spans
-    let lex_result = lexer().parse(src);
-    if lex_result.has_errors() {
-        for err in lex_result.errors() {
-            let span = err.span().clone();
-            let SimpleSpan { start, end, .. } = span;
-            let r1 = start..end;
-            let r2 = r1.clone();
-
-            // Create lex error report if lexing fails
-            Report::build(ReportKind::Error, (file_id, r1))
-                .with_message(format!("{:?}", err))
-                .with_label(Label::new((file_id, r2)).with_color(Color::Red))
-                .finish()
-                .print((file_id, Source::from(src)))
-                .unwrap();
+    let spanned: Vec<(Token, SimpleSpan)> = match lex(src) {
+        Ok(spanned) => spanned,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                diagnostic.print(file_id, src);
+            }
+            std::process::exit(1);
         }
-        std::process::exit(1);
-    }
-
-    // Get the lexer output: vector of tokens with their spans
-    let spanned: Vec<(Token, SimpleSpan)> = lex_result.output().cloned().expect("no tokens");
+    };
     let tokens: Vec<Token> = spanned.iter().map(|(t, _)| t.clone()).collect();
 
     // Parse the tokens into an AST
@@ -202,8 +457,11 @@ pub fn lex_and_parse_compound(src: &str) -> Compound {
     }
 
     // If parsing was successful, print the AST
-    let output: &(Compound, SimpleSpan) = parse_result.output().expect("No output from parser");
-    output.clone().0
+    let Some((compound, _span)) = parse_result.output() else {
+        eprintln!("No output from parser");
+        std::process::exit(1);
+    };
+    compound.clone()
 }
 
 pub fn lex_and_parse_safe(
@@ -212,8 +470,8 @@ pub fn lex_and_parse_safe(
     // Placeholder when parsing from files
     let file_id: &'static str = "input";
 
-    // Lex all the tokens with their character spans
-    let lex_result = lexer().parse(src);
+    // Lex all the tokens with their character spans, stopping at the first bad character
+    let lex_result = lexer_strict().parse(src);
     if lex_result.has_errors() {
         for err in lex_result.errors() {
             let span = err.span().clone();
@@ -221,22 +479,26 @@ pub fn lex_and_parse_safe(
             let r1 = start..end;
             let r2 = r1.clone();
 
-            // Create lex error report if lexing fails
-            Report::build(ReportKind::Error, (file_id, r1))
+            // Create lex error report if lexing fails; the error is returned even if it can't
+            // be printed
+            let _ = Report::build(ReportKind::Error, (file_id, r1))
                 .with_message(format!("{:?}", err))
                 .with_label(Label::new((file_id, r2)).with_color(Color::Red))
                 .finish()
-                .print((file_id, Source::from(src)))
-                .unwrap();
+                .print((file_id, Source::from(src)));
 
             return Err(Box::<dyn Error + Send + Sync>::from(format!(
-                "Failed to parse"
+                "Failed to lex at {:?}: {}",
+                span.into_range(),
+                err
             )));
         }
     }
 
     // Get the lexer output: vector of tokens with their spans
-    let spanned: Vec<(Token, SimpleSpan)> = lex_result.output().cloned().expect("no tokens");
+    let spanned: Vec<(Token, SimpleSpan)> =
+        lex_result.output().cloned().ok_or("No tokens from lexer")?;
+    check_nesting(&spanned)?;
     let tokens: Vec<Token> = spanned.iter().map(|(t, _)| t.clone()).collect();
 
     // Parse the tokens into an AST
@@ -279,8 +541,8 @@ pub fn lex_and_parse_safe(
                 )
             };
 
-            // Create a parse error report
-            Report::build(ReportKind::Error, (file_id, report_range))
+            // Create a parse error report; the error is returned even if it can't be printed
+            let _ = Report::build(ReportKind::Error, (file_id, report_range))
                 .with_message(msg.clone())
                 .with_label(
                     Label::new((file_id, label_range))
@@ -288,8 +550,7 @@ pub fn lex_and_parse_safe(
                         .with_message(msg),
                 )
                 .finish()
-                .print((file_id, Source::from(src)))
-                .unwrap();
+                .print((file_id, Source::from(src)));
             return Err(Box::<dyn Error + Send + Sync>::from(format!(
                 "Failed to parse"
             )));
@@ -297,10 +558,107 @@ pub fn lex_and_parse_safe(
     }
 
     // If parsing was successful, print the AST
-    let output: &Vec<(Rule, SimpleSpan)> = parse_result.output().expect("No output from parser");
+    let output: &Vec<(Rule, SimpleSpan)> = parse_result.output().ok_or("No output from parser")?;
     Ok(output
         .clone()
         .into_iter()
         .map(|(rule, _span)| rule)
         .collect::<Vec<Rule>>())
 }
+
+/// Lexes `src` and runs `parse` over its tokens, for the entry points that return their first
+/// problem as a plain message instead of printing it. `what` names the input in the message,
+/// as in `Failed to parse compound: unexpected . at 6`, where 6 is a byte offset.
+fn parse_tokens<'src, T>(
+    src: &'src str,
+    what: &str,
+    parse: impl for<'tokens> FnOnce(
+        &'tokens [Token<'src>],
+    ) -> ParseResult<T, Rich<'tokens, Token<'src>, Span>>,
+) -> Result<T, Box<dyn Error + Send + Sync>> {
+    let spanned = lex(src).map_err(|diagnostics| {
+        let first = &diagnostics[0];
+        format!(
+            "Failed to lex {} at {:?}: {}",
+            what,
+            first.span.into_range(),
+            first.message
+        )
+    })?;
+    check_nesting(&spanned)?;
+    let tokens: Vec<Token> = spanned.iter().map(|(t, _)| t.clone()).collect();
+
+    let (output, errors) = parse(tokens.as_slice()).into_output_errors();
+    if let Some(err) = errors.first() {
+        let char_start = spanned
+            .get(err.span().start)
+            .map(|(_, sp)| sp.start)
+            .unwrap_or(src.len());
+        // Custom errors, such as an unknown directive name, already say what went wrong
+        if let RichReason::Custom(msg) = err.reason() {
+            return Err(Box::<dyn Error + Send + Sync>::from(format!(
+                "Failed to parse {}: {} at {}",
+                what, msg, char_start
+            )));
+        }
+        let found = err
+            .found()
+            .map(|t| t.to_string())
+            .unwrap_or_else(|| "end of input".into());
+        return Err(Box::<dyn Error + Send + Sync>::from(format!(
+            "Failed to parse {}: unexpected {} at {}",
+            what, found, char_start
+        )));
+    }
+    Ok(output.ok_or("No output from parser")?)
+}
+
+/// Lexes and parses a single compound, such as `{#e => #a.} as r`.
+/// Errors are returned as plain messages instead of being printed.
+pub fn lex_and_parse_compound_safe(
+    src: &str,
+) -> Result<Compound, Box<dyn std::error::Error + Send + Sync>> {
+    // Parse the whole token stream as one compound
+    let (compound, _span) = parse_tokens(src, "compound", |tokens| {
+        compound_parser().then_ignore(end()).parse(tokens)
+    })?;
+    Ok(compound)
+}
+
+/// Lexes and parses a single condition, such as `loaded and not busy`.
+/// Errors are returned as plain messages instead of being printed.
+pub fn lex_and_parse_condition(
+    src: &str,
+) -> Result<Condition, Box<dyn std::error::Error + Send + Sync>> {
+    // Parse the whole token stream as one condition
+    let (condition, _span) = parse_tokens(src, "condition", |tokens| {
+        condition_parser().then_ignore(end()).parse(tokens)
+    })?;
+    Ok(condition)
+}
+
+/// Lexes and parses a sequence of directives, such as `@interleaving @external(sensor)`.
+/// Errors are returned as plain messages instead of being printed.
+pub fn lex_and_parse_directives(
+    src: &str,
+) -> Result<Vec<Directive>, Box<dyn std::error::Error + Send + Sync>> {
+    // Parse the whole token stream as directives only
+    let directives = parse_tokens(src, "directives", |tokens| {
+        directive_parser()
+            .repeated()
+            .collect::<Vec<_>>()
+            .then_ignore(end())
+            .parse(tokens)
+    })?;
+    Ok(directives.into_iter().map(|(directive, _span)| directive).collect())
+}
+
+/// Lexes and parses a program mixing rules and directives, such as a config file holding
+/// `@include(base) @external(sensor)` and no rules at all. Errors are returned as plain messages
+/// instead of being printed.
+pub fn lex_and_parse_items(
+    src: &str,
+) -> Result<Vec<ProgramItem>, Box<dyn std::error::Error + Send + Sync>> {
+    let items = parse_tokens(src, "program", |tokens| program_items_parser().parse(tokens))?;
+    Ok(items.into_iter().map(|(item, _span)| item).collect())
+}
@@ -10,6 +10,8 @@ pub type Spanned<T> = (T, Span);
 /// This parser currently handles:
 /// - Primitive actions: `#event`, `+event`, `-event`
 /// - Action sequences: `a; b; c`, `a, b, c`, `a par b par c`, `a seq b seq c`, `a alt b alt c`
+/// - Function calls: `seq(a b c)`, `par(a b c)`, `alt(a b c)`, which bind tighter than any of
+///   the infix forms, so `par(a b); c` is a sequence of `par(a b)` and `c`
 pub fn action_parser<'tokens, 'src: 'tokens, I>()
 -> impl Parser<'tokens, I, Spanned<Action>, extra::Err<Rich<'tokens, Token<'src>, Span>>> + Clone
 where
@@ -155,7 +157,14 @@ where
             .map_with(|(cond, _), span| (Condition::Parentheses(Box::new(cond)), span.span()))
             .labelled("parenthesized condition");
 
-        let primary = atomic_condition.or(parentheses);
+        // Comparison: counter >= number
+        let at_least = primitive_condition_parser::<I>()
+            .then_ignore(just(Token::GreaterEqual))
+            .then(select! { Token::Number(num) => num })
+            .map_with(|((var, _), num), span| (Condition::AtLeast(var, num), span.span()))
+            .labelled("comparison");
+
+        let primary = at_least.or(atomic_condition).or(parentheses);
 
         // Not operator: not <condition>
         let not = just(Token::Not)
@@ -246,7 +255,13 @@ where
 
     atomic_condition_parser.define(ac_parser);
 
-    // Reactive rules: ECA or CA
+    // Reactive rules: ECA or CA, optionally with an else action after `|`
+    let else_action = just(Token::Pipe)
+        .ignore_then(action_parser.clone())
+        .map(|(action, _)| action)
+        .labelled("else action")
+        .or_not();
+
     // ECA rule:    #event : condition => action        #event => action
     let eca_rule = primitive_event_parser
         .clone()
@@ -257,13 +272,15 @@ where
         )
         .then_ignore(just(Token::FatArrow))
         .then(action_parser.clone())
+        .then(else_action.clone())
         .then_ignore(just(Token::EndRule))
-        .map_with(|(((event, _), cond), (action, _)), span| {
+        .map_with(|((((event, _), cond), (action, _)), else_action), span| {
             (
                 Rule::Reactive(ReactiveRule::ECA {
                     event,
                     condition: cond.map(|(c, _)| c),
                     action,
+                    else_action,
                 }),
                 span.span(),
             )
@@ -275,10 +292,15 @@ where
         .ignore_then(condition_parser.clone())
         .then_ignore(just(Token::FatArrow))
         .then(action_parser.clone())
+        .then(else_action)
         .then_ignore(just(Token::EndRule))
-        .map_with(|((condition, _), (action, _)), span| {
+        .map_with(|(((condition, _), (action, _)), else_action), span| {
             (
-                Rule::Reactive(ReactiveRule::CA { condition, action }),
+                Rule::Reactive(ReactiveRule::CA {
+                    condition,
+                    action,
+                    else_action,
+                }),
                 span.span(),
             )
         })
@@ -377,18 +399,45 @@ where
 
     primitive_event_parser.define(pe_parser);
 
-    let a_parser = recursive(|_| {
+    let a_parser = recursive(|action| {
         // Primitive Event:
         let primitive_event_action = primitive_event_parser
             .clone()
             .map_with(|(pe, _), span| (Action::Primitive(pe), span.span()))
             .labelled("primitive action");
 
+        // Function call: seq(a b c), par(a b c) or alt(a b c)
+        // Every argument is a whole action, so `seq(a, b c)` runs `a, b` before `c`
+        let list_kind = just(Token::Seq)
+            .to(ActionList::Sequence as fn(Vec<Action>) -> ActionList)
+            .or(just(Token::Par).to(ActionList::Parallel as fn(Vec<Action>) -> ActionList))
+            .or(just(Token::Alt).to(ActionList::Alternative as fn(Vec<Action>) -> ActionList));
+        let call = list_kind
+            .then(
+                action
+                    .repeated()
+                    .at_least(1)
+                    .collect::<Vec<_>>()
+                    .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis)),
+            )
+            .map_with(|(list, actions), span| {
+                (
+                    Action::List(list(actions.into_iter().map(|(a, _)| a).collect())),
+                    span.span(),
+                )
+            })
+            .labelled("action call");
+
+        // A call is a single operand of the infix forms below, whose keywords never take a
+        // `(` after them, so `a seq(b c)` is two operands rather than `a seq` something
+        let operand = primitive_event_action.or(call);
+        let infix = |keyword| just(keyword).then_ignore(just(Token::LeftParenthesis).not());
+
         // Action Sequence:
         // Parallel: a, b, c    or    a par b par c
-        let parallel = primitive_event_action
+        let parallel = operand
             .clone()
-            .separated_by(just(Token::Comma).or(just(Token::Par)))
+            .separated_by(just(Token::Comma).or(infix(Token::Par)))
             .at_least(1)
             .allow_trailing()
             .collect::<Vec<_>>()
@@ -407,7 +456,7 @@ where
 
         // Alternative: a alt b alt c
         let alternate = parallel
-            .separated_by(just(Token::Alt))
+            .separated_by(infix(Token::Alt))
             .at_least(1)
             .allow_trailing()
             .collect::<Vec<_>>()
@@ -426,7 +475,7 @@ where
 
         // Sequence: a; b; c    or    a seq b seq c
         let sequence = alternate
-            .separated_by(just(Token::Semicolon).or(just(Token::Seq)))
+            .separated_by(just(Token::Semicolon).or(infix(Token::Seq)))
             .at_least(1)
             .allow_trailing()
             .collect::<Vec<_>>()
@@ -472,6 +521,27 @@ where
         .labelled("program")
 }
 
+/// A Parser for a program made of rules and directives in any order. Either kind may be
+/// missing, so a file of only directives, only rules, or nothing at all is accepted.
+pub fn program_items_parser<'tokens, 'src: 'tokens, I>()
+-> impl Parser<'tokens, I, Vec<Spanned<ProgramItem>>, extra::Err<Rich<'tokens, Token<'src>, Span>>>
++ Clone
+where
+    I: ValueInput<'tokens, Token = Token<'src>, Span = Span>,
+{
+    // Directives all start with `@`, so trying them first never hides a rule
+    let directive = directive_parser::<I>().map(|(d, s)| (ProgramItem::Directive(d), s));
+    let rule = rule_parser::<I>().map(|(r, s)| (ProgramItem::Rule(r), s));
+
+    // No label over the whole program: it would replace the error of a bad first item, such as
+    // an unknown directive, with a bare "expected program"
+    directive
+        .or(rule)
+        .repeated()
+        .collect::<Vec<_>>()
+        .then_ignore(end())
+}
+
 /// A Parser for directives in the CL0 language.
 pub fn directive_parser<'tokens, 'src: 'tokens, I>()
 -> impl Parser<'tokens, I, Spanned<Directive>, extra::Err<Rich<'tokens, Token<'src>, Span>>> + Clone
@@ -484,10 +554,12 @@ where
         .clone()
         .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis));
 
-    let scale = just(Token::At)
-        .ignore_then(just(Token::Descriptor("scale")))
+    let scale = just(Token::AtScale)
         .then(
             (select! { Token::Number(num) => num })
+                .try_map(|num, span| {
+                    u8::try_from(num).map_err(|_| Rich::custom(span, "scale out of range for u8"))
+                })
                 .delimited_by(just(Token::LeftParenthesis), just(Token::RightParenthesis)),
         )
         .then(compound_parser().clone())
@@ -501,27 +573,26 @@ where
             )
         });
 
-    let include = just(Token::At)
-        .ignore_then(just(Token::Descriptor("include")))
+    let include = just(Token::AtInclude)
         .then(inside_params.clone())
         .map_with(|(_, desc), s| (Directive::Include(desc.to_string()), s.span()));
 
-    let exclude = just(Token::At)
-        .ignore_then(just(Token::Descriptor("exclude")))
+    let exclude = just(Token::AtExclude)
         .then(inside_params.clone())
         .map_with(|(_, desc), s| (Directive::Exclude(desc.to_string()), s.span()));
 
-    let interleaving = just(Token::At)
-        .ignore_then(just(Token::Descriptor("interleaving")))
+    let interleaving = just(Token::AtInterleaving)
         .map_with(|_, s| (Directive::Interleaving, s.span()));
 
-    let external_var = just(Token::At)
-        .ignore_then(just(Token::Descriptor("external")))
+    let external_var = just(Token::AtExternal)
         .then(inside_params.clone())
         .map_with(|(_, desc), s| (Directive::ExternalVar(desc.to_string()), s.span()));
 
-    let external_event = just(Token::At)
-        .ignore_then(just(Token::Descriptor("external")))
+    let counter = just(Token::AtCounter)
+        .then(inside_params.clone())
+        .map_with(|(_, desc), s| (Directive::Counter(desc.to_string()), s.span()));
+
+    let external_event = just(Token::AtExternal)
         .then(
             primitive_event_parser()
                 .clone()
@@ -529,11 +600,19 @@ where
         )
         .map_with(|(_, (pe, _)), s| (Directive::ExternalEvent(pe), s.span()));
 
+    // Any other `@name` is reported as such, rather than as a mismatch of every directive, so it
+    // sits outside the label
+    let unknown = just(Token::At)
+        .ignore_then(descriptor)
+        .try_map(|name, span| Err(Rich::custom(span, format!("unknown directive @{}", name))));
+
     scale
         .or(include)
         .or(exclude)
         .or(interleaving)
         .or(external_event)
         .or(external_var)
+        .or(counter)
         .labelled("directive")
+        .or(unknown)
 }
@@ -60,17 +60,93 @@ pub enum Token<'src> {
     /// Represents a thin arrow `->`
     ThinArrow,
 
+    /// Represents `>=` for comparing a counter, eg. `<counter> >= <number>`
+    GreaterEqual,
+
+    /// Symbol for the else action of a reactive rule, eg. `<event>: <condition> => <action> | <action>`
+    Pipe,
+
     /// A variable/function name, like `foo` or `my_var`
     Descriptor(&'src str),
 
     /// `as` keyword for aliasing, e.g., `foo as bar`
     As,
 
-    /// `@` used for directives, e.g., `@scale()`
+    /// `@` in front of a name that isn't a directive, e.g., `@bogus`
     At,
 
+    /// `@scale` directive, e.g., `@scale(3) { ... }`
+    AtScale,
+    /// `@include` directive, e.g., `@include(base)`
+    AtInclude,
+    /// `@exclude` directive, e.g., `@exclude(base)`
+    AtExclude,
+    /// `@interleaving` directive
+    AtInterleaving,
+    /// `@external` directive, e.g., `@external(temperature)`
+    AtExternal,
+    /// `@counter` directive, e.g., `@counter(retries)`
+    AtCounter,
+
     /// A numeric literal, e.g., `42`
-    Number(u8),
+    Number(i64),
+}
+
+/// Broad kind of a token, e.g. to pick a color when highlighting source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenCategory {
+    /// Reserved words: `seq`, `par`, `alt`, `and`, `or`, `not`, `as`, and the directive names
+    /// such as `@scale`
+    Keyword,
+    /// Symbols acting on what follows or surrounds them: `=>`, `->`, `-o`, `>=`, `+`, `-`, `#`, `@`
+    Operator,
+    /// Variable, event and alias names
+    Identifier,
+    /// Punctuation that groups or separates: brackets, `:`, `;`, `,`, `.`, `|`
+    Delimiter,
+    /// Numeric literals
+    Number,
+}
+
+impl<'src> Token<'src> {
+    /// Returns the category of this token.
+    pub fn category(&self) -> TokenCategory {
+        match self {
+            Token::Seq
+            | Token::Par
+            | Token::Alt
+            | Token::And
+            | Token::Or
+            | Token::Not
+            | Token::As
+            | Token::AtScale
+            | Token::AtInclude
+            | Token::AtExclude
+            | Token::AtInterleaving
+            | Token::AtExternal
+            | Token::AtCounter => TokenCategory::Keyword,
+            Token::FatArrow
+            | Token::ThinArrow
+            | Token::DashO
+            | Token::GreaterEqual
+            | Token::Plus
+            | Token::Minus
+            | Token::Hash
+            | Token::At => TokenCategory::Operator,
+            Token::Descriptor(_) => TokenCategory::Identifier,
+            Token::Colon
+            | Token::Semicolon
+            | Token::LeftParenthesis
+            | Token::RightParenthesis
+            | Token::LeftCBracket
+            | Token::RightCBracket
+            | Token::Comma
+            | Token::Dot
+            | Token::EndRule
+            | Token::Pipe => TokenCategory::Delimiter,
+            Token::Number(_) => TokenCategory::Number,
+        }
+    }
 }
 
 impl<'src> fmt::Display for Token<'src> {
@@ -97,9 +173,17 @@ impl<'src> fmt::Display for Token<'src> {
             Token::DashO => write!(f, "-o"),
             Token::FatArrow => write!(f, "=>"),
             Token::ThinArrow => write!(f, "->"),
+            Token::GreaterEqual => write!(f, ">="),
+            Token::Pipe => write!(f, "|"),
             Token::Descriptor(s) => write!(f, "\"{}\"", s),
             Token::As => write!(f, "as"),
             Token::At => write!(f, "@"),
+            Token::AtScale => write!(f, "@scale"),
+            Token::AtInclude => write!(f, "@include"),
+            Token::AtExclude => write!(f, "@exclude"),
+            Token::AtInterleaving => write!(f, "@interleaving"),
+            Token::AtExternal => write!(f, "@external"),
+            Token::AtCounter => write!(f, "@counter"),
             Token::Number(n) => write!(f, "{}", n),
         }
     }
@@ -0,0 +1,293 @@
+//! Semantic checks for rules the grammar accepts but a node can't meaningfully run.
+//!
+//! [`validate_rule`] rejects:
+//! - [`SemanticError::DeclarativeInCompound`]: a compound holding a declarative rule. Compounds
+//!   are activated and deactivated as a whole, which declarative rules don't support.
+//! - [`SemanticError::CompoundEvent`]: an ECA rule reacting to the production or consumption of
+//!   a compound, such as `+{ a. } => +b.`. Only triggers and variables raise events.
+//! - [`SemanticError::EmptyCompound`]: a compound without rules or alias, such as `+{}`, which
+//!   has no effect.
+//! - [`SemanticError::BareTrigger`]: a compound holding a bare trigger, such as the fact
+//!   `{ => #e. }.`. A node stores only the reactive rules and facts of a compound, so the
+//!   trigger would never fire.
+//!
+//! [`validate_program`] also rejects [`SemanticError::UndefinedAlias`]: a consumption of an alias
+//! no rule of the program defines, such as `=> -r.x.` on its own. Which aliases exist depends on
+//! the whole program, so a single rule can't be checked for it.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+use crate::ast::{
+    Action, ActionList, AtomicCondition, CaseRule, Compound, Condition, DeclarativeRule, FactRule,
+    PrimitiveEvent, ReactiveRule, Rule,
+};
+
+/// A rule the node can't meaningfully handle, see the module documentation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SemanticError {
+    DeclarativeInCompound { rule: String },
+    CompoundEvent { event: String },
+    EmptyCompound,
+    BareTrigger { rule: String },
+    UndefinedAlias { alias: String },
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SemanticError::DeclarativeInCompound { rule } => {
+                write!(f, "Declarative rule inside a compound: {}", rule)
+            }
+            SemanticError::CompoundEvent { event } => {
+                write!(f, "Compounds don't raise events: {}", event)
+            }
+            SemanticError::EmptyCompound => write!(f, "Compound without rules or alias"),
+            SemanticError::BareTrigger { rule } => {
+                write!(f, "Trigger inside a compound never fires: {}", rule)
+            }
+            SemanticError::UndefinedAlias { alias } => {
+                write!(f, "Consumption of undefined alias: {}", alias)
+            }
+        }
+    }
+}
+
+impl Error for SemanticError {}
+
+/// Checks a rule, including the compounds nested in it, for the forms listed in the module
+/// documentation. The first problem found is returned.
+pub fn validate_rule(rule: &Rule) -> Result<(), SemanticError> {
+    match rule {
+        Rule::Reactive(ReactiveRule::ECA {
+            event,
+            condition,
+            action,
+            else_action,
+        }) => {
+            if let PrimitiveEvent::Production(AtomicCondition::Compound(_))
+            | PrimitiveEvent::Consumption(AtomicCondition::Compound(_)) = event
+            {
+                return Err(SemanticError::CompoundEvent {
+                    event: event.to_string(),
+                });
+            }
+            if let Some(condition) = condition {
+                validate_condition(condition)?;
+            }
+            validate_action(action)?;
+            else_action.as_ref().map_or(Ok(()), validate_action)
+        }
+        Rule::Reactive(ReactiveRule::CA {
+            condition,
+            action,
+            else_action,
+        }) => {
+            validate_condition(condition)?;
+            validate_action(action)?;
+            else_action.as_ref().map_or(Ok(()), validate_action)
+        }
+        Rule::Declarative(DeclarativeRule::CC { premise, condition }) => {
+            if let Some(premise) = premise {
+                validate_condition(premise)?;
+            }
+            validate_atomic_condition(condition)
+        }
+        Rule::Declarative(DeclarativeRule::CT { premise, condition }) => {
+            if let Some(premise) = premise {
+                validate_condition(premise)?;
+            }
+            validate_condition(condition)
+        }
+        Rule::Case(CaseRule { action }) => validate_action(action),
+        Rule::Fact(FactRule { condition }) => validate_atomic_condition(condition),
+    }
+}
+
+fn validate_compound(compound: &Compound) -> Result<(), SemanticError> {
+    if compound.rules.is_empty() && compound.alias.is_none() {
+        return Err(SemanticError::EmptyCompound);
+    }
+    for rule in compound.rules.iter() {
+        match rule {
+            Rule::Declarative(_) => {
+                return Err(SemanticError::DeclarativeInCompound {
+                    rule: rule.to_string(),
+                });
+            }
+            Rule::Case(CaseRule {
+                action: Action::Primitive(PrimitiveEvent::Trigger(_)),
+            }) => {
+                return Err(SemanticError::BareTrigger {
+                    rule: rule.to_string(),
+                });
+            }
+            _ => {}
+        }
+        validate_rule(rule)?;
+    }
+    Ok(())
+}
+
+fn validate_atomic_condition(condition: &AtomicCondition) -> Result<(), SemanticError> {
+    match condition {
+        AtomicCondition::Primitive(_) => Ok(()),
+        AtomicCondition::Compound(compound) => validate_compound(compound),
+        AtomicCondition::SubCompound { condition, .. } => validate_atomic_condition(condition),
+    }
+}
+
+fn validate_condition(condition: &Condition) -> Result<(), SemanticError> {
+    match condition {
+        Condition::Atomic(atomic) => validate_atomic_condition(atomic),
+        Condition::Not(inner) | Condition::Parentheses(inner) => validate_condition(inner),
+        Condition::Conjunction(conditions) | Condition::Disjunction(conditions) => {
+            conditions.iter().try_for_each(validate_condition)
+        }
+        Condition::AtLeast(..) => Ok(()),
+    }
+}
+
+fn validate_action(action: &Action) -> Result<(), SemanticError> {
+    match action {
+        Action::Primitive(PrimitiveEvent::Trigger(_)) => Ok(()),
+        Action::Primitive(PrimitiveEvent::Production(condition))
+        | Action::Primitive(PrimitiveEvent::Consumption(condition)) => {
+            validate_atomic_condition(condition)
+        }
+        Action::List(
+            ActionList::Sequence(actions)
+            | ActionList::Parallel(actions)
+            | ActionList::Alternative(actions),
+        ) => actions.iter().try_for_each(validate_action),
+    }
+}
+
+/// Checks every rule of a program with [`validate_rule`], then that every consumption of an
+/// alias, such as `-r.x` or `-{ ... } as r`, names one the program defines. An alias counts as
+/// defined once any rule stores, derives or produces a compound or namespace under its name,
+/// at any depth, so the check only rejects names that can't exist when the program runs alone.
+pub fn validate_program(rules: &[Rule]) -> Result<(), SemanticError> {
+    rules.iter().try_for_each(validate_rule)?;
+    let mut aliases = HashSet::new();
+    for rule in rules {
+        collect_aliases(rule, &mut aliases);
+    }
+    rules.iter().try_for_each(|rule| check_consumptions(rule, &aliases))
+}
+
+/// The primitive events the actions of `rule` produce, with action lists flattened.
+fn action_events(rule: &Rule) -> Vec<&PrimitiveEvent> {
+    fn flatten<'a>(action: &'a Action, events: &mut Vec<&'a PrimitiveEvent>) {
+        match action {
+            Action::Primitive(event) => events.push(event),
+            Action::List(
+                ActionList::Sequence(actions)
+                | ActionList::Parallel(actions)
+                | ActionList::Alternative(actions),
+            ) => actions.iter().for_each(|action| flatten(action, events)),
+        }
+    }
+
+    let mut events = Vec::new();
+    match rule {
+        Rule::Reactive(
+            ReactiveRule::ECA {
+                action,
+                else_action,
+                ..
+            }
+            | ReactiveRule::CA {
+                action,
+                else_action,
+                ..
+            },
+        ) => {
+            flatten(action, &mut events);
+            if let Some(else_action) = else_action {
+                flatten(else_action, &mut events);
+            }
+        }
+        Rule::Case(CaseRule { action }) => flatten(action, &mut events),
+        Rule::Declarative(_) | Rule::Fact(_) => {}
+    }
+    events
+}
+
+/// The atomic conditions `rule` stores: its fact, the condition it derives, and what its
+/// actions produce.
+fn stored_conditions(rule: &Rule) -> Vec<&AtomicCondition> {
+    let mut stored: Vec<&AtomicCondition> = action_events(rule)
+        .into_iter()
+        .filter_map(|event| match event {
+            PrimitiveEvent::Production(condition) => Some(condition),
+            _ => None,
+        })
+        .collect();
+    if let Rule::Fact(FactRule { condition })
+    | Rule::Declarative(DeclarativeRule::CC { condition, .. }) = rule
+    {
+        stored.push(condition);
+    }
+    stored
+}
+
+fn collect_aliases<'a>(rule: &'a Rule, aliases: &mut HashSet<&'a str>) {
+    fn collect<'a>(condition: &'a AtomicCondition, aliases: &mut HashSet<&'a str>) {
+        match condition {
+            AtomicCondition::Primitive(_) => {}
+            AtomicCondition::Compound(compound) => {
+                if let Some(alias) = &compound.alias {
+                    aliases.insert(alias);
+                }
+                compound.rules.iter().for_each(|rule| collect_aliases(rule, aliases));
+            }
+            AtomicCondition::SubCompound {
+                namespace,
+                condition,
+            } => {
+                aliases.insert(namespace);
+                collect(condition, aliases);
+            }
+        }
+    }
+
+    for condition in stored_conditions(rule) {
+        collect(condition, aliases);
+    }
+}
+
+fn check_consumptions(rule: &Rule, aliases: &HashSet<&str>) -> Result<(), SemanticError> {
+    for event in action_events(rule) {
+        if let PrimitiveEvent::Consumption(
+            AtomicCondition::Compound(Compound {
+                alias: Some(alias), ..
+            })
+            | AtomicCondition::SubCompound {
+                namespace: alias, ..
+            },
+        ) = event
+            && !aliases.contains(alias.as_str())
+        {
+            return Err(SemanticError::UndefinedAlias {
+                alias: alias.clone(),
+            });
+        }
+    }
+
+    // The rules of stored compounds consume aliases as well
+    let mut nested: Vec<&AtomicCondition> = stored_conditions(rule);
+    while let Some(condition) = nested.pop() {
+        match condition {
+            AtomicCondition::Primitive(_) => {}
+            AtomicCondition::Compound(compound) => {
+                for rule in compound.rules.iter() {
+                    check_consumptions(rule, aliases)?;
+                }
+            }
+            AtomicCondition::SubCompound { condition, .. } => nested.push(condition),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use cl0_parser::ast::{ActionList, Condition, RuleBuilder, consume, event_trigger, produce};
+use cl0_parser::lex_and_parse;
+
+#[test]
+fn builds_the_parsed_rules() {
+    let built = vec![
+        RuleBuilder::eca(event_trigger("e"))
+            .then(produce("a"))
+            .build(),
+        RuleBuilder::eca(event_trigger("e"))
+            .when("c")
+            .when(Condition::Not(Box::new("d".into())))
+            .then(ActionList::Parallel(vec![
+                produce("a").into(),
+                consume("b").into(),
+            ]))
+            .build(),
+        RuleBuilder::eca(produce("a"))
+            .then(ActionList::Sequence(vec![
+                event_trigger("f").into(),
+                consume("a").into(),
+            ]))
+            .build(),
+        RuleBuilder::ca("loaded").then(produce("ready")).build(),
+        RuleBuilder::eca(event_trigger("e"))
+            .when("c")
+            .then(produce("a"))
+            .otherwise(produce("b"))
+            .build(),
+    ];
+    assert_eq!(
+        built,
+        lex_and_parse(
+            "#e => +a. #e: c and not d => +a, -b. +a => #f; -a. : loaded => +ready. \
+             #e: c => +a | +b."
+        )
+    );
+}
+
+#[test]
+#[should_panic(expected = "RuleBuilder::then must be called before build")]
+fn build_requires_an_action() {
+    RuleBuilder::eca(event_trigger("e")).build();
+}
@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+
+use cl0_parser::cli::{EXIT_OK, EXIT_PARSE_ERROR, EXIT_USAGE, run};
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+/// Writes `src` to a policy file unique to the calling test.
+fn policy_file(name: &str, src: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("cl0_cli_{}_{}.cl0", name, std::process::id()));
+    std::fs::write(&path, src).unwrap();
+    path
+}
+
+#[test]
+fn check_file() {
+    let good = policy_file("good", "#e: c => +a.\n#f => -a.\n");
+    let bad = policy_file("bad", "#e => .\n");
+
+    assert_eq!(run(args(&["--check", "--file", good.to_str().unwrap()])), EXIT_OK);
+    assert_eq!(run(args(&["--file", good.to_str().unwrap()])), EXIT_OK);
+    assert_eq!(run(args(&["--file", bad.to_str().unwrap(), "--check"])), EXIT_PARSE_ERROR);
+
+    std::fs::remove_file(good).unwrap();
+    std::fs::remove_file(bad).unwrap();
+}
+
+#[test]
+fn check_source_argument() {
+    assert_eq!(run(args(&["--check", "#e => +a."])), EXIT_OK);
+    assert_eq!(run(args(&["--check", "#e => ."])), EXIT_PARSE_ERROR);
+    assert_eq!(run(args(&["#e => +a."])), EXIT_OK);
+    assert_eq!(run(args(&["#e => .\n#f => ."])), EXIT_PARSE_ERROR);
+}
+
+#[test]
+fn bad_arguments() {
+    assert_eq!(run(args(&[])), EXIT_USAGE);
+    assert_eq!(run(args(&["--file"])), EXIT_USAGE);
+    assert_eq!(run(args(&["--verbose", "a."])), EXIT_USAGE);
+    assert_eq!(run(args(&["a.", "--file", "policy.cl0"])), EXIT_USAGE);
+    assert_eq!(run(args(&["--file", "/nonexistent/policy.cl0"])), EXIT_USAGE);
+}
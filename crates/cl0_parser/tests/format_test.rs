@@ -0,0 +1,84 @@
+use cl0_parser::format::format_program;
+use cl0_parser::lex_and_parse;
+
+#[test]
+fn normalizes_messy_input() {
+    assert_eq!(format_program("#e:c=>+a .").unwrap(), "#e: c => +a.\n");
+    assert_eq!(
+        format_program("  #e=>+a,+b   alt -c;#f.\n\n:x and not y=>#g.").unwrap(),
+        "#e => +a, +b alt -c; #f.\n:x and not y => #g.\n"
+    );
+    assert_eq!(format_program("p-o c. p->c.").unwrap(), "p -o c.\np -> c.\n");
+    assert_eq!(format_program("").unwrap(), "");
+}
+
+#[test]
+fn formatting_is_idempotent() {
+    let messy = "#e:c=>+a .  a.  =>#e.\n{#f=>+b.  c.}as r .  r.{x.}.  (a or b) and c->d. -o e. \
+                 #g: not (a and b) or c => +x alt -y alt #z; +w.";
+    let once = format_program(messy).unwrap();
+    assert_eq!(format_program(&once).unwrap(), once);
+    assert_eq!(lex_and_parse(&once), lex_and_parse(messy));
+}
+
+#[test]
+fn reports_parse_errors() {
+    let diagnostics = format_program("#e => .").unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].span.into_range(), 6..7);
+}
+
+#[test]
+fn nested_action_lists_round_trip() {
+    let kinds = ["seq", "par", "alt"];
+    let mut sources = vec!["#e => seq(#a).".to_string(), "#e => #a | par(#b).".to_string()];
+    for outer in kinds {
+        for inner in kinds {
+            sources.push(format!("#e => {}({}(#a #b) #c).", outer, inner));
+            sources.push(format!("#e => {}(#a {}(#b #c)).", outer, inner));
+            for deepest in kinds {
+                sources.push(format!(
+                    "#e: c => {}(#a {}({}(#b #c) #d)) | {}(#f #g).",
+                    outer, inner, deepest, inner
+                ));
+            }
+        }
+    }
+
+    for src in sources {
+        let formatted = format_program(&src).unwrap();
+        assert_eq!(lex_and_parse(&formatted), lex_and_parse(&src), "{} -> {}", src, formatted);
+        assert_eq!(format_program(&formatted).unwrap(), formatted);
+    }
+    assert_eq!(
+        format_program("#e => par(seq(#a #b) #c).").unwrap(),
+        "#e => seq(#a #b), #c.\n"
+    );
+}
+
+#[test]
+fn quoted_names_round_trip() {
+    assert_eq!(
+        format_program("`user logged in`. `and`.").unwrap(),
+        "`user logged in`.\n`and`.\n"
+    );
+
+    let src = "#`door opened`: `is locked` and not `or` => +`alarm on`; #`2fa`. \
+               {#e => -`x y`.} as `my alias`. `my alias`.{#e => -`x y`.}. plain_1 -> `not`.";
+    let formatted = format_program(src).unwrap();
+    assert_eq!(lex_and_parse(&formatted), lex_and_parse(src), "{}", formatted);
+    assert_eq!(format_program(&formatted).unwrap(), formatted);
+
+    // A quoted namespace nested in another one stays a single rule
+    let src = "#e => +r.`my alias`.x. `my ns`.`inner ns`.{#f => +y.}.";
+    let rules = lex_and_parse(src);
+    assert_eq!(rules.len(), 2);
+    let formatted = format_program(src).unwrap();
+    assert_eq!(lex_and_parse(&formatted), rules, "{}", formatted);
+    assert_eq!(format_program(&formatted).unwrap(), formatted);
+
+    // Consuming a name starting with `o` must not read back as `-o`
+    let src = "#e => - off, - o.q, -`on hold`.";
+    let formatted = format_program(src).unwrap();
+    assert_eq!(lex_and_parse(&formatted), lex_and_parse(src), "{}", formatted);
+}
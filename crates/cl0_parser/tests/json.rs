@@ -0,0 +1,28 @@
+#![cfg(feature = "serde")]
+
+use cl0_parser::json::{parse_rules_json, rules_to_json};
+use cl0_parser::lex_and_parse;
+
+#[test]
+fn test_json_eca_rule_matches_text() {
+    let src = r#"[{"Reactive": {"ECA": {
+        "event": {"Trigger": "e"},
+        "condition": null,
+        "action": {"Primitive": {"Production": {"Primitive": {"Var": "a"}}}}
+    }}}]"#;
+    assert_eq!(parse_rules_json(src).unwrap(), lex_and_parse("#e => +a."));
+}
+
+#[test]
+fn test_json_round_trip() {
+    let rules = lex_and_parse("#e : ready and not busy => +a; -b. loaded. => #e. { #f => +c. } as p.");
+    let json = rules_to_json(&rules).unwrap();
+    assert_eq!(parse_rules_json(&json).unwrap(), rules);
+}
+
+#[test]
+fn test_json_errors() {
+    assert!(parse_rules_json("").is_err());
+    assert!(parse_rules_json(r#"[{"Reactive": {"Unknown": {}}}]"#).is_err());
+    assert!(parse_rules_json("[]").unwrap().is_empty());
+}
@@ -1,5 +1,10 @@
 use chumsky::Parser;
-use cl0_parser::lexer::lexer;
+use cl0_parser::lexer::{lexer, lexer_strict, lexer_with_comments};
+use cl0_parser::{
+    MAX_NESTING, lex_and_parse_compound_safe, lex_and_parse_condition, lex_and_parse_directives,
+    lex_and_parse_items, lex_and_parse_safe, lex_and_parse_with_docs, parse_program, position,
+    tokenize,
+};
 use cl0_parser::token::Token;
 
 #[test]
@@ -66,3 +71,419 @@ fn dot_vs_endrule4() {
     assert_eq!(tokens, vec![Token::Not, Token::Descriptor("condition"), Token::Dot, Token::LeftCBracket]);
 }
 
+
+#[test]
+fn identifier_with_leading_underscore() {
+    let input = "_internal _x";
+    let tokens = lexer().parse(input).unwrap();
+    let tokens: Vec<_> = tokens.into_iter().map(|(tok, _)| tok).collect();
+
+    assert_eq!(tokens, vec![Token::Descriptor("_internal"), Token::Descriptor("_x")]);
+}
+
+#[test]
+fn identifier_with_digits() {
+    let input = "state1 my_var2 a_1_b";
+    let tokens = lexer().parse(input).unwrap();
+    let tokens: Vec<_> = tokens.into_iter().map(|(tok, _)| tok).collect();
+
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Descriptor("state1"),
+            Token::Descriptor("my_var2"),
+            Token::Descriptor("a_1_b"),
+        ]
+    );
+}
+
+#[test]
+fn identifier_cannot_start_with_digit() {
+    let input = "1state";
+    let tokens: Vec<_> = lexer()
+        .parse(input)
+        .into_output()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(tok, _)| tok)
+        .collect();
+
+    assert!(!tokens.contains(&Token::Descriptor("1state")));
+}
+
+#[test]
+fn number_alone() {
+    let input = "42";
+    let tokens = lexer().parse(input).unwrap();
+    let tokens: Vec<_> = tokens.into_iter().map(|(tok, _)| tok).collect();
+
+    assert_eq!(tokens, vec![Token::Number(42)]);
+}
+
+#[test]
+fn number_inside_parentheses() {
+    let input = "@scale(3)";
+    let tokens = lexer().parse(input).unwrap();
+    let tokens: Vec<_> = tokens.into_iter().map(|(tok, _)| tok).collect();
+
+    assert_eq!(
+        tokens,
+        vec![
+            Token::AtScale,
+            Token::LeftParenthesis,
+            Token::Number(3),
+            Token::RightParenthesis,
+        ]
+    );
+}
+
+#[test]
+fn directive_names_are_keywords() {
+    let input = "@include @exclude @interleaving @external @counter @bogus";
+    let tokens = lexer().parse(input).unwrap();
+    let tokens: Vec<_> = tokens.into_iter().map(|(tok, _)| tok).collect();
+
+    assert_eq!(
+        tokens,
+        vec![
+            Token::AtInclude,
+            Token::AtExclude,
+            Token::AtInterleaving,
+            Token::AtExternal,
+            Token::AtCounter,
+            Token::At,
+            Token::Descriptor("bogus"),
+        ]
+    );
+}
+
+#[test]
+fn numbers_beyond_a_byte() {
+    let tokens = lexer().parse("300 9223372036854775807").unwrap();
+    let tokens: Vec<_> = tokens.into_iter().map(|(tok, _)| tok).collect();
+
+    assert_eq!(tokens, vec![Token::Number(300), Token::Number(i64::MAX)]);
+    assert!(lexer().parse("9223372036854775808").has_errors());
+}
+
+#[test]
+fn number_followed_by_letters_is_an_error() {
+    for input in ["3d", "1state", "42_x"] {
+        assert!(lexer().parse(input).has_errors(), "expected {:?} to fail", input);
+    }
+}
+
+#[test]
+fn dot_vs_endrule_nested_namespace() {
+    let input = "r.s.{ x. }";
+    let tokens = lexer().parse(input).unwrap();
+    let tokens: Vec<_> = tokens.into_iter().map(|(tok, _)| tok).collect();
+
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Descriptor("r"),
+            Token::Dot,
+            Token::Descriptor("s"),
+            Token::Dot,
+            Token::LeftCBracket,
+            Token::Descriptor("x"),
+            Token::EndRule,
+            Token::RightCBracket,
+        ]
+    );
+}
+
+#[test]
+fn dot_vs_endrule_quoted_namespace() {
+    let input = "#e => +r.`my alias`.x.";
+    let tokens = lexer().parse(input).unwrap();
+    let tokens: Vec<_> = tokens.into_iter().map(|(tok, _)| tok).collect();
+
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Hash,
+            Token::Descriptor("e"),
+            Token::FatArrow,
+            Token::Plus,
+            Token::Descriptor("r"),
+            Token::Dot,
+            Token::Descriptor("my alias"),
+            Token::Dot,
+            Token::Descriptor("x"),
+            Token::EndRule,
+        ]
+    );
+}
+
+#[test]
+fn dot_vs_endrule_at_end_of_input() {
+    let input = "foo.";
+    let tokens = lexer().parse(input).unwrap();
+    let tokens: Vec<_> = tokens.into_iter().map(|(tok, _)| tok).collect();
+
+    assert_eq!(tokens, vec![Token::Descriptor("foo"), Token::EndRule]);
+}
+
+#[test]
+fn dot_vs_endrule_two_facts() {
+    for input in ["a. b.", "a.\nb.", "a. % comment\nb."] {
+        let tokens = lexer().parse(input).unwrap();
+        let tokens: Vec<_> = tokens.into_iter().map(|(tok, _)| tok).collect();
+
+        assert_eq!(
+            tokens,
+            vec![Token::Descriptor("a"), Token::EndRule, Token::Descriptor("b"), Token::EndRule],
+            "on {:?}",
+            input
+        );
+    }
+}
+
+#[test]
+fn dot_vs_endrule_underscore_namespace() {
+    let input = "r._internal";
+    let tokens = lexer().parse(input).unwrap();
+    let tokens: Vec<_> = tokens.into_iter().map(|(tok, _)| tok).collect();
+
+    assert_eq!(tokens, vec![Token::Descriptor("r"), Token::Dot, Token::Descriptor("_internal")]);
+}
+
+#[test]
+fn quoted_identifier_with_spaces() {
+    let input = "#`user logged in` => +`session open`.";
+    let tokens = lexer().parse(input).unwrap();
+    let tokens: Vec<_> = tokens.into_iter().map(|(tok, _)| tok).collect();
+
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Hash,
+            Token::Descriptor("user logged in"),
+            Token::FatArrow,
+            Token::Plus,
+            Token::Descriptor("session open"),
+            Token::EndRule,
+        ]
+    );
+}
+
+#[test]
+fn quoted_reserved_word() {
+    let input = "`and` and `not`";
+    let tokens = lexer().parse(input).unwrap();
+    let tokens: Vec<_> = tokens.into_iter().map(|(tok, _)| tok).collect();
+
+    assert_eq!(tokens, vec![Token::Descriptor("and"), Token::And, Token::Descriptor("not")]);
+}
+
+#[test]
+fn quoted_identifier_errors() {
+    for input in ["`unterminated", "`split\nline`", "``"] {
+        assert!(lexer().parse(input).has_errors(), "expected {:?} to fail", input);
+    }
+}
+
+#[test]
+fn strict_lexer_reports_first_bad_character() {
+    let input = "#e => +a $ -b.";
+    let result = lexer_strict().parse(input);
+    let errors: Vec<_> = result.errors().collect();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].span().into_range(), 9..10);
+    assert!(result.output().is_none());
+}
+
+#[test]
+fn recovering_lexer_skips_bad_character() {
+    let input = "#e => +a $ -b.";
+    let result = lexer().parse(input);
+
+    assert!(result.has_errors());
+    let tokens: Vec<_> = result.into_output().unwrap().into_iter().map(|(tok, _)| tok).collect();
+    assert!(tokens.contains(&Token::Descriptor("b")));
+}
+
+#[test]
+fn safe_entry_points_report_bad_character() {
+    let err = lex_and_parse_safe("#e => +a $ -b.").unwrap_err();
+    assert!(err.to_string().contains("9..10"), "unexpected error: {}", err);
+
+    let err = lex_and_parse_condition("a and $b").unwrap_err();
+    assert!(err.to_string().contains("6..7"), "unexpected error: {}", err);
+}
+
+#[test]
+fn safe_entry_points_never_panic() {
+    // Deep enough to overflow the stack before nesting was bounded
+    let deep_condition = format!("{}a{}", "(".repeat(20_000), ")".repeat(20_000));
+    let deep_rule = format!("#e: {} => +a.", deep_condition);
+    let deep_compound = format!("{}#e => #a.{}", "{".repeat(20_000), "}".repeat(20_000));
+    let not_chain = format!("{}a", "not ".repeat(20_000));
+    let inputs = [
+        "",
+        "@",
+        "{",
+        "}}}}",
+        "#e => +a |",
+        "@scale(999999999999999999999) {#e => #a.}",
+        "`unterminated",
+        "é => +a.",
+        ". . .",
+        deep_condition.as_str(),
+        deep_rule.as_str(),
+        deep_compound.as_str(),
+        not_chain.as_str(),
+    ];
+    for input in inputs {
+        let _ = lex_and_parse_safe(input);
+        let _ = lex_and_parse_condition(input);
+        let _ = lex_and_parse_compound_safe(input);
+        let _ = lex_and_parse_directives(input);
+        let _ = lex_and_parse_items(input);
+        let _ = parse_program(input);
+    }
+
+    let err = lex_and_parse_condition(&deep_condition).unwrap_err();
+    assert!(err.to_string().contains("Nesting deeper than"), "{}", err);
+    let diagnostics = parse_program(&deep_rule).unwrap_err();
+    assert_eq!(diagnostics[0].message, format!("Nesting deeper than {} levels", MAX_NESTING));
+
+    let nested = format!("{}a{}", "(".repeat(MAX_NESTING), ")".repeat(MAX_NESTING));
+    assert!(lex_and_parse_condition(&nested).is_ok());
+}
+
+#[test]
+fn prefix_chains_count_as_nesting() {
+    // Deep enough to overflow the stack before prefix operators counted
+    let not_rule = format!(": {}a => +b.", "not ".repeat(50_000));
+    let err = lex_and_parse_safe(&not_rule).unwrap_err();
+    assert!(err.to_string().contains("Nesting deeper than"), "{}", err);
+    let diagnostics = parse_program(&not_rule).unwrap_err();
+    assert_eq!(diagnostics[0].message, format!("Nesting deeper than {} levels", MAX_NESTING));
+
+    let namespace_rule = format!("#e => +{}x.", "a.".repeat(50_000));
+    let err = lex_and_parse_safe(&namespace_rule).unwrap_err();
+    assert!(err.to_string().contains("Nesting deeper than"), "{}", err);
+
+    // Brackets and prefixes add up, but a chain ends with its operand
+    let mixed = format!("{}a{}", "not (".repeat(MAX_NESTING / 2 + 1), ")".repeat(MAX_NESTING / 2 + 1));
+    assert!(lex_and_parse_condition(&mixed).is_err());
+    let siblings = vec![format!("{}a", "not ".repeat(MAX_NESTING)); 3].join(" and ");
+    assert!(lex_and_parse_condition(&siblings).is_ok());
+    assert!(lex_and_parse_safe(&format!("#e => +{}x.", "a.".repeat(MAX_NESTING))).is_ok());
+}
+
+#[test]
+fn tokenize_with_spans() {
+    let input = "#e: c => +a.";
+    let tokens: Vec<_> = tokenize(input)
+        .unwrap()
+        .into_iter()
+        .map(|(tok, span)| (tok, span.into_range()))
+        .collect();
+
+    assert_eq!(
+        tokens,
+        vec![
+            (Token::Hash, 0..1),
+            (Token::Descriptor("e"), 1..2),
+            (Token::Colon, 2..3),
+            (Token::Descriptor("c"), 4..5),
+            (Token::FatArrow, 6..8),
+            (Token::Plus, 9..10),
+            (Token::Descriptor("a"), 10..11),
+            (Token::EndRule, 11..12),
+        ]
+    );
+}
+
+#[test]
+fn tokenize_spans_are_character_offsets() {
+    let input = "+`café` `ü`.";
+    let tokens = tokenize(input).unwrap();
+    let spans: Vec<_> = tokens.iter().map(|(_, span)| span.into_range()).collect();
+
+    // `é` and `ü` take two bytes each, but a single offset
+    assert_eq!(spans, vec![0..1, 1..7, 8..11, 11..12]);
+    let chars: Vec<char> = input.chars().collect();
+    let texts: Vec<String> = spans.iter().map(|range| chars[range.clone()].iter().collect()).collect();
+    assert_eq!(texts, vec!["+", "`café`", "`ü`", "."]);
+}
+
+#[test]
+fn tokenize_reports_every_bad_character() {
+    let diagnostics = tokenize("a $ b ? c").unwrap_err();
+    let spans: Vec<_> = diagnostics.iter().map(|d| d.span.into_range()).collect();
+
+    assert_eq!(spans, vec![2..3, 6..7]);
+}
+
+#[test]
+fn position_counts_lines_and_characters() {
+    let src = "ab\ncé d\n";
+    assert_eq!(position(src, 0), (1, 1));
+    assert_eq!(position(src, 2), (1, 3));
+    assert_eq!(position(src, 3), (2, 1));
+    // `é` is two bytes, but a single column
+    assert_eq!(position(src, 6), (2, 3));
+    assert_eq!(position(src, 7), (2, 4));
+    assert_eq!(position(src, 5), (2, 2));
+    assert_eq!(position(src, 100), (3, 1));
+}
+
+#[test]
+fn diagnostics_carry_line_and_column() {
+    let src = "#e => +a.\n% café\n#f => .";
+    let diagnostics = parse_program(src).unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!((diagnostics[0].line, diagnostics[0].column), (3, 7));
+
+    let diagnostics = tokenize("#e => +a.\n  `café` $").unwrap_err();
+    let positions: Vec<_> = diagnostics.iter().map(|d| (d.line, d.column)).collect();
+    assert_eq!(positions, vec![(2, 10)]);
+}
+
+#[test]
+fn comments_in_separate_channel() {
+    let input = "% first\n#e => +a. % trailing\n`100%` . %";
+    let (tokens, comments) = lexer_with_comments().parse(input).unwrap();
+    let tokens: Vec<_> = tokens.into_iter().map(|(tok, _)| tok).collect();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Hash,
+            Token::Descriptor("e"),
+            Token::FatArrow,
+            Token::Plus,
+            Token::Descriptor("a"),
+            Token::EndRule,
+            Token::Descriptor("100%"),
+            Token::EndRule,
+        ]
+    );
+    let comments: Vec<_> = comments.into_iter().map(|(text, span)| (text, span.into_range())).collect();
+    assert_eq!(comments, vec![(" first", 0..7), (" trailing", 18..28), ("", 38..39)]);
+}
+
+#[test]
+fn comments_above_rules_become_docs() {
+    let src = "% Marks the node ready\n% once it is loaded.\n#loaded => +ready.\n\
+               % Not attached, a blank line follows\n\n#e => +a. % neither is this\n\
+               #f => +b.\n  % indented is fine\n=> #f.";
+    let docs: Vec<_> = lex_and_parse_with_docs(src)
+        .into_iter()
+        .map(|(rule, doc, _span)| (rule.to_string(), doc))
+        .collect();
+    assert_eq!(
+        docs,
+        vec![
+            ("#loaded => +ready.".to_string(), Some("Marks the node ready\nonce it is loaded.".to_string())),
+            ("#e => +a.".to_string(), None),
+            ("#f => +b.".to_string(), None),
+            ("=> #f.".to_string(), Some("indented is fine".to_string())),
+        ]
+    );
+}
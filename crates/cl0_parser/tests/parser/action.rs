@@ -227,3 +227,45 @@ fn create_valid_list_action_order_of_ops() {
 fn empty_fail() {
     assert_fails("");
 }
+
+#[test]
+fn create_valid_call_action_seq() {
+    assert_parses_to(
+        "seq(#a #b)",
+        Action::List(ActionList::Sequence(vec![
+            Action::Primitive(PrimitiveEvent::Trigger("a".to_string())),
+            Action::Primitive(PrimitiveEvent::Trigger("b".to_string())),
+        ])),
+    );
+}
+
+#[test]
+fn create_valid_call_action_mixed_with_infix() {
+    assert_parses_to(
+        "par(#a #b); #c",
+        Action::List(ActionList::Sequence(vec![
+            Action::List(ActionList::Parallel(vec![
+                Action::Primitive(PrimitiveEvent::Trigger("a".to_string())),
+                Action::Primitive(PrimitiveEvent::Trigger("b".to_string())),
+            ])),
+            Action::Primitive(PrimitiveEvent::Trigger("c".to_string())),
+        ])),
+    );
+    assert_parses_to(
+        "alt(#a seq(#b #c))",
+        Action::List(ActionList::Alternative(vec![
+            Action::Primitive(PrimitiveEvent::Trigger("a".to_string())),
+            Action::List(ActionList::Sequence(vec![
+                Action::Primitive(PrimitiveEvent::Trigger("b".to_string())),
+                Action::Primitive(PrimitiveEvent::Trigger("c".to_string())),
+            ])),
+        ])),
+    );
+}
+
+#[test]
+fn create_valid_call_action_fail() {
+    assert_fails("seq()");
+    assert_fails("seq(#a");
+    assert_fails("seq #a #b");
+}
@@ -44,6 +44,7 @@ fn create_valid_compound() {
                 action: Action::Primitive(PrimitiveEvent::Production(AtomicCondition::Primitive(
                     PrimitiveCondition::Var("a".to_string()),
                 ))),
+                else_action: None,
             })],
             alias: None,
         },
@@ -61,6 +62,7 @@ fn create_valid_compound_with_alias() {
                 action: Action::Primitive(PrimitiveEvent::Production(AtomicCondition::Primitive(
                     PrimitiveCondition::Var("a".to_string()),
                 ))),
+                else_action: None,
             })],
             alias: Some("alias".to_string()),
         },
@@ -1,6 +1,7 @@
 use crate::utils::lex_tokens;
 use cl0_parser::{
     ast::{AtomicCondition, Condition, PrimitiveCondition},
+    lex_and_parse_condition,
     parser::condition_parser,
 };
 use chumsky::Parser;
@@ -274,7 +275,77 @@ fn create_not_with_parentheses_complex_condition2() {
     );
 }
 
+#[test]
+fn create_valid_at_least_condition() {
+    assert_parses_to(
+        "retries >= 3",
+        Condition::AtLeast(PrimitiveCondition::Var("retries".to_string()), 3),
+    );
+    assert_parses_to(
+        "not retries >= 3 and done",
+        Condition::Conjunction(vec![
+            Condition::Not(Box::new(Condition::AtLeast(
+                PrimitiveCondition::Var("retries".to_string()),
+                3,
+            ))),
+            var("done"),
+        ]),
+    );
+    assert_parses_to(
+        "retries >= 300",
+        Condition::AtLeast(PrimitiveCondition::Var("retries".to_string()), 300),
+    );
+}
+
+#[test]
+fn create_valid_at_least_condition_fail() {
+    assert_fails("retries >= many");
+    assert_fails("3 >= retries");
+    assert_fails("(retries) >= 3");
+}
+
 #[test]
 fn empty_fail() {
     assert_fails("");
 }
+
+fn var(name: &str) -> Condition {
+    Condition::Atomic(AtomicCondition::Primitive(PrimitiveCondition::Var(name.to_string())))
+}
+
+#[test]
+fn display_round_trips_parentheses() {
+    for src in ["(a and b) or c", "a and (b or c)", "not (a or b) and c", "not a >= 2 or b"] {
+        let parsed = lex_and_parse_condition(src).unwrap();
+        assert_eq!(parsed.to_string(), src);
+        assert_eq!(lex_and_parse_condition(&parsed.to_string()).unwrap(), parsed);
+    }
+}
+
+#[test]
+fn display_adds_parentheses_for_precedence() {
+    let cases = [
+        (
+            Condition::Disjunction(vec![Condition::Conjunction(vec![var("a"), var("b")]), var("c")]),
+            "a and b or c",
+        ),
+        (
+            Condition::Conjunction(vec![var("a"), Condition::Disjunction(vec![var("b"), var("c")])]),
+            "a and (b or c)",
+        ),
+        (
+            Condition::Not(Box::new(Condition::Conjunction(vec![var("a"), var("b")]))),
+            "not (a and b)",
+        ),
+        (
+            Condition::Conjunction(vec![Condition::Conjunction(vec![var("a"), var("b")]), var("c")]),
+            "(a and b) and c",
+        ),
+    ];
+    for (condition, want) in cases {
+        assert_eq!(condition.to_string(), want);
+        // Printing the re-parsed condition gives the same text, so the structure was kept
+        let reparsed = lex_and_parse_condition(want).unwrap();
+        assert_eq!(reparsed.to_string(), want);
+    }
+}
@@ -3,8 +3,9 @@ use chumsky::Parser;
 use cl0_parser::{
     ast::{
         Action, ActionList, AtomicCondition, Compound, Directive, FactRule, PrimitiveCondition,
-        PrimitiveEvent, Rule,
+        PrimitiveEvent, ProgramItem, Rule,
     },
+    lex_and_parse, lex_and_parse_directives, lex_and_parse_items,
     parser::directive_parser,
 };
 
@@ -57,4 +58,69 @@ fn create_valid_directive_scale() {
 #[test]
 fn create_valid_directive_scale_fail() {
     assert_fails("@scale(test){f.}");
+    assert_fails("@scale(256){f.}");
+}
+
+#[test]
+fn lex_and_parse_directive_sequence() {
+    assert_eq!(
+        lex_and_parse_directives("@interleaving @include(base)").unwrap(),
+        vec![Directive::Interleaving, Directive::Include("base".to_string())]
+    );
+    assert_eq!(lex_and_parse_directives("").unwrap(), vec![]);
+    assert!(lex_and_parse_directives("@interleaving a.").is_err());
+}
+
+#[test]
+fn create_valid_directive_counter() {
+    assert_parses_to("@counter(retries)", Directive::Counter("retries".to_string()));
+    assert_eq!(Directive::Counter("retries".to_string()).to_string(), "@counter(retries)");
+    assert_fails("@counter(+retries)");
+}
+
+#[test]
+fn unknown_directive_is_reported() {
+    let err = lex_and_parse_directives("@bogus(3)").unwrap_err().to_string();
+    assert!(err.contains("unknown directive @bogus"), "{}", err);
+    assert_fails("@bogus");
+
+    // Also at the start of a program mixing rules and directives
+    for src in ["@bogus(3)", "@bogus", "a.\n@bogus(3)"] {
+        let err = lex_and_parse_items(src).unwrap_err().to_string();
+        assert!(err.contains("unknown directive @bogus"), "{}: {}", src, err);
+    }
+}
+
+#[test]
+fn program_of_only_directives() {
+    assert_eq!(
+        lex_and_parse_items("@include(base) @external(sensor)").unwrap(),
+        vec![
+            ProgramItem::Directive(Directive::Include("base".to_string())),
+            ProgramItem::Directive(Directive::ExternalVar("sensor".to_string())),
+        ]
+    );
+    assert_eq!(lex_and_parse_items("").unwrap(), vec![]);
+}
+
+#[test]
+fn program_of_only_rules() {
+    let src = "#e => +a. :a => #f.";
+    let want: Vec<_> = lex_and_parse(src).into_iter().map(ProgramItem::Rule).collect();
+    assert_eq!(want.len(), 2);
+    assert_eq!(lex_and_parse_items(src).unwrap(), want);
+}
+
+#[test]
+fn program_mixing_rules_and_directives() {
+    let items = lex_and_parse_items("@interleaving #e => +a. @counter(hits) a.").unwrap();
+    assert_eq!(items.len(), 4);
+    assert_eq!(items[0], ProgramItem::Directive(Directive::Interleaving));
+    assert!(matches!(items[1], ProgramItem::Rule(Rule::Reactive(_))));
+    assert_eq!(items[2], ProgramItem::Directive(Directive::Counter("hits".to_string())));
+    assert!(matches!(items[3], ProgramItem::Rule(Rule::Fact(_))));
+
+    assert!(lex_and_parse_items("@include(base) #e =>").is_err());
+    let err = lex_and_parse_items("a. @bogus").unwrap_err().to_string();
+    assert!(err.contains("unknown directive @bogus"), "{}", err);
 }
@@ -45,6 +45,7 @@ fn create_valid_eca_rule() {
             action: Action::Primitive(PrimitiveEvent::Production(AtomicCondition::Primitive(
                 PrimitiveCondition::Var("a".to_string()),
             ))),
+            else_action: None,
         }),
     );
 }
@@ -79,6 +80,7 @@ fn create_valid_eca_rule_no_condition() {
             action: Action::Primitive(PrimitiveEvent::Production(AtomicCondition::Primitive(
                 PrimitiveCondition::Var("a".to_string()),
             ))),
+            else_action: None,
         }),
     );
 }
@@ -94,6 +96,7 @@ fn create_valid_ca_rule() {
             action: Action::Primitive(PrimitiveEvent::Production(AtomicCondition::Primitive(
                 PrimitiveCondition::Var("a".to_string()),
             ))),
+            else_action: None,
         }),
     );
 }
@@ -108,6 +111,62 @@ fn create_bad_ca_rule2() {
     assert_fails(":c => a."); // a not a valid action
 }
 
+#[test]
+fn create_valid_eca_rule_with_else() {
+    assert_parses_to(
+        "#e: c => +a | -a, #f.",
+        Rule::Reactive(ReactiveRule::ECA {
+            event: PrimitiveEvent::Trigger("e".to_string()),
+            condition: Some(Condition::Atomic(AtomicCondition::Primitive(
+                PrimitiveCondition::Var("c".to_string()),
+            ))),
+            action: Action::Primitive(PrimitiveEvent::Production(AtomicCondition::Primitive(
+                PrimitiveCondition::Var("a".to_string()),
+            ))),
+            else_action: Some(Action::List(ActionList::Parallel(vec![
+                Action::Primitive(PrimitiveEvent::Consumption(AtomicCondition::Primitive(
+                    PrimitiveCondition::Var("a".to_string()),
+                ))),
+                Action::Primitive(PrimitiveEvent::Trigger("f".to_string())),
+            ]))),
+        }),
+    );
+}
+
+#[test]
+fn create_valid_ca_rule_with_else() {
+    assert_parses_to(
+        ": c => +a | +b.",
+        Rule::Reactive(ReactiveRule::CA {
+            condition: Condition::Atomic(AtomicCondition::Primitive(PrimitiveCondition::Var(
+                "c".to_string(),
+            ))),
+            action: Action::Primitive(PrimitiveEvent::Production(AtomicCondition::Primitive(
+                PrimitiveCondition::Var("a".to_string()),
+            ))),
+            else_action: Some(Action::Primitive(PrimitiveEvent::Production(
+                AtomicCondition::Primitive(PrimitiveCondition::Var("b".to_string())),
+            ))),
+        }),
+    );
+}
+
+#[test]
+fn create_bad_else_rules() {
+    assert_fails("#e: c => +a |."); // no else action
+    assert_fails("#e: c => +a | +b | +c."); // only one else action
+    assert_fails("#e: c => | +b."); // no action
+}
+
+#[test]
+fn else_action_round_trips_through_display() {
+    for src in ["#e: c => +a | +b.", ":c and d => #f; -c | #g.", "#e => +a | -a."] {
+        let rule = cl0_parser::lex_and_parse(src).remove(0);
+        assert_eq!(rule.to_string(), src);
+        assert_eq!(cl0_parser::lex_and_parse(&rule.to_string()), vec![rule]);
+    }
+}
+
 #[test]
 fn create_valid_cc_rule1() {
     assert_parses_to(
@@ -187,3 +246,53 @@ fn create_valid_fact_rule() {
 fn empty_fail() {
     assert_fails("");
 }
+
+#[test]
+fn bare_number_is_not_a_rule() {
+    assert_fails("42.");
+    assert_fails("#e => +3.");
+}
+
+#[test]
+fn quoted_names_are_variables() {
+    assert_parses_to(
+        "#`user logged in` : `and` => +`session open`.",
+        Rule::Reactive(ReactiveRule::ECA {
+            event: PrimitiveEvent::Trigger("user logged in".to_string()),
+            condition: Some(Condition::Atomic(AtomicCondition::Primitive(PrimitiveCondition::Var(
+                "and".to_string(),
+            )))),
+            action: Action::Primitive(PrimitiveEvent::Production(AtomicCondition::Primitive(
+                PrimitiveCondition::Var("session open".to_string()),
+            ))),
+            else_action: None,
+        }),
+    );
+}
+
+#[test]
+fn structural_equality_ignores_nested_aliases() {
+    let parse = |src: &str| cl0_parser::lex_and_parse(src).remove(0);
+    let a = parse("#e: { #f => +a. } as p => +{ #g => -b. } as q.");
+    let b = parse("#e: { #f => +a. } as x => +{ #g => -b. }.");
+    assert_ne!(a, b);
+    assert!(a.structurally_eq(&b));
+    assert!(!a.structurally_eq(&parse("#e: { #f => +a. } as p => +{ #g => +b. } as q.")));
+}
+
+#[test]
+fn compound_identifier_ignores_aliases() {
+    let event = |src: &str| match cl0_parser::lex_and_parse(src).remove(0) {
+        Rule::Reactive(ReactiveRule::ECA { event, .. }) => event,
+        other => panic!("expected an ECA rule, got {:?}", other),
+    };
+    let a = event("+{ #f => +{ #g => +b. } as p. } as r => +x.");
+    let b = event("+{ #f => +{ #g => +b. } as q. } => +x.");
+    assert_eq!(a.get_identifier(), b.get_identifier());
+    assert!(a.get_identifier().len() < a.to_string().len());
+    assert_ne!(a.get_identifier(), event("+{ #f => -b. } => +x.").get_identifier());
+
+    // Variables and namespaced compounds stay readable
+    assert_eq!(event("+a => +x.").get_identifier(), "a");
+    assert!(event("-ns.{ #f => +b. } => +x.").get_identifier().starts_with("ns.{"));
+}
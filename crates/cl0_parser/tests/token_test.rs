@@ -0,0 +1,66 @@
+use cl0_parser::token::{Token, TokenCategory};
+
+#[test]
+fn keyword_category() {
+    for token in [
+        Token::Seq,
+        Token::Par,
+        Token::Alt,
+        Token::And,
+        Token::Or,
+        Token::Not,
+        Token::As,
+        Token::AtScale,
+        Token::AtInclude,
+        Token::AtExclude,
+        Token::AtInterleaving,
+        Token::AtExternal,
+        Token::AtCounter,
+    ] {
+        assert_eq!(token.category(), TokenCategory::Keyword, "{:?}", token);
+    }
+}
+
+#[test]
+fn operator_category() {
+    for token in [
+        Token::FatArrow,
+        Token::ThinArrow,
+        Token::DashO,
+        Token::GreaterEqual,
+        Token::Plus,
+        Token::Minus,
+        Token::Hash,
+        Token::At,
+    ] {
+        assert_eq!(token.category(), TokenCategory::Operator, "{:?}", token);
+    }
+}
+
+#[test]
+fn identifier_category() {
+    assert_eq!(Token::Descriptor("loaded").category(), TokenCategory::Identifier);
+}
+
+#[test]
+fn delimiter_category() {
+    for token in [
+        Token::Colon,
+        Token::Semicolon,
+        Token::LeftParenthesis,
+        Token::RightParenthesis,
+        Token::LeftCBracket,
+        Token::RightCBracket,
+        Token::Comma,
+        Token::Dot,
+        Token::EndRule,
+        Token::Pipe,
+    ] {
+        assert_eq!(token.category(), TokenCategory::Delimiter, "{:?}", token);
+    }
+}
+
+#[test]
+fn number_category() {
+    assert_eq!(Token::Number(3).category(), TokenCategory::Number);
+}
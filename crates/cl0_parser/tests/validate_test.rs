@@ -0,0 +1,94 @@
+use cl0_parser::lex_and_parse;
+use cl0_parser::validate::{SemanticError, validate_program, validate_rule};
+
+fn validate(src: &str) -> Result<(), SemanticError> {
+    let rules = lex_and_parse(src);
+    assert_eq!(rules.len(), 1);
+    validate_rule(&rules[0])
+}
+
+#[test]
+fn accepts_regular_rules() {
+    for src in [
+        "#e: a and not b => +c, -d alt #f.",
+        ":a => +{ #e => +b. c. } as r.",
+        "r.{ #e => +a. }.",
+        "{} as r.",
+        "a -> b.",
+        "a -o b and c.",
+    ] {
+        assert_eq!(validate(src), Ok(()), "{}", src);
+    }
+}
+
+#[test]
+fn rejects_declarative_rule_in_compound() {
+    assert_eq!(
+        validate("#e => +{ a -> b. }."),
+        Err(SemanticError::DeclarativeInCompound {
+            rule: "a -> b.".to_string()
+        })
+    );
+    // Nested compounds are checked as well
+    assert!(matches!(
+        validate("{ #e => +{ -o c. }. }."),
+        Err(SemanticError::DeclarativeInCompound { .. })
+    ));
+}
+
+#[test]
+fn rejects_compound_event() {
+    assert_eq!(
+        validate("+{ a. } => +b."),
+        Err(SemanticError::CompoundEvent {
+            event: "+{ a. }".to_string()
+        })
+    );
+}
+
+#[test]
+fn rejects_empty_compound() {
+    assert_eq!(validate("#e => +{}."), Err(SemanticError::EmptyCompound));
+}
+
+#[test]
+fn rejects_bare_trigger_in_compound() {
+    assert_eq!(
+        validate("{ => #e. }."),
+        Err(SemanticError::BareTrigger {
+            rule: "=> #e.".to_string()
+        })
+    );
+    assert!(matches!(
+        validate(":a => +{ #e => +b. => #f. } as r."),
+        Err(SemanticError::BareTrigger { .. })
+    ));
+}
+
+#[test]
+fn rejects_consumption_of_undefined_alias() {
+    assert_eq!(
+        validate_program(&lex_and_parse("#e => -r.x.")),
+        Err(SemanticError::UndefinedAlias {
+            alias: "r".to_string()
+        })
+    );
+    assert!(matches!(
+        validate_program(&lex_and_parse("{ #e => +a. } as r. #f => -{ #e => +a. } as s.")),
+        Err(SemanticError::UndefinedAlias { alias }) if alias == "s"
+    ));
+    // Nested compounds are checked as well
+    assert!(matches!(
+        validate_program(&lex_and_parse("{ #e => -q.x. } as r.")),
+        Err(SemanticError::UndefinedAlias { alias }) if alias == "q"
+    ));
+
+    // Defined by a fact, a namespace or a production anywhere in the program
+    for src in [
+        "{ #e => +a. } as r. #f => -{ #e => +a. } as r.",
+        "#f => -r.x. r.{ x. }.",
+        "#g => +{ #h => +b. } as s. #f => -s.{ #h => +b. }.",
+    ] {
+        assert_eq!(validate_program(&lex_and_parse(src)), Ok(()), "{}", src);
+    }
+}
@@ -10,7 +10,7 @@ import type { Message } from "@bufbuild/protobuf";
  * Describes the file web.proto.
  */
 export const file_web: GenFile = /*@__PURE__*/
-  fileDesc("Cgl3ZWIucHJvdG8SA3dlYiJ8CgVTY29wZRIdCgRraW5kGAEgASgOMg8ud2ViLlNjb3BlLktpbmQSCgoCaWQYAiABKAkiSAoES2luZBIUChBLSU5EX1VOU1BFQ0lGSUVEEAASEQoNQ09OVFJPTF9QTEFORRABEg0KCU5PREVfUE9PTBACEggKBE5PREUQAyIHCgVFbXB0eSJECgRKb2luEg8KB3VzZXJfaWQYASABKAkSGQoFc2NvcGUYAiABKAsyCi53ZWIuU2NvcGUSEAoIc2luY2VfaWQYAyABKAkiQQoFSW5wdXQSDwoHdXNlcl9pZBgBIAEoCRIZCgVzY29wZRgCIAEoCzIKLndlYi5TY29wZRIMCgRjb2RlGAMgASgJImEKBk91dHB1dBIKCgJpZBgBIAEoCRIZCgVzY29wZRgCIAEoCzIKLndlYi5TY29wZRIPCgd1c2VyX2lkGAMgASgJEg4KBnN0ZG91dBgEIAEoCRIPCgd1bml4X3RzGAUgASgDIlMKDEhpc3RvcnlDaHVuaxIZCgVzY29wZRgBIAEoCzIKLndlYi5TY29wZRIaCgVpdGVtcxgCIAMoCzILLndlYi5PdXRwdXQSDAoEZG9uZRgDIAEoCCIsCgNBY2sSEgoKaW5wdXRfZWNobxgBIAEoCRIRCglvdXRwdXRfaWQYAiABKAkiNwoMU2VydmVyTm90aWNlEhkKBXNjb3BlGAEgASgLMgoud2ViLlNjb3BlEgwKBHRleHQYAiABKAkimAEKC1NlcnZlckV2ZW50EiQKB2hpc3RvcnkYASABKAsyES53ZWIuSGlzdG9yeUNodW5rSAASHQoGb3V0cHV0GAIgASgLMgsud2ViLk91dHB1dEgAEhcKA2FjaxgDIAEoCzIILndlYi5BY2tIABIjCgZub3RpY2UYBCABKAsyES53ZWIuU2VydmVyTm90aWNlSABCBgoEa2luZCI+CgpSdWxlU3RhdHVzEhEKCW5hbWVzcGFjZRgBIAEoCRIMCgRuYW1lGAIgASgJEg8KB2VuYWJsZWQYAyABKAgiKgoJVmFyU3RhdHVzEgwKBG5hbWUYASABKAkSDwoHZW5hYmxlZBgCIAEoCCJpCg5TdGF0dXNTbmFwc2hvdBIZCgVzY29wZRgBIAEoCzIKLndlYi5TY29wZRIeCgVydWxlcxgCIAMoCzIPLndlYi5SdWxlU3RhdHVzEhwKBHZhcnMYAyADKAsyDi53ZWIuVmFyU3RhdHVzIioKDk5vZGVEZXNjcmlwdG9yEgoKAmlkGAEgASgJEgwKBG5hbWUYAiABKAkiUgoSTm9kZVBvb2xEZXNjcmlwdG9yEgoKAmlkGAEgASgJEgwKBG5hbWUYAiABKAkSIgoFbm9kZXMYAyADKAsyEy53ZWIuTm9kZURlc2NyaXB0b3IiUwoKU3lzdGVtVHJlZRIYChBjb250cm9sX3BsYW5lX2lkGAEgASgJEisKCm5vZGVfcG9vbHMYAiADKAsyFy53ZWIuTm9kZVBvb2xEZXNjcmlwdG9yIjYKCFByZXNlbmNlEg8KB3VzZXJfaWQYASABKAkSGQoFc2NvcGUYAiABKAsyCi53ZWIuU2NvcGUiMAoQUHJlc2VuY2VTbmFwc2hvdBIcCgV1c2VycxgBIAMoCzINLndlYi5QcmVzZW5jZSKUAQoOUHJlc2VuY2VVcGRhdGUSJgoEa2luZBgBIAEoDjIYLndlYi5QcmVzZW5jZVVwZGF0ZS5LaW5kEhsKBHVzZXIYAiABKAsyDS53ZWIuUHJlc2VuY2UiPQoES2luZBIUChBLSU5EX1VOU1BFQ0lGSUVEEAASCgoGSk9JTkVEEAESCAoETEVGVBACEgkKBU1PVkVEEAMiaQoNUHJlc2VuY2VFdmVudBIpCghzbmFwc2hvdBgBIAEoCzIVLndlYi5QcmVzZW5jZVNuYXBzaG90SAASJQoGdXBkYXRlGAIgASgLMhMud2ViLlByZXNlbmNlVXBkYXRlSABCBgoEa2luZDJeCgtSZXBsU2VydmljZRIqCglTdWJzY3JpYmUSCS53ZWIuSm9pbhoQLndlYi5TZXJ2ZXJFdmVudDABEiMKC1NlbmRDb21tYW5kEgoud2ViLklucHV0Gggud2ViLkFjazI9Cg1TdGF0dXNTZXJ2aWNlEiwKCUdldFN0YXR1cxIKLndlYi5TY29wZRoTLndlYi5TdGF0dXNTbmFwc2hvdDJFChNDb250cm9sUGxhbmVTZXJ2aWNlEi4KDVN1YnNjcmliZVRyZWUSCi53ZWIuU2NvcGUaDy53ZWIuU3lzdGVtVHJlZTABMocBCg9QcmVzZW5jZVNlcnZpY2USLQoJU3Vic2NyaWJlEgoud2ViLkVtcHR5GhIud2ViLlByZXNlbmNlRXZlbnQwARIhCgRKb2luEg0ud2ViLlByZXNlbmNlGgoud2ViLkVtcHR5EiIKBUxlYXZlEg0ud2ViLlByZXNlbmNlGgoud2ViLkVtcHR5YgZwcm90bzM");
+  fileDesc("Cgl3ZWIucHJvdG8SA3dlYiJ8CgVTY29wZRIdCgRraW5kGAEgASgOMg8ud2ViLlNjb3BlLktpbmQSCgoCaWQYAiABKAkiSAoES2luZBIUChBLSU5EX1VOU1BFQ0lGSUVEEAASEQoNQ09OVFJPTF9QTEFORRABEg0KCU5PREVfUE9PTBACEggKBE5PREUQAyIHCgVFbXB0eSJXCgRKb2luEg8KB3VzZXJfaWQYASABKAkSGQoFc2NvcGUYAiABKAsyCi53ZWIuU2NvcGUSEAoIc2luY2VfaWQYAyABKAkSEQoJc2luY2Vfc2VxGAQgASgEIkEKBUlucHV0Eg8KB3VzZXJfaWQYASABKAkSGQoFc2NvcGUYAiABKAsyCi53ZWIuU2NvcGUSDAoEY29kZRgDIAEoCSJuCgZPdXRwdXQSCgoCaWQYASABKAkSGQoFc2NvcGUYAiABKAsyCi53ZWIuU2NvcGUSDwoHdXNlcl9pZBgDIAEoCRIOCgZzdGRvdXQYBCABKAkSDwoHdW5peF90cxgFIAEoAxILCgNzZXEYBiABKAQiUwoMSGlzdG9yeUNodW5rEhkKBXNjb3BlGAEgASgLMgoud2ViLlNjb3BlEhoKBWl0ZW1zGAIgAygLMgsud2ViLk91dHB1dBIMCgRkb25lGAMgASgIIiwKA0FjaxISCgppbnB1dF9lY2hvGAEgASgJEhEKCW91dHB1dF9pZBgCIAEoCSI3CgxTZXJ2ZXJOb3RpY2USGQoFc2NvcGUYASABKAsyCi53ZWIuU2NvcGUSDAoEdGV4dBgCIAEoCSKYAQoLU2VydmVyRXZlbnQSJAoHaGlzdG9yeRgBIAEoCzIRLndlYi5IaXN0b3J5Q2h1bmtIABIdCgZvdXRwdXQYAiABKAsyCy53ZWIuT3V0cHV0SAASFwoDYWNrGAMgASgLMggud2ViLkFja0gAEiMKBm5vdGljZRgEIAEoCzIRLndlYi5TZXJ2ZXJOb3RpY2VIAEIGCgRraW5kIj4KClJ1bGVTdGF0dXMSEQoJbmFtZXNwYWNlGAEgASgJEgwKBG5hbWUYAiABKAkSDwoHZW5hYmxlZBgDIAEoCCJRCglWYXJTdGF0dXMSDAoEbmFtZRgBIAEoCRIPCgdlbmFibGVkGAIgASgIEiUKBnN0YXR1cxgDIAEoDjIVLndlYi5BY3RpdmF0aW9uU3RhdHVzImkKDlN0YXR1c1NuYXBzaG90EhkKBXNjb3BlGAEgASgLMgoud2ViLlNjb3BlEh4KBXJ1bGVzGAIgAygLMg8ud2ViLlJ1bGVTdGF0dXMSHAoEdmFycxgDIAMoCzIOLndlYi5WYXJTdGF0dXMiKgoOTm9kZURlc2NyaXB0b3ISCgoCaWQYASABKAkSDAoEbmFtZRgCIAEoCSJSChJOb2RlUG9vbERlc2NyaXB0b3ISCgoCaWQYASABKAkSDAoEbmFtZRgCIAEoCRIiCgVub2RlcxgDIAMoCzITLndlYi5Ob2RlRGVzY3JpcHRvciJTCgpTeXN0ZW1UcmVlEhgKEGNvbnRyb2xfcGxhbmVfaWQYASABKAkSKwoKbm9kZV9wb29scxgCIAMoCzIXLndlYi5Ob2RlUG9vbERlc2NyaXB0b3IiRwoRQ3JlYXRlTm9kZVJlcXVlc3QSDwoHcG9vbF9pZBgBIAEoCRIhCgRub2RlGAIgASgLMhMud2ViLk5vZGVEZXNjcmlwdG9yIkAKDlJlbW92ZVJlc3BvbnNlEg8KB3JlbW92ZWQYASABKAgSHQoEdHJlZRgCIAEoCzIPLndlYi5TeXN0ZW1UcmVlIkkKCFByZXNlbmNlEg8KB3VzZXJfaWQYASABKAkSGQoFc2NvcGUYAiABKAsyCi53ZWIuU2NvcGUSEQoJbGFzdF9zZWVuGAMgASgDIjAKEFByZXNlbmNlU25hcHNob3QSHAoFdXNlcnMYASADKAsyDS53ZWIuUHJlc2VuY2UilAEKDlByZXNlbmNlVXBkYXRlEiYKBGtpbmQYASABKA4yGC53ZWIuUHJlc2VuY2VVcGRhdGUuS2luZBIbCgR1c2VyGAIgASgLMg0ud2ViLlByZXNlbmNlIj0KBEtpbmQSFAoQS0lORF9VTlNQRUNJRklFRBAAEgoKBkpPSU5FRBABEggKBExFRlQQAhIJCgVNT1ZFRBADImkKDVByZXNlbmNlRXZlbnQSKQoIc25hcHNob3QYASABKAsyFS53ZWIuUHJlc2VuY2VTbmFwc2hvdEgAEiUKBnVwZGF0ZRgCIAEoCzITLndlYi5QcmVzZW5jZVVwZGF0ZUgAQgYKBGtpbmQqjgEKEEFjdGl2YXRpb25TdGF0dXMSIQodQUNUSVZBVElPTl9TVEFUVVNfVU5TUEVDSUZJRUQQABIaChZBQ1RJVkFUSU9OX1NUQVRVU19UUlVFEAESGwoXQUNUSVZBVElPTl9TVEFUVVNfRkFMU0UQAhIeChpBQ1RJVkFUSU9OX1NUQVRVU19DT05GTElDVBADMosBCgtSZXBsU2VydmljZRIqCglTdWJzY3JpYmUSCS53ZWIuSm9pbhoQLndlYi5TZXJ2ZXJFdmVudDABEiMKC1NlbmRDb21tYW5kEgoud2ViLklucHV0Gggud2ViLkFjaxIrCgdTZXNzaW9uEgoud2ViLklucHV0GhAud2ViLlNlcnZlckV2ZW50KAEwATJvCg1TdGF0dXNTZXJ2aWNlEiwKCUdldFN0YXR1cxIKLndlYi5TY29wZRoTLndlYi5TdGF0dXNTbmFwc2hvdBIwCgtXYXRjaFN0YXR1cxIKLndlYi5TY29wZRoTLndlYi5TdGF0dXNTbmFwc2hvdDABMqgCChNDb250cm9sUGxhbmVTZXJ2aWNlEi4KDVN1YnNjcmliZVRyZWUSCi53ZWIuU2NvcGUaDy53ZWIuU3lzdGVtVHJlZTABEjYKCkNyZWF0ZVBvb2wSFy53ZWIuTm9kZVBvb2xEZXNjcmlwdG9yGg8ud2ViLlN5c3RlbVRyZWUSNQoKQ3JlYXRlTm9kZRIWLndlYi5DcmVhdGVOb2RlUmVxdWVzdBoPLndlYi5TeXN0ZW1UcmVlEjoKClJlbW92ZVBvb2wSFy53ZWIuTm9kZVBvb2xEZXNjcmlwdG9yGhMud2ViLlJlbW92ZVJlc3BvbnNlEjYKClJlbW92ZU5vZGUSEy53ZWIuTm9kZURlc2NyaXB0b3IaEy53ZWIuUmVtb3ZlUmVzcG9uc2UyhwEKD1ByZXNlbmNlU2VydmljZRItCglTdWJzY3JpYmUSCi53ZWIuU2NvcGUaEi53ZWIuUHJlc2VuY2VFdmVudDABEiEKBEpvaW4SDS53ZWIuUHJlc2VuY2UaCi53ZWIuRW1wdHkSIgoFTGVhdmUSDS53ZWIuUHJlc2VuY2UaCi53ZWIuRW1wdHliBnByb3RvMw");
 
 /**
  * / Identifies where an action/event takes place.
@@ -106,6 +106,13 @@ export type Join = Message<"web.Join"> & {
    * @generated from field: string since_id = 3;
    */
   sinceId: string;
+
+  /**
+   * optional: resume from this sequence number, preferred over since_id
+   *
+   * @generated from field: uint64 since_seq = 4;
+   */
+  sinceSeq: bigint;
 };
 
 /**
@@ -178,6 +185,13 @@ export type Output = Message<"web.Output"> & {
    * @generated from field: int64 unix_ts = 5;
    */
   unixTs: bigint;
+
+  /**
+   * server-assigned, increases by one per output of the scope
+   *
+   * @generated from field: uint64 seq = 6;
+   */
+  seq: bigint;
 };
 
 /**
@@ -334,6 +348,41 @@ export type RuleStatus = Message<"web.RuleStatus"> & {
 export const RuleStatusSchema: GenMessage<RuleStatus> = /*@__PURE__*/
   messageDesc(file_web, 9);
 
+/**
+ * / Value of a variable or rule on a node.
+ *
+ * @generated from enum web.ActivationStatus
+ */
+export enum ActivationStatus {
+  /**
+   * @generated from enum value: ACTIVATION_STATUS_UNSPECIFIED = 0;
+   */
+  UNSPECIFIED = 0,
+
+  /**
+   * @generated from enum value: ACTIVATION_STATUS_TRUE = 1;
+   */
+  TRUE = 1,
+
+  /**
+   * @generated from enum value: ACTIVATION_STATUS_FALSE = 2;
+   */
+  FALSE = 2,
+
+  /**
+   * set to both true and false at once
+   *
+   * @generated from enum value: ACTIVATION_STATUS_CONFLICT = 3;
+   */
+  CONFLICT = 3,
+}
+
+/**
+ * Describes the enum web.ActivationStatus.
+ */
+export const ActivationStatusSchema: GenEnum<ActivationStatus> = /*@__PURE__*/
+  enumDesc(file_web, 0);
+
 /**
  * @generated from message web.VarStatus
  */
@@ -344,9 +393,16 @@ export type VarStatus = Message<"web.VarStatus"> & {
   name: string;
 
   /**
+   * status == ACTIVATION_STATUS_TRUE
+   *
    * @generated from field: bool enabled = 2;
    */
   enabled: boolean;
+
+  /**
+   * @generated from field: web.ActivationStatus status = 3;
+   */
+  status: ActivationStatus;
 };
 
 /**
@@ -456,6 +512,54 @@ export type SystemTree = Message<"web.SystemTree"> & {
 export const SystemTreeSchema: GenMessage<SystemTree> = /*@__PURE__*/
   messageDesc(file_web, 14);
 
+/**
+ * / Adds a node to an existing node pool.
+ *
+ * @generated from message web.CreateNodeRequest
+ */
+export type CreateNodeRequest = Message<"web.CreateNodeRequest"> & {
+  /**
+   * @generated from field: string pool_id = 1;
+   */
+  poolId: string;
+
+  /**
+   * @generated from field: web.NodeDescriptor node = 2;
+   */
+  node?: NodeDescriptor;
+};
+
+/**
+ * Describes the message web.CreateNodeRequest.
+ * Use `create(CreateNodeRequestSchema)` to create a new message.
+ */
+export const CreateNodeRequestSchema: GenMessage<CreateNodeRequest> = /*@__PURE__*/
+  messageDesc(file_web, 15);
+
+/**
+ * / Result of removing a node or pool; removed is false when the id was unknown.
+ *
+ * @generated from message web.RemoveResponse
+ */
+export type RemoveResponse = Message<"web.RemoveResponse"> & {
+  /**
+   * @generated from field: bool removed = 1;
+   */
+  removed: boolean;
+
+  /**
+   * @generated from field: web.SystemTree tree = 2;
+   */
+  tree?: SystemTree;
+};
+
+/**
+ * Describes the message web.RemoveResponse.
+ * Use `create(RemoveResponseSchema)` to create a new message.
+ */
+export const RemoveResponseSchema: GenMessage<RemoveResponse> = /*@__PURE__*/
+  messageDesc(file_web, 16);
+
 /**
  * @generated from message web.Presence
  */
@@ -471,6 +575,13 @@ export type Presence = Message<"web.Presence"> & {
    * @generated from field: web.Scope scope = 2;
    */
   scope?: Scope;
+
+  /**
+   * unix seconds of the last activity, server-assigned
+   *
+   * @generated from field: int64 last_seen = 3;
+   */
+  lastSeen: bigint;
 };
 
 /**
@@ -478,7 +589,7 @@ export type Presence = Message<"web.Presence"> & {
  * Use `create(PresenceSchema)` to create a new message.
  */
 export const PresenceSchema: GenMessage<Presence> = /*@__PURE__*/
-  messageDesc(file_web, 15);
+  messageDesc(file_web, 17);
 
 /**
  * @generated from message web.PresenceSnapshot
@@ -495,7 +606,7 @@ export type PresenceSnapshot = Message<"web.PresenceSnapshot"> & {
  * Use `create(PresenceSnapshotSchema)` to create a new message.
  */
 export const PresenceSnapshotSchema: GenMessage<PresenceSnapshot> = /*@__PURE__*/
-  messageDesc(file_web, 16);
+  messageDesc(file_web, 18);
 
 /**
  * @generated from message web.PresenceUpdate
@@ -517,7 +628,7 @@ export type PresenceUpdate = Message<"web.PresenceUpdate"> & {
  * Use `create(PresenceUpdateSchema)` to create a new message.
  */
 export const PresenceUpdateSchema: GenMessage<PresenceUpdate> = /*@__PURE__*/
-  messageDesc(file_web, 17);
+  messageDesc(file_web, 19);
 
 /**
  * @generated from enum web.PresenceUpdate.Kind
@@ -548,7 +659,7 @@ export enum PresenceUpdate_Kind {
  * Describes the enum web.PresenceUpdate.Kind.
  */
 export const PresenceUpdate_KindSchema: GenEnum<PresenceUpdate_Kind> = /*@__PURE__*/
-  enumDesc(file_web, 17, 0);
+  enumDesc(file_web, 19, 0);
 
 /**
  * @generated from message web.PresenceEvent
@@ -581,7 +692,7 @@ export type PresenceEvent = Message<"web.PresenceEvent"> & {
  * Use `create(PresenceEventSchema)` to create a new message.
  */
 export const PresenceEventSchema: GenMessage<PresenceEvent> = /*@__PURE__*/
-  messageDesc(file_web, 18);
+  messageDesc(file_web, 20);
 
 /**
  * @generated from service web.ReplService
@@ -607,6 +718,17 @@ export const ReplService: GenService<{
     input: typeof InputSchema;
     output: typeof AckSchema;
   },
+  /**
+   * / Subscribe and send commands over one stream. The first Input picks the scope and user,
+   * / the events are those of Subscribe plus an Ack per Input.
+   *
+   * @generated from rpc web.ReplService.Session
+   */
+  session: {
+    methodKind: "bidi_streaming";
+    input: typeof InputSchema;
+    output: typeof ServerEventSchema;
+  },
 }> = /*@__PURE__*/
   serviceDesc(file_web, 0);
 
@@ -624,6 +746,16 @@ export const StatusService: GenService<{
     input: typeof ScopeSchema;
     output: typeof StatusSnapshotSchema;
   },
+  /**
+   * / Stream status snapshots (initial snapshot + one per change).
+   *
+   * @generated from rpc web.StatusService.WatchStatus
+   */
+  watchStatus: {
+    methodKind: "server_streaming";
+    input: typeof ScopeSchema;
+    output: typeof StatusSnapshotSchema;
+  },
 }> = /*@__PURE__*/
   serviceDesc(file_web, 1);
 
@@ -642,6 +774,46 @@ export const ControlPlaneService: GenService<{
     input: typeof ScopeSchema;
     output: typeof SystemTreeSchema;
   },
+  /**
+   * / Create a node pool (the nodes listed are ignored), returning the updated tree.
+   *
+   * @generated from rpc web.ControlPlaneService.CreatePool
+   */
+  createPool: {
+    methodKind: "unary";
+    input: typeof NodePoolDescriptorSchema;
+    output: typeof SystemTreeSchema;
+  },
+  /**
+   * / Create a node in an existing pool, returning the updated tree.
+   *
+   * @generated from rpc web.ControlPlaneService.CreateNode
+   */
+  createNode: {
+    methodKind: "unary";
+    input: typeof CreateNodeRequestSchema;
+    output: typeof SystemTreeSchema;
+  },
+  /**
+   * / Remove a node pool and its nodes (only the id is used).
+   *
+   * @generated from rpc web.ControlPlaneService.RemovePool
+   */
+  removePool: {
+    methodKind: "unary";
+    input: typeof NodePoolDescriptorSchema;
+    output: typeof RemoveResponseSchema;
+  },
+  /**
+   * / Remove a node from its pool (only the id is used).
+   *
+   * @generated from rpc web.ControlPlaneService.RemoveNode
+   */
+  removeNode: {
+    methodKind: "unary";
+    input: typeof NodeDescriptorSchema;
+    output: typeof RemoveResponseSchema;
+  },
 }> = /*@__PURE__*/
   serviceDesc(file_web, 2);
 
@@ -650,13 +822,14 @@ export const ControlPlaneService: GenService<{
  */
 export const PresenceService: GenService<{
   /**
-   * / Stream presence events (initial snapshot + live updates).
+   * / Stream presence events (initial snapshot + live updates) for a scope.
+   * / An unspecified scope kind streams the presence of every scope.
    *
    * @generated from rpc web.PresenceService.Subscribe
    */
   subscribe: {
     methodKind: "server_streaming";
-    input: typeof EmptySchema;
+    input: typeof ScopeSchema;
     output: typeof PresenceEventSchema;
   },
   /**
@@ -1,7 +1,7 @@
 import { useEffect } from "react";
 import { presenceClient } from "../api/live/transport";
 import { useStore } from "../store";
-import { PresenceUpdate_Kind } from "../api/gen/web_pb";
+import { PresenceUpdate_Kind, Scope_Kind } from "../api/gen/web_pb";
 
 export const usePresence = () => {
 	const setPresence = useStore((s) => s.setPresence);
@@ -13,7 +13,9 @@ export const usePresence = () => {
 
 		(async () => {
 			for await (const evt of presenceClient.subscribe({
-				$typeName: "web.Empty",
+				$typeName: "web.Scope",
+				kind: Scope_Kind.KIND_UNSPECIFIED,
+				id: "",
 			})) {
 				if (aborted) break;
 
@@ -44,6 +44,7 @@ export const useRepl = () => {
 					$typeName: "web.Scope",
 				},
 				sinceId: "",
+				sinceSeq: 0n,
 				$typeName: "web.Join",
 			};
 
@@ -1,108 +1,46 @@
 use std::{
-    io::{self, Write}, sync::Arc
+    io::{self, Write}, sync::Arc, time::Duration
 };
 
-use cl0_node::{node::Node, types::{RuleWithArgs, ActivationStatus}};
-use cl0_parser::{ast::Compound, lex_and_parse_compound, lex_and_parse_safe};
+use cl0_node::{node::{Node, NodeCheckpoint, NodeConfig}, repl::{InputBuffer, Style, handler_states, policy_source, write_alias_tree, write_banner, write_handlers, write_snapshot}, types::RuleWithArgs};
+use cl0_parser::{ast::{Compound, Directive}, lex_and_parse_compound_safe, lex_and_parse_directives, lex_and_parse_safe};
 
-// ANSI color codes
-const RESET: &str = "\x1b[0m";
-const BOLD: &str = "\x1b[1m";
-const BLUE: &str = "\x1b[34m";
-const CYAN: &str = "\x1b[36m";
-const GREEN: &str = "\x1b[32m";
-const YELLOW: &str = "\x1b[33m";
-const MAGENTA: &str = "\x1b[35m";
-const RED: &str = "\x1b[31m";
+// How long to wait for a batch of rules before giving the prompt back
+const RULES_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[tokio::main]
 async fn main() {
     let stdin = io::stdin();
     let mut stdout = io::stdout();
 
+    // Colors are left out with --no-color or when the output isn't a terminal
+    let no_color = std::env::args().skip(1).any(|arg| arg == "--no-color");
+    let style = Style::detect(no_color);
+
     fn validate_policy(policy: &str) -> Option<Compound> {
-        // Try to catch panics from lex_and_parse_compound
-        let result = std::panic::catch_unwind(|| lex_and_parse_compound(policy));
-        match result {
-            Ok(compound) => Some(compound),
-            Err(_err) => None,
-        }
+        lex_and_parse_compound_safe(policy).ok()
     }
 
     // Welcome banner
-    println!("{}{}Welcome to \"CL0 Node REPL\"!{}", BOLD, CYAN, RESET);
-    println!();
-    println!(
-        "{}This REPL resembles the engine behind a single node in the system.{}",
-        BLUE, RESET
-    );
-    println!();
-    println!();
-    println!(
-        "{}Enter initial rules (optionally) by wrapping {{ ... }}{}",
-        BLUE, RESET
-    );
-    println!();
-    println!("{}For example:{}", GREEN, RESET);
-    println!("{}{{{}", YELLOW, RESET);
-    println!("  {}#e: c => +a.{}", MAGENTA, RESET);
-    println!("  {}=> #e.{}", MAGENTA, RESET);
-    println!("  {}{{ #f => +v }} as alias.{}", MAGENTA, RESET);
-    println!("{}}}{}", YELLOW, RESET);
-    println!();
-    println!(
-        "{}Use {}{{}}{} for empty policy initialization.{}",
-        BLUE, YELLOW, BLUE, RESET
-    );
-    println!();
-    println!();
-    println!(
-        "{}After initialization, run rules one by one.{}",
-        BLUE, RESET
-    );
-    println!();
-    println!(
-        "{}Use the 'observe' command to view state.{}\n",
-        BLUE, RESET
-    );
-    println!();
-
-    // Basic syntax guide
-    println!("{}{}Basic Syntax:{}\n", BOLD, CYAN, RESET);
-    println!("{}Events:{}", BOLD, RESET);
-    println!("  {}#event{}        Trigger event", GREEN, RESET);
-    println!("  {}+condition{}    Production event", GREEN, RESET);
-    println!("  {}-condition{}    Consumption event\n", GREEN, RESET);
-    println!("{}Conditions:{}", BOLD, RESET);
-    println!("  {}foo{}           Atomic condition", YELLOW, RESET);
-    println!("  {}not foo{}       Negation", YELLOW, RESET);
-    println!("  {}( foo ){}       Priority", YELLOW, RESET);
-    println!("  {}a and b{}       Conjunction", YELLOW, RESET);
-    println!("  {}a or b{}        Disjunction", YELLOW, RESET);
-    println!("  {}{{ ... }} as r{}  Compound alias", YELLOW, RESET);
-    println!("\n{}Actions:{}", BOLD, RESET);
-    println!("  {}#e{}            Trigger event", GREEN, RESET);
-    println!("  {}+v{}            Production action", GREEN, RESET);
-    println!("  {}-v{}            Consumption action", GREEN, RESET);
-    println!("  {}a, b{}          Parallel actions", GREEN, RESET);
-    println!("  {}a; b{}          Sequential actions", GREEN, RESET);
-    println!("  {}a alt b{}       Alternative actions\n", GREEN, RESET);
-
+    write_banner(&mut stdout, style).expect("Failed to write to stdout");
     // Get initial policy
     println!(
         "{}{}Enter initial policy (or leave empty):{}.",
-        BOLD, CYAN, RESET
+        style.bold(), style.cyan(), style.reset()
     );
 
     // The node data
     let mut node: Option<Arc<Node>> = None;
 
+    // Directives entered before the initial policy, applied when the node is created
+    let mut directives: Vec<Directive> = Vec::new();
+
     let initial_policy = {
         let mut policy = String::new();
         let mut bracket_depth = 0;
         loop {
             // Prompt for policy lines
-            print!("{}{}> {}", BOLD, MAGENTA, RESET);
+            print!("{}{}> {}", style.bold(), style.magenta(), style.reset());
             stdout.flush().expect("Failed to flush stdout");
 
             let mut line = String::new();
@@ -113,13 +51,26 @@ async fn main() {
                     if line.trim().eq_ignore_ascii_case("reset") {
                         println!(
                             "{}{}Enter initial policy (or leave empty):{}.",
-                            BOLD, CYAN, RESET
+                            style.bold(), style.cyan(), style.reset()
                         );
                         policy.clear();
+                        directives.clear();
                         bracket_depth = 0;
                         continue;
                     }
 
+                    // Directives such as `@interleaving` come before the policy, one or more per line
+                    if policy.is_empty() && line.trim_start().starts_with('@') {
+                        match lex_and_parse_directives(&line) {
+                            Ok(parsed) => {
+                                println!("{}Directives accepted.{}", style.green(), style.reset());
+                                directives.extend(parsed);
+                            }
+                            Err(e) => eprintln!("{}{}{}", style.magenta(), e, style.reset()),
+                        }
+                        continue;
+                    }
+
                     // Count the brackets to determine if we are done
                     bracket_depth += line.chars().filter(|&c| c == '{').count();
                     bracket_depth -= line.chars().filter(|&c| c == '}').count();
@@ -134,21 +85,22 @@ async fn main() {
                     // Check validity on full policy text
                     if bracket_depth == 0 {
                         if let Some(compound) = validate_policy(&policy) {
-                            println!("{}Valid policy detected, proceeding...{}", GREEN, RESET);
-                            println!("{}Parsed policy:\n{}{}{}", GREEN, RESET, compound, RESET);
+                            println!("{}Valid policy detected, proceeding...{}", style.green(), style.reset());
+                            println!("{}Parsed policy:\n{}{}{}", style.green(), style.reset(), compound, style.reset());
 
-                            node = Some(Node::new_with_rules(Some(compound.clone().rules)).await);
+                            let config = NodeConfig::default().with_directives(&directives);
+                            node = Some(Node::new_with_config(Some(compound.clone().rules), config).await);
                             break policy.clone();
                         } else {
                             println!(
                                 "{}Policy incomplete or invalid, continue typing... Type \"reset\" to start over{}",
-                                YELLOW, RESET
+                                style.yellow(), style.reset()
                             );
                         }
                     }
                 }
                 Err(err) => {
-                    eprintln!("{}Error reading policy: {}{}", MAGENTA, err, RESET);
+                    eprintln!("{}Error reading policy: {}{}", style.magenta(), err, style.reset());
                     break policy.clone();
                 }
             }
@@ -156,19 +108,25 @@ async fn main() {
     };
     println!(
         "{}Initial policy accepted:\n{}{}{}",
-        GREEN, RESET, initial_policy, RESET
+        style.green(), style.reset(), initial_policy, style.reset()
     );
     
     
     // Unwrap the node (By this point, it should be created)
     if node.is_none() {
-        node = Some(Node::new_with_rules(None).await);
+        node = Some(Node::new_with_config(None, NodeConfig::default().with_directives(&directives)).await);
     }
     let node = node.unwrap();
 
+    // Lines of a rule that isn't complete yet
+    let mut buffer = InputBuffer::new();
+
+    // Applied rule batches, each with the node's state from before it, for 'undo'
+    let mut history: Vec<(String, NodeCheckpoint)> = Vec::new();
+
     loop {
-        // Prompt
-        print!(">> ");
+        // Prompt, showing when the previous lines are continued
+        print!("{}", if buffer.is_empty() { ">> " } else { ".. " });
         stdout.flush().expect("Failed to flush stdout");
 
         // Read a line of input
@@ -179,6 +137,12 @@ async fn main() {
                 println!();
                 break;
             }
+            Ok(_) if !buffer.is_empty() => {
+                // Continue the pending rule, commands are only read at the start of one
+                if let Some(src) = buffer.push_line(&input) {
+                    apply_recorded(&node, src, &mut history, style).await;
+                }
+            }
             Ok(_) => {
                 let trimmed = input.trim_end();
                 // Exit commands
@@ -186,78 +150,85 @@ async fn main() {
                     break;
                 }
                 if trimmed.eq_ignore_ascii_case("observe") {
-                    // Show the current state of the node
-                    println!("{}Current state:{}", BLUE, RESET);
-                    println!("{}Rules:{}", YELLOW, RESET);
-                    println!("==========================");
-                    // Fetch and print the rules
-                    let rule_res = node.api.get_rules.call(true).await;
-                    match rule_res {
-                        Ok(rules) => {
-                            if rules.is_empty() {
-                                println!("{}    No rules defined.{}", YELLOW, RESET);
-                            } else {
-                                for rule in rules {
-                                    let namespace_string = match rule.alias {
-                                        Some(ns) => ns.join(".") + ".",
-                                        None => "".to_string(),
-                                    };
-                                    println!("{}    {}{}{}{}: {}{}{}", BLUE, namespace_string, RESET,rule.rule.to_string().trim_end_matches("."), YELLOW, (if rule.value == ActivationStatus::True { GREEN } else { RED }), rule.value, RESET);
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("{}Failed to fetch rules: {}{}", MAGENTA, e, RESET);
-                        }
+                    let snapshot = node.observe().await;
+                    write_snapshot(&mut stdout, snapshot, style).expect("Failed to write to stdout");
+                    continue;
+                }
+                if trimmed.eq_ignore_ascii_case("tree") {
+                    write_alias_tree(&mut stdout, &node.alias_tree(), style).expect("Failed to write to stdout");
+                    continue;
+                }
+                if trimmed.eq_ignore_ascii_case("handlers") {
+                    let handlers = handler_states(&node).await;
+                    write_handlers(&mut stdout, &handlers, style).expect("Failed to write to stdout");
+                    continue;
+                }
+                if trimmed.eq_ignore_ascii_case("reset") {
+                    // Start over with an empty policy, but keep the old state around for 'undo'
+                    match node.snapshot().await {
+                        Ok(checkpoint) => history.push(("reset".to_string(), checkpoint)),
+                        Err(e) => eprintln!("{}Failed to record state: {}{}", style.magenta(), e, style.reset()),
                     }
-                    println!("==========================");
-                    println!("{}Variables:{}", YELLOW, RESET);
-                    println!("==========================");
-
-                    // Fetch and print the variables
-                    let vars_res = node.vars.iter().collect::<Vec<_>>();
-
-                    for var in vars_res {
-                        let guard_key = var.key().clone();
-                        let guard_value = var.value().clone();
-                        println!("{}    {}: {}{}{}", BLUE, guard_key, (if guard_value == ActivationStatus::True { GREEN } else { RED }), guard_value, RESET);
+                    node.reset();
+                    println!("{}Node state cleared.{}", style.green(), style.reset());
+                    continue;
+                }
+                if trimmed.eq_ignore_ascii_case("history") {
+                    if history.is_empty() {
+                        println!("{}Nothing applied yet.{}", style.yellow(), style.reset());
+                    }
+                    for (i, (src, _)) in history.iter().enumerate() {
+                        println!("{}{:>4}{}  {}", style.blue(), i + 1, style.reset(), src);
                     }
-                    println!("==========================");
-
-                    // let state = node.observe().await;
-                    // println!("{:#?}", state);
                     continue;
                 }
-                // Parse the input as a rule
-                let rules = match lex_and_parse_safe(trimmed) {
-                    Ok(rules) => rules,
-                    Err(_) => {
-                        // Should automatically print the error
-                        continue;
+                if let Some(path) = trimmed.strip_prefix("save ") {
+                    // Write the accumulated rules out as CL0 source
+                    let result = match policy_source(&node).await {
+                        Ok(src) => std::fs::write(path.trim(), src).map_err(|e| e.into()),
+                        Err(e) => Err(e),
+                    };
+                    match result {
+                        Ok(()) => println!("{}Saved policy to {}.{}", style.green(), path.trim(), style.reset()),
+                        Err(e) => eprintln!("{}Failed to save: {}{}", style.magenta(), e, style.reset()),
                     }
-                };
-
-                // Add the rules to the node
-                let result = node
-                    .api
-                    .new_rules
-                    .call(rules.clone().into_iter().map(|r| RuleWithArgs::from(r)).collect())
-                    .await;
-                match result {
-                    Ok(_) => {
-                        if rules.clone().len() == 0 {
-                            println!("{}No rules were added.{}", YELLOW, RESET);
-                        } else {
-                            println!("{}Added {} rule(s) successfully.{}", GREEN, rules.len(), RESET);
-                        }
+                    continue;
+                }
+                if let Some(path) = trimmed.strip_prefix("load ") {
+                    // Apply the rules of a file as one batch, so 'undo' reverts the whole file
+                    match std::fs::read_to_string(path.trim()) {
+                        Ok(src) => apply_recorded(&node, src, &mut history, style).await,
+                        Err(e) => eprintln!("{}Failed to load {}: {}{}", style.magenta(), path.trim(), e, style.reset()),
                     }
-                    Err(e) => {
-                        eprintln!("{}Failed to add rules: {}{}", MAGENTA, e, RESET);
+                    continue;
+                }
+                if trimmed.eq_ignore_ascii_case("undo") {
+                    // Revert the last batch by going back to the state from before it
+                    match history.pop() {
+                        None => println!("{}Nothing to undo.{}", style.yellow(), style.reset()),
+                        Some((src, checkpoint)) => match node.clone().restore(checkpoint).await {
+                            Ok(()) => println!("{}Undone: {}{}", style.green(), src, style.reset()),
+                            Err(e) => eprintln!("{}Failed to undo: {}{}", style.magenta(), e, style.reset()),
+                        },
                     }
+                    continue;
+                }
+                if let Some(cond_src) = trimmed
+                    .strip_prefix("query ")
+                    .or_else(|| trimmed.strip_prefix("QUERY "))
+                {
+                    // Evaluate a condition against the node
+                    match node.clone().query(cond_src).await {
+                        Ok(true) => println!("{}{}: True{}", style.green(), cond_src.trim(), style.reset()),
+                        Ok(false) => println!("{}{}: False{}", style.red(), cond_src.trim(), style.reset()),
+                        Err(e) => eprintln!("{}Failed to query: {}{}", style.magenta(), e, style.reset()),
+                    }
+                    continue;
+                }
+                // Parse the input as rules once they are complete
+                if let Some(src) = buffer.push_line(trimmed) {
+                    apply_recorded(&node, src, &mut history, style).await;
                 }
-
-                // Echo back the input
-                println!("{}", trimmed);
             }
             Err(err) => {
                 eprintln!("Error reading line: {}", err);
@@ -266,3 +237,73 @@ async fn main() {
         }
     }
 }
+
+/// Applies `src` like `apply_rules`, recording the state from before it in `history`.
+async fn apply_recorded(
+    node: &Arc<Node>,
+    src: String,
+    history: &mut Vec<(String, NodeCheckpoint)>,
+    style: Style,
+) {
+    let checkpoint = match node.snapshot().await {
+        Ok(checkpoint) => checkpoint,
+        Err(e) => {
+            eprintln!("{}Failed to record state: {}{}", style.magenta(), e, style.reset());
+            return;
+        }
+    };
+    if apply_rules(node, &src, style).await {
+        history.push((src, checkpoint));
+    }
+}
+
+/// Parses `src` as rules and adds them to the node, reporting the outcome.
+/// Returns whether the rules parsed and the node took them without failing. Directives are
+/// refused as syntax errors: the node's options are fixed once it is created, so they can only
+/// be given before the initial policy.
+async fn apply_rules(node: &Arc<Node>, src: &str, style: Style) -> bool {
+    // Parse the input as rules
+    let rules = match lex_and_parse_safe(src) {
+        Ok(rules) => rules,
+        Err(_) => {
+            // Should automatically print the error
+            return false;
+        }
+    };
+
+    // Add the rules to the node
+    let result = node
+        .api
+        .new_rules
+        .call_timeout(
+            rules.clone().into_iter().map(RuleWithArgs::from).collect(),
+            RULES_TIMEOUT,
+        )
+        .await;
+    match result {
+        Ok(results) => {
+            let added = results.iter().filter(|ok| **ok).count();
+            if rules.is_empty() {
+                println!("{}No rules were added.{}", style.yellow(), style.reset());
+            } else if added == rules.len() {
+                println!("{}Added {} rule(s) successfully.{}", style.green(), added, style.reset());
+            } else {
+                println!(
+                    "{}Added {} of {} rule(s), the others were not applied.{}",
+                    style.yellow(),
+                    added,
+                    rules.len(),
+                    style.reset()
+                );
+            }
+        }
+        Err(e) => {
+            eprintln!("{}Failed to add rules: {}{}", style.magenta(), e, style.reset());
+            return false;
+        }
+    }
+
+    // Echo back the input
+    println!("{}", src);
+    true
+}
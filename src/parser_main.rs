@@ -1,10 +1,9 @@
 
-use cl0_parser::parse_and_print;
+use cl0_parser::cli::run;
 
 fn main() {
     let mut args = std::env::args();
     let _bin = args.next();
-    let input = args.next().expect("Please provide a string to parse as the first argument.");
 
-    parse_and_print(&input);
-}
\ No newline at end of file
+    std::process::exit(run(args));
+}
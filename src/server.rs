@@ -1,6 +1,22 @@
-use cl0_node::server::serve;
+use cl0_node::server::{ServerConfig, TlsConfig, serve};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    serve(None).await
-}
\ No newline at end of file
+    let tls = match (
+        std::env::var_os("CL0_TLS_CERT"),
+        std::env::var_os("CL0_TLS_KEY"),
+    ) {
+        (Some(cert), Some(key)) => Some(TlsConfig {
+            cert: cert.into(),
+            key: key.into(),
+        }),
+        _ => None,
+    };
+    let config = ServerConfig {
+        auth_token: std::env::var("CL0_AUTH_TOKEN").ok(),
+        persist_dir: std::env::var_os("CL0_PERSIST_DIR").map(std::path::PathBuf::from),
+        tls,
+        ..Default::default()
+    };
+    serve(None, config).await
+}